@@ -1,6 +1,6 @@
 #!/bin/bash
 
-TARGET=${TARGET-x86_64-unknown-linux-gnu}
+TARGET=${TARGET-$(uname -m)-unknown-linux-gnu}
 if [ "$TARGET" != "" ]; then
     TGT="--target $TARGET"
 fi
@@ -9,6 +9,12 @@ if [[ "$1" == "--release" ]]; then
     VERSION=release
 fi
 
-cargo build --verbose $TGT $1
+# musl targets are linked statically. There is no static libseccomp for them
+# on the build hosts, so the static binary is built without seccomp support.
+if [[ "$TARGET" == *-musl ]]; then
+    FEATURES="--no-default-features --features systemd_cgroups,criu"
+fi
+
+cargo build --verbose $TGT $FEATURES $1
 rm -f youki
 cp target/$TARGET/$VERSION/youki .
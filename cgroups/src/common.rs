@@ -1,24 +1,55 @@
 use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
     fmt::{Debug, Display},
     fs::{self, File},
     io::{BufRead, BufReader, Write},
-    path::{Path, PathBuf},
+    os::unix::io::{AsRawFd, FromRawFd},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
     time::Duration,
 };
 
 use anyhow::{bail, Context, Result};
 use nix::{
-    sys::statfs::{statfs, CGROUP2_SUPER_MAGIC, TMPFS_MAGIC},
+    fcntl::{self, OFlag},
+    sys::stat::Mode,
     unistd::Pid,
 };
 use oci_spec::runtime::{
-    LinuxDevice, LinuxDeviceBuilder, LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType,
-    LinuxResources,
+    LinuxBlockIo, LinuxDevice, LinuxDeviceBuilder, LinuxDeviceCgroup, LinuxDeviceCgroupBuilder,
+    LinuxDeviceType, LinuxResources,
 };
+use procfs::process::{MountInfo, Process, ProcessCgroup};
+/// Checks if the system has been booted with systemd the same way as
+/// sd_booted(3), without having to link against libsystemd
 #[cfg(feature = "systemd_cgroups")]
-use systemd::daemon::booted;
+fn booted() -> bool {
+    fs::symlink_metadata("/run/systemd/system/").map_or(false, |m| m.is_dir())
+}
+
+#[cfg(feature = "systemd_cgroups")]
+fn create_systemd_cgroup_manager(
+    cgroup_path: PathBuf,
+    unit_properties: Vec<(String, String)>,
+) -> Result<Box<dyn CgroupManager>> {
+    if !booted() {
+        bail!(
+            "systemd cgroup flag passed, but systemd support for managing cgroups is not available"
+        );
+    }
+    log::info!("systemd cgroup manager will be used");
+    Ok(Box::new(
+        v2::SystemDCGroupManager::new(DEFAULT_CGROUP_ROOT.into(), cgroup_path)?
+            .with_unit_properties(unit_properties),
+    ))
+}
+
 #[cfg(not(feature = "systemd_cgroups"))]
-fn booted() -> Result<bool> {
+fn create_systemd_cgroup_manager(
+    _cgroup_path: PathBuf,
+    _unit_properties: Vec<(String, String)>,
+) -> Result<Box<dyn CgroupManager>> {
     bail!("This build does not include the systemd cgroups feature")
 }
 
@@ -29,6 +60,7 @@ use super::stats::Stats;
 
 pub const CGROUP_PROCS: &str = "cgroup.procs";
 pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+pub(crate) const SYS_DEV_BLOCK: &str = "/sys/dev/block";
 
 pub trait CgroupManager {
     /// Adds a task specified by its pid to the cgroup
@@ -48,6 +80,10 @@ pub trait CgroupManager {
 
     // Gets the PIDs inside the cgroup
     fn get_all_pids(&self) -> Result<Vec<Pid>>;
+
+    /// Kills all processes in the cgroup at once, which requires cgroup.kill
+    /// of cgroup v2 (Linux 5.14). Returns false if it is not supported.
+    fn kill_all(&self) -> Result<bool>;
 }
 
 #[derive(Debug)]
@@ -80,6 +116,38 @@ pub enum FreezerState {
     Thawed,
 }
 
+/// Determines how resource restrictions are handled, which require a controller
+/// that is not mounted or not delegated to the cgroup
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MissingControllerPolicy {
+    /// Fail to apply the resource restrictions
+    Fail,
+    /// Skip the restrictions of the missing controller with a warning and
+    /// apply the remaining ones
+    Warn,
+}
+
+impl Default for MissingControllerPolicy {
+    fn default() -> Self {
+        Self::Fail
+    }
+}
+
+impl FromStr for MissingControllerPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(policy: &str) -> Result<Self> {
+        match policy {
+            "fail" => Ok(Self::Fail),
+            "warn" => Ok(Self::Warn),
+            _ => bail!(
+                "invalid missing controller policy {}, expected fail or warn",
+                policy
+            ),
+        }
+    }
+}
+
 /// ControllerOpt is given all cgroup controller for applying cgroup configuration.
 #[derive(Clone, Debug)]
 pub struct ControllerOpt<'a> {
@@ -91,16 +159,79 @@ pub struct ControllerOpt<'a> {
     pub oom_score_adj: Option<i32>,
     /// FreezerState is given to freezer contoller for suspending process.
     pub freezer_state: Option<FreezerState>,
+    /// Handling of restrictions which require a controller that is not available.
+    pub missing_controllers: MissingControllerPolicy,
+}
+
+thread_local! {
+    // directory fds of the cgroups written to, while with_pooled_dir_fds runs
+    static DIR_FDS: RefCell<Option<HashMap<PathBuf, File>>> = RefCell::new(None);
+}
+
+/// Runs f with the directory fds of the cgroups it writes to kept open, so
+/// that each write only has to look up the file in its directory with openat
+/// instead of resolving the whole path. The fds are closed when f returns, so
+/// they never refer to a removed cgroup. The pool belongs to the calling thread.
+pub(crate) fn with_pooled_dir_fds<T, F: FnOnce() -> T>(f: F) -> T {
+    let activated = DIR_FDS.with(|dir_fds| {
+        let mut dir_fds = dir_fds.borrow_mut();
+        if dir_fds.is_some() {
+            return false;
+        }
+        *dir_fds = Some(HashMap::new());
+        true
+    });
+
+    let result = f();
+    if activated {
+        DIR_FDS.with(|dir_fds| dir_fds.borrow_mut().take());
+    }
+    result
+}
+
+fn open_cgroup_file(path: &Path) -> Result<File> {
+    let pooled = DIR_FDS.with(|dir_fds| -> Result<Option<File>> {
+        let mut dir_fds = dir_fds.borrow_mut();
+        let dir_fds = match dir_fds.as_mut() {
+            Some(dir_fds) => dir_fds,
+            None => return Ok(None),
+        };
+        let (dir, file_name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(file_name)) => (dir, file_name),
+            _ => return Ok(None),
+        };
+
+        if !dir_fds.contains_key(dir) {
+            let fd = fcntl::open(
+                dir,
+                OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+                Mode::empty(),
+            )?;
+            dir_fds.insert(dir.to_path_buf(), unsafe { File::from_raw_fd(fd) });
+        }
+        let fd = fcntl::openat(
+            dir_fds[dir].as_raw_fd(),
+            file_name,
+            OFlag::O_WRONLY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )?;
+        Ok(Some(unsafe { File::from_raw_fd(fd) }))
+    });
+
+    match pooled.with_context(|| format!("failed to open {:?}", path))? {
+        Some(file) => Ok(file),
+        None => fs::OpenOptions::new()
+            .create(false)
+            .write(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("failed to open {:?}", path)),
+    }
 }
 
 #[inline]
 pub fn write_cgroup_file_str<P: AsRef<Path>>(path: P, data: &str) -> Result<()> {
-    fs::OpenOptions::new()
-        .create(false)
-        .write(true)
-        .truncate(false)
-        .open(path.as_ref())
-        .with_context(|| format!("failed to open {:?}", path.as_ref()))?
+    open_cgroup_file(path.as_ref())?
         .write_all(data.as_bytes())
         .with_context(|| format!("failed to write to {:?}", path.as_ref()))?;
 
@@ -109,12 +240,7 @@ pub fn write_cgroup_file_str<P: AsRef<Path>>(path: P, data: &str) -> Result<()>
 
 #[inline]
 pub fn write_cgroup_file<P: AsRef<Path>, T: ToString>(path: P, data: T) -> Result<()> {
-    fs::OpenOptions::new()
-        .create(false)
-        .write(true)
-        .truncate(false)
-        .open(path.as_ref())
-        .with_context(|| format!("failed to open {:?}", path.as_ref()))?
+    open_cgroup_file(path.as_ref())?
         .write_all(data.to_string().as_bytes())
         .with_context(|| format!("failed to write to {:?}", path.as_ref()))?;
 
@@ -132,51 +258,64 @@ pub fn read_cgroup_file<P: AsRef<Path>>(path: P) -> Result<String> {
 /// - Unified: Pure cgroup v2 system.
 /// - Legacy: Pure cgroup v1 system.
 /// - Hybrid: Hybrid is basically a cgroup v1 system, except for
-///   an additional unified hierarchy which doesn't have any
-///   controllers attached. Resource control can purely be achieved
-///   through the cgroup v1 hierarchy, not through the cgroup v2 hierarchy.
+///   an additional unified hierarchy. Usually no controllers are attached
+///   to the unified hierarchy, but controllers which are not mounted on
+///   the v1 hierarchy can be enabled on it.
 pub fn get_cgroup_setup() -> Result<CgroupSetup> {
-    let default_root = Path::new(DEFAULT_CGROUP_ROOT);
-    match default_root.exists() {
-        true => {
-            // If the filesystem is of type cgroup2, the system is in unified mode.
-            // If the filesystem is tmpfs instead the system is either in legacy or
-            // hybrid mode. If a cgroup2 filesystem has been mounted under the "unified"
-            // folder we are in hybrid mode, otherwise we are in legacy mode.
-            let stat = statfs(default_root).with_context(|| {
-                format!(
-                    "failed to stat default cgroup root {}",
-                    &default_root.display()
-                )
-            })?;
-            if stat.filesystem_type() == CGROUP2_SUPER_MAGIC {
-                return Ok(CgroupSetup::Unified);
-            }
-
-            if stat.filesystem_type() == TMPFS_MAGIC {
-                let unified = Path::new("/sys/fs/cgroup/unified");
-                if Path::new(unified).exists() {
-                    let stat = statfs(unified)
-                        .with_context(|| format!("failed to stat {}", unified.display()))?;
-                    if stat.filesystem_type() == CGROUP2_SUPER_MAGIC {
-                        return Ok(CgroupSetup::Hybrid);
-                    }
-                }
+    let mount_infos = Process::myself()?
+        .mountinfo()
+        .context("failed to read mount info")?;
+    cgroup_setup_from_mounts(&mount_infos)
+}
 
-                return Ok(CgroupSetup::Legacy);
-            }
+/// Determines the cgroup setup from the cgroup filesystems which are mounted.
+/// If a cgroup2 filesystem is mounted at the default cgroup root, the system
+/// is in unified mode. If cgroup v1 filesystems are mounted the system is in
+/// hybrid mode if a cgroup2 filesystem has been mounted in addition to them, otherwise
+/// it is in legacy mode.
+fn cgroup_setup_from_mounts(mount_infos: &[MountInfo]) -> Result<CgroupSetup> {
+    let default_root = Path::new(DEFAULT_CGROUP_ROOT);
+    let mut has_v1 = false;
+    let mut has_v2 = false;
+    for mount_info in mount_infos {
+        match mount_info.fs_type.as_str() {
+            "cgroup" => has_v1 = true,
+            "cgroup2" if mount_info.mount_point == default_root => return Ok(CgroupSetup::Unified),
+            "cgroup2" => has_v2 = true,
+            _ => {}
         }
-        false => bail!("non default cgroup root not supported"),
     }
 
-    bail!("failed to detect cgroup setup");
+    match (has_v1, has_v2) {
+        (true, true) => Ok(CgroupSetup::Hybrid),
+        (true, false) => Ok(CgroupSetup::Legacy),
+        (false, true) => bail!("non default cgroup root not supported"),
+        (false, false) => bail!("failed to detect cgroup setup, no cgroup filesystem is mounted"),
+    }
 }
 
 pub fn create_cgroup_manager<P: Into<PathBuf>>(
     cgroup_path: P,
     systemd_cgroup: bool,
 ) -> Result<Box<dyn CgroupManager>> {
+    create_cgroup_manager_with_unit_properties(cgroup_path, systemd_cgroup, Vec::new())
+}
+
+/// Creates the cgroup manager like create_cgroup_manager. The systemd cgroup
+/// manager additionally sets the properties, given as name and value, on the
+/// unit of the container when it is started. They are ignored by the other
+/// managers.
+pub fn create_cgroup_manager_with_unit_properties<P: Into<PathBuf>>(
+    cgroup_path: P,
+    systemd_cgroup: bool,
+    unit_properties: Vec<(String, String)>,
+) -> Result<Box<dyn CgroupManager>> {
+    if !systemd_cgroup && !unit_properties.is_empty() {
+        log::warn!("systemd unit properties are ignored without the systemd cgroup manager");
+    }
+
     let cgroup_setup = get_cgroup_setup()?;
+    log::debug!("detected {} cgroup setup", cgroup_setup);
 
     match cgroup_setup {
         CgroupSetup::Legacy | CgroupSetup::Hybrid => {
@@ -185,14 +324,7 @@ pub fn create_cgroup_manager<P: Into<PathBuf>>(
         }
         CgroupSetup::Unified => {
             if systemd_cgroup {
-                if !booted()? {
-                    bail!("systemd cgroup flag passed, but systemd support for managing cgroups is not available");
-                }
-                log::info!("systemd cgroup manager will be used");
-                return Ok(Box::new(v2::SystemDCGroupManager::new(
-                    DEFAULT_CGROUP_ROOT.into(),
-                    cgroup_path.into(),
-                )?));
+                return create_systemd_cgroup_manager(cgroup_path.into(), unit_properties);
             }
             log::info!("cgroup manager V2 will be used");
             Ok(Box::new(v2::manager::Manager::new(
@@ -203,6 +335,98 @@ pub fn create_cgroup_manager<P: Into<PathBuf>>(
     }
 }
 
+/// Resolves the directories of a sub-cgroup below the cgroups the process is a
+/// member of, e.g. to place additional processes of a container apart from its
+/// main workload. The sub-cgroup is not created, it has to exist in all
+/// hierarchies with controllers attached. Hierarchies without controllers are
+/// only joined if the sub-cgroup exists in them.
+pub fn get_sub_cgroup_paths(pid: Pid, sub_cgroup: &Path) -> Result<Vec<PathBuf>> {
+    let process = Process::new(pid.as_raw())?;
+    let cgroups = process
+        .cgroups()
+        .with_context(|| format!("failed to read cgroups of {}", pid))?;
+    let mount_infos = Process::myself()?
+        .mountinfo()
+        .context("failed to read mount info")?;
+    sub_cgroup_paths(&cgroups, &mount_infos, sub_cgroup)
+}
+
+fn sub_cgroup_paths(
+    cgroups: &[ProcessCgroup],
+    mount_infos: &[MountInfo],
+    sub_cgroup: &Path,
+) -> Result<Vec<PathBuf>> {
+    if !sub_cgroup
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        bail!(
+            "sub-cgroup {} must be a relative path within the cgroup",
+            sub_cgroup.display()
+        );
+    }
+
+    // in hybrid setups the unified hierarchy usually has no controllers
+    let is_unified = cgroups.iter().all(|c| c.hierarchy == 0);
+    let mut paths = Vec::new();
+    for cgroup in cgroups {
+        let has_controllers = if cgroup.hierarchy == 0 {
+            is_unified
+        } else {
+            cgroup.controllers.iter().any(|c| !c.starts_with("name="))
+        };
+
+        let mount_info = match mount_infos.iter().find(|m| is_hierarchy_mount(m, cgroup)) {
+            Some(mount_info) => mount_info,
+            None if has_controllers => bail!(
+                "could not find mountpoint of cgroup hierarchy {}",
+                cgroup.hierarchy
+            ),
+            None => continue,
+        };
+        let cgroup_path =
+            cgroup_path_in_mount(Path::new(&cgroup.pathname), Path::new(&mount_info.root));
+        let path = mount_info
+            .mount_point
+            .join(cgroup_path.strip_prefix("/").unwrap_or(&cgroup_path))
+            .join(sub_cgroup);
+        if path.exists() {
+            paths.push(path);
+        } else if has_controllers {
+            bail!("sub-cgroup {} does not exist", path.display());
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Makes a cgroup as listed in /proc/<pid>/cgroup relative to the root of the
+/// mount of its hierarchy. Without a cgroup namespace the cgroup is a path of
+/// the host, while the mount may only show a subtree of the hierarchy, e.g.
+/// /docker/<id> when youki runs nested in a container. Within a cgroup
+/// namespace the cgroup is already relative to the root of the namespace.
+pub fn cgroup_path_in_mount(cgroup: &Path, mount_root: &Path) -> PathBuf {
+    match cgroup.strip_prefix(mount_root) {
+        Ok(relative) => Path::new("/").join(relative),
+        Err(_) => cgroup.to_path_buf(),
+    }
+}
+
+fn is_hierarchy_mount(mount_info: &MountInfo, cgroup: &ProcessCgroup) -> bool {
+    if cgroup.hierarchy == 0 {
+        return mount_info.fs_type == "cgroup2";
+    }
+
+    mount_info.fs_type == "cgroup"
+        && cgroup
+            .controllers
+            .iter()
+            .all(|controller| match controller.strip_prefix("name=") {
+                Some(name) => mount_info.super_options.get("name") == Some(&Some(name.to_owned())),
+                None => mount_info.super_options.contains_key(controller),
+            })
+}
+
 pub fn get_all_pids(path: &Path) -> Result<Vec<Pid>> {
     log::debug!("scan pids in folder: {:?}", path);
     let mut result = vec![];
@@ -355,6 +579,33 @@ pub(crate) fn default_devices() -> Vec<LinuxDevice> {
     ]
 }
 
+/// Removes the cgroup and all cgroups below it, as a cgroup can only be
+/// removed once it has no children. The processes left in the cgroups are
+/// killed and the removal is retried until the killed processes are gone.
+pub(crate) fn remove_cgroup_tree(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_cgroup_tree(&entry.path())?;
+        }
+    }
+
+    let procs_path = path.join(CGROUP_PROCS);
+    if procs_path.exists() {
+        for line in fs::read_to_string(&procs_path)?.lines() {
+            let pid: i32 = line.parse()?;
+            let _ = nix::sys::signal::kill(Pid::from_raw(pid), nix::sys::signal::SIGKILL);
+        }
+    }
+
+    log::debug!("remove cgroup {:?}", path);
+    delete_with_retry(path, 10, Duration::from_millis(100))
+}
+
 /// Attempts to delete the path the requested number of times.
 pub(crate) fn delete_with_retry<P: AsRef<Path>, L: Into<Option<Duration>>>(
     path: P,
@@ -372,8 +623,8 @@ pub(crate) fn delete_with_retry<P: AsRef<Path>, L: Into<Option<Duration>>>(
         }
 
         std::thread::sleep(delay);
-        attempts += attempts;
-        delay *= attempts;
+        attempts += 1;
+        delay *= 2;
         if delay > limit {
             delay = limit;
         }
@@ -381,3 +632,328 @@ pub(crate) fn delete_with_retry<P: AsRef<Path>, L: Into<Option<Duration>>>(
 
     bail!("could not delete {:?}", path)
 }
+
+/// Checks that all block devices referenced by the throttles exist, so that
+/// a list of throttles is either applied completely or not at all. The block
+/// devices of the system are listed as MAJ:MIN in the sys_dev_block directory.
+pub(crate) fn validate_throttle_devices(blkio: &LinuxBlockIo, sys_dev_block: &Path) -> Result<()> {
+    let throttles = [
+        ("throttleReadBpsDevice", blkio.throttle_read_bps_device()),
+        ("throttleWriteBpsDevice", blkio.throttle_write_bps_device()),
+        ("throttleReadIOPSDevice", blkio.throttle_read_iops_device()),
+        (
+            "throttleWriteIOPSDevice",
+            blkio.throttle_write_iops_device(),
+        ),
+    ];
+
+    for (name, throttle_devices) in throttles.iter() {
+        for (i, device) in throttle_devices.iter().flatten().enumerate() {
+            let (major, minor) = (device.major(), device.minor());
+            if major < 0 || minor < 0 {
+                bail!("{}[{}]: invalid device number {}:{}", name, i, major, minor);
+            }
+            if !sys_dev_block.join(format!("{}:{}", major, minor)).exists() {
+                bail!(
+                    "{}[{}]: block device {}:{} does not exist",
+                    name,
+                    i,
+                    major,
+                    minor
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the requested memory nodes of a cpuset are effectively used.
+/// The kernel restricts the nodes to those of the parent cgroup and of the
+/// system without failing the write, which would leave NUMA pinned workloads
+/// on the wrong nodes. Kernels which do not report the effective nodes are
+/// not checked.
+pub(crate) fn check_effective_mems(requested: &str, effective_path: &Path) -> Result<()> {
+    if !effective_path.exists() {
+        return Ok(());
+    }
+
+    let effective = read_cgroup_file(effective_path)?;
+    let effective_nodes = parse_cpuset_list(&effective)
+        .with_context(|| format!("invalid memory nodes in {}", effective_path.display()))?;
+    let missing: Vec<u32> = parse_cpuset_list(requested)
+        .with_context(|| format!("invalid memory nodes {:?}", requested))?
+        .difference(&effective_nodes)
+        .copied()
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "memory nodes {:?} are not available, the effective memory nodes are {:?}",
+            missing,
+            effective.trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Reports a swap limit which is skipped, because the swap files of the
+/// memory controller only exist if swap accounting is enabled. Like runc,
+/// the container is still created, its swap just stays unlimited.
+pub(crate) fn skip_swap_limit(swap: i64) {
+    if swap == -1 {
+        log::debug!("swap accounting is not enabled, swap stays unlimited");
+    } else {
+        log::warn!(
+            "swap accounting is not enabled, swap limit {} is not applied",
+            swap
+        );
+    }
+}
+
+/// Parses a list of cpus or memory nodes in the format of the cpuset
+/// controller, e.g. 0-2,4
+fn parse_cpuset_list(list: &str) -> Result<BTreeSet<u32>> {
+    let mut ids = BTreeSet::new();
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let (start, end): (u32, u32) = (start.parse()?, end.parse()?);
+                if start > end {
+                    bail!("invalid range {}", range);
+                }
+                ids.extend(start..=end);
+            }
+            None => {
+                ids.insert(range.parse()?);
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{create_temp_dir, set_fixture};
+
+    #[test]
+    fn test_cgroup_path_in_mount() {
+        assert_eq!(
+            cgroup_path_in_mount(Path::new("/docker/1234/youki"), Path::new("/docker/1234")),
+            PathBuf::from("/youki")
+        );
+        assert_eq!(
+            cgroup_path_in_mount(Path::new("/docker/1234"), Path::new("/docker/1234")),
+            PathBuf::from("/")
+        );
+        // within a cgroup namespace the path is already relative to its root
+        assert_eq!(
+            cgroup_path_in_mount(Path::new("/youki"), Path::new("/")),
+            PathBuf::from("/youki")
+        );
+        assert_eq!(
+            cgroup_path_in_mount(Path::new("/youki"), Path::new("/docker/1234")),
+            PathBuf::from("/youki")
+        );
+    }
+
+    #[test]
+    fn test_with_pooled_dir_fds() -> Result<()> {
+        let tmp = create_temp_dir("test_with_pooled_dir_fds")?;
+        let first = set_fixture(&tmp, "cpu.weight", "")?;
+        let second = set_fixture(&tmp, "cpu.max", "")?;
+
+        with_pooled_dir_fds(|| -> Result<()> {
+            write_cgroup_file(&first, 100)?;
+            write_cgroup_file_str(&second, "max")?;
+            DIR_FDS.with(|dir_fds| {
+                assert_eq!(dir_fds.borrow().as_ref().map(|fds| fds.len()), Some(1))
+            });
+            assert!(write_cgroup_file(tmp.join("cpu.idle"), 1).is_err());
+            Ok(())
+        })?;
+
+        DIR_FDS.with(|dir_fds| assert!(dir_fds.borrow().is_none()));
+        assert_eq!(read_cgroup_file(&first)?, "100");
+        assert_eq!(read_cgroup_file(&second)?, "max");
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_throttle_devices() -> Result<()> {
+        use oci_spec::runtime::{LinuxBlockIoBuilder, LinuxThrottleDeviceBuilder};
+
+        let tmp = create_temp_dir("test_validate_throttle_devices")?;
+        set_fixture(&tmp, "8:0", "")?;
+        let throttle = |major: i64, minor: i64| {
+            LinuxThrottleDeviceBuilder::default()
+                .major(major)
+                .minor(minor)
+                .rate(100u64)
+                .build()
+                .unwrap()
+        };
+
+        let blkio = LinuxBlockIoBuilder::default()
+            .throttle_read_bps_device(vec![throttle(8, 0)])
+            .throttle_write_iops_device(vec![throttle(8, 0), throttle(8, 16)])
+            .build()
+            .unwrap();
+        let err = validate_throttle_devices(&blkio, &tmp).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "throttleWriteIOPSDevice[1]: block device 8:16 does not exist"
+        );
+
+        let blkio = LinuxBlockIoBuilder::default()
+            .throttle_read_bps_device(vec![throttle(-1, 0)])
+            .build()
+            .unwrap();
+        assert!(validate_throttle_devices(&blkio, &tmp).is_err());
+
+        let blkio = LinuxBlockIoBuilder::default()
+            .throttle_write_bps_device(vec![throttle(8, 0)])
+            .build()
+            .unwrap();
+        validate_throttle_devices(&blkio, &tmp)
+    }
+
+    #[test]
+    fn test_parse_cpuset_list() -> Result<()> {
+        assert!(parse_cpuset_list("")?.is_empty());
+        assert_eq!(
+            parse_cpuset_list("0\n")?,
+            vec![0].into_iter().collect::<BTreeSet<_>>()
+        );
+        assert_eq!(
+            parse_cpuset_list("0-2,4")?,
+            vec![0, 1, 2, 4].into_iter().collect::<BTreeSet<_>>()
+        );
+        assert!(parse_cpuset_list("2-0").is_err());
+        assert!(parse_cpuset_list("a").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_effective_mems() -> Result<()> {
+        let tmp = create_temp_dir("test_check_effective_mems")?;
+        let effective = tmp.join("cpuset.mems.effective");
+        check_effective_mems("1", &effective)?;
+
+        set_fixture(&tmp, "cpuset.mems.effective", "0-1\n")?;
+        check_effective_mems("0-1", &effective)?;
+        check_effective_mems("1", &effective)?;
+        assert!(check_effective_mems("1-2", &effective).is_err());
+        Ok(())
+    }
+
+    fn mount_info(fs_type: &str, mount_point: &str) -> MountInfo {
+        MountInfo {
+            mnt_id: 0,
+            pid: 0,
+            majmin: "".to_string(),
+            root: "/".to_string(),
+            mount_point: PathBuf::from(mount_point),
+            mount_options: Default::default(),
+            opt_fields: vec![],
+            fs_type: fs_type.to_string(),
+            mount_source: Some(fs_type.to_string()),
+            super_options: Default::default(),
+        }
+    }
+
+    fn process_cgroup(hierarchy: u32, controllers: &[&str], pathname: &str) -> ProcessCgroup {
+        ProcessCgroup {
+            hierarchy,
+            controllers: controllers.iter().map(|c| c.to_string()).collect(),
+            pathname: pathname.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sub_cgroup_paths() -> Result<()> {
+        let tmp = crate::test::create_temp_dir("test_sub_cgroup_paths")?;
+        let memory = tmp.join("memory");
+        let systemd = tmp.join("systemd");
+        fs::create_dir_all(memory.join("youki/test/debug"))?;
+        fs::create_dir_all(systemd.join("youki/test"))?;
+
+        let mut memory_mount = mount_info("cgroup", memory.to_str().unwrap());
+        memory_mount.super_options.insert("memory".to_owned(), None);
+        let mut systemd_mount = mount_info("cgroup", systemd.to_str().unwrap());
+        systemd_mount
+            .super_options
+            .insert("name".to_owned(), Some("systemd".to_owned()));
+        let mount_infos = vec![memory_mount, systemd_mount];
+        let cgroups = vec![
+            process_cgroup(2, &["memory"], "/youki/test"),
+            process_cgroup(1, &["name=systemd"], "/youki/test"),
+        ];
+
+        // the sub-cgroup does not exist in the named hierarchy
+        let paths = sub_cgroup_paths(&cgroups, &mount_infos, Path::new("debug"))?;
+        assert_eq!(paths, vec![memory.join("youki/test/debug")]);
+
+        assert!(sub_cgroup_paths(&cgroups, &mount_infos, Path::new("missing")).is_err());
+        assert!(sub_cgroup_paths(&cgroups, &mount_infos, Path::new("../debug")).is_err());
+        assert!(sub_cgroup_paths(&cgroups, &mount_infos, Path::new("/debug")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_unified_setup() {
+        let mount_infos = vec![
+            mount_info("ext4", "/"),
+            mount_info("cgroup2", "/sys/fs/cgroup"),
+        ];
+        let setup = cgroup_setup_from_mounts(&mount_infos).unwrap();
+        assert!(matches!(setup, CgroupSetup::Unified));
+    }
+
+    #[test]
+    fn test_detect_hybrid_setup() {
+        let mount_infos = vec![
+            mount_info("tmpfs", "/sys/fs/cgroup"),
+            mount_info("cgroup2", "/sys/fs/cgroup/unified"),
+            mount_info("cgroup", "/sys/fs/cgroup/systemd"),
+            mount_info("cgroup", "/sys/fs/cgroup/memory"),
+        ];
+        let setup = cgroup_setup_from_mounts(&mount_infos).unwrap();
+        assert!(matches!(setup, CgroupSetup::Hybrid));
+    }
+
+    #[test]
+    fn test_detect_legacy_setup() {
+        let mount_infos = vec![
+            mount_info("tmpfs", "/sys/fs/cgroup"),
+            mount_info("cgroup", "/sys/fs/cgroup/cpu,cpuacct"),
+            mount_info("cgroup", "/sys/fs/cgroup/memory"),
+        ];
+        let setup = cgroup_setup_from_mounts(&mount_infos).unwrap();
+        assert!(matches!(setup, CgroupSetup::Legacy));
+    }
+
+    #[test]
+    fn test_detect_no_cgroups() {
+        let mount_infos = vec![mount_info("ext4", "/"), mount_info("proc", "/proc")];
+        assert!(cgroup_setup_from_mounts(&mount_infos).is_err());
+        let mount_infos = vec![mount_info("cgroup2", "/run/cgroup2")];
+        assert!(cgroup_setup_from_mounts(&mount_infos).is_err());
+    }
+
+    #[test]
+    fn test_remove_cgroup_tree() -> Result<()> {
+        let tmp = crate::test::create_temp_dir("test_remove_cgroup_tree")?;
+        let cgroup = tmp.join("container");
+        fs::create_dir_all(cgroup.join("child").join("grandchild"))?;
+        fs::create_dir_all(cgroup.join("sibling"))?;
+
+        remove_cgroup_tree(&cgroup)?;
+        assert!(!cgroup.exists());
+        // removing a cgroup which does not exist succeeds
+        remove_cgroup_tree(&cgroup)?;
+        Ok(())
+    }
+}
@@ -121,6 +121,12 @@ pub struct MemoryStats {
     pub cache: u64,
     /// Returns true if hierarchical accounting is enabled
     pub hierarchy: bool,
+    /// Number of times the cgroup hit its memory limit and the oom killer
+    /// was invoked. Cgroup v1 only counts kills, so this is the same as
+    /// oom_kill there
+    pub oom: u64,
+    /// Number of processes of the cgroup killed by the oom killer
+    pub oom_kill: u64,
     /// Various memory statistics
     pub stats: HashMap<String, u64>,
 }
@@ -134,6 +140,8 @@ impl Default for MemoryStats {
             kernel_tcp: MemoryData::default(),
             cache: 0,
             hierarchy: false,
+            oom: 0,
+            oom_kill: 0,
             stats: HashMap::default(),
         }
     }
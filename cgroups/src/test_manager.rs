@@ -47,6 +47,10 @@ impl CgroupManager for TestManager {
     fn get_all_pids(&self) -> Result<Vec<Pid>> {
         unimplemented!()
     }
+
+    fn kill_all(&self) -> Result<bool> {
+        unimplemented!()
+    }
 }
 
 impl TestManager {
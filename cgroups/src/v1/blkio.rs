@@ -78,6 +78,7 @@ impl Controller for Blkio {
         log::debug!("Apply blkio cgroup config");
 
         if let Some(blkio) = Self::needs_to_handle(controller_opt) {
+            common::validate_throttle_devices(blkio, Path::new(common::SYS_DEV_BLOCK))?;
             Self::apply(cgroup_root, blkio)?;
         }
 
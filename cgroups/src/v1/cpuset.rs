@@ -11,6 +11,7 @@ use super::{util, Controller, ControllerType};
 
 const CGROUP_CPUSET_CPUS: &str = "cpuset.cpus";
 const CGROUP_CPUSET_MEMS: &str = "cpuset.mems";
+const CGROUP_CPUSET_EFFECTIVE_MEMS: &str = "cpuset.effective_mems";
 
 pub struct CpuSet {}
 
@@ -57,6 +58,7 @@ impl CpuSet {
 
         if let Some(mems) = &cpuset.mems() {
             common::write_cgroup_file_str(cgroup_path.join(CGROUP_CPUSET_MEMS), mems)?;
+            common::check_effective_mems(mems, &cgroup_path.join(CGROUP_CPUSET_EFFECTIVE_MEMS))?;
         }
 
         Ok(())
@@ -4,7 +4,7 @@ use anyhow::Result;
 
 use super::controller::Controller;
 use crate::common::{self, default_allow_devices, default_devices, ControllerOpt};
-use oci_spec::runtime::LinuxDeviceCgroup;
+use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType};
 
 pub struct Devices {}
 
@@ -46,9 +46,33 @@ impl Devices {
             cgroup_root.join("devices.deny")
         };
 
-        common::write_cgroup_file_str(path, &device.to_string())?;
+        common::write_cgroup_file_str(path, &Self::device_rule(device))?;
         Ok(())
     }
+
+    /// Formats the rule as expected by devices.allow and devices.deny. A
+    /// missing or negative major or minor number matches all devices and is
+    /// written as '*', the kernel ignores the numbers of rules with type 'a'.
+    fn device_rule(device: &LinuxDeviceCgroup) -> String {
+        let typ = match device.typ().unwrap_or_default() {
+            LinuxDeviceType::A => "a",
+            LinuxDeviceType::B => "b",
+            LinuxDeviceType::C | LinuxDeviceType::U => "c",
+            LinuxDeviceType::P => "p",
+        };
+        let number = |n: Option<i64>| match n {
+            Some(n) if n >= 0 => n.to_string(),
+            _ => "*".to_owned(),
+        };
+
+        format!(
+            "{} {}:{} {}",
+            typ,
+            number(device.major()),
+            number(device.minor()),
+            device.access().as_deref().unwrap_or("")
+        )
+    }
 }
 
 #[cfg(test)]
@@ -56,7 +80,7 @@ mod tests {
     use super::*;
     use crate::test::create_temp_dir;
     use crate::test::set_fixture;
-    use oci_spec::runtime::{LinuxDeviceCgroupBuilder, LinuxDeviceType};
+    use oci_spec::runtime::LinuxDeviceCgroupBuilder;
     use std::fs::read_to_string;
 
     #[test]
@@ -77,11 +101,11 @@ mod tests {
             if d.allow() {
                 let allowed_content =
                     read_to_string(tmp.join("devices.allow")).expect("read to string");
-                assert_eq!(allowed_content, d.to_string());
+                assert_eq!(allowed_content, Devices::device_rule(d));
             } else {
                 let denied_content =
                     read_to_string(tmp.join("devices.deny")).expect("read to string");
-                assert_eq!(denied_content, d.to_string());
+                assert_eq!(denied_content, Devices::device_rule(d));
             }
         });
     }
@@ -129,15 +153,36 @@ mod tests {
             if d.allow() {
                 let allowed_content =
                     read_to_string(tmp.join("devices.allow")).expect("read to string");
-                assert_eq!(allowed_content, d.to_string());
+                assert_eq!(allowed_content, Devices::device_rule(d));
             } else {
                 let denied_content =
                     read_to_string(tmp.join("devices.deny")).expect("read to string");
-                assert_eq!(denied_content, d.to_string());
+                assert_eq!(denied_content, Devices::device_rule(d));
             }
         });
     }
 
+    #[test]
+    fn test_device_rule_wildcards() {
+        let rule = LinuxDeviceCgroupBuilder::default()
+            .allow(true)
+            .typ(LinuxDeviceType::C)
+            .major(195)
+            .minor(-1)
+            .access("rwm")
+            .build()
+            .unwrap();
+        assert_eq!(Devices::device_rule(&rule), "c 195:* rwm");
+
+        let rule = LinuxDeviceCgroupBuilder::default()
+            .allow(true)
+            .typ(LinuxDeviceType::A)
+            .access("rwm")
+            .build()
+            .unwrap();
+        assert_eq!(Devices::device_rule(&rule), "a *:* rwm");
+    }
+
     quickcheck! {
         fn property_test_apply_device(device: LinuxDeviceCgroup) -> bool {
             let tmp = create_temp_dir("property_test_apply_device").expect("create temp directory for test");
@@ -147,11 +192,11 @@ mod tests {
             if device.allow() {
                 let allowed_content =
                     read_to_string(tmp.join("devices.allow")).expect("read to string");
-                allowed_content == device.to_string()
+                allowed_content == Devices::device_rule(&device)
             } else {
                 let denied_content =
                     read_to_string(tmp.join("devices.deny")).expect("read to string");
-                denied_content == device.to_string()
+                denied_content == Devices::device_rule(&device)
             }
         }
 
@@ -165,11 +210,11 @@ mod tests {
                     if device.allow() {
                         let allowed_content =
                             read_to_string(tmp.join("devices.allow")).expect("read to string");
-                        allowed_content == device.to_string()
+                        allowed_content == Devices::device_rule(&device)
                     } else {
                         let denied_content =
                             read_to_string(tmp.join("devices.deny")).expect("read to string");
-                        denied_content == device.to_string()
+                        denied_content == Devices::device_rule(&device)
                     }
                 })
                 .all(|is_ok| is_ok)
@@ -184,6 +184,7 @@ mod tests {
                 freezer_state: Some(state),
                 oom_score_adj: None,
                 disable_oom_killer: false,
+                missing_controllers: Default::default(),
             };
 
             let pid = Pid::from_raw(1000);
@@ -211,6 +212,7 @@ mod tests {
                 freezer_state: Some(state),
                 oom_score_adj: None,
                 disable_oom_killer: false,
+                missing_controllers: Default::default(),
             };
 
             let pid = Pid::from_raw(1001);
@@ -239,6 +241,7 @@ mod tests {
                 freezer_state: Some(state),
                 oom_score_adj: None,
                 disable_oom_killer: false,
+                missing_controllers: Default::default(),
             };
 
             let pid = Pid::from_raw(1002);
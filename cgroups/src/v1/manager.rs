@@ -1,10 +1,8 @@
-use std::fs;
 use std::path::Path;
-use std::time::Duration;
 use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::bail;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use nix::unistd::Pid;
 
 use procfs::process::Process;
@@ -17,11 +15,41 @@ use super::{
     perf_event::PerfEvent, pids::Pids, util, Controller,
 };
 
-use crate::common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt, CGROUP_PROCS};
+use crate::common::{
+    self, CgroupManager, ControllerOpt, FreezerState, MissingControllerPolicy, PathBufExt,
+};
 use crate::stats::{Stats, StatsProvider};
+use crate::v2::{self, controller_type::ControllerType as UnifiedCtrlType};
 
 pub struct Manager {
     subsystems: HashMap<CtrlType, PathBuf>,
+    /// Controllers of a hybrid setup which are not mounted on the v1 hierarchy,
+    /// but are enabled on the unified hierarchy
+    unified: Option<UnifiedControllers>,
+}
+
+struct UnifiedControllers {
+    manager: v2::manager::Manager,
+    controllers: Vec<UnifiedCtrlType>,
+}
+
+impl UnifiedControllers {
+    fn provides(&self, controller: &CtrlType) -> bool {
+        self.controllers
+            .iter()
+            .any(|c| v1_equivalent(c) == *controller)
+    }
+}
+
+fn v1_equivalent(controller: &UnifiedCtrlType) -> CtrlType {
+    match controller {
+        UnifiedCtrlType::Cpu => CtrlType::Cpu,
+        UnifiedCtrlType::CpuSet => CtrlType::CpuSet,
+        UnifiedCtrlType::HugeTlb => CtrlType::HugeTlb,
+        UnifiedCtrlType::Io => CtrlType::Blkio,
+        UnifiedCtrlType::Memory => CtrlType::Memory,
+        UnifiedCtrlType::Pids => CtrlType::Pids,
+    }
 }
 
 impl Manager {
@@ -36,12 +64,64 @@ impl Manager {
             }
         }
 
-        Ok(Manager { subsystems })
+        let unified = Self::get_unified_controllers(&cgroup_path, &subsystems)?;
+        Ok(Manager {
+            subsystems,
+            unified,
+        })
+    }
+
+    /// In a hybrid setup controllers which are not mounted on the v1 hierarchy
+    /// can be enabled on the unified hierarchy instead. These controllers are
+    /// managed through the unified hierarchy.
+    fn get_unified_controllers(
+        cgroup_path: &Path,
+        subsystems: &HashMap<CtrlType, PathBuf>,
+    ) -> Result<Option<UnifiedControllers>> {
+        // without a cgroup2 mount this is a legacy setup
+        let mount = match v2::util::get_unified_mount() {
+            Ok(mount) => mount,
+            Err(_) => return Ok(None),
+        };
+        let root_path = mount.mount_point;
+
+        let controllers: Vec<UnifiedCtrlType> = v2::util::get_available_controllers(&root_path)?
+            .into_iter()
+            .filter(|c| !subsystems.contains_key(&v1_equivalent(c)))
+            .collect();
+        if controllers.is_empty() {
+            return Ok(None);
+        }
+
+        let cgroup_path = if cgroup_path.as_os_str().is_empty() {
+            let cgroup = Process::myself()?
+                .cgroups()?
+                .into_iter()
+                .find(|c| c.hierarchy == 0)
+                .context("failed to find cgroup of the unified hierarchy")?;
+            common::cgroup_path_in_mount(Path::new(&cgroup.pathname), Path::new(&mount.root))
+        } else {
+            Path::new("/").join(cgroup_path)
+        };
+
+        log::info!(
+            "Cgroups {} will be managed through the unified hierarchy",
+            controllers
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok(Some(UnifiedControllers {
+            manager: v2::manager::Manager::new(root_path, cgroup_path)?,
+            controllers,
+        }))
     }
 
     fn get_subsystem_path(cgroup_path: &Path, subsystem: &CtrlType) -> Result<PathBuf> {
         log::debug!("Get path for subsystem: {}", subsystem);
-        let mount_point = util::get_subsystem_mount_point(subsystem)?;
+        let mount = util::get_subsystem_mount(subsystem)?;
+        let mount_point = &mount.mount_point;
 
         let cgroup = Process::myself()?
             .cgroups()?
@@ -50,7 +130,10 @@ impl Manager {
             .unwrap();
 
         let p = if cgroup_path.to_string_lossy().into_owned().is_empty() {
-            mount_point.join_safely(Path::new(&cgroup.pathname))?
+            mount_point.join_safely(&common::cgroup_path_in_mount(
+                Path::new(&cgroup.pathname),
+                Path::new(&mount.root),
+            ))?
         } else if cgroup_path.is_absolute() {
             mount_point.join_safely(cgroup_path)?
         } else {
@@ -89,6 +172,20 @@ impl Manager {
             if required {
                 if let Some(subsystem_path) = self.subsystems.get(controller) {
                     required_controllers.insert(controller, subsystem_path);
+                } else if self
+                    .unified
+                    .as_ref()
+                    .map_or(false, |u| u.provides(controller))
+                {
+                    log::debug!(
+                        "Cgroup {} is managed through the unified hierarchy",
+                        controller
+                    );
+                } else if controller_opt.missing_controllers == MissingControllerPolicy::Warn {
+                    log::warn!(
+                        "Cgroup {} is required to fullfill the request, but is not supported by this system. Its restrictions are not applied",
+                        controller
+                    );
                 } else {
                     bail!("Cgroup {} is required to fullfill the request, but is not supported by this system", controller);
                 }
@@ -126,6 +223,10 @@ impl CgroupManager for Manager {
             }
         }
 
+        if let Some(unified) = &self.unified {
+            unified.manager.add_task(pid)?;
+        }
+
         Ok(())
     }
 
@@ -147,23 +248,24 @@ impl CgroupManager for Manager {
             }
         }
 
+        if let Some(unified) = &self.unified {
+            for controller in &unified.controllers {
+                unified
+                    .manager
+                    .apply_controller(controller, controller_opt)?;
+            }
+        }
+
         Ok(())
     }
 
     fn remove(&self) -> Result<()> {
         for cgroup_path in &self.subsystems {
-            if cgroup_path.1.exists() {
-                log::debug!("remove cgroup {:?}", cgroup_path.1);
-                let procs_path = cgroup_path.1.join(CGROUP_PROCS);
-                let procs = fs::read_to_string(&procs_path)?;
-
-                for line in procs.lines() {
-                    let pid: i32 = line.parse()?;
-                    let _ = nix::sys::signal::kill(Pid::from_raw(pid), nix::sys::signal::SIGKILL);
-                }
+            common::remove_cgroup_tree(cgroup_path.1)?;
+        }
 
-                common::delete_with_retry(cgroup_path.1, 4, Duration::from_millis(100))?;
-            }
+        if let Some(unified) = &self.unified {
+            unified.manager.remove()?;
         }
 
         Ok(())
@@ -175,11 +277,12 @@ impl CgroupManager for Manager {
             freezer_state: Some(state),
             oom_score_adj: None,
             disable_oom_killer: false,
+            missing_controllers: Default::default(),
         };
-        Freezer::apply(
-            &controller_opt,
-            self.subsystems.get(&CtrlType::Freezer).unwrap(),
-        )
+        match self.subsystems.get(&CtrlType::Freezer) {
+            Some(freezer_path) => Freezer::apply(&controller_opt, freezer_path),
+            None => bail!("freezer cgroup is not mounted"),
+        }
     }
 
     fn stats(&self) -> Result<Stats> {
@@ -199,4 +302,13 @@ impl CgroupManager for Manager {
 
         Ok(stats)
     }
+
+    fn kill_all(&self) -> Result<bool> {
+        // the processes are members of the unified hierarchy as well, if
+        // controllers are managed through it
+        match &self.unified {
+            Some(unified) => unified.manager.kill_all(),
+            None => Ok(false),
+        }
+    }
 }
@@ -116,11 +116,17 @@ impl StatsProvider for Memory {
 
     fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
         let memory = Self::get_memory_data(cgroup_path, MEMORY_PREFIX)?;
-        let memswap = Self::get_memory_data(cgroup_path, MEMORY_AND_SWAP_PREFIX)?;
+        let memswap = if cgroup_path.join(CGROUP_MEMORY_SWAP_LIMIT).exists() {
+            Self::get_memory_data(cgroup_path, MEMORY_AND_SWAP_PREFIX)?
+        } else {
+            // swap accounting is not enabled
+            MemoryData::default()
+        };
         let kernel = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_PREFIX)?;
         let kernel_tcp = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_TCP_PREFIX)?;
         let hierarchy = Self::hierarchy_enabled(cgroup_path)?;
         let stats = Self::get_stat_data(cgroup_path)?;
+        let oom_kill = Self::get_oom_kill_count(cgroup_path)?;
 
         Ok(MemoryStats {
             memory,
@@ -129,6 +135,8 @@ impl StatsProvider for Memory {
             kernel_tcp,
             cache: stats["cache"],
             hierarchy,
+            oom: oom_kill,
+            oom_kill,
             stats,
         })
     }
@@ -166,6 +174,13 @@ impl Memory {
         stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))
     }
 
+    // memory.oom_control only contains the oom_kill counter since Linux 4.13
+    fn get_oom_kill_count(cgroup_path: &Path) -> Result<u64> {
+        let oom_control =
+            stats::parse_flat_keyed_data(&cgroup_path.join(CGROUP_MEMORY_OOM_CONTROL))?;
+        Ok(oom_control.get("oom_kill").copied().unwrap_or_default())
+    }
+
     fn get_memory_usage(cgroup_root: &Path) -> Result<u64> {
         let path = cgroup_root.join(CGROUP_MEMORY_USAGE);
         let mut contents = String::new();
@@ -268,7 +283,13 @@ impl Memory {
             return Ok(());
         }
 
-        common::write_cgroup_file(cgroup_root.join(CGROUP_MEMORY_SWAP_LIMIT), swap)?;
+        let path = cgroup_root.join(CGROUP_MEMORY_SWAP_LIMIT);
+        if !path.exists() {
+            common::skip_swap_limit(swap);
+            return Ok(());
+        }
+
+        common::write_cgroup_file(path, swap)?;
         Ok(())
     }
 
@@ -353,6 +374,38 @@ mod tests {
         assert_eq!(content, sample_val)
     }
 
+    #[test]
+    fn test_set_memory_reservation() {
+        let tmp =
+            create_temp_dir("test_set_memory_reservation").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_MEMORY_RESERVATION, "0").expect("set fixture for reservation");
+        set_fixture(&tmp, CGROUP_MEMORY_OOM_CONTROL, "0").expect("set fixture for oom control");
+
+        for reservation in [512, -1] {
+            let resources = LinuxResourcesBuilder::default()
+                .memory(
+                    LinuxMemoryBuilder::default()
+                        .reservation(reservation)
+                        .build()
+                        .expect("build memory"),
+                )
+                .build()
+                .expect("build resources");
+            let controller_opt = ControllerOpt {
+                resources: &resources,
+                disable_oom_killer: false,
+                missing_controllers: Default::default(),
+                oom_score_adj: None,
+                freezer_state: None,
+            };
+
+            <Memory as Controller>::apply(&controller_opt, &tmp).expect("apply memory");
+            let content = std::fs::read_to_string(tmp.join(CGROUP_MEMORY_RESERVATION))
+                .expect("read memory reservation");
+            assert_eq!(content, reservation.to_string());
+        }
+    }
+
     #[test]
     fn test_set_swap() {
         let limit = 512;
@@ -364,6 +417,15 @@ mod tests {
         assert_eq!(limit.to_string(), content)
     }
 
+    #[test]
+    fn test_set_swap_without_swap_accounting() {
+        let tmp = create_temp_dir("test_set_swap_without_swap_accounting")
+            .expect("create temp directory for test");
+        Memory::set_swap(512, &tmp).expect("Set swap limit");
+        Memory::set_swap(-1, &tmp).expect("Set swap limit");
+        assert!(!tmp.join(CGROUP_MEMORY_SWAP_LIMIT).exists());
+    }
+
     #[test]
     fn test_set_memory_and_swap() {
         let tmp =
@@ -434,6 +496,7 @@ mod tests {
                 let controller_opt = ControllerOpt {
                     resources: &linux_resources,
                     disable_oom_killer,
+                    missing_controllers: Default::default(),
                     oom_score_adj: None,
                     freezer_state: None,
                 };
@@ -594,6 +657,32 @@ mod tests {
         assert!(!enabled)
     }
 
+    #[test]
+    fn test_stat_oom_kill_count() {
+        let tmp = create_temp_dir("test_stat_oom_kill_count").expect("create test directory");
+        set_fixture(
+            &tmp,
+            CGROUP_MEMORY_OOM_CONTROL,
+            "oom_kill_disable 0\nunder_oom 0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Memory::get_oom_kill_count(&tmp).expect("get cgroup stats"),
+            0
+        );
+
+        set_fixture(
+            &tmp,
+            CGROUP_MEMORY_OOM_CONTROL,
+            "oom_kill_disable 0\nunder_oom 1\noom_kill 3\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Memory::get_oom_kill_count(&tmp).expect("get cgroup stats"),
+            3
+        );
+    }
+
     #[test]
     fn test_stat_memory_stats() {
         let tmp = create_temp_dir("test_stat_memory_stats").expect("create test directory");
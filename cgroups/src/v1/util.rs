@@ -1,7 +1,7 @@
 use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::{anyhow, Result};
-use procfs::process::Process;
+use procfs::process::{MountInfo, Process};
 
 use super::{controller_type::CONTROLLERS, ControllerType};
 
@@ -18,6 +18,11 @@ pub fn list_subsystem_mount_points() -> Result<HashMap<ControllerType, PathBuf>>
 }
 
 pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf> {
+    get_subsystem_mount(subsystem).map(|m| m.mount_point)
+}
+
+/// Returns the mount of the hierarchy the subsystem is attached to
+pub fn get_subsystem_mount(subsystem: &ControllerType) -> Result<MountInfo> {
     let subsystem = subsystem.to_string();
     Process::myself()?
         .mountinfo()?
@@ -48,6 +53,5 @@ pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf>
             }
             m.mount_point.ends_with(&subsystem)
         })
-        .map(|m| m.mount_point)
         .ok_or_else(|| anyhow!("could not find mountpoint for {}", subsystem))
 }
@@ -1,4 +1,5 @@
 use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
 use std::path::Path;
 
 use crate::{
@@ -12,7 +13,11 @@ use super::controller::Controller;
 
 const CGROUP_CPU_WEIGHT: &str = "cpu.weight";
 const CGROUP_CPU_MAX: &str = "cpu.max";
+pub(super) const CGROUP_CPU_BURST: &str = "cpu.max.burst";
+pub(super) const CGROUP_CPU_IDLE: &str = "cpu.idle";
 const DEFAULT_PERIOD: &str = "100000";
+const MIN_CPU_SHARES: u64 = 2;
+const MAX_CPU_SHARES: u64 = 262144;
 const UNRESTRICTED_QUOTA: &str = "max";
 
 const CPU_STAT: &str = "cpu.stat";
@@ -25,6 +30,11 @@ impl Controller for Cpu {
             Self::apply(path, cpu).context("failed to apply cpu resource restrictions")?;
         }
 
+        if let Some(unified) = controller_opt.resources.unified() {
+            Self::apply_burst(path, unified).context("failed to apply cpu burst")?;
+            Self::apply_idle(path, unified).context("failed to apply cpu idle")?;
+        }
+
         Ok(())
     }
 }
@@ -94,12 +104,54 @@ impl Cpu {
         Ok(())
     }
 
-    fn convert_shares_to_cgroup2(shares: u64) -> u64 {
+    // cpu.max.burst has no field in LinuxCpu, it can only be set through the
+    // unified map. The burst must not exceed the quota, therefore it is
+    // written after cpu.max and not in arbitrary order with the other unified
+    // values.
+    fn apply_burst(path: &Path, unified: &HashMap<String, String>) -> Result<()> {
+        let burst = match unified.get(CGROUP_CPU_BURST) {
+            Some(burst) => burst.trim(),
+            None => return Ok(()),
+        };
+
+        if burst.parse::<u64>().is_err() {
+            bail!("invalid cpu burst {}, expected microseconds", burst);
+        }
+        if !path.join(CGROUP_CPU_BURST).exists() {
+            bail!("cpu burst is not supported by the kernel, it requires at least Linux 5.14");
+        }
+
+        common::write_cgroup_file_str(path.join(CGROUP_CPU_BURST), burst)
+    }
+
+    // Same as the burst, idle is not part of the runtime spec version yet. An
+    // idle cgroup is scheduled with the lowest priority, its weight is kept
+    // but has no effect until idle is reset to 0.
+    fn apply_idle(path: &Path, unified: &HashMap<String, String>) -> Result<()> {
+        let idle = match unified.get(CGROUP_CPU_IDLE).map(|v| v.trim()) {
+            Some(idle @ "0") | Some(idle @ "1") => idle,
+            Some(idle) => bail!("invalid cpu idle {}, expected 0 or 1", idle),
+            None => return Ok(()),
+        };
+
+        if !path.join(CGROUP_CPU_IDLE).exists() {
+            bail!("cpu idle is not supported by the kernel, it requires at least Linux 5.15");
+        }
+
+        common::write_cgroup_file_str(path.join(CGROUP_CPU_IDLE), idle)
+    }
+
+    /// Converts cpu.shares (2-262144) of cgroup v1 into cpu.weight (1-10000)
+    /// of cgroup v2, so that the minimum, default and maximum are mapped onto
+    /// each other. Shares outside of the range of cgroup v1 are clamped, as the
+    /// kernel would do. 0 means the shares are not set.
+    pub(crate) fn convert_shares_to_cgroup2(shares: u64) -> u64 {
         if shares == 0 {
             return 0;
         }
 
-        1 + ((shares - 2) * 9999) / 262142
+        let shares = shares.clamp(MIN_CPU_SHARES, MAX_CPU_SHARES);
+        1 + ((shares - MIN_CPU_SHARES) * 9999) / (MAX_CPU_SHARES - MIN_CPU_SHARES)
     }
 
     fn is_realtime_requested(cpu: &LinuxCpu) -> bool {
@@ -139,6 +191,16 @@ mod tests {
         assert_eq!(content, 840.to_string());
     }
 
+    #[test]
+    fn test_convert_shares_to_cgroup2() {
+        assert_eq!(Cpu::convert_shares_to_cgroup2(0), 0);
+        assert_eq!(Cpu::convert_shares_to_cgroup2(1), 1);
+        assert_eq!(Cpu::convert_shares_to_cgroup2(2), 1);
+        assert_eq!(Cpu::convert_shares_to_cgroup2(1024), 39);
+        assert_eq!(Cpu::convert_shares_to_cgroup2(262144), 10000);
+        assert_eq!(Cpu::convert_shares_to_cgroup2(u64::MAX), 10000);
+    }
+
     #[test]
     fn test_set_positive_quota() {
         // arrange
@@ -228,6 +290,76 @@ mod tests {
         assert_eq!(content, format!("{} {}", QUOTA, PERIOD));
     }
 
+    #[test]
+    fn test_set_burst() {
+        // arrange
+        let (tmp, burst) = setup("test_set_burst", CGROUP_CPU_BURST);
+        let unified: HashMap<String, String> =
+            vec![(CGROUP_CPU_BURST.to_owned(), "20000".to_owned())]
+                .into_iter()
+                .collect();
+
+        // act
+        Cpu::apply_burst(&tmp, &unified).expect("apply cpu burst");
+
+        // assert
+        let content = fs::read_to_string(burst)
+            .unwrap_or_else(|_| panic!("read {} file content", CGROUP_CPU_BURST));
+        assert_eq!(content, "20000");
+    }
+
+    #[test]
+    fn test_burst_not_supported() {
+        // arrange
+        let tmp =
+            create_temp_dir("test_burst_not_supported").expect("create temp directory for test");
+        let unified: HashMap<String, String> =
+            vec![(CGROUP_CPU_BURST.to_owned(), "20000".to_owned())]
+                .into_iter()
+                .collect();
+
+        // act
+        let result = Cpu::apply_burst(&tmp, &unified);
+
+        // assert
+        assert!(
+            result.is_err(),
+            "cpu.max.burst does not exist and should return an error"
+        );
+    }
+
+    #[test]
+    fn test_set_idle() {
+        // arrange
+        let (tmp, idle) = setup("test_set_idle", CGROUP_CPU_IDLE);
+        let unified: HashMap<String, String> = vec![(CGROUP_CPU_IDLE.to_owned(), "1".to_owned())]
+            .into_iter()
+            .collect();
+
+        // act
+        Cpu::apply_idle(&tmp, &unified).expect("apply cpu idle");
+
+        // assert
+        let content = fs::read_to_string(idle)
+            .unwrap_or_else(|_| panic!("read {} file content", CGROUP_CPU_IDLE));
+        assert_eq!(content, "1");
+    }
+
+    #[test]
+    fn test_set_invalid_idle() {
+        // arrange
+        let (tmp, _) = setup("test_set_invalid_idle", CGROUP_CPU_IDLE);
+        let unified: HashMap<String, String> = vec![(CGROUP_CPU_IDLE.to_owned(), "2".to_owned())]
+            .into_iter()
+            .collect();
+
+        // act
+        let result = Cpu::apply_idle(&tmp, &unified);
+
+        // assert
+        assert!(result.is_err(), "only 0 and 1 are valid values for idle");
+    }
+
     #[test]
     fn test_realtime_runtime_not_supported() {
         // arrange
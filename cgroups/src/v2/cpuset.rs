@@ -8,6 +8,7 @@ use super::controller::Controller;
 
 const CGROUP_CPUSET_CPUS: &str = "cpuset.cpus";
 const CGROUP_CPUSET_MEMS: &str = "cpuset.mems";
+const CGROUP_CPUSET_MEMS_EFFECTIVE: &str = "cpuset.mems.effective";
 
 pub struct CpuSet {}
 
@@ -30,6 +31,7 @@ impl CpuSet {
 
         if let Some(mems) = &cpuset.mems() {
             common::write_cgroup_file_str(path.join(CGROUP_CPUSET_MEMS), mems)?;
+            common::check_effective_mems(mems, &path.join(CGROUP_CPUSET_MEMS_EFFECTIVE))?;
         }
 
         Ok(())
@@ -41,7 +43,7 @@ mod tests {
     use std::fs;
 
     use super::*;
-    use crate::test::setup;
+    use crate::test::{set_fixture, setup};
     use oci_spec::runtime::LinuxCpuBuilder;
 
     #[test]
@@ -79,4 +81,17 @@ mod tests {
             .unwrap_or_else(|_| panic!("read {} file content", CGROUP_CPUSET_MEMS));
         assert_eq!(content, "1-3");
     }
+
+    #[test]
+    fn test_err_unavailable_mems() {
+        let (tmp, _) = setup("test_err_unavailable_mems", CGROUP_CPUSET_MEMS);
+        set_fixture(&tmp, CGROUP_CPUSET_MEMS_EFFECTIVE, "0\n")
+            .expect("set fixture for effective mems");
+        let cpuset = LinuxCpuBuilder::default()
+            .mems("0-1".to_owned())
+            .build()
+            .unwrap();
+
+        assert!(CpuSet::apply(&tmp, &cpuset).is_err());
+    }
 }
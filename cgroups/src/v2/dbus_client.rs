@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use nix::unistd::Pid;
+use zbus::{Connection, Proxy};
+use zvariant::{OwnedObjectPath, Value};
+
+const DESTINATION: &str = "org.freedesktop.systemd1";
+const OBJECT_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+// systemd replaces a queued job of the unit instead of failing
+const JOB_MODE: &str = "replace";
+
+/// Properties of a unit as passed to systemd, a list of name and value
+pub type UnitProperties<'a> = Vec<(String, Value<'a>)>;
+
+/// Client for the D-Bus API of a systemd instance. The bus is talked to
+/// natively, it does not require libdbus or the systemd tools.
+/// For more information see https://www.freedesktop.org/wiki/Software/systemd/dbus/
+pub struct Client {
+    conn: Connection,
+}
+
+impl Client {
+    /// Connects to the systemd instance of the system
+    pub fn new_system() -> Result<Self> {
+        let conn = Connection::new_system().context("failed to connect to the system bus")?;
+        Ok(Client { conn })
+    }
+
+    /// Connects to the systemd instance of the user session
+    pub fn new_session() -> Result<Self> {
+        let conn = Connection::new_session().context("failed to connect to the session bus")?;
+        Ok(Client { conn })
+    }
+
+    fn proxy(&self) -> Result<Proxy<'_>> {
+        Proxy::new(&self.conn, DESTINATION, OBJECT_PATH, MANAGER_INTERFACE)
+            .context("failed to create proxy for systemd")
+    }
+
+    /// Returns the cgroup of the systemd instance, relative to the root of the
+    /// cgroup hierarchy. For the user instance this is usually
+    /// /user.slice/user-<uid>.slice/user@<uid>.service
+    pub fn control_group(&self) -> Result<String> {
+        self.proxy()?
+            .get_property("ControlGroup")
+            .context("failed to get the control group of systemd")
+    }
+
+    /// Starts a transient scope unit in the given slice and moves the process
+    /// into it. The cgroup of the unit is delegated, so the caller manages
+    /// the controllers of the cgroup itself. Additional properties are set
+    /// on the unit when it is created.
+    pub fn start_transient_unit(
+        &self,
+        unit_name: &str,
+        slice: &str,
+        pid: Pid,
+        properties: UnitProperties,
+    ) -> Result<()> {
+        let description = format!("youki container {}", unit_name);
+        let mut unit_properties: UnitProperties = vec![
+            ("Description".to_owned(), Value::from(description.as_str())),
+            ("Slice".to_owned(), Value::from(slice)),
+            ("Delegate".to_owned(), Value::from(true)),
+            ("PIDs".to_owned(), Value::from(vec![pid.as_raw() as u32])),
+        ];
+        unit_properties.extend(properties);
+        let aux: Vec<(String, UnitProperties)> = Vec::new();
+
+        let _job: OwnedObjectPath = self
+            .proxy()?
+            .call(
+                "StartTransientUnit",
+                &(unit_name, JOB_MODE, unit_properties, aux),
+            )
+            .with_context(|| format!("failed to start transient unit {}", unit_name))?;
+
+        Ok(())
+    }
+
+    /// Changes properties of a running unit. They are only applied at
+    /// runtime, the unit files of systemd are left alone.
+    pub fn set_unit_properties(&self, unit_name: &str, properties: UnitProperties) -> Result<()> {
+        self.proxy()?
+            .call::<_, ()>("SetUnitProperties", &(unit_name, true, properties))
+            .with_context(|| format!("failed to set properties of unit {}", unit_name))
+    }
+
+    /// Stops a unit, which removes a transient unit together with its cgroup
+    pub fn stop_unit(&self, unit_name: &str) -> Result<()> {
+        let _job: OwnedObjectPath = self
+            .proxy()?
+            .call("StopUnit", &(unit_name, JOB_MODE))
+            .with_context(|| format!("failed to stop unit {}", unit_name))?;
+
+        Ok(())
+    }
+}
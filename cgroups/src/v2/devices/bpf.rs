@@ -1,21 +1,30 @@
 use anyhow::{bail, Result};
+use std::ffi::CString;
+use std::os::raw::c_char;
 use std::os::unix::io::RawFd;
 use std::ptr;
 
 // FIXME: add tests
 
+// Size of struct bpf_insn
+const INSN_SIZE: usize = 8;
+
 pub fn prog_load(license: &str, insns: &[u8]) -> Result<RawFd> {
+    let insns = to_host_layout(insns, cfg!(target_endian = "big"));
     let insns_cnt = insns.len() / std::mem::size_of::<libbpf_sys::bpf_insn>();
-    let insns = insns as *const _ as *const libbpf_sys::bpf_insn;
+    let insns = insns.as_ptr() as *const libbpf_sys::bpf_insn;
+    let license = CString::new(license)?;
 
+    // c_char is unsigned on aarch64, so the pointer types must not be spelled
+    // out as i8
     let prog_fd = unsafe {
         libbpf_sys::bpf_load_program(
             libbpf_sys::BPF_PROG_TYPE_CGROUP_DEVICE,
             insns,
-            insns_cnt as u64,
-            license as *const _ as *const i8,
+            insns_cnt as _,
+            license.as_ptr(),
             0,
-            ptr::null_mut::<i8>(),
+            ptr::null_mut::<c_char>(),
             0,
         )
     };
@@ -26,6 +35,23 @@ pub fn prog_load(license: &str, insns: &[u8]) -> Result<RawFd> {
     Ok(prog_fd)
 }
 
+/// rbpf assembles instructions in the layout of little endian hosts. On big
+/// endian hosts like s390x the kernel expects the offset and the immediate in
+/// big endian and the destination register in the high nibble of the register
+/// byte, as struct bpf_insn uses bitfields for the registers.
+fn to_host_layout(insns: &[u8], big_endian: bool) -> Vec<u8> {
+    let mut converted = insns.to_vec();
+    if big_endian {
+        for insn in converted.chunks_exact_mut(INSN_SIZE) {
+            insn[1] = insn[1].rotate_left(4);
+            insn[2..4].reverse();
+            insn[4..8].reverse();
+        }
+    }
+
+    converted
+}
+
 pub struct ProgramInfo {
     pub id: u32,
     pub fd: i32,
@@ -115,3 +141,25 @@ pub fn bump_memlock_rlimit() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_host_layout() {
+        // r1 = *(u32 *)(r2 + 4), followed by r0 = 0x12345678
+        let insns = [
+            0x61, 0x21, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, //
+            0xb7, 0x00, 0x00, 0x00, 0x78, 0x56, 0x34, 0x12,
+        ];
+        assert_eq!(to_host_layout(&insns, false), insns.to_vec());
+        assert_eq!(
+            to_host_layout(&insns, true),
+            vec![
+                0x61, 0x12, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, //
+                0xb7, 0x00, 0x00, 0x00, 0x12, 0x34, 0x56, 0x78,
+            ]
+        );
+    }
+}
@@ -8,8 +8,9 @@ use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType};
 // I chose to implement a very simple algorithm, which will just work in most cases, but with
 // diversion from cgroupv1 in some cases:
 //  1. just add used-defined rules one by one
-//  2. discard existing rules when encountering a rule with type='a', and change to deny/allow all
-//     list according the 'allow' of the rule
+//  2. discard existing rules when encountering a rule with type='a' and wildcard major and minor,
+//     and change to deny/allow all list according the 'allow' of the rule. A rule with type='a'
+//     and a major or minor number is kept and matches block and char devices
 //  3. bpf program will check rule one by one in *reversed* order, return action of first rule
 //     which matches device access operation
 //
@@ -37,8 +38,11 @@ impl Emulator {
 
     pub fn add_rule(&mut self, rule: &LinuxDeviceCgroup) -> Result<()> {
         // special case, switch to blacklist or whitelist and clear all existing rules
-        // NOTE: we ignore other fields when type='a', this is same as cgroup v1 and runc
-        if rule.typ().unwrap_or_default() == LinuxDeviceType::A {
+        // NOTE: we ignore the access when type='a', this is same as cgroup v1 and runc
+        if rule.typ().unwrap_or_default() == LinuxDeviceType::A
+            && is_wildcard(rule.major())
+            && is_wildcard(rule.minor())
+        {
             self.default_allow = rule.allow();
             self.rules.clear();
             return Ok(());
@@ -54,4 +58,43 @@ impl Emulator {
     }
 }
 
-// FIXME: add some tests
+/// A missing or negative device number matches all devices
+fn is_wildcard(number: Option<i64>) -> bool {
+    number.map_or(true, |n| n < 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::LinuxDeviceCgroupBuilder;
+
+    fn rule(typ: LinuxDeviceType, major: Option<i64>, allow: bool) -> LinuxDeviceCgroup {
+        let mut builder = LinuxDeviceCgroupBuilder::default();
+        if let Some(major) = major {
+            builder = builder.major(major);
+        }
+        builder.typ(typ).allow(allow).access("rwm").build().unwrap()
+    }
+
+    #[test]
+    fn test_type_wildcard_switches_default() -> Result<()> {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator.add_rule(&rule(LinuxDeviceType::C, Some(10), true))?;
+        emulator.add_rule(&rule(LinuxDeviceType::A, None, true))?;
+        assert!(emulator.default_allow);
+        assert!(emulator.rules.is_empty());
+
+        emulator.add_rule(&rule(LinuxDeviceType::A, Some(-1), false))?;
+        assert!(!emulator.default_allow);
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_wildcard_with_major_is_kept() -> Result<()> {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator.add_rule(&rule(LinuxDeviceType::A, Some(195), true))?;
+        assert!(!emulator.default_allow);
+        assert_eq!(emulator.rules.len(), 1);
+        Ok(())
+    }
+}
@@ -90,7 +90,11 @@ impl Program {
     }
 
     fn add_rule(&mut self, rule: &LinuxDeviceCgroup) -> Result<()> {
-        let dev_type = bpf_dev_type(rule.typ().unwrap_or_default())?;
+        // a rule with type 'a' matches block and char devices alike
+        let dev_type = match rule.typ().unwrap_or_default() {
+            LinuxDeviceType::A => None,
+            typ => Some(bpf_dev_type(typ)?),
+        };
         let access = bpf_access(rule.access().clone().unwrap_or_default())?;
         let has_access = access
             != (libbpf_sys::BPF_DEVCG_ACC_READ
@@ -101,7 +105,10 @@ impl Program {
         let has_minor = rule.minor().is_some() && rule.minor().unwrap() >= 0;
 
         // count of instructions of this rule
-        let mut instruction_count = 1; // execute dev_type
+        let mut instruction_count = 0;
+        if dev_type.is_some() {
+            instruction_count += 1;
+        }
         if has_access {
             instruction_count += 3;
         }
@@ -113,14 +120,17 @@ impl Program {
         }
         instruction_count += 2;
 
-        // if (R2 != dev_type) goto next rule
-        let mut next_rule_offset = instruction_count - 1;
-        self.prog
-            .jump_conditional(Cond::NotEquals, Source::Imm)
-            .set_dst(2)
-            .set_imm(dev_type as i32)
-            .set_off(next_rule_offset)
-            .push();
+        let mut next_rule_offset = instruction_count;
+        if let Some(dev_type) = dev_type {
+            next_rule_offset -= 1;
+            // if (R2 != dev_type) goto next rule
+            self.prog
+                .jump_conditional(Cond::NotEquals, Source::Imm)
+                .set_dst(2)
+                .set_imm(dev_type as i32)
+                .set_off(next_rule_offset)
+                .push();
+        }
 
         if has_access {
             next_rule_offset -= 3;
@@ -202,9 +212,7 @@ fn bpf_dev_type(typ: LinuxDeviceType) -> Result<u32> {
         LinuxDeviceType::U => bail!("unbuffered char device not supported"),
         LinuxDeviceType::B => libbpf_sys::BPF_DEVCG_DEV_BLOCK,
         LinuxDeviceType::P => bail!("pipe device not supported"),
-        LinuxDeviceType::A => {
-            bail!("wildcard device type should be removed when cleaning rules")
-        }
+        LinuxDeviceType::A => bail!("wildcard device type has no bpf device type"),
     };
     Ok(dev_type)
 }
@@ -411,6 +419,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_devices_allow_type_wildcard() {
+        let rules = vec![LinuxDeviceCgroupBuilder::default()
+            .allow(true)
+            .typ(LinuxDeviceType::A)
+            .major(10)
+            .access("rw")
+            .build()
+            .unwrap()];
+
+        let prog = build_bpf_program(&Some(rules)).unwrap();
+        let ty_list = vec![LinuxDeviceType::C, LinuxDeviceType::B];
+        let major_list = vec![10_u32, 99_u32];
+        let minor_list = vec![20_u32, 00_u32];
+        let access_list = vec!["r", "w", "m"];
+        for ty in &ty_list {
+            for major in &major_list {
+                for minor in &minor_list {
+                    for access in &access_list {
+                        let ret = prog.execute(*ty, *major, *minor, access.to_string());
+                        assert!(ret.is_ok());
+
+                        println!(
+                            "execute {:?} {} {} {} -> {:?}",
+                            ty, major, minor, access, ret
+                        );
+                        if *major == 10 && (access.eq(&"r") || access.eq(&"w")) {
+                            assert_eq!(ret.unwrap(), 1);
+                        } else {
+                            assert_eq!(ret.unwrap(), 0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_devices_negative_major_is_wildcard() {
+        let rules = vec![LinuxDeviceCgroupBuilder::default()
+            .allow(true)
+            .typ(LinuxDeviceType::C)
+            .major(-1)
+            .minor(-1)
+            .access("rwm")
+            .build()
+            .unwrap()];
+
+        let prog = build_bpf_program(&Some(rules)).unwrap();
+        for major in &[1_u32, 195_u32] {
+            let ret = prog.execute(LinuxDeviceType::C, *major, 0, "rwm".to_string());
+            assert_eq!(ret.unwrap(), 1);
+            let ret = prog.execute(LinuxDeviceType::B, *major, 0, "r".to_string());
+            assert_eq!(ret.unwrap(), 0);
+        }
+    }
+
     #[test]
     fn test_devices_allow_and_deny() {
         let rules = vec![
@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
@@ -8,10 +9,11 @@ use crate::{
 };
 
 use super::controller::Controller;
-use oci_spec::runtime::LinuxBlockIo;
+use oci_spec::runtime::{LinuxBlockIo, LinuxThrottleDevice};
 
 const CGROUP_BFQ_IO_WEIGHT: &str = "io.bfq.weight";
 const CGROUP_IO_WEIGHT: &str = "io.weight";
+pub(super) const CGROUP_IO_LATENCY: &str = "io.latency";
 const CGROUP_IO_STAT: &str = "io.stat";
 
 pub struct Io {}
@@ -20,8 +22,12 @@ impl Controller for Io {
     fn apply(controller_opt: &ControllerOpt, cgroup_root: &Path) -> Result<()> {
         log::debug!("Apply io cgroup v2 config");
         if let Some(io) = &controller_opt.resources.block_io() {
+            common::validate_throttle_devices(io, Path::new(common::SYS_DEV_BLOCK))?;
             Self::apply(cgroup_root, io).context("failed to apply io resource restrictions")?;
         }
+        if let Some(unified) = controller_opt.resources.unified() {
+            Self::apply_latency(cgroup_root, unified).context("failed to apply io latency")?;
+        }
         Ok(())
     }
 }
@@ -85,12 +91,27 @@ impl Io {
 
     // linux kernel doc: https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html#io
     fn apply(root_path: &Path, blkio: &LinuxBlockIo) -> Result<()> {
+        // The weights are used by the BFQ scheduler, which has the same range as
+        // blkio.weight of cgroup v1. Without BFQ the weights are applied to the
+        // io cost model, which uses the range of io.weight.
+        let bfq = root_path.join(CGROUP_BFQ_IO_WEIGHT).exists();
+        let (weight_path, convert): (_, fn(u16) -> u16) = if bfq {
+            (root_path.join(CGROUP_BFQ_IO_WEIGHT), |weight| weight)
+        } else {
+            (
+                root_path.join(CGROUP_IO_WEIGHT),
+                Self::convert_weight_to_cgroup2,
+            )
+        };
+
         if let Some(weight_device) = blkio.weight_device() {
             for wd in weight_device {
-                common::write_cgroup_file(
-                    root_path.join(CGROUP_BFQ_IO_WEIGHT),
-                    &format!("{}:{} {}", wd.major(), wd.minor(), wd.weight().unwrap()),
-                )?;
+                if let Some(weight) = wd.weight() {
+                    common::write_cgroup_file(
+                        &weight_path,
+                        &format!("{}:{} {}", wd.major(), wd.minor(), convert(weight)),
+                    )?;
+                }
             }
         }
         if let Some(leaf_weight) = blkio.leaf_weight() {
@@ -100,50 +121,91 @@ impl Io {
         }
         if let Some(io_weight) = blkio.weight() {
             if io_weight > 0 {
-                common::write_cgroup_file(
-                    root_path.join(CGROUP_IO_WEIGHT),
-                    format!("{}", io_weight),
-                )?;
+                common::write_cgroup_file(&weight_path, convert(io_weight))?;
             }
         }
 
-        if let Some(throttle_read_bps_device) = blkio.throttle_read_bps_device() {
-            for trbd in throttle_read_bps_device {
-                common::write_cgroup_file(
-                    Self::io_max_path(root_path),
-                    &format!("{}:{} rbps={}", trbd.major(), trbd.minor(), trbd.rate()),
-                )?;
-            }
+        for entry in Self::io_max_entries(blkio) {
+            common::write_cgroup_file_str(Self::io_max_path(root_path), &entry)?;
         }
 
-        if let Some(throttle_write_bps_device) = blkio.throttle_write_bps_device() {
-            for twbd in throttle_write_bps_device {
-                common::write_cgroup_file(
-                    Self::io_max_path(root_path),
-                    format!("{}:{} wbps={}", twbd.major(), twbd.minor(), twbd.rate()),
-                )?;
-            }
+        Ok(())
+    }
+
+    // Converts a blkio.weight (10-1000) into an io.weight (1-10000), same as runc
+    fn convert_weight_to_cgroup2(weight: u16) -> u16 {
+        if weight < 10 {
+            return 1;
         }
 
-        if let Some(throttle_read_iops_device) = blkio.throttle_read_iops_device() {
-            for trid in throttle_read_iops_device {
-                common::write_cgroup_file(
-                    Self::io_max_path(root_path),
-                    format!("{}:{} riops={}", trid.major(), trid.minor(), trid.rate()),
-                )?;
+        (1 + (u32::from(weight.min(1000)) - 10) * 9999 / 990) as u16
+    }
+
+    // LinuxBlockIo has no latency target, so it is requested through the
+    // unified map. Each line has the form MAJ:MIN target=<microseconds>, a
+    // target of max removes it.
+    fn apply_latency(root_path: &Path, unified: &HashMap<String, String>) -> Result<()> {
+        let latency = match unified.get(CGROUP_IO_LATENCY) {
+            Some(latency) => latency,
+            None => return Ok(()),
+        };
+
+        for entry in latency.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let valid = match entry.split_once(' ') {
+                Some((device, target)) => {
+                    stats::parse_device_number(device).is_ok()
+                        && target
+                            .strip_prefix("target=")
+                            .map_or(false, |t| t == "max" || t.parse::<u64>().is_ok())
+                }
+                None => false,
+            };
+            if !valid {
+                bail!(
+                    "invalid io latency {}, expected MAJ:MIN target=<microseconds>",
+                    entry
+                );
             }
+
+            common::write_cgroup_file_str(root_path.join(CGROUP_IO_LATENCY), entry)?;
         }
 
-        if let Some(throttle_write_iops_device) = blkio.throttle_write_iops_device() {
-            for twid in throttle_write_iops_device {
-                common::write_cgroup_file(
-                    Self::io_max_path(root_path),
-                    format!("{}:{} wiops={}", twid.major(), twid.minor(), twid.rate()),
-                )?;
+        Ok(())
+    }
+
+    // cgroup v1 has a file for each throttle, in cgroup v2 all throttles of a
+    // device are configured with a single entry of io.max, e.g.
+    // 8:16 rbps=2097152 wbps=max riops=max wiops=120
+    fn io_max_entries(blkio: &LinuxBlockIo) -> Vec<String> {
+        let mut devices: BTreeMap<(i64, i64), Vec<String>> = BTreeMap::new();
+        let throttles = [
+            ("rbps", blkio.throttle_read_bps_device()),
+            ("wbps", blkio.throttle_write_bps_device()),
+            ("riops", blkio.throttle_read_iops_device()),
+            ("wiops", blkio.throttle_write_iops_device()),
+        ];
+
+        for (key, throttle_devices) in throttles.iter() {
+            for device in throttle_devices.iter().flatten() {
+                devices
+                    .entry((device.major(), device.minor()))
+                    .or_default()
+                    .push(format!("{}={}", key, Self::io_max_rate(device)));
             }
         }
 
-        Ok(())
+        devices
+            .into_iter()
+            .map(|((major, minor), limits)| format!("{}:{} {}", major, minor, limits.join(" ")))
+            .collect()
+    }
+
+    // same as in cgroup v1 a rate of 0 removes the limit
+    fn io_max_rate(device: &LinuxThrottleDevice) -> String {
+        match device.rate() {
+            0 => "max".to_owned(),
+            rate => rate.to_string(),
+        }
     }
 }
 #[cfg(test)]
@@ -236,6 +298,29 @@ mod test {
         assert_eq!("8:0 wiops=102400", content);
     }
 
+    #[test]
+    fn test_io_max_entries_per_device() {
+        let throttle = |major: i64, minor: i64, rate: u64| {
+            LinuxThrottleDeviceBuilder::default()
+                .major(major)
+                .minor(minor)
+                .rate(rate)
+                .build()
+                .unwrap()
+        };
+        let blkio = LinuxBlockIoBuilder::default()
+            .throttle_read_bps_device(vec![throttle(8, 16, 2097152), throttle(8, 0, 1024)])
+            .throttle_write_bps_device(vec![throttle(8, 16, 0)])
+            .throttle_write_iops_device(vec![throttle(8, 16, 120)])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            Io::io_max_entries(&blkio),
+            vec!["8:0 rbps=1024", "8:16 rbps=2097152 wbps=max wiops=120"]
+        );
+    }
+
     #[test]
     fn test_set_ioweight_device() {
         let (tmp, throttle) = setup("test_set_io_weight_device", CGROUP_BFQ_IO_WEIGHT);
@@ -269,9 +354,51 @@ mod test {
         let content =
             fs::read_to_string(throttle).unwrap_or_else(|_| panic!("read bfq_io_weight content"));
 
+        assert_eq!(Io::convert_weight_to_cgroup2(100).to_string(), content);
+    }
+
+    #[test]
+    fn test_set_ioweight_bfq() {
+        let (tmp, throttle) = setup("test_set_io_weight_bfq", CGROUP_BFQ_IO_WEIGHT);
+        let blkio = LinuxBlockIoBuilder::default()
+            .weight(100u16)
+            .build()
+            .unwrap();
+
+        Io::apply(&tmp, &blkio).expect("apply blkio");
+        let content =
+            fs::read_to_string(throttle).unwrap_or_else(|_| panic!("read bfq_io_weight content"));
+
         assert_eq!("100", content);
     }
 
+    #[test]
+    fn test_convert_weight_to_cgroup2() {
+        assert_eq!(Io::convert_weight_to_cgroup2(10), 1);
+        assert_eq!(Io::convert_weight_to_cgroup2(100), 910);
+        assert_eq!(Io::convert_weight_to_cgroup2(500), 4950);
+        assert_eq!(Io::convert_weight_to_cgroup2(1000), 10000);
+    }
+
+    #[test]
+    fn test_set_io_latency() {
+        let (tmp, latency) = setup("test_set_io_latency", CGROUP_IO_LATENCY);
+        let unified: HashMap<String, String> =
+            vec![(CGROUP_IO_LATENCY.to_owned(), "8:0 target=10000".to_owned())]
+                .into_iter()
+                .collect();
+
+        Io::apply_latency(&tmp, &unified).expect("apply io latency");
+        let content = fs::read_to_string(latency).expect("read io latency content");
+        assert_eq!("8:0 target=10000", content);
+
+        let unified: HashMap<String, String> =
+            vec![(CGROUP_IO_LATENCY.to_owned(), "8:0 10000".to_owned())]
+                .into_iter()
+                .collect();
+        assert!(Io::apply_latency(&tmp, &unified).is_err());
+    }
+
     #[test]
     fn test_stat_io() {
         let tmp = create_temp_dir("test_stat_io").expect("create test directory");
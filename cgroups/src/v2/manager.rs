@@ -2,11 +2,11 @@ use std::{
     fs::{self},
     os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
-    time::Duration,
 };
 
 use anyhow::Result;
 
+use nix::errno::Errno;
 use nix::unistd::Pid;
 
 #[cfg(feature = "cgroupsv2_devices")]
@@ -24,7 +24,7 @@ use super::{
     memory::Memory,
     pids::Pids,
     unified::Unified,
-    util::{self, CGROUP_SUBTREE_CONTROL},
+    util::{self, CGROUP_CONTROLLERS, CGROUP_SUBTREE_CONTROL},
 };
 use crate::{
     common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt, CGROUP_PROCS},
@@ -55,7 +55,20 @@ impl Manager {
             .map(|c| format!("{}{}", "+", c.to_string()))
             .collect();
 
-        Self::write_controllers(&self.root_path, &controllers)?;
+        // Nested in a container, the root of the cgroup namespace usually
+        // contains the processes of the container, which prevents enabling
+        // controllers for its children. The cgroup is created anyway, only
+        // without the controllers that the parent has not delegated to it.
+        if let Err(e) = Self::write_controllers(&self.root_path, &controllers) {
+            if !is_busy(&e) {
+                return Err(e);
+            }
+            log::warn!(
+                "cannot enable controllers below {}, it contains processes: {:?}",
+                self.root_path.display(),
+                e
+            );
+        }
 
         let mut current_path = self.root_path.clone();
         let mut components = self.cgroup_path.components().skip(1).peekable();
@@ -77,8 +90,31 @@ impl Manager {
         Ok(())
     }
 
+    /// Applies the resource restrictions of a single controller to the cgroup
+    pub(crate) fn apply_controller(
+        &self,
+        controller: &ControllerType,
+        controller_opt: &ControllerOpt,
+    ) -> Result<()> {
+        match controller {
+            ControllerType::Cpu => Cpu::apply(controller_opt, &self.full_path),
+            ControllerType::CpuSet => CpuSet::apply(controller_opt, &self.full_path),
+            ControllerType::HugeTlb => HugeTlb::apply(controller_opt, &self.full_path),
+            ControllerType::Io => Io::apply(controller_opt, &self.full_path),
+            ControllerType::Memory => Memory::apply(controller_opt, &self.full_path),
+            ControllerType::Pids => Pids::apply(controller_opt, &self.full_path),
+        }
+    }
+
+    // Enables the controllers for the children of the cgroup, as far as they
+    // are available in the cgroup itself
     fn write_controllers(path: &Path, controllers: &[String]) -> Result<()> {
-        for controller in controllers {
+        let available = common::read_cgroup_file(path.join(CGROUP_CONTROLLERS))?;
+        let available: Vec<&str> = available.split_whitespace().collect();
+        for controller in controllers
+            .iter()
+            .filter(|c| available.contains(&c.trim_start_matches('+')))
+        {
             common::write_cgroup_file_str(path.join(CGROUP_SUBTREE_CONTROL), controller)?;
         }
 
@@ -86,55 +122,52 @@ impl Manager {
     }
 }
 
+// Writing to the subtree control of a cgroup which contains processes fails
+// with EBUSY, due to the no internal processes rule of cgroup v2
+fn is_busy(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .and_then(|e| e.raw_os_error())
+            == Some(Errno::EBUSY as i32)
+    })
+}
+
 impl CgroupManager for Manager {
     fn add_task(&self, pid: Pid) -> Result<()> {
         self.create_unified_cgroup(pid)?;
         Ok(())
     }
 
+    // All controllers write to files in the directory of the cgroup, which is
+    // opened once for all of them
     fn apply(&self, controller_opt: &ControllerOpt) -> Result<()> {
-        for controller in CONTROLLER_TYPES {
-            match controller {
-                ControllerType::Cpu => Cpu::apply(controller_opt, &self.full_path)?,
-                ControllerType::CpuSet => CpuSet::apply(controller_opt, &self.full_path)?,
-                ControllerType::HugeTlb => HugeTlb::apply(controller_opt, &self.full_path)?,
-                ControllerType::Io => Io::apply(controller_opt, &self.full_path)?,
-                ControllerType::Memory => Memory::apply(controller_opt, &self.full_path)?,
-                ControllerType::Pids => Pids::apply(controller_opt, &self.full_path)?,
+        common::with_pooled_dir_fds(|| -> Result<()> {
+            for controller in
+                util::controllers_to_apply(&self.full_path, CONTROLLER_TYPES, controller_opt)?
+            {
+                self.apply_controller(&controller, controller_opt)?;
             }
-        }
-
-        #[cfg(feature = "cgroupsv2_devices")]
-        Devices::apply(controller_opt, &self.cgroup_path)?;
 
-        for pseudoctlr in PSEUDO_CONTROLLER_TYPES {
-            if let PseudoControllerType::Unified = pseudoctlr {
-                Unified::apply(
-                    controller_opt,
-                    &self.cgroup_path,
-                    util::get_available_controllers(&self.root_path)?,
-                )?;
+            #[cfg(feature = "cgroupsv2_devices")]
+            Devices::apply(controller_opt, &self.full_path)?;
+
+            for pseudoctlr in PSEUDO_CONTROLLER_TYPES {
+                if let PseudoControllerType::Unified = pseudoctlr {
+                    Unified::apply(
+                        controller_opt,
+                        &self.full_path,
+                        util::get_available_controllers(&self.root_path)?,
+                    )?;
+                }
             }
-        }
 
-        Ok(())
+            Ok(())
+        })
     }
 
     fn remove(&self) -> Result<()> {
-        if self.full_path.exists() {
-            log::debug!("remove cgroup {:?}", self.full_path);
-            let procs_path = self.full_path.join(CGROUP_PROCS);
-            let procs = fs::read_to_string(&procs_path)?;
-
-            for line in procs.lines() {
-                let pid: i32 = line.parse()?;
-                let _ = nix::sys::signal::kill(Pid::from_raw(pid), nix::sys::signal::SIGKILL);
-            }
-
-            common::delete_with_retry(&self.full_path, 4, Duration::from_millis(100))?;
-        }
-
-        Ok(())
+        common::remove_cgroup_tree(&self.full_path)
     }
 
     fn freeze(&self, state: FreezerState) -> Result<()> {
@@ -143,6 +176,7 @@ impl CgroupManager for Manager {
             freezer_state: Some(state),
             oom_score_adj: None,
             disable_oom_killer: false,
+            missing_controllers: Default::default(),
         };
         Freezer::apply(&controller_opt, &self.full_path)
     }
@@ -167,4 +201,8 @@ impl CgroupManager for Manager {
     fn get_all_pids(&self) -> Result<Vec<Pid>> {
         common::get_all_pids(&self.full_path)
     }
+
+    fn kill_all(&self) -> Result<bool> {
+        util::kill_cgroup(&self.full_path)
+    }
 }
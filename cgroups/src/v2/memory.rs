@@ -1,4 +1,5 @@
 use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
 use std::path::Path;
 
 use oci_spec::runtime::LinuxMemory;
@@ -12,30 +13,87 @@ use super::controller::Controller;
 
 const CGROUP_MEMORY_SWAP: &str = "memory.swap.max";
 const CGROUP_MEMORY_MAX: &str = "memory.max";
-const CGROUP_MEMORY_LOW: &str = "memory.low";
+pub(super) const CGROUP_MEMORY_LOW: &str = "memory.low";
+pub(super) const CGROUP_MEMORY_MIN: &str = "memory.min";
+pub(super) const CGROUP_MEMORY_OOM_GROUP: &str = "memory.oom.group";
 const MEMORY_STAT: &str = "memory.stat";
+const MEMORY_EVENTS: &str = "memory.events";
 
 pub struct Memory {}
 
 impl Controller for Memory {
     fn apply(controller_opt: &ControllerOpt, cgroup_path: &Path) -> Result<()> {
+        // cgroup v2 has no equivalent of memory.oom_control, the OOM killer
+        // cannot be disabled for a cgroup
+        if controller_opt.disable_oom_killer {
+            bail!("disabling the OOM killer is not supported on cgroup v2");
+        }
+
         if let Some(memory) = &controller_opt.resources.memory() {
             Self::apply(cgroup_path, memory)
                 .context("failed to apply memory resource restrictions")?;
         }
 
+        if let Some(unified) = controller_opt.resources.unified() {
+            let protection = MemoryProtection::from_unified(unified)?;
+            Self::apply_protection(cgroup_path, &protection)
+                .context("failed to apply memory protection")?;
+            Self::apply_oom_group(cgroup_path, unified)
+                .context("failed to apply memory oom group")?;
+        }
+
         Ok(())
     }
 }
 
+/// Memory of the cgroup which is protected from reclaim. Of the two, only
+/// memory.low has a field in LinuxMemory (the reservation), so both can
+/// also be requested through the unified map.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct MemoryProtection {
+    /// Hard protection (memory.min), the memory is never reclaimed
+    pub min: Option<i64>,
+    /// Best-effort protection (memory.low), the memory is only reclaimed if
+    /// there is no unprotected memory left
+    pub low: Option<i64>,
+}
+
+impl MemoryProtection {
+    fn from_unified(unified: &HashMap<String, String>) -> Result<Self> {
+        let parse = |file: &str| -> Result<Option<i64>> {
+            match unified.get(file).map(|v| v.trim()) {
+                None => Ok(None),
+                Some("max") => Ok(Some(-1)),
+                Some(value) => match value.parse::<i64>() {
+                    Ok(bytes) if bytes >= 0 => Ok(Some(bytes)),
+                    _ => bail!("invalid {} value: {}", file, value),
+                },
+            }
+        };
+
+        Ok(Self {
+            min: parse(CGROUP_MEMORY_MIN)?,
+            low: parse(CGROUP_MEMORY_LOW)?,
+        })
+    }
+}
+
 impl StatsProvider for Memory {
     type Stats = MemoryStats;
 
     fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+        let events = stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_EVENTS))?;
         let stats = MemoryStats {
             memory: Self::get_memory_data(cgroup_path, "memory", "oom")?,
-            memswap: Self::get_memory_data(cgroup_path, "memory.swap", "fail")?,
+            memswap: if cgroup_path.join(CGROUP_MEMORY_SWAP).exists() {
+                Self::get_memory_data(cgroup_path, "memory.swap", "fail")?
+            } else {
+                // swap accounting is not enabled
+                MemoryData::default()
+            },
             hierarchy: true,
+            oom: events.get("oom").copied().unwrap_or_default(),
+            oom_kill: events.get("oom_kill").copied().unwrap_or_default(),
             stats: stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))?,
             ..Default::default()
         };
@@ -82,54 +140,99 @@ impl Memory {
         }
     }
 
+    /// In cgroup v1 swap is memory+swap, but in cgroup v2 swap is a separate
+    /// value, so the swap value of the runtime spec needs to be converted by
+    /// subtracting the memory limit. Same as runc, zero means the value is not
+    /// set and -1 means unlimited. Returns None if swap.max should not be set.
+    fn convert_swap_to_cgroup2(limit: i64, swap: i64) -> Result<Option<i64>> {
+        match (limit, swap) {
+            // unlimited memory, so swap is unlimited as well
+            (-1, 0) => Ok(Some(-1)),
+            (_, -1) => Ok(Some(-1)),
+            (_, 0) => Ok(None),
+            (0, _) | (-1, _) => bail!("unable to set swap limit without memory limit"),
+            (limit, swap) if swap < limit => bail!(
+                "swap memory ({}) should be bigger than memory limit ({})",
+                swap,
+                limit
+            ),
+            (limit, swap) => Ok(Some(swap - limit)),
+        }
+    }
+
+    fn apply_protection(path: &Path, protection: &MemoryProtection) -> Result<()> {
+        if let (Some(min), Some(low)) = (protection.min, protection.low) {
+            if low != -1 && (min == -1 || min > low) {
+                bail!(
+                    "memory.min ({}) should not be bigger than memory.low ({})",
+                    min,
+                    low
+                );
+            }
+        }
+
+        // zero is the default of both values, unlike in Memory::set it has to be
+        // written to reset a value set before, e.g. on update
+        for (file, value) in [
+            (CGROUP_MEMORY_MIN, protection.min),
+            (CGROUP_MEMORY_LOW, protection.low),
+        ] {
+            match value {
+                Some(-1) => common::write_cgroup_file_str(path.join(file), "max")?,
+                Some(bytes) => common::write_cgroup_file(path.join(file), bytes)?,
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // With oom.group the OOM killer kills all processes of the cgroup together
+    // instead of a single one, which would leave the container half-alive. It
+    // is not part of the runtime spec, so it is requested through the unified map.
+    fn apply_oom_group(path: &Path, unified: &HashMap<String, String>) -> Result<()> {
+        let oom_group = match unified.get(CGROUP_MEMORY_OOM_GROUP).map(|v| v.trim()) {
+            Some(oom_group @ "0") | Some(oom_group @ "1") => oom_group,
+            Some(oom_group) => bail!("invalid memory oom group {}, expected 0 or 1", oom_group),
+            None => return Ok(()),
+        };
+
+        if !path.join(CGROUP_MEMORY_OOM_GROUP).exists() {
+            bail!(
+                "memory oom group is not supported by the kernel, it requires at least Linux 4.19"
+            );
+        }
+
+        common::write_cgroup_file_str(path.join(CGROUP_MEMORY_OOM_GROUP), oom_group)
+    }
+
     fn apply(path: &Path, memory: &LinuxMemory) -> Result<()> {
         // if nothing is set just exit right away
         if memory.reservation().is_none() && memory.limit().is_none() && memory.swap().is_none() {
             return Ok(());
         }
 
-        match memory.limit() {
-            Some(limit) if limit < -1 => {
+        if let Some(limit) = memory.limit() {
+            if limit < -1 {
                 bail!("invalid memory value: {}", limit);
             }
-            Some(limit) => match memory.swap() {
-                Some(swap) if swap < -1 => {
-                    bail!("invalid swap value: {}", swap);
-                }
-                Some(swap) => {
-                    // -1 means max
-                    if swap == -1 || limit == -1 {
-                        Memory::set(path.join(CGROUP_MEMORY_SWAP), swap)?;
-                    } else {
-                        if swap < limit {
-                            bail!(
-                                "swap memory ({}) should be bigger than memory limit ({})",
-                                swap,
-                                limit
-                            );
-                        }
-
-                        // In cgroup v1 swap is memory+swap, but in cgroup v2 swap is
-                        // a separate value, so the swap value in the runtime spec needs
-                        // to be converted from the cgroup v1 value to the cgroup v2 value
-                        // by subtracting limit from swap
-                        Memory::set(path.join(CGROUP_MEMORY_SWAP), swap - limit)?;
-                    }
-                    Memory::set(path.join(CGROUP_MEMORY_MAX), limit)?;
-                }
-                None => {
-                    if limit == -1 {
-                        Memory::set(path.join(CGROUP_MEMORY_SWAP), -1)?;
-                    }
-                    Memory::set(path.join(CGROUP_MEMORY_MAX), limit)?;
-                }
-            },
-            None => {
-                if memory.swap().is_some() {
-                    bail!("unable to set swap limit without memory limit");
-                }
+        }
+        if let Some(swap) = memory.swap() {
+            if swap < -1 {
+                bail!("invalid swap value: {}", swap);
             }
-        };
+        }
+
+        let limit = memory.limit().unwrap_or(0);
+        let swap_path = path.join(CGROUP_MEMORY_SWAP);
+        match Self::convert_swap_to_cgroup2(limit, memory.swap().unwrap_or(0))? {
+            Some(swap) if !swap_path.exists() => common::skip_swap_limit(swap),
+            Some(-1) => common::write_cgroup_file_str(swap_path, "max")?,
+            // unlike the other values zero is written, as it disables swap
+            Some(swap) => common::write_cgroup_file(swap_path, swap)?,
+            None => {}
+        }
+        Memory::set(path.join(CGROUP_MEMORY_MAX), limit)?;
 
         if let Some(reservation) = memory.reservation() {
             if reservation < -1 {
@@ -146,7 +249,7 @@ impl Memory {
 mod tests {
     use super::*;
     use crate::test::{create_temp_dir, set_fixture};
-    use oci_spec::runtime::LinuxMemoryBuilder;
+    use oci_spec::runtime::{LinuxMemoryBuilder, LinuxResources};
     use std::fs::read_to_string;
 
     #[test]
@@ -180,6 +283,93 @@ mod tests {
         assert_eq!(reservation_content, reservation.to_string());
     }
 
+    #[test]
+    fn test_set_memory_protection() {
+        let tmp =
+            create_temp_dir("test_set_memory_protection").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_MEMORY_MIN, "0").expect("set fixture for memory min");
+        set_fixture(&tmp, CGROUP_MEMORY_LOW, "0").expect("set fixture for memory low");
+
+        let unified: HashMap<String, String> = vec![
+            (CGROUP_MEMORY_MIN.to_owned(), "1024".to_owned()),
+            (CGROUP_MEMORY_LOW.to_owned(), "max".to_owned()),
+        ]
+        .into_iter()
+        .collect();
+        let protection = MemoryProtection::from_unified(&unified).expect("parse protection");
+        assert_eq!(
+            protection,
+            MemoryProtection {
+                min: Some(1024),
+                low: Some(-1),
+            }
+        );
+
+        Memory::apply_protection(&tmp, &protection).expect("apply memory protection");
+
+        let min_content = read_to_string(tmp.join(CGROUP_MEMORY_MIN)).expect("read memory min");
+        assert_eq!(min_content, "1024");
+        let low_content = read_to_string(tmp.join(CGROUP_MEMORY_LOW)).expect("read memory low");
+        assert_eq!(low_content, "max");
+    }
+
+    #[test]
+    fn test_set_memory_oom_group() {
+        let tmp =
+            create_temp_dir("test_set_memory_oom_group").expect("create temp directory for test");
+        let unified: HashMap<String, String> =
+            vec![(CGROUP_MEMORY_OOM_GROUP.to_owned(), "1".to_owned())]
+                .into_iter()
+                .collect();
+        assert!(Memory::apply_oom_group(&tmp, &unified).is_err());
+
+        set_fixture(&tmp, CGROUP_MEMORY_OOM_GROUP, "0").expect("set fixture for memory oom group");
+        Memory::apply_oom_group(&tmp, &unified).expect("apply memory oom group");
+        let content =
+            read_to_string(tmp.join(CGROUP_MEMORY_OOM_GROUP)).expect("read memory oom group");
+        assert_eq!(content, "1");
+
+        let unified: HashMap<String, String> =
+            vec![(CGROUP_MEMORY_OOM_GROUP.to_owned(), "yes".to_owned())]
+                .into_iter()
+                .collect();
+        assert!(Memory::apply_oom_group(&tmp, &unified).is_err());
+    }
+
+    #[test]
+    fn test_err_disable_oom_killer() {
+        let tmp =
+            create_temp_dir("test_err_disable_oom_killer").expect("create temp directory for test");
+        let resources = LinuxResources::default();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: true,
+            missing_controllers: Default::default(),
+        };
+
+        let result = <Memory as Controller>::apply(&controller_opt, &tmp);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_err_memory_protection() {
+        let unified: HashMap<String, String> =
+            vec![(CGROUP_MEMORY_MIN.to_owned(), "-5".to_owned())]
+                .into_iter()
+                .collect();
+        assert!(MemoryProtection::from_unified(&unified).is_err());
+
+        let tmp =
+            create_temp_dir("test_err_memory_protection").expect("create temp directory for test");
+        let protection = MemoryProtection {
+            min: Some(2048),
+            low: Some(1024),
+        };
+        assert!(Memory::apply_protection(&tmp, &protection).is_err());
+    }
+
     #[test]
     fn test_set_memory_unlimited() {
         let tmp = create_temp_dir("test_set_memory_unlimited_v2")
@@ -199,6 +389,24 @@ mod tests {
         assert_eq!(swap_content, "max");
     }
 
+    #[test]
+    fn test_set_memory_without_swap_accounting() {
+        let tmp = create_temp_dir("test_set_memory_without_swap_accounting")
+            .expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_MEMORY_MAX, "0").expect("set fixture for memory limit");
+
+        let memory_limits = LinuxMemoryBuilder::default()
+            .limit(1024)
+            .swap(2048)
+            .build()
+            .unwrap();
+        Memory::apply(&tmp, &memory_limits).expect("apply memory limits");
+
+        let limit_content = read_to_string(tmp.join(CGROUP_MEMORY_MAX)).expect("read memory limit");
+        assert_eq!(limit_content, "1024");
+        assert!(!tmp.join(CGROUP_MEMORY_SWAP).exists());
+    }
+
     #[test]
     fn test_err_swap_no_memory() {
         let tmp =
@@ -214,6 +422,20 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_convert_swap_to_cgroup2() -> Result<()> {
+        assert_eq!(Memory::convert_swap_to_cgroup2(1024, 2048)?, Some(1024));
+        // swap equal to the limit disables swap
+        assert_eq!(Memory::convert_swap_to_cgroup2(1024, 1024)?, Some(0));
+        assert_eq!(Memory::convert_swap_to_cgroup2(1024, -1)?, Some(-1));
+        assert_eq!(Memory::convert_swap_to_cgroup2(-1, 0)?, Some(-1));
+        assert_eq!(Memory::convert_swap_to_cgroup2(0, -1)?, Some(-1));
+        assert_eq!(Memory::convert_swap_to_cgroup2(1024, 0)?, None);
+        assert!(Memory::convert_swap_to_cgroup2(-1, 2048).is_err());
+        assert!(Memory::convert_swap_to_cgroup2(2048, 1024).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_err_bad_limit() {
         let tmp = create_temp_dir("test_err_bad_limit_v2").expect("create temp directory for test");
@@ -267,13 +489,12 @@ mod tests {
                 if swap < -1 {
                     return result.is_err();
                 }
-                if linux_memory.limit().is_none() {
+                let limit = linux_memory.limit().unwrap_or(0);
+                if swap > 0 && (limit == 0 || limit == -1) {
                     return result.is_err();
                 }
-                if let Some(limit) = linux_memory.limit() {
-                    if limit != -1 && swap != -1 && swap < limit {
-                        return result.is_err();
-                    }
+                if swap > 0 && swap < limit {
+                    return result.is_err();
                 }
             }
 
@@ -293,25 +514,15 @@ mod tests {
 
             // check the swap file is set as expected
             let swap_content = read_to_string(tmp.join(CGROUP_MEMORY_SWAP)).expect("read swap limit to string");
-            let swap_check = match linux_memory.swap() {
-                Some(swap) if swap == -1 => swap_content == "max",
-                Some(swap) => {
-                    if let Some(limit) = linux_memory.limit() {
-                        if limit == -1 {
-                            swap_content == swap.to_string()
-                        } else {
-                            swap_content == (swap - linux_memory.limit().unwrap()).to_string()
-                        }
-                    } else {
-                        false
-                    }
-                }
-                None => {
+            let swap_check = match linux_memory.swap().unwrap_or(0) {
+                -1 => swap_content == "max",
+                0 => {
                     match linux_memory.limit() {
                         Some(limit) if limit == -1 => swap_content == "max",
                         _ => swap_content == "0",
                     }
                 }
+                swap => swap_content == (swap - linux_memory.limit().unwrap()).to_string(),
             };
 
 
@@ -348,4 +559,26 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_stat_oom_events() {
+        let tmp = create_temp_dir("test_stat_oom_events").expect("create test directory");
+        for (file, value) in [
+            ("memory.current", "12500"),
+            ("memory.max", "max"),
+            ("memory.swap.current", "0"),
+            ("memory.swap.max", "max"),
+            ("memory.swap.events", "max 0\nfail 0"),
+            (MEMORY_STAT, "anon 13"),
+        ] {
+            set_fixture(&tmp, file, value).unwrap();
+        }
+        let events = ["low 0", "high 0", "max 8", "oom 2", "oom_kill 1"].join("\n");
+        set_fixture(&tmp, MEMORY_EVENTS, &events).unwrap();
+
+        let stats = Memory::stats(&tmp).expect("get cgroup stats");
+        assert_eq!(stats.memory.fail_count, 2);
+        assert_eq!(stats.oom, 2);
+        assert_eq!(stats.oom_kill, 1);
+    }
 }
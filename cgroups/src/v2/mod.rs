@@ -2,15 +2,19 @@ mod controller;
 pub mod controller_type;
 mod cpu;
 mod cpuset;
+#[cfg(feature = "systemd_cgroups")]
+mod dbus_client;
 mod freezer;
 mod hugetlb;
 mod io;
 pub mod manager;
 mod memory;
 mod pids;
+#[cfg(feature = "systemd_cgroups")]
 pub mod systemd_manager;
 mod unified;
 pub mod util;
+#[cfg(feature = "systemd_cgroups")]
 pub use systemd_manager::SystemDCGroupManager;
 #[cfg(feature = "cgroupsv2_devices")]
 pub mod devices;
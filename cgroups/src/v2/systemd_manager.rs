@@ -1,24 +1,35 @@
-use std::{
-    fs::{self},
-    os::unix::fs::PermissionsExt,
-};
+use std::{thread, time::Duration};
 
-use anyhow::{anyhow, bail, Result};
-use nix::unistd::Pid;
+use anyhow::{anyhow, bail, Context, Result};
+use nix::unistd::{self, Pid};
+use oci_spec::runtime::LinuxResources;
 use std::path::{Path, PathBuf};
+use zvariant::Value;
 
 #[cfg(feature = "cgroupsv2_devices")]
 use super::devices::Devices;
 use super::{
-    controller::Controller, controller_type::ControllerType, cpu::Cpu, cpuset::CpuSet,
-    freezer::Freezer, hugetlb::HugeTlb, io::Io, memory::Memory, pids::Pids,
+    controller::Controller,
+    controller_type::ControllerType,
+    cpu::Cpu,
+    cpuset::CpuSet,
+    dbus_client::{Client, UnitProperties},
+    freezer::Freezer,
+    hugetlb::HugeTlb,
+    io::Io,
+    memory::Memory,
+    pids::Pids,
+    util,
 };
 use crate::common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt};
 use crate::stats::Stats;
 
-const CGROUP_PROCS: &str = "cgroup.procs";
-const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
-const CGROUP_SUBTREE_CONTROL: &str = "cgroup.subtree_control";
+// default slice of the systemd system instance
+const DEFAULT_SLICE: &str = "machine.slice";
+// default slice of the systemd user instance
+const DEFAULT_USER_SLICE: &str = "user.slice";
+// cpu period of the kernel if none is set
+const DEFAULT_CPU_PERIOD: u64 = 100_000;
 
 // v2 systemd only supports cpu, io, memory and pids.
 const CONTROLLER_TYPES: &[ControllerType] = &[
@@ -30,15 +41,28 @@ const CONTROLLER_TYPES: &[ControllerType] = &[
 
 /// SystemDCGroupManager is a driver for managing cgroups via systemd.
 pub struct SystemDCGroupManager {
-    root_path: PathBuf,
-    cgroups_path: PathBuf,
     full_path: PathBuf,
+    /// The container is placed into a transient unit, which delegates the
+    /// cgroup of the unit to youki.
+    unit: TransientUnit,
+    /// Unprivileged users cannot create units of the system instance. For
+    /// them the unit is created by the systemd user instance instead.
+    rootless: bool,
+    /// Additional properties of the unit as name and value, which are set
+    /// when the unit is started
+    unit_properties: Vec<(String, String)>,
+}
+
+struct TransientUnit {
+    name: String,
+    slice: String,
 }
 
 /// Represents the systemd cgroups path:
 /// It should be of the form [slice]:[scope_prefix]:[name].
 /// The slice is the "parent" and should be expanded properly,
 /// see expand_slice below.
+#[derive(Clone)]
 struct CgroupsPath {
     parent: String,
     scope: String,
@@ -47,41 +71,95 @@ struct CgroupsPath {
 
 impl SystemDCGroupManager {
     pub fn new(root_path: PathBuf, cgroups_path: PathBuf) -> Result<Self> {
-        // TODO: create the systemd unit using a dbus client.
         let destructured_path = Self::destructure_cgroups_path(cgroups_path)?;
+        if !unistd::geteuid().is_root() {
+            return Self::new_user(root_path, destructured_path);
+        }
+
+        let unit = TransientUnit {
+            name: Self::get_unit_name(destructured_path.clone()),
+            slice: if destructured_path.parent.is_empty() {
+                DEFAULT_SLICE.to_owned()
+            } else {
+                destructured_path.parent.clone()
+            },
+        };
         let cgroups_path = Self::construct_cgroups_path(destructured_path)?;
         let full_path = root_path.join_safely(&cgroups_path)?;
 
         Ok(SystemDCGroupManager {
-            root_path,
-            cgroups_path,
             full_path,
+            unit,
+            rootless: false,
+            unit_properties: Vec::new(),
         })
     }
 
+    fn new_user(root_path: PathBuf, cgroups_path: CgroupsPath) -> Result<Self> {
+        let client = Client::new_session()?;
+        let user_cgroup = PathBuf::from(client.control_group()?);
+
+        let unit = TransientUnit {
+            name: Self::get_unit_name(cgroups_path.clone()),
+            slice: if cgroups_path.parent.is_empty() {
+                DEFAULT_USER_SLICE.to_owned()
+            } else {
+                cgroups_path.parent.clone()
+            },
+        };
+        let cgroups_path = Self::construct_user_cgroups_path(cgroups_path, &user_cgroup)?;
+        let full_path = root_path.join_safely(&cgroups_path)?;
+        log::debug!(
+            "cgroup {:?} will be delegated by the systemd user instance",
+            full_path
+        );
+
+        Ok(SystemDCGroupManager {
+            full_path,
+            unit,
+            rootless: true,
+            unit_properties: Vec::new(),
+        })
+    }
+
+    /// Sets additional properties of the unit, e.g. from the annotations of
+    /// the container. Their values are given in the syntax of systemctl
+    /// set-property, see parse_unit_property.
+    pub fn with_unit_properties(mut self, unit_properties: Vec<(String, String)>) -> Self {
+        self.unit_properties = unit_properties;
+        self
+    }
+
     fn destructure_cgroups_path(cgroups_path: PathBuf) -> Result<CgroupsPath> {
-        // cgroups path may never be empty as it is defaulted to `/youki`
-        // see 'get_cgroup_path' under utils.rs.
-        // if cgroups_path was provided it should be of the form [slice]:[scope_prefix]:[name],
-        // for example: "system.slice:docker:1234".
-        let mut parent = "";
-        let scope;
-        let name;
-        if cgroups_path.starts_with("/youki") {
-            scope = "youki";
-            name = cgroups_path
-                .strip_prefix("/youki/")?
-                .to_str()
-                .ok_or_else(|| anyhow!("Failed to parse cgroupsPath field."))?;
-        } else {
-            let parts = cgroups_path
-                .to_str()
-                .ok_or_else(|| anyhow!("Failed to parse cgroupsPath field."))?
-                .split(':')
-                .collect::<Vec<&str>>();
-            parent = parts[0];
-            scope = parts[1];
-            name = parts[2];
+        // cgroups_path was either provided in the form [slice]:[scope_prefix]:[name],
+        // for example: "system.slice:docker:1234" as docker with the systemd cgroup
+        // driver does, or it defaults to the container id, see 'get_cgroup_path' under
+        // utils.rs. Older versions of youki defaulted to `/youki/<id>`.
+        let path = cgroups_path
+            .to_str()
+            .ok_or_else(|| anyhow!("Failed to parse cgroupsPath field."))?;
+        if let Some(name) = path.strip_prefix("/youki/") {
+            return Ok(CgroupsPath {
+                parent: String::new(),
+                scope: "youki".to_owned(),
+                name: name.to_owned(),
+            });
+        }
+
+        let parts = path.split(':').collect::<Vec<&str>>();
+        let (parent, scope, name) = match parts[..] {
+            [name] if !name.is_empty() && !name.contains('/') => ("", "youki", name),
+            [parent, scope, name] => (parent, scope, name),
+            _ => bail!(
+                "expected cgroupsPath to be of format \"slice:prefix:name\" for systemd cgroups, got {:?}",
+                path
+            ),
+        };
+        if name.is_empty() || name.contains('/') {
+            bail!("invalid name {:?} in cgroupsPath {:?}", name, path);
+        }
+        if !parent.is_empty() && !parent.ends_with(".slice") {
+            bail!("invalid slice {:?} in cgroupsPath {:?}", parent, path);
         }
 
         Ok(CgroupsPath {
@@ -95,10 +173,13 @@ impl SystemDCGroupManager {
     /// for example: foo:docker:bar returns in '/docker-bar.scope'
     fn get_unit_name(cgroups_path: CgroupsPath) -> String {
         // By default we create a scope unless specified explicitly.
-        if !cgroups_path.name.ends_with(".slice") {
-            return format!("{}-{}.scope", cgroups_path.scope, cgroups_path.name);
+        if cgroups_path.name.ends_with(".slice") {
+            return cgroups_path.name;
+        }
+        if cgroups_path.scope.is_empty() {
+            return format!("{}.scope", cgroups_path.name);
         }
-        cgroups_path.name
+        format!("{}-{}.scope", cgroups_path.scope, cgroups_path.name)
     }
 
     // systemd represents slice hierarchy using `-`, so we need to follow suit when
@@ -121,7 +202,7 @@ impl SystemDCGroupManager {
         }
         for component in slice_name.split('-') {
             if component.is_empty() {
-                anyhow!("Invalid slice name: {}", slice);
+                bail!("invalid slice name: {}", slice);
             }
             // Append the component to the path and to the prefix.
             path = format!("{}/{}{}{}", path, prefix, component, suffix);
@@ -134,7 +215,7 @@ impl SystemDCGroupManager {
     // an example of the final path: "/machine.slice/docker-foo.scope"
     fn construct_cgroups_path(cgroups_path: CgroupsPath) -> Result<PathBuf> {
         // the root slice is under 'machine.slice'.
-        let mut slice = Path::new("/machine.slice").to_path_buf();
+        let mut slice = Path::new("/").join(DEFAULT_SLICE);
         // if the user provided a '.slice' (as in a branch of a tree)
         // we need to "unpack it".
         if !cgroups_path.parent.is_empty() {
@@ -145,76 +226,257 @@ impl SystemDCGroupManager {
         Ok(cgroups_path)
     }
 
-    /// create_unified_cgroup verifies sure that *each level* in the downward path from the root cgroup
-    /// down to the cgroup_path provided by the user is a valid cgroup hierarchy,
-    /// containing the attached controllers and that it contains the container pid.
-    fn create_unified_cgroup(&self, pid: Pid) -> Result<()> {
-        let controllers: Vec<String> = self
-            .get_available_controllers(&self.root_path)?
-            .into_iter()
-            .map(|c| format!("{}{}", "+", c.to_string()))
-            .collect();
-
-        // Write the controllers to the root_path.
-        Self::write_controllers(&self.root_path, &controllers)?;
-
-        let mut current_path = self.root_path.clone();
-        let mut components = self.cgroups_path.components().skip(1).peekable();
-        // Verify that *each level* in the downward path from the root cgroup
-        // down to the cgroup_path provided by the user is a valid cgroup hierarchy.
-        // containing the attached controllers.
-        while let Some(component) = components.next() {
-            current_path = current_path.join(component);
-            if !current_path.exists() {
-                fs::create_dir(&current_path)?;
-                fs::metadata(&current_path)?.permissions().set_mode(0o755);
-            }
+    // construct_user_cgroups_path generates the cgroups path of a unit of the systemd user instance,
+    // which is located below the cgroup of the user instance itself. An example of the final path:
+    // "/user.slice/user-1000.slice/user@1000.service/user.slice/youki-foo.scope"
+    fn construct_user_cgroups_path(
+        cgroups_path: CgroupsPath,
+        user_cgroup: &Path,
+    ) -> Result<PathBuf> {
+        let slice = if cgroups_path.parent.is_empty() {
+            Self::expand_slice(DEFAULT_USER_SLICE)?
+        } else {
+            Self::expand_slice(&cgroups_path.parent)?
+        };
+        let unit_name = Self::get_unit_name(cgroups_path);
+        Ok(user_cgroup
+            .to_path_buf()
+            .join_safely(&slice)?
+            .join(unit_name))
+    }
 
-            // last component cannot have subtree_control enabled due to internal process constraint
-            // if this were set, writing to the cgroups.procs file will fail with Erno 16 (device or resource busy)
-            if components.peek().is_some() {
-                Self::write_controllers(&current_path, &controllers)?;
+    /// Connects to the systemd instance which manages the unit
+    fn client(&self) -> Result<Client> {
+        if self.rootless {
+            Client::new_session()
+        } else {
+            Client::new_system()
+        }
+    }
+
+    /// Asks systemd to place the process into a transient unit. The unit is
+    /// started asynchronously, so wait until its cgroup shows up.
+    fn start_unit(&self, pid: Pid) -> Result<()> {
+        let unit = &self.unit;
+        let properties = self
+            .unit_properties
+            .iter()
+            .map(|(name, value)| Self::parse_unit_property(name, value))
+            .collect::<Result<_>>()?;
+        self.client()?
+            .start_transient_unit(&unit.name, &unit.slice, pid, properties)?;
+
+        for _ in 0..100 {
+            if self.full_path.exists() {
+                return Ok(());
             }
+            thread::sleep(Duration::from_millis(10));
         }
 
-        common::write_cgroup_file(self.full_path.join(CGROUP_PROCS), pid)
+        bail!(
+            "cgroup {:?} of unit {} has not been created",
+            self.full_path,
+            unit.name
+        )
     }
 
-    fn get_available_controllers<P: AsRef<Path>>(
-        &self,
-        cgroups_path: P,
-    ) -> Result<Vec<ControllerType>> {
-        let controllers_path = self.root_path.join(cgroups_path).join(CGROUP_CONTROLLERS);
-        if !controllers_path.exists() {
+    /// Converts a property given as strings into a property of the unit, with
+    /// the value typed as systemd expects it for the property. Like systemctl,
+    /// time spans of properties ending in Sec are given with units, e.g. 1min
+    /// 30s, or as plain seconds and passed to systemd in microseconds as the
+    /// USec property. Sizes may have a K, M, G or T suffix. Properties which
+    /// are not known to youki are passed as strings. The properties youki
+    /// sets itself cannot be overridden.
+    fn parse_unit_property(name: &str, value: &str) -> Result<(String, Value<'static>)> {
+        if RESERVED_UNIT_PROPERTIES.contains(&name) {
             bail!(
-                "cannot get available controllers. {:?} does not exist",
-                controllers_path
-            )
+                "unit property {} is set by youki and cannot be changed",
+                name
+            );
         }
 
-        let mut controllers = Vec::new();
-        for controller in fs::read_to_string(&controllers_path)?.split_whitespace() {
-            match controller {
-                "cpu" => controllers.push(ControllerType::Cpu),
-                "io" => controllers.push(ControllerType::Io),
-                "memory" => controllers.push(ControllerType::Memory),
-                "pids" => controllers.push(ControllerType::Pids),
-                _ => continue,
-            }
+        if let Some(prefix) = name.strip_suffix("Sec") {
+            let usec = parse_time_span(value).with_context(|| {
+                format!("invalid time span {} of unit property {}", value, name)
+            })?;
+            return Ok((format!("{}USec", prefix), usec.into()));
         }
 
-        Ok(controllers)
+        let invalid = || format!("invalid value {} of unit property {}", value, name);
+        let value = match unit_property_type(name) {
+            UnitPropertyType::Bool => match value {
+                "yes" | "true" | "on" | "1" => Value::from(true),
+                "no" | "false" | "off" | "0" => Value::from(false),
+                _ => bail!(invalid()),
+            },
+            UnitPropertyType::Int32 => Value::from(value.parse::<i32>().with_context(invalid)?),
+            UnitPropertyType::UInt64 => match value {
+                "infinity" => Value::from(u64::MAX),
+                _ => Value::from(value.parse::<u64>().with_context(invalid)?),
+            },
+            UnitPropertyType::Size => match value {
+                "infinity" => Value::from(u64::MAX),
+                _ => Value::from(parse_size(value).with_context(invalid)?),
+            },
+            UnitPropertyType::String => Value::from(value.to_owned()),
+        };
+        Ok((name.to_owned(), value))
     }
 
-    fn write_controllers(path: &Path, controllers: &[String]) -> Result<()> {
-        for controller in controllers {
-            common::write_cgroup_file_str(path.join(CGROUP_SUBTREE_CONTROL), controller)?;
+    /// Translates the resources of the container into properties of the
+    /// unit, so that systemd knows about the limits and does not reset them
+    /// when it reloads. Resources without an equivalent are only written to
+    /// the cgroup.
+    fn unit_properties(resources: &LinuxResources) -> UnitProperties<'static> {
+        let mut properties: UnitProperties = Vec::new();
+
+        if let Some(cpu) = resources.cpu() {
+            if let Some(shares) = cpu.shares() {
+                let weight = Cpu::convert_shares_to_cgroup2(shares);
+                if weight != 0 {
+                    properties.push(("CPUWeight".to_owned(), weight.into()));
+                }
+            }
+
+            if let Some(quota) = cpu.quota().filter(|quota| *quota > 0) {
+                let period = cpu
+                    .period()
+                    .filter(|period| *period > 0)
+                    .unwrap_or(DEFAULT_CPU_PERIOD);
+                // systemd expects the quota per second of cpu time
+                let quota_per_sec = quota as u64 * 1_000_000 / period;
+                properties.push(("CPUQuotaPerSecUSec".to_owned(), quota_per_sec.into()));
+            }
         }
 
-        Ok(())
+        if let Some(limit) = resources.memory().as_ref().and_then(|m| m.limit()) {
+            match limit {
+                -1 => properties.push(("MemoryMax".to_owned(), u64::MAX.into())),
+                limit if limit > 0 => {
+                    properties.push(("MemoryMax".to_owned(), (limit as u64).into()))
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(pids) = resources.pids() {
+            match pids.limit() {
+                -1 => properties.push(("TasksMax".to_owned(), u64::MAX.into())),
+                limit if limit > 0 => {
+                    properties.push(("TasksMax".to_owned(), (limit as u64).into()))
+                }
+                _ => {}
+            }
+        }
+
+        properties
     }
 }
 
+// set by youki when it starts the transient unit of a container
+const RESERVED_UNIT_PROPERTIES: &[&str] = &["Description", "Slice", "Delegate", "PIDs"];
+
+enum UnitPropertyType {
+    Bool,
+    Int32,
+    UInt64,
+    Size,
+    String,
+}
+
+// D-Bus types of the properties of scope and service units which take
+// something else than a string
+fn unit_property_type(name: &str) -> UnitPropertyType {
+    match name {
+        "CPUAccounting"
+        | "MemoryAccounting"
+        | "IOAccounting"
+        | "BlockIOAccounting"
+        | "TasksAccounting"
+        | "IPAccounting"
+        | "DefaultDependencies"
+        | "SendSIGKILL"
+        | "SendSIGHUP"
+        | "MemoryDenyWriteExecute"
+        | "NoNewPrivileges" => UnitPropertyType::Bool,
+        "Nice"
+        | "KillSignal"
+        | "FinalKillSignal"
+        | "RestartKillSignal"
+        | "WatchdogSignal"
+        | "OOMScoreAdjust"
+        | "CPUSchedulingPriority" => UnitPropertyType::Int32,
+        "CPUWeight"
+        | "StartupCPUWeight"
+        | "CPUShares"
+        | "StartupCPUShares"
+        | "IOWeight"
+        | "StartupIOWeight"
+        | "BlockIOWeight"
+        | "StartupBlockIOWeight"
+        | "TasksMax" => UnitPropertyType::UInt64,
+        "MemoryMin" | "MemoryLow" | "MemoryHigh" | "MemoryMax" | "MemorySwapMax"
+        | "MemoryLimit" => UnitPropertyType::Size,
+        _ => UnitPropertyType::String,
+    }
+}
+
+// Parses a time span as systemctl does, a sequence of numbers with units,
+// plain numbers are seconds. Returns the span in microseconds.
+fn parse_time_span(value: &str) -> Result<u64> {
+    if value == "infinity" {
+        return Ok(u64::MAX);
+    }
+
+    let mut usec = 0f64;
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        bail!("empty time span");
+    }
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or_else(|| rest.len());
+        let number: f64 = rest[..number_len].parse()?;
+        rest = rest[number_len..].trim_start();
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.' || c.is_whitespace())
+            .unwrap_or_else(|| rest.len());
+        let factor = match &rest[..unit_len] {
+            "us" | "usec" | "\u{b5}s" => 1.0,
+            "ms" | "msec" => 1e3,
+            "" | "s" | "sec" | "second" | "seconds" => 1e6,
+            "m" | "min" | "minute" | "minutes" => 60e6,
+            "h" | "hr" | "hour" | "hours" => 3600e6,
+            "d" | "day" | "days" => 86400e6,
+            "w" | "week" | "weeks" => 604800e6,
+            unit => bail!("unknown time unit {}", unit),
+        };
+        usec += number * factor;
+        rest = rest[unit_len..].trim_start();
+    }
+
+    if !usec.is_finite() || usec >= u64::MAX as f64 {
+        bail!("time span is out of range");
+    }
+    Ok(usec as u64)
+}
+
+// Parses a size in bytes with an optional binary suffix
+fn parse_size(value: &str) -> Result<u64> {
+    let (number, factor) = match value.char_indices().last() {
+        Some((i, 'K')) => (&value[..i], 1u64 << 10),
+        Some((i, 'M')) => (&value[..i], 1 << 20),
+        Some((i, 'G')) => (&value[..i], 1 << 30),
+        Some((i, 'T')) => (&value[..i], 1 << 40),
+        _ => (value, 1),
+    };
+    number
+        .parse::<u64>()?
+        .checked_mul(factor)
+        .context("size is out of range")
+}
+
 impl CgroupManager for SystemDCGroupManager {
     fn add_task(&self, pid: Pid) -> Result<()> {
         // Dont attach any pid to the cgroup if -1 is specified as a pid
@@ -222,12 +484,27 @@ impl CgroupManager for SystemDCGroupManager {
             return Ok(());
         }
 
-        self.create_unified_cgroup(pid)?;
-        Ok(())
+        // further processes join the delegated cgroup of the running unit,
+        // e.g. the restored descendants of the init process of a container
+        if self.full_path.exists() {
+            return common::write_cgroup_file(self.full_path.join(common::CGROUP_PROCS), pid);
+        }
+
+        self.start_unit(pid)
     }
 
     fn apply(&self, controller_opt: &ControllerOpt) -> Result<()> {
-        for controller in CONTROLLER_TYPES {
+        let properties = Self::unit_properties(controller_opt.resources);
+        if !properties.is_empty() {
+            self.client()?
+                .set_unit_properties(&self.unit.name, properties)?;
+        }
+
+        // systemd does not know all resources, as the cgroup is delegated
+        // they are written to it as well
+        for controller in
+            util::controllers_to_apply(&self.full_path, CONTROLLER_TYPES, controller_opt)?
+        {
             match controller {
                 ControllerType::Cpu => Cpu::apply(controller_opt, &self.full_path)?,
                 ControllerType::CpuSet => CpuSet::apply(controller_opt, &self.full_path)?,
@@ -244,6 +521,12 @@ impl CgroupManager for SystemDCGroupManager {
     }
 
     fn remove(&self) -> Result<()> {
+        // the unit is stopped by systemd once its processes exit, stopping it
+        // again only makes sure that it is gone
+        if let Err(e) = self.client().and_then(|c| c.stop_unit(&self.unit.name)) {
+            log::debug!("failed to stop unit {}: {:#}", self.unit.name, e);
+        }
+
         Ok(())
     }
 
@@ -253,6 +536,7 @@ impl CgroupManager for SystemDCGroupManager {
             freezer_state: Some(state),
             oom_score_adj: None,
             disable_oom_killer: false,
+            missing_controllers: Default::default(),
         };
         Freezer::apply(&controller_opt, &self.full_path)
     }
@@ -264,11 +548,104 @@ impl CgroupManager for SystemDCGroupManager {
     fn get_all_pids(&self) -> Result<Vec<Pid>> {
         common::get_all_pids(&self.full_path)
     }
+
+    fn kill_all(&self) -> Result<bool> {
+        util::kill_cgroup(&self.full_path)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use oci_spec::runtime::{
+        LinuxCpuBuilder, LinuxMemoryBuilder, LinuxPidsBuilder, LinuxResourcesBuilder,
+    };
+
+    #[test]
+    fn unit_properties_works() -> Result<()> {
+        let resources = LinuxResourcesBuilder::default()
+            .cpu(
+                LinuxCpuBuilder::default()
+                    .shares(1024u64)
+                    .quota(50_000i64)
+                    .build()?,
+            )
+            .memory(LinuxMemoryBuilder::default().limit(-1i64).build()?)
+            .pids(LinuxPidsBuilder::default().limit(100i64).build()?)
+            .build()?;
+
+        assert_eq!(
+            SystemDCGroupManager::unit_properties(&resources),
+            vec![
+                ("CPUWeight".to_owned(), Value::from(39u64)),
+                ("CPUQuotaPerSecUSec".to_owned(), Value::from(500_000u64)),
+                ("MemoryMax".to_owned(), Value::from(u64::MAX)),
+                ("TasksMax".to_owned(), Value::from(100u64)),
+            ]
+        );
+        assert!(SystemDCGroupManager::unit_properties(&LinuxResources::default()).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_unit_property_works() -> Result<()> {
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("RuntimeMaxSec", "1.5")?,
+            ("RuntimeMaxUSec".to_owned(), Value::from(1_500_000u64))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("TimeoutStopSec", "infinity")?,
+            ("TimeoutStopUSec".to_owned(), Value::from(u64::MAX))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("CPUWeight", "200")?,
+            ("CPUWeight".to_owned(), Value::from(200u64))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("IOAccounting", "yes")?,
+            ("IOAccounting".to_owned(), Value::from(true))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("CollectMode", "inactive-or-failed")?,
+            (
+                "CollectMode".to_owned(),
+                Value::from("inactive-or-failed".to_owned())
+            )
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("RuntimeMaxSec", "1min 30s")?,
+            ("RuntimeMaxUSec".to_owned(), Value::from(90_000_000u64))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("TimeoutStopSec", "1h500ms")?,
+            ("TimeoutStopUSec".to_owned(), Value::from(3_600_500_000u64))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("Nice", "-5")?,
+            ("Nice".to_owned(), Value::from(-5i32))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("KillSignal", "15")?,
+            ("KillSignal".to_owned(), Value::from(15i32))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("MemoryHigh", "1G")?,
+            ("MemoryHigh".to_owned(), Value::from(1u64 << 30))
+        );
+        assert_eq!(
+            SystemDCGroupManager::parse_unit_property("Description", "test")
+                .map_err(|e| e.to_string()),
+            Err("unit property Description is set by youki and cannot be changed".to_owned())
+        );
+        assert!(SystemDCGroupManager::parse_unit_property("PIDs", "1").is_err());
+        assert!(SystemDCGroupManager::parse_unit_property("RuntimeMaxSec", "-1").is_err());
+        assert!(SystemDCGroupManager::parse_unit_property("RuntimeMaxSec", "1parsec").is_err());
+        assert!(SystemDCGroupManager::parse_unit_property("CPUWeight", "high").is_err());
+        assert!(SystemDCGroupManager::parse_unit_property("IOAccounting", "maybe").is_err());
+
+        Ok(())
+    }
 
     #[test]
     fn expand_slice_works() -> Result<()> {
@@ -322,4 +699,85 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn destructure_cgroups_path_works() -> Result<()> {
+        let cgroups_path = SystemDCGroupManager::destructure_cgroups_path(PathBuf::from(
+            "system.slice:docker:1234",
+        ))?;
+        assert_eq!(cgroups_path.parent, "system.slice");
+        assert_eq!(
+            SystemDCGroupManager::get_unit_name(cgroups_path.clone()),
+            "docker-1234.scope"
+        );
+        assert_eq!(
+            SystemDCGroupManager::construct_cgroups_path(cgroups_path)?,
+            PathBuf::from("/system.slice/docker-1234.scope"),
+        );
+
+        let cgroups_path = SystemDCGroupManager::destructure_cgroups_path(PathBuf::from("1234"))?;
+        assert_eq!(
+            SystemDCGroupManager::construct_cgroups_path(cgroups_path)?,
+            PathBuf::from("/machine.slice/youki-1234.scope"),
+        );
+
+        let cgroups_path =
+            SystemDCGroupManager::destructure_cgroups_path(PathBuf::from("system.slice::1234"))?;
+        assert_eq!(
+            SystemDCGroupManager::get_unit_name(cgroups_path),
+            "1234.scope"
+        );
+
+        for invalid in &[
+            "",
+            "/sys/fs/cgroup/1234",
+            "system.slice:docker",
+            "system.slice:docker:",
+            "system:docker:1234",
+            "system.slice:docker:a/b",
+            "a:b:c:d",
+        ] {
+            assert!(
+                SystemDCGroupManager::destructure_cgroups_path(PathBuf::from(invalid)).is_err(),
+                "{} should be rejected",
+                invalid
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn expand_slice_rejects_empty_components() {
+        assert!(SystemDCGroupManager::expand_slice("test--a.slice").is_err());
+        assert!(SystemDCGroupManager::expand_slice("test.slice/a.slice").is_err());
+    }
+
+    #[test]
+    fn get_user_cgroups_path_works() -> Result<()> {
+        let user_cgroup = Path::new("/user.slice/user-1000.slice/user@1000.service");
+        let cgroups_path =
+            SystemDCGroupManager::destructure_cgroups_path(PathBuf::from("/youki/foo")).expect("");
+
+        assert_eq!(
+            SystemDCGroupManager::construct_user_cgroups_path(cgroups_path, user_cgroup)?,
+            PathBuf::from(
+                "/user.slice/user-1000.slice/user@1000.service/user.slice/youki-foo.scope"
+            ),
+        );
+
+        let cgroups_path = SystemDCGroupManager::destructure_cgroups_path(PathBuf::from(
+            "test-a.slice:docker:foo",
+        ))
+        .expect("");
+
+        assert_eq!(
+            SystemDCGroupManager::construct_user_cgroups_path(cgroups_path, user_cgroup)?,
+            PathBuf::from(
+                "/user.slice/user-1000.slice/user@1000.service/test.slice/test-a.slice/docker-foo.scope"
+            ),
+        );
+
+        Ok(())
+    }
 }
@@ -1,10 +1,22 @@
 use std::path::Path;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
-use super::controller_type::ControllerType;
+use super::{controller_type::ControllerType, cpu, io, memory};
 use crate::common::{self, ControllerOpt};
 
+const CGROUP_CPUSET_PARTITION: &str = "cpuset.cpus.partition";
+// files which the controllers validate and write themselves, in the order
+// they require, so they are not written a second time
+const CONTROLLER_FILES: &[&str] = &[
+    cpu::CGROUP_CPU_BURST,
+    cpu::CGROUP_CPU_IDLE,
+    memory::CGROUP_MEMORY_MIN,
+    memory::CGROUP_MEMORY_LOW,
+    memory::CGROUP_MEMORY_OOM_GROUP,
+    io::CGROUP_IO_LATENCY,
+];
+
 pub struct Unified {}
 
 impl Unified {
@@ -15,7 +27,12 @@ impl Unified {
     ) -> Result<()> {
         if let Some(unified) = &controller_opt.resources.unified() {
             log::debug!("Apply unified cgroup config");
-            for (cgroup_file, value) in unified {
+            // the cpus of a cpuset have to be set before it can become a partition
+            let (partition, files): (Vec<_>, Vec<_>) = unified
+                .iter()
+                .filter(|(cgroup_file, _)| !CONTROLLER_FILES.contains(&cgroup_file.as_str()))
+                .partition(|(cgroup_file, _)| cgroup_file.as_str() == CGROUP_CPUSET_PARTITION);
+            for (cgroup_file, value) in files.into_iter().chain(partition.iter().copied()) {
                 common::write_cgroup_file_str(cgroup_path.join(cgroup_file), value).map_err(
                     |e| {
                         let (subsystem, _) = cgroup_file
@@ -37,6 +54,27 @@ impl Unified {
                     },
                 )?;
             }
+
+            if let Some((_, requested)) = partition.first() {
+                Self::check_partition(cgroup_path, requested)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The kernel accepts partitions which violate its constraints, e.g. cpus
+    /// which are not exclusive, but marks them as invalid and does not isolate
+    /// the cpus. For an isolated partition this has to be detected.
+    fn check_partition(cgroup_path: &Path, requested: &str) -> Result<()> {
+        let partition = common::read_cgroup_file(cgroup_path.join(CGROUP_CPUSET_PARTITION))?;
+        let partition = partition.trim();
+        if partition.contains("invalid") {
+            bail!(
+                "cpuset partition {} is invalid ({}), the cpus of the container must be exclusive",
+                requested,
+                partition
+            );
         }
 
         Ok(())
@@ -82,6 +120,7 @@ mod tests {
             freezer_state: None,
             oom_score_adj: None,
             disable_oom_killer: false,
+            missing_controllers: Default::default(),
         };
 
         // act
@@ -94,6 +133,55 @@ mod tests {
         assert_eq!(cpu_weight, "5000");
     }
 
+    #[test]
+    fn test_skip_controller_files() -> Result<()> {
+        let tmp = create_temp_dir("test_skip_controller_files")?;
+        let idle_path = set_fixture(&tmp, cpu::CGROUP_CPU_IDLE, "0")?;
+
+        let mut unified = HashMap::new();
+        unified.insert(cpu::CGROUP_CPU_IDLE.to_owned(), "1".to_owned());
+        let resources = LinuxResourcesBuilder::default().unified(unified).build()?;
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+            missing_controllers: Default::default(),
+        };
+
+        Unified::apply(&controller_opt, &tmp, vec![ControllerType::Cpu])?;
+        assert_eq!(fs::read_to_string(idle_path)?, "0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_cpuset_partition() -> Result<()> {
+        let tmp = create_temp_dir("test_set_cpuset_partition")?;
+        let cpus_path = set_fixture(&tmp, "cpuset.cpus", "")?;
+        let partition_path = set_fixture(&tmp, CGROUP_CPUSET_PARTITION, "member")?;
+
+        let mut unified = HashMap::new();
+        unified.insert(CGROUP_CPUSET_PARTITION.to_owned(), "isolated".to_owned());
+        unified.insert("cpuset.cpus".to_owned(), "2-3".to_owned());
+        let resources = LinuxResourcesBuilder::default().unified(unified).build()?;
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+            missing_controllers: Default::default(),
+        };
+
+        Unified::apply(&controller_opt, &tmp, vec![ControllerType::CpuSet])?;
+        assert_eq!(fs::read_to_string(cpus_path)?, "2-3");
+        assert_eq!(fs::read_to_string(&partition_path)?, "isolated");
+
+        // the kernel reports partitions it cannot establish as invalid
+        fs::write(&partition_path, "isolated invalid")?;
+        assert!(Unified::check_partition(&tmp, "isolated").is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_set_unified_failed_to_write_subsystem_not_enabled() {
         // arrange
@@ -120,6 +208,7 @@ mod tests {
             freezer_state: None,
             oom_score_adj: None,
             disable_oom_killer: false,
+            missing_controllers: Default::default(),
         };
 
         // act
@@ -153,6 +242,7 @@ mod tests {
             resources: &resources,
             oom_score_adj: None,
             disable_oom_killer: false,
+            missing_controllers: Default::default(),
             freezer_state: None,
         };
 
@@ -1,21 +1,27 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, bail, Result};
-use procfs::process::Process;
+use oci_spec::runtime::LinuxResources;
+use procfs::process::{MountInfo, Process};
 
-use crate::common;
+use crate::common::{self, ControllerOpt, MissingControllerPolicy};
 
 use super::controller_type::ControllerType;
 
 pub const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
 pub const CGROUP_SUBTREE_CONTROL: &str = "cgroup.subtree_control";
+pub const CGROUP_KILL: &str = "cgroup.kill";
 
 pub fn get_unified_mount_point() -> Result<PathBuf> {
+    get_unified_mount().map(|m| m.mount_point)
+}
+
+/// Returns the mount of the unified hierarchy
+pub fn get_unified_mount() -> Result<MountInfo> {
     Process::myself()?
         .mountinfo()?
         .into_iter()
         .find(|m| m.fs_type == "cgroup2")
-        .map(|m| m.mount_point)
         .ok_or_else(|| anyhow!("could not find mountpoint for unified"))
 }
 
@@ -43,3 +49,106 @@ pub fn get_available_controllers(root_path: &Path) -> Result<Vec<ControllerType>
 
     Ok(controllers)
 }
+
+/// Sends SIGKILL to all processes in the cgroup and its descendants by writing
+/// to cgroup.kill. Unlike signaling the pids one by one, processes which are
+/// forked meanwhile cannot escape. Returns false if cgroup.kill does not exist.
+pub fn kill_cgroup(cgroup_path: &Path) -> Result<bool> {
+    let kill_path = cgroup_path.join(CGROUP_KILL);
+    if !kill_path.exists() {
+        return Ok(false);
+    }
+
+    common::write_cgroup_file(kill_path, 1)?;
+    Ok(true)
+}
+
+/// Returns the controllers whose restrictions are applied to the cgroup.
+/// Controllers which are not enabled for the cgroup have no interface files
+/// in it, so if missing controllers should only be warned about, they are
+/// skipped.
+pub fn controllers_to_apply(
+    cgroup_path: &Path,
+    controllers: &[ControllerType],
+    controller_opt: &ControllerOpt,
+) -> Result<Vec<ControllerType>> {
+    if controller_opt.missing_controllers == MissingControllerPolicy::Fail {
+        return Ok(controllers.to_vec());
+    }
+
+    let available = get_available_controllers(cgroup_path)?;
+    let mut to_apply = Vec::new();
+    for controller in controllers {
+        if available.contains(controller) {
+            to_apply.push(*controller);
+        } else if is_requested(controller, controller_opt.resources) {
+            log::warn!(
+                "Cgroup {} is required to fullfill the request, but is not available to the cgroup. Its restrictions are not applied",
+                controller
+            );
+        }
+    }
+
+    Ok(to_apply)
+}
+
+/// Checks if the resources contain restrictions for the controller
+fn is_requested(controller: &ControllerType, resources: &LinuxResources) -> bool {
+    match controller {
+        ControllerType::Cpu | ControllerType::CpuSet => resources.cpu().is_some(),
+        ControllerType::HugeTlb => resources.hugepage_limits().is_some(),
+        ControllerType::Io => resources.block_io().is_some(),
+        ControllerType::Memory => resources.memory().is_some(),
+        ControllerType::Pids => resources.pids().is_some(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{create_temp_dir, set_fixture};
+    use oci_spec::runtime::{LinuxMemoryBuilder, LinuxResourcesBuilder};
+    use std::fs;
+
+    #[test]
+    fn test_kill_cgroup() -> Result<()> {
+        let tmp = create_temp_dir("test_kill_cgroup")?;
+        assert!(!kill_cgroup(&tmp)?);
+
+        let kill_path = set_fixture(&tmp, CGROUP_KILL, "")?;
+        assert!(kill_cgroup(&tmp)?);
+        assert_eq!(fs::read_to_string(kill_path)?, "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_controllers_to_apply() -> Result<()> {
+        let tmp = create_temp_dir("test_controllers_to_apply")?;
+        set_fixture(&tmp, CGROUP_CONTROLLERS, "cpu pids")?;
+        let resources = LinuxResourcesBuilder::default()
+            .memory(LinuxMemoryBuilder::default().limit(1024).build()?)
+            .build()?;
+        let mut controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+            missing_controllers: MissingControllerPolicy::Fail,
+        };
+        let controllers = [
+            ControllerType::Cpu,
+            ControllerType::Memory,
+            ControllerType::Pids,
+        ];
+
+        // ControllerType does not implement Debug
+        assert!(controllers_to_apply(&tmp, &controllers, &controller_opt)? == controllers);
+
+        controller_opt.missing_controllers = MissingControllerPolicy::Warn;
+        assert!(
+            controllers_to_apply(&tmp, &controllers, &controller_opt)?
+                == [ControllerType::Cpu, ControllerType::Pids]
+        );
+        Ok(())
+    }
+}
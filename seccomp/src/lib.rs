@@ -153,6 +153,11 @@ pub struct seccomp_notif_resp {
     pub flags: __u32,
 }
 
+// libseccomp is linked dynamically, a static musl build of youki has to
+// disable the seccomp feature
+#[cfg(target_env = "musl")]
+compile_error!("libseccomp can not be linked into a static musl binary");
+
 #[link(name = "seccomp")]
 extern "C" {
     /**
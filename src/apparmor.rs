@@ -4,7 +4,7 @@ use std::{
     path::Path,
 };
 
-use crate::utils;
+use crate::utils::secure_fs;
 
 const ENABLED_PARAMETER_PATH: &str = "/sys/module/apparmor/parameters/enabled";
 
@@ -32,6 +32,5 @@ pub fn apply_profile(profile: &str) -> Result<()> {
 }
 
 fn activate_profile(path: &Path, profile: &str) -> Result<()> {
-    utils::ensure_procfs(path)?;
-    utils::write_file(path, format!("exec {}", profile))
+    secure_fs::write_proc_file(path, format!("exec {}", profile))
 }
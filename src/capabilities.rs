@@ -130,7 +130,10 @@ pub fn reset_effective<S: Syscall + ?Sized>(syscall: &S) -> Result<()> {
     Ok(())
 }
 
-/// Drop any extra granted capabilities, and reset to defaults which are in oci specification
+/// Drop any extra granted capabilities, and reset to defaults which are in oci specification.
+/// Has to be called after the switch to the user of the container, as the
+/// ambient capabilities are what keeps the capabilities of a non-root user
+/// across execve.
 pub fn drop_privileges<S: Syscall + ?Sized>(cs: &LinuxCapabilities, syscall: &S) -> Result<()> {
     log::debug!("dropping bounding capabilities to {:?}", cs.bounding());
     if let Some(bounding) = cs.bounding() {
@@ -151,7 +154,7 @@ pub fn drop_privileges<S: Syscall + ?Sized>(cs: &LinuxCapabilities, syscall: &S)
 
     if let Some(ambient) = cs.ambient() {
         // check specifically for ambient, as those might not always be available
-        if let Err(e) = syscall.set_capability(CapSet::Ambient, &to_set(ambient)) {
+        if let Err(e) = syscall.set_capability(CapSet::Ambient, &ambient_set(cs, ambient)) {
             log::error!("failed to set ambient capabilities: {}", e);
         }
     }
@@ -159,6 +162,31 @@ pub fn drop_privileges<S: Syscall + ?Sized>(cs: &LinuxCapabilities, syscall: &S)
     Ok(())
 }
 
+/// Returns the ambient capabilities which can be raised. The kernel only
+/// allows to raise capabilities which are permitted and inheritable, the
+/// others are left out, so that they do not prevent raising the rest, like
+/// runc does.
+fn ambient_set(cs: &LinuxCapabilities, ambient: &Capabilities) -> CapsHashSet {
+    let mut raisable = to_set(ambient);
+    for (name, set) in [
+        ("permitted", cs.permitted()),
+        ("inheritable", cs.inheritable()),
+    ] {
+        if let Some(set) = set {
+            let set = to_set(set);
+            raisable.retain(|c| {
+                let keep = set.contains(c);
+                if !keep {
+                    log::warn!("ambient capability {} is not {}, it is not raised", c, name);
+                }
+                keep
+            });
+        }
+    }
+
+    raisable
+}
+
 #[cfg(test)]
 mod tests {
     use oci_spec::runtime::LinuxCapabilitiesBuilder;
@@ -662,4 +690,35 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_ambient_set() {
+        let cs = LinuxCapabilitiesBuilder::default()
+            .permitted(
+                vec![SpecCapability::NetBindService, SpecCapability::Kill]
+                    .into_iter()
+                    .collect::<Capabilities>(),
+            )
+            .inheritable(
+                vec![SpecCapability::NetBindService, SpecCapability::Chown]
+                    .into_iter()
+                    .collect::<Capabilities>(),
+            )
+            .build()
+            .unwrap();
+        let ambient: Capabilities = vec![
+            SpecCapability::NetBindService,
+            SpecCapability::Kill,
+            SpecCapability::Chown,
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            ambient_set(&cs, &ambient),
+            vec![CapsCapability::CAP_NET_BIND_SERVICE]
+                .into_iter()
+                .collect::<CapsHashSet>()
+        );
+    }
 }
@@ -0,0 +1,87 @@
+//! Contains functionality of checkpoint container command
+#[cfg(feature = "criu")]
+use crate::commands::load_container;
+#[cfg(feature = "criu")]
+use crate::container::CheckpointOptions;
+use std::path::PathBuf;
+
+#[cfg(not(feature = "criu"))]
+use anyhow::bail;
+#[cfg(feature = "criu")]
+use anyhow::Context;
+use anyhow::Result;
+use clap::Clap;
+
+/// Checkpoint a running container using CRIU
+#[derive(Clap, Debug)]
+pub struct Checkpoint {
+    /// Path for saving the checkpoint images
+    #[clap(long, default_value = "checkpoint")]
+    pub image_path: PathBuf,
+    /// Path for saving the work files and logs of CRIU (default: image path)
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+    /// Leave the processes running after checkpointing
+    #[clap(long)]
+    pub leave_running: bool,
+    /// Allow open tcp connections
+    #[clap(long)]
+    pub tcp_established: bool,
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+    /// Allow file locks
+    #[clap(long)]
+    pub file_locks: bool,
+    /// Dump the memory only, leaving the container running
+    #[clap(long)]
+    pub pre_dump: bool,
+    /// Path for the previous pre-dump images, relative to the image path
+    #[clap(long)]
+    pub parent_path: Option<PathBuf>,
+    /// Use lazy migration mechanism, the memory pages are fetched on demand
+    #[clap(long)]
+    pub lazy_pages: bool,
+    /// Address of the page server to send the memory pages to (ADDRESS:PORT)
+    #[clap(long)]
+    pub page_server: Option<String>,
+    /// Cgroups mode of CRIU
+    #[clap(long, possible_values = &["soft", "full", "strict", "ignore"])]
+    pub manage_cgroups_mode: Option<String>,
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+}
+
+impl Checkpoint {
+    #[cfg(feature = "criu")]
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        log::debug!("start checkpointing container {}", self.container_id);
+        let mut container = load_container(root_path, &self.container_id)?;
+        let opts = CheckpointOptions {
+            image_path: self.image_path.clone(),
+            work_path: self.work_path.clone(),
+            leave_running: self.leave_running,
+            tcp_established: self.tcp_established,
+            ext_unix_sk: self.ext_unix_sk,
+            file_locks: self.file_locks,
+            pre_dump: self.pre_dump,
+            parent_path: self.parent_path.clone(),
+            lazy_pages: self.lazy_pages,
+            page_server: self.page_server.clone(),
+            manage_cgroups_mode: self
+                .manage_cgroups_mode
+                .as_ref()
+                .map(|mode| mode.parse())
+                .transpose()?,
+        };
+
+        container
+            .checkpoint(&opts)
+            .with_context(|| format!("failed to checkpoint container {}", self.container_id))
+    }
+
+    #[cfg(not(feature = "criu"))]
+    pub fn exec(&self, _root_path: PathBuf) -> Result<()> {
+        bail!("youki has been built without the criu feature, checkpoints are not supported")
+    }
+}
@@ -0,0 +1,32 @@
+//! Contains functionality of the completion command, which generates shell
+//! completions from the definition of the command line interface
+use std::io;
+
+use anyhow::{bail, Result};
+use clap::{App, Clap};
+use clap_generate::{
+    generate,
+    generators::{Bash, Fish, Zsh},
+};
+
+/// Generate shell completions for youki
+#[derive(Clap, Debug)]
+pub struct Completion {
+    /// Shell to generate the completions for
+    #[clap(possible_values = &["bash", "zsh", "fish"])]
+    pub shell: String,
+}
+
+impl Completion {
+    pub fn exec(&self, mut app: App) -> Result<()> {
+        let mut stdout = io::stdout();
+        match self.shell.as_str() {
+            "bash" => generate::<Bash, _>(&mut app, "youki", &mut stdout),
+            "zsh" => generate::<Zsh, _>(&mut app, "youki", &mut stdout),
+            "fish" => generate::<Fish, _>(&mut app, "youki", &mut stdout),
+            shell => bail!("completions for {} are not supported", shell),
+        }
+
+        Ok(())
+    }
+}
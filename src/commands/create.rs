@@ -3,7 +3,11 @@ use anyhow::Result;
 use clap::Clap;
 use std::path::PathBuf;
 
-use crate::{container::builder::ContainerBuilder, syscall::syscall::create_syscall};
+use crate::{
+    container::builder::ContainerBuilder,
+    stdio_log::{self, StdioLog},
+    syscall::syscall::create_syscall,
+};
 
 /// Create a container
 #[derive(Clap, Debug)]
@@ -21,6 +25,18 @@ pub struct Create {
     /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
     #[clap(long, default_value = "0")]
     preserve_fds: i32,
+    /// Do not use pivot root to jail process inside rootfs
+    #[clap(long)]
+    no_pivot: bool,
+    /// Create the container even if youki does not implement the ociVersion of the spec
+    #[clap(long)]
+    ignore_oci_version: bool,
+    /// Capture stdout and stderr of the container into a log file in the CRI format
+    #[clap(long)]
+    stdio_log: Option<PathBuf>,
+    /// Size in bytes at which the stdio log is rotated (default: 10 MiB)
+    #[clap(long)]
+    stdio_log_max_size: Option<u64>,
     /// name of the container instance to be started
     #[clap(forbid_empty_values = true, required = true)]
     pub container_id: String,
@@ -41,6 +57,14 @@ impl Create {
             .with_preserved_fds(self.preserve_fds)
             .as_init(&self.bundle)
             .with_systemd(systemd_cgroup)
+            .with_no_pivot(self.no_pivot)
+            .with_ignore_oci_version(self.ignore_oci_version)
+            .with_stdio_log(self.stdio_log.as_ref().map(|path| {
+                StdioLog::new(path).with_max_size(
+                    self.stdio_log_max_size
+                        .unwrap_or(stdio_log::DEFAULT_MAX_SIZE),
+                )
+            }))
             .build()?;
 
         Ok(())
@@ -7,7 +7,7 @@ use std::path::PathBuf;
 #[derive(Clap, Debug)]
 pub struct Delete {
     #[clap(forbid_empty_values = true, required = true)]
-    container_id: String,
+    pub container_id: String,
     /// forces deletion of the container if it is still running (using SIGKILL)
     #[clap(short, long)]
     force: bool,
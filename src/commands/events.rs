@@ -1,16 +1,18 @@
 use clap::Clap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 
 use crate::commands::load_container;
+use crate::utils;
 
 /// Show resource statistics for the container
 #[derive(Clap, Debug)]
 pub struct Events {
-    /// Sets the stats collection interval in seconds (default: 5s)
-    #[clap(long, default_value = "5")]
-    pub interval: u32,
+    /// Sets the stats collection interval, e.g. 500ms or 5s (default: 5s)
+    #[clap(long, default_value = "5s", parse(try_from_str = utils::parse_duration))]
+    pub interval: Duration,
     /// Display the container stats only once
     #[clap(long)]
     pub stats: bool,
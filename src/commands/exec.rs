@@ -30,6 +30,18 @@ pub struct Exec {
     /// Detach from the container process
     #[clap(short, long)]
     pub detach: bool,
+    /// Run the process in an existing sub-cgroup of the container, given relative to its cgroup
+    #[clap(long)]
+    pub cgroup: Option<PathBuf>,
+    /// AppArmor profile of the process, instead of the one of the container
+    #[clap(long)]
+    pub apparmor: Option<String>,
+    /// SELinux label of the process, instead of the one of the container
+    #[clap(long)]
+    pub process_label: Option<String>,
+    /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
+    #[clap(long, default_value = "0")]
+    pub preserve_fds: i32,
     /// Identifier of the container
     #[clap(forbid_empty_values = true, required = true)]
     pub container_id: String,
@@ -45,12 +57,16 @@ impl Exec {
             .with_root_path(root_path)
             .with_console_socket(self.console_socket.as_ref())
             .with_pid_file(self.pid_file.as_ref())
+            .with_preserved_fds(self.preserve_fds)
             .as_tenant()
             .with_cwd(self.cwd.as_ref())
             .with_env(self.env.clone().into_iter().collect())
             .with_process(self.process.as_ref())
             .with_no_new_privs(self.no_new_privs)
             .with_process(self.process.as_ref())
+            .with_cgroup(self.cgroup.as_ref())
+            .with_apparmor_profile(self.apparmor.as_ref())
+            .with_process_label(self.process_label.as_ref())
             .with_container_args(self.command.clone())
             .build()
     }
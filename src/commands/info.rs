@@ -1,24 +1,38 @@
 //! Contains functions related to printing information about system running Youki
-use std::{collections::HashSet, fs, path::Path};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
 use clap::Clap;
-use procfs::{CpuInfo, Meminfo};
+use procfs::{process::Process, CpuInfo, Meminfo};
 
 use cgroups::{self, common::CgroupSetup, v2::controller_type::ControllerType};
 
+use crate::features::Features;
+
 /// Show information about the system
 #[derive(Clap, Debug)]
 pub struct Info {}
 
+// default directories of OCI hooks of higher level runtimes like podman and cri-o
+const HOOKS_DIRS: &[&str] = &[
+    "/usr/share/containers/oci/hooks.d",
+    "/etc/containers/oci/hooks.d",
+];
+
 impl Info {
-    pub fn exec(&self) -> Result<()> {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
         print_youki();
         print_kernel();
         print_os();
         print_hardware();
         print_cgroups();
         print_namespaces();
+        print_features();
+        print_paths(&root_path);
 
         Ok(())
     }
@@ -146,11 +160,25 @@ pub fn print_cgroups() {
                     let display = FeatureDisplay::with_status("device", "attached", "detached");
                     print_feature_status(&config, "CONFIG_CGROUP_BPF", display);
                 }
+
+                if let Some(controllers) = delegated_controllers(unified) {
+                    println!("{:<18}{}", "Delegated", controllers.join(" "));
+                }
             }
         }
     }
 }
 
+/// Returns the controllers which are available in the cgroup of youki, i.e.
+/// which have been delegated to the user running youki
+fn delegated_controllers(unified: &Path) -> Option<Vec<String>> {
+    let cgroups = Process::myself().ok()?.cgroups().ok()?;
+    let cgroup = cgroups.iter().find(|c| c.hierarchy == 0)?;
+    let cgroup_path = unified.join(cgroup.pathname.trim_start_matches('/'));
+    let controllers = cgroups::v2::util::get_available_controllers(&cgroup_path).ok()?;
+    Some(controllers.iter().map(|c| c.to_string()).collect())
+}
+
 fn read_kernel_config() -> Option<String> {
     let uname = nix::sys::utsname::uname();
     let kernel_config = Path::new("/boot").join(format!("config-{}", uname.release()));
@@ -182,6 +210,52 @@ pub fn print_namespaces() {
     }
 }
 
+/// Print the features of the system youki can use for containers
+pub fn print_features() {
+    let features = Features::detect();
+    let status = |enabled: bool| if enabled { "enabled" } else { "disabled" };
+    println!("Features");
+    println!("  {:<16}{}", "seccomp", status(features.seccomp));
+    println!("  {:<16}{}", "apparmor", status(features.apparmor));
+    println!("  {:<16}{}", "selinux", status(features.selinux));
+    let idmap = if features.idmap_mounts {
+        "supported"
+    } else {
+        "unsupported"
+    };
+    println!("  {:<16}{}", "idmapped mounts", idmap);
+    let criu = match &features.criu {
+        None => "disabled".to_owned(),
+        Some(criu) => match (&criu.binary, criu.version) {
+            (None, _) => "not installed".to_owned(),
+            (Some(_), None) => "unknown version".to_owned(),
+            (Some(_), Some(version)) if criu.kernel_supported => version.to_string(),
+            (Some(_), Some(version)) => format!("{} (kernel check failed)", version),
+        },
+    };
+    println!("  {:<16}{}", "criu", criu);
+    if features.user_namespace {
+        println!("  {:<16}in user namespace", "nested");
+    }
+    if features.read_only_proc_sys {
+        println!("  {:<16}read-only", "/proc/sys");
+    }
+}
+
+/// Print the directories youki and higher level runtimes use
+pub fn print_paths(root_path: &Path) {
+    println!("{:<18}{}", "State root", root_path.display());
+    println!("Hooks directories");
+    for dir in HOOKS_DIRS {
+        let status = if Path::new(dir).is_dir() {
+            "present"
+        } else {
+            "absent"
+        };
+        println!("  {:<34}{}", dir, status);
+    }
+}
+
 fn print_feature_status(config: &str, feature: &str, display: FeatureDisplay) {
     if let Some(status_flag) = find_parameter(config, feature) {
         let status = if status_flag == "y" {
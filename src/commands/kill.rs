@@ -4,23 +4,30 @@ use std::path::PathBuf;
 use anyhow::{Context, Result};
 use clap::Clap;
 
-use crate::{commands::load_container, signal::ToSignal};
+use crate::{commands::load_container, signal};
 
 /// Send the specified signal to the container
 #[derive(Clap, Debug)]
 pub struct Kill {
     #[clap(forbid_empty_values = true, required = true)]
-    container_id: String,
+    pub container_id: String,
+    /// Signal by name, number or relative to RTMIN/RTMAX, e.g. SIGTERM, TERM, 15 or RTMIN+3
+    #[clap(default_value = "SIGTERM")]
     signal: String,
+    /// Send the signal to all processes of the container, not only to its init process
+    #[clap(short, long)]
+    all: bool,
 }
 
 impl Kill {
     pub fn exec(&self, root_path: PathBuf) -> Result<()> {
         let mut container = load_container(root_path, &self.container_id)?;
-        let signal = self
-            .signal
-            .to_signal()
+        let signal = signal::parse_signal(&self.signal)
             .with_context(|| format!("signal {} is unknown", self.signal))?;
-        container.kill(signal)
+        if self.all {
+            container.kill_all(signal)
+        } else {
+            container.kill(signal)
+        }
     }
 }
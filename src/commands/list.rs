@@ -1,27 +1,110 @@
 //! Contains Functionality of list container command
+use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::io::Write;
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use anyhow::Result;
-use chrono::{DateTime, Local};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
 use clap::Clap;
+use serde::Serialize;
 use tabwriter::TabWriter;
 
-use crate::container::{state::State, Container};
+use crate::container::{state::State, Container, ContainerStatus};
 
 /// List created containers
 #[derive(Clap, Debug)]
-pub struct List {}
+pub struct List {
+    /// format to display containers: table or json (default: "table")
+    #[clap(short, long, default_value = "table")]
+    format: String,
+    /// only list containers matching the filter, either status=<status> or
+    /// annotation.<key>=<value>. Containers have to match all filters.
+    #[clap(long = "filter", number_of_values = 1)]
+    filters: Vec<Filter>,
+}
+
+/// Filter on the containers displayed by list
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Status(String),
+    Annotation(String, String),
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(filter: &str) -> Result<Self> {
+        let (key, value) = filter
+            .split_once('=')
+            .with_context(|| format!("invalid filter {}, expected KEY=VALUE", filter))?;
+        if key == "status" {
+            return Ok(Self::Status(value.to_lowercase()));
+        }
+        match key.strip_prefix("annotation.") {
+            Some(annotation) if !annotation.is_empty() => {
+                Ok(Self::Annotation(annotation.to_owned(), value.to_owned()))
+            }
+            _ => bail!(
+                "unknown filter {}, expected status or annotation.<key>",
+                key
+            ),
+        }
+    }
+}
+
+impl Filter {
+    fn matches(&self, container: &ContainerInfo) -> bool {
+        match self {
+            Self::Status(status) => container.status.to_string().to_lowercase() == *status,
+            Self::Annotation(key, value) => container.annotations.get(key) == Some(value),
+        }
+    }
+}
+
+/// Information about a container as displayed by list. The annotations are
+/// included, as they are used by CRI implementations to correlate containers.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ContainerInfo {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<i32>,
+    status: ContainerStatus,
+    bundle: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<DateTime<Utc>>,
+    owner: String,
+    annotations: HashMap<String, String>,
+}
+
+impl From<&Container> for ContainerInfo {
+    fn from(container: &Container) -> Self {
+        Self {
+            id: container.id().to_owned(),
+            pid: container.pid().map(|pid| pid.as_raw()),
+            status: container.status(),
+            bundle: container.bundle().clone(),
+            created: container.created(),
+            owner: container
+                .creator()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            annotations: container.annotations().cloned().unwrap_or_default(),
+        }
+    }
+}
 
 impl List {
     /// lists all existing containers
     pub fn exec(&self, root_path: PathBuf) -> Result<()> {
         let root_path = fs::canonicalize(root_path)?;
-        let mut content = String::new();
+        let mut containers = Vec::new();
         // all containers' data is stored in their respective dir in root directory
-        // so we iterate through each and print the various info
+        // so we iterate through each and collect the various info
         for container_dir in fs::read_dir(root_path)? {
             let container_dir = container_dir?.path();
             let state_file = State::file_path(&container_dir);
@@ -30,37 +113,116 @@ impl List {
             }
 
             let container = Container::load(container_dir)?;
-            let pid = if let Some(pid) = container.pid() {
-                pid.to_string()
-            } else {
-                "".to_owned()
-            };
-
-            let user_name = container.creator().unwrap_or_default();
-
-            let created = if let Some(utc) = container.created() {
-                let local: DateTime<Local> = DateTime::from(utc);
-                local.to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
-            } else {
-                "".to_owned()
-            };
-
-            content.push_str(&format!(
-                "{}\t{}\t{}\t{}\t{}\t{}\n",
-                container.id(),
-                pid,
-                container.status(),
-                container.bundle().to_string_lossy(),
-                created,
-                user_name.to_string_lossy()
-            ));
+            let info = ContainerInfo::from(&container);
+            if self.filters.iter().all(|filter| filter.matches(&info)) {
+                containers.push(info);
+            }
+        }
+
+        match self.format.as_str() {
+            "table" => print_table(&containers),
+            "json" => {
+                println!("{}", serde_json::to_string_pretty(&containers)?);
+                Ok(())
+            }
+            _ => bail!("unknown format: {}", self.format),
         }
+    }
+}
+
+fn print_table(containers: &[ContainerInfo]) -> Result<()> {
+    let mut tab_writer = TabWriter::new(io::stdout());
+    writeln!(&mut tab_writer, "ID\tPID\tSTATUS\tBUNDLE\tCREATED\tCREATOR")?;
+    for container in containers {
+        let pid = if let Some(pid) = container.pid {
+            pid.to_string()
+        } else {
+            "".to_owned()
+        };
+
+        let created = if let Some(utc) = container.created {
+            let local: DateTime<Local> = DateTime::from(utc);
+            local.to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+        } else {
+            "".to_owned()
+        };
+
+        writeln!(
+            &mut tab_writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            container.id,
+            pid,
+            container.status,
+            container.bundle.to_string_lossy(),
+            created,
+            container.owner
+        )?;
+    }
+    tab_writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_container_info_includes_annotations() -> Result<()> {
+        let mut container = Container::default();
+        container.state.id = "test".to_owned();
+        container.set_annotations(Some(
+            vec![("io.kubernetes.pod.name".to_owned(), "nginx".to_owned())]
+                .into_iter()
+                .collect(),
+        ));
+
+        let info = serde_json::to_value(ContainerInfo::from(&container))?;
+        assert_eq!(info["id"], json!("test"));
+        assert_eq!(
+            info["annotations"],
+            json!({ "io.kubernetes.pod.name": "nginx" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter() -> Result<()> {
+        let mut container = Container::default();
+        container
+            .set_status(ContainerStatus::Running)
+            .set_annotations(Some(
+                vec![("io.kubernetes.pod.name".to_owned(), "foo".to_owned())]
+                    .into_iter()
+                    .collect(),
+            ));
+        let info = ContainerInfo::from(&container);
 
-        let mut tab_writer = TabWriter::new(io::stdout());
-        writeln!(&mut tab_writer, "ID\tPID\tSTATUS\tBUNDLE\tCREATED\tCREATOR")?;
-        write!(&mut tab_writer, "{}", content)?;
-        tab_writer.flush()?;
+        let list = List::try_parse_from(&[
+            "list",
+            "--filter",
+            "status=running",
+            "--filter",
+            "annotation.io.kubernetes.pod.name=foo",
+        ])?;
+        assert_eq!(
+            list.filters,
+            vec![
+                Filter::Status("running".to_owned()),
+                Filter::Annotation("io.kubernetes.pod.name".to_owned(), "foo".to_owned())
+            ]
+        );
+        assert!(list.filters.iter().all(|filter| filter.matches(&info)));
 
+        assert!(!"status=paused".parse::<Filter>()?.matches(&info));
+        assert!(!"annotation.io.kubernetes.pod.name=bar"
+            .parse::<Filter>()?
+            .matches(&info));
+        assert!(!"annotation.missing=foo".parse::<Filter>()?.matches(&info));
+        assert!("status".parse::<Filter>().is_err());
+        assert!("name=foo".parse::<Filter>().is_err());
+        assert!("annotation.=foo".parse::<Filter>().is_err());
         Ok(())
     }
 }
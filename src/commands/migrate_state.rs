@@ -0,0 +1,212 @@
+//! Moves the state of all containers from one root directory to another, e.g.
+//! when the root directory of youki is changed on a node with running
+//! containers. The state is rewritten in the current format on the way.
+use std::fs;
+use std::os::unix::fs::{symlink, FileTypeExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Clap;
+use nix::errno::Errno;
+
+use crate::container::{Container, ContainerStatus, State};
+use crate::error::YoukiError;
+use crate::utils;
+
+/// Move the state of all containers to a new root directory
+#[derive(Clap, Debug)]
+pub struct MigrateState {
+    /// root directory the state is currently stored in
+    #[clap(long)]
+    from: PathBuf,
+    /// root directory the state is moved to, created if it does not exist
+    #[clap(long)]
+    to: PathBuf,
+}
+
+impl MigrateState {
+    pub fn exec(&self) -> Result<()> {
+        let from = fs::canonicalize(&self.from)
+            .with_context(|| format!("failed to canonicalize {}", self.from.display()))?;
+        utils::create_dir_all(&self.to)?;
+        let to = fs::canonicalize(&self.to)
+            .with_context(|| format!("failed to canonicalize {}", self.to.display()))?;
+        if from == to {
+            bail!(
+                "{} and {} are the same directory",
+                from.display(),
+                to.display()
+            );
+        }
+
+        for container_root in container_roots(&from, &to)? {
+            let id = container_root
+                .file_name()
+                .and_then(|name| name.to_str())
+                .with_context(|| format!("invalid container dir {}", container_root.display()))?
+                .to_owned();
+            migrate_container(&container_root, &to.join(&id))
+                .with_context(|| format!("failed to migrate container {}", id))?;
+            println!("{}", id);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the state directories of all containers in the root directory,
+/// ignoring the target if it is located inside of it
+fn container_roots(from: &Path, to: &Path) -> Result<Vec<PathBuf>> {
+    let mut roots = Vec::new();
+    for entry in fs::read_dir(from).with_context(|| format!("failed to read {}", from.display()))? {
+        let path = entry?.path();
+        if path.is_dir() && path != to && State::file_path(&path).exists() {
+            roots.push(path);
+        }
+    }
+
+    roots.sort();
+    Ok(roots)
+}
+
+fn migrate_container(from: &Path, to: &Path) -> Result<()> {
+    if to.exists() {
+        let id = to.file_name().unwrap_or_default().to_string_lossy();
+        bail!(YoukiError::AlreadyExists(id.into_owned()));
+    }
+
+    let mut container = Container::load(from.to_path_buf())?;
+    let _lock = container.lock().context("failed to lock container state")?;
+    if container.systemd().is_none() {
+        // older states did not record the cgroup manager, it is derived from
+        // the format of the cgroups path instead
+        let spec = container.spec()?;
+        let use_systemd = spec
+            .linux()
+            .as_ref()
+            .and_then(|linux| linux.cgroups_path().as_ref())
+            .map_or(false, |path| is_systemd_cgroups_path(path));
+        container.set_systemd(use_systemd);
+    }
+
+    match fs::rename(from, to) {
+        Ok(()) => {}
+        Err(e) if e.raw_os_error() == Some(Errno::EXDEV as i32) => {
+            // the exec fifo of a created container is held open by its init
+            // process, it cannot be copied to another filesystem
+            if container.status() == ContainerStatus::Created {
+                bail!(
+                    "{} is on another filesystem, start the container before migrating it",
+                    to.display()
+                );
+            }
+            copy_dir(from, to)?;
+            fs::remove_dir_all(from)
+                .with_context(|| format!("failed to remove {}", from.display()))?;
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to move {} to {}", from.display(), to.display()))
+        }
+    }
+
+    container.root = to.to_path_buf();
+    container.save()
+}
+
+/// Copies the container state directory with its subdirectories, e.g. the
+/// images of a checkpoint. Sockets cannot be copied and are skipped.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir(to).with_context(|| format!("failed to create {}", to.display()))?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            symlink(fs::read_link(entry.path())?, &target)?;
+        } else if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("failed to copy {}", entry.path().display()))?;
+        } else if !file_type.is_socket() {
+            bail!(
+                "unexpected file {} in container state",
+                entry.path().display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks for the slice:prefix:name format of systemd cgroups paths
+fn is_systemd_cgroups_path(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    !path.starts_with('/') && path.split(':').count() == 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_is_systemd_cgroups_path() {
+        assert!(is_systemd_cgroups_path(Path::new(
+            "system.slice:youki:test"
+        )));
+        assert!(!is_systemd_cgroups_path(Path::new("/youki/test")));
+        assert!(!is_systemd_cgroups_path(Path::new("test")));
+    }
+
+    #[test]
+    fn test_migrate_state() -> Result<()> {
+        let tmp = create_temp_dir("test_migrate_state")?;
+        let from = tmp.join("old");
+        let to = from.join("youki");
+        let container_root = from.join("test");
+        fs::create_dir_all(&container_root)?;
+        fs::write(
+            State::file_path(&container_root),
+            r#"{"ociVersion": "v1.0.2", "id": "test", "status": "stopped", "bundle": "/bundle"}"#,
+        )?;
+        fs::write(
+            container_root.join("config.json"),
+            serde_json::to_string(&oci_spec::runtime::Spec::default())?,
+        )?;
+
+        MigrateState {
+            from: from.clone(),
+            to: to.clone(),
+        }
+        .exec()?;
+
+        assert!(!container_root.exists());
+        let state = State::load(&to.join("test"))?;
+        assert_eq!(state.id, "test");
+        assert_eq!(state.use_systemd, Some(false));
+        assert!(to.join("test").join("config.json").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_dir() -> Result<()> {
+        let tmp = create_temp_dir("test_copy_dir")?;
+        let from = tmp.join("from");
+        fs::create_dir_all(from.join("checkpoint/images"))?;
+        fs::write(from.join("state.json"), "state")?;
+        fs::write(from.join("checkpoint/images/inventory.img"), "image")?;
+        symlink("state.json", from.join("link"))?;
+
+        let to = tmp.join("to");
+        copy_dir(&from, &to)?;
+        assert_eq!(fs::read_to_string(to.join("state.json"))?, "state");
+        assert_eq!(
+            fs::read_to_string(to.join("checkpoint/images/inventory.img"))?,
+            "image"
+        );
+        assert_eq!(fs::read_link(to.join("link"))?, Path::new("state.json"));
+        Ok(())
+    }
+}
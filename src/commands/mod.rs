@@ -2,7 +2,10 @@ use anyhow::{bail, Context, Result};
 use std::{fs, path::Path};
 
 use crate::container::Container;
+use crate::error::YoukiError;
 
+pub mod checkpoint;
+pub mod completion;
 pub mod create;
 pub mod delete;
 pub mod events;
@@ -10,13 +13,18 @@ pub mod exec;
 pub mod info;
 pub mod kill;
 pub mod list;
+pub mod migrate_state;
 pub mod pause;
 pub mod ps;
+pub mod restore;
 pub mod resume;
 pub mod run;
 pub mod spec_json;
 pub mod start;
 pub mod state;
+pub mod update;
+pub mod validate;
+pub mod wait;
 
 fn load_container<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<Container> {
     // resolves relative paths, symbolic links etc. and get complete path
@@ -25,7 +33,7 @@ fn load_container<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<Co
     // the state of the container is stored in a directory named after the container id
     let container_root = root_path.join(container_id);
     if !container_root.exists() {
-        bail!("{} does not exist.", container_id)
+        bail!(YoukiError::NotFound(container_id.to_owned()))
     }
 
     Container::load(container_root)
@@ -10,6 +10,10 @@ pub struct Ps {
     /// format to display processes: table or json (default: "table")
     #[clap(short, long, default_value = "table")]
     format: String,
+    /// list the details of the processes in the json format instead of only
+    /// their pids
+    #[clap(long)]
+    details: bool,
     #[clap(forbid_empty_values = true, required = true)]
     pub container_id: String,
     /// options will be passed to the ps utility
@@ -38,6 +42,10 @@ impl Ps {
             let systemd_cgroup = container
                 .systemd()
                 .context("could not determine cgroup manager")?;
+            if self.format == "json" && self.details {
+                println!("{}", serde_json::to_string(&container.processes()?)?);
+                return Ok(());
+            }
             let cmanager = cgroups::common::create_cgroup_manager(cgroups_path, systemd_cgroup)?;
             let pids: Vec<i32> = cmanager
                 .get_all_pids()?
@@ -0,0 +1,89 @@
+//! Contains functionality of restore container command
+#[cfg(not(feature = "criu"))]
+use anyhow::bail;
+#[cfg(feature = "criu")]
+use anyhow::Context;
+use anyhow::Result;
+use clap::Clap;
+use std::path::PathBuf;
+
+#[cfg(feature = "criu")]
+use crate::{
+    container::{builder::ContainerBuilder, RestoreOptions},
+    syscall::syscall::create_syscall,
+};
+
+/// Restore a container from a previous checkpoint
+#[derive(Clap, Debug)]
+pub struct Restore {
+    /// Path to the checkpoint images
+    #[clap(long, default_value = "checkpoint")]
+    pub image_path: PathBuf,
+    /// Path for saving the work files and logs of CRIU (default: image path)
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+    /// Path to the bundle directory of the restored container, containing config.json and root filesystem
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// File to write pid of the restored container
+    #[clap(short, long)]
+    pub pid_file: Option<PathBuf>,
+    /// Allow open tcp connections
+    #[clap(long)]
+    pub tcp_established: bool,
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+    /// Allow file locks
+    #[clap(long)]
+    pub file_locks: bool,
+    /// Fetch the memory pages lazily from the page server
+    #[clap(long)]
+    pub lazy_pages: bool,
+    /// Cgroups mode of CRIU, soft, full and strict apply the cgroup properties of the checkpoint
+    #[clap(long, possible_values = &["soft", "full", "strict", "ignore"])]
+    pub manage_cgroups_mode: Option<String>,
+    /// Restore the container even if youki does not implement the ociVersion of the spec
+    #[clap(long)]
+    pub ignore_oci_version: bool,
+    /// Name of the restored container instance, may differ from the checkpointed one
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+}
+
+impl Restore {
+    #[cfg(feature = "criu")]
+    pub fn exec(&self, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
+        log::debug!("start restoring container {}", self.container_id);
+        let opts = RestoreOptions {
+            image_path: self.image_path.clone(),
+            work_path: self.work_path.clone(),
+            tcp_established: self.tcp_established,
+            ext_unix_sk: self.ext_unix_sk,
+            file_locks: self.file_locks,
+            lazy_pages: self.lazy_pages,
+            manage_cgroups_mode: self
+                .manage_cgroups_mode
+                .as_ref()
+                .map(|mode| mode.parse())
+                .transpose()?,
+        };
+
+        let syscall = create_syscall();
+        ContainerBuilder::new(self.container_id.clone(), syscall.as_ref())
+            .with_pid_file(self.pid_file.as_ref())
+            .with_root_path(root_path)
+            .as_init(&self.bundle)
+            .with_systemd(systemd_cgroup)
+            .with_ignore_oci_version(self.ignore_oci_version)
+            .restore(&opts)
+            .with_context(|| format!("failed to restore container {}", self.container_id))?;
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "criu"))]
+    pub fn exec(&self, _root_path: PathBuf, _systemd_cgroup: bool) -> Result<()> {
+        bail!("youki has been built without the criu feature, checkpoints cannot be restored")
+    }
+}
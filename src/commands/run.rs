@@ -1,11 +1,16 @@
 use std::path::PathBuf;
 
-use crate::container::builder::ContainerBuilder;
+use crate::container::{builder::ContainerBuilder, Container, ContainerStatus};
 use crate::syscall::syscall::create_syscall;
-use anyhow::{Context, Result};
+use crate::tty::ForegroundConsole;
+use crate::utils;
+use anyhow::{bail, Context, Result};
 use clap::Clap;
+use nix::sys::wait::waitpid;
+use nix::unistd::{self, Pid};
+use oci_spec::runtime::Spec;
 
-/// Create a container and immediately start it
+/// Create a container, start it and wait in the foreground until it exits
 #[derive(Clap, Debug)]
 pub struct Run {
     /// File to write pid of the container created
@@ -21,25 +26,128 @@ pub struct Run {
     /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
     #[clap(long, default_value = "0")]
     preserve_fds: i32,
+    /// Do not use pivot root to jail process inside rootfs
+    #[clap(long)]
+    no_pivot: bool,
+    /// Create the container even if youki does not implement the ociVersion of the spec
+    #[clap(long)]
+    ignore_oci_version: bool,
+    /// Keep the state of the container after it exits, e.g. to inspect it
+    /// before deleting it with youki delete
+    #[clap(long)]
+    keep: bool,
     /// name of the container instance to be started
     #[clap(forbid_empty_values = true, required = true)]
     pub container_id: String,
 }
 
 impl Run {
-    pub fn exec(&self, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
+    /// Returns the exit code of the container, with which youki has to exit
+    pub fn exec(&self, root_path: PathBuf, systemd_cgroup: bool) -> Result<i32> {
+        // The container init process is forked by the intermediate process,
+        // which exits as soon as the container has been created. Becoming a
+        // subreaper makes sure that the init process gets re-parented to us
+        // instead of the host init, so that we are able to wait for it.
+        prctl::set_child_subreaper(true)
+            .map_err(nix::Error::from_i32)
+            .context("failed to become a child subreaper")?;
+
+        // If the container requests a terminal, but no console socket has been
+        // passed, the terminal of youki is connected to the container.
+        let mut console = if self.console_socket.is_none() && self.requests_terminal()? {
+            Some(ForegroundConsole::new(&self.container_id)?)
+        } else {
+            None
+        };
+        let console_socket = match &console {
+            Some(console) => Some(console.socket_path()),
+            None => self.console_socket.clone(),
+        };
+
         let syscall = create_syscall();
         let mut container = ContainerBuilder::new(self.container_id.clone(), syscall.as_ref())
             .with_pid_file(self.pid_file.as_ref())
-            .with_console_socket(self.console_socket.as_ref())
+            .with_console_socket(console_socket)
             .with_root_path(root_path)
             .with_preserved_fds(self.preserve_fds)
             .as_init(&self.bundle)
             .with_systemd(systemd_cgroup)
+            .with_no_pivot(self.no_pivot)
+            .with_ignore_oci_version(self.ignore_oci_version)
             .build()?;
 
+        // the container is deleted even if it could not be started, otherwise
+        // its init process would be left waiting for the start
+        let result = self.start_and_wait(&mut container, &mut console);
+        if !self.keep {
+            if let Err(e) = container.delete(true) {
+                let e = e.context(format!("failed to delete container {}", self.container_id));
+                if result.is_ok() {
+                    return Err(e);
+                }
+                log::warn!("{:#}", e);
+            }
+        }
+
+        result
+    }
+
+    fn start_and_wait(
+        &self,
+        container: &mut Container,
+        console: &mut Option<ForegroundConsole>,
+    ) -> Result<i32> {
+        if let Some(console) = console {
+            console
+                .attach()
+                .context("failed to attach to container terminal")?;
+        }
+
         container
             .start()
-            .with_context(|| format!("failed to start container {}", self.container_id))
+            .with_context(|| format!("failed to start container {}", self.container_id))?;
+
+        let pid = container
+            .pid()
+            .with_context(|| format!("container {} has no init process", self.container_id))?;
+        let exit_code = wait_for_exit(pid)
+            .with_context(|| format!("failed to wait for container {}", self.container_id))?;
+        log::debug!("container {} exited with {}", self.container_id, exit_code);
+        container
+            .transition(ContainerStatus::Stopped)?
+            .set_exit_code(exit_code)
+            .save()
+            .with_context(|| format!("failed to save state of container {}", self.container_id))?;
+        if let Some(console) = console {
+            console.detach()?;
+        }
+
+        Ok(exit_code)
+    }
+
+    fn requests_terminal(&self) -> Result<bool> {
+        let spec = Spec::load(self.bundle.join("config.json"))?;
+        let terminal = spec
+            .process()
+            .as_ref()
+            .and_then(|p| p.terminal())
+            .unwrap_or(false);
+
+        Ok(terminal && unistd::isatty(0).unwrap_or(false))
+    }
+}
+
+/// Blocks until the process with the given pid exits and returns its exit code.
+fn wait_for_exit(pid: Pid) -> Result<i32> {
+    loop {
+        match waitpid(pid, None) {
+            Ok(status) => {
+                if let Some(code) = utils::exit_code(status) {
+                    return Ok(code);
+                }
+            }
+            Err(nix::Error::EINTR) => continue,
+            Err(e) => bail!("waitpid on {} failed: {}", pid, e),
+        }
     }
 }
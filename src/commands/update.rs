@@ -0,0 +1,163 @@
+//! Contains functionality of update container command
+use crate::commands::load_container;
+use crate::utils;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Clap;
+use oci_spec::runtime::{
+    LinuxCpuBuilder, LinuxMemoryBuilder, LinuxPidsBuilder, LinuxResources, LinuxResourcesBuilder,
+};
+
+/// Update the resource limits of a container
+#[derive(Clap, Debug)]
+pub struct Update {
+    /// Path to a JSON file with the resource limits to update, "-" reads them from stdin
+    #[clap(short, long)]
+    pub resources: Option<PathBuf>,
+    /// Memory limit in bytes, units like 512m are supported, -1 means unlimited
+    #[clap(long, conflicts_with = "resources", allow_hyphen_values = true, parse(try_from_str = utils::parse_size))]
+    pub memory: Option<i64>,
+    /// CPU CFS quota in microseconds, -1 means unlimited
+    #[clap(long, conflicts_with = "resources", allow_hyphen_values = true)]
+    pub cpu_quota: Option<i64>,
+    /// CPU CFS period in microseconds
+    #[clap(long, conflicts_with = "resources")]
+    pub cpu_period: Option<u64>,
+    /// CPUs the container may use, e.g. 0-3 or 1,3
+    #[clap(long, conflicts_with = "resources")]
+    pub cpuset_cpus: Option<String>,
+    /// Maximum number of processes, -1 means unlimited
+    #[clap(long, conflicts_with = "resources", allow_hyphen_values = true)]
+    pub pids_limit: Option<i64>,
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+}
+
+impl Update {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        log::debug!("start updating container {}", self.container_id);
+        let resources = match &self.resources {
+            Some(path) => read_resources(path)?,
+            None => self.flag_resources()?,
+        };
+
+        let mut container = load_container(root_path, &self.container_id)?;
+        container
+            .update(&resources)
+            .with_context(|| format!("failed to update container {}", self.container_id))
+    }
+
+    fn flag_resources(&self) -> Result<LinuxResources> {
+        let mut builder = LinuxResourcesBuilder::default();
+        let mut any = false;
+
+        if let Some(memory) = self.memory {
+            builder = builder.memory(LinuxMemoryBuilder::default().limit(memory).build()?);
+            any = true;
+        }
+
+        if self.cpu_quota.is_some() || self.cpu_period.is_some() || self.cpuset_cpus.is_some() {
+            let mut cpu = LinuxCpuBuilder::default();
+            if let Some(quota) = self.cpu_quota {
+                cpu = cpu.quota(quota);
+            }
+            if let Some(period) = self.cpu_period {
+                cpu = cpu.period(period);
+            }
+            if let Some(cpus) = &self.cpuset_cpus {
+                cpu = cpu.cpus(cpus.clone());
+            }
+            builder = builder.cpu(cpu.build()?);
+            any = true;
+        }
+
+        if let Some(pids_limit) = self.pids_limit {
+            builder = builder.pids(LinuxPidsBuilder::default().limit(pids_limit).build()?);
+            any = true;
+        }
+
+        if !any {
+            bail!("no resources to update, use --resources or one of the resource flags");
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+fn read_resources(path: &Path) -> Result<LinuxResources> {
+    let content = if path == Path::new("-") {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .context("failed to read resources from stdin")?;
+        content
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("failed to read resources from {}", path.display()))?
+    };
+
+    serde_json::from_str(&content).context("failed to parse resources")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_read_resources() -> Result<()> {
+        let tmp = create_temp_dir("test_read_resources")?;
+        let path = tmp.join("resources.json");
+        fs::write(
+            &path,
+            r#"{"memory": {"limit": 1048576}, "pids": {"limit": 10}}"#,
+        )?;
+
+        let resources = read_resources(&path)?;
+        assert_eq!(
+            resources.memory().as_ref().and_then(|m| m.limit()),
+            Some(1048576)
+        );
+        assert_eq!(resources.pids().as_ref().map(|p| p.limit()), Some(10));
+        Ok(())
+    }
+
+    #[test]
+    fn test_flag_resources() -> Result<()> {
+        let update = Update::try_parse_from(&[
+            "update",
+            "--memory",
+            "512m",
+            "--cpu-quota",
+            "-1",
+            "--cpuset-cpus",
+            "0-3",
+            "container",
+        ])?;
+
+        let resources = update.flag_resources()?;
+        assert_eq!(
+            resources.memory().as_ref().and_then(|m| m.limit()),
+            Some(512 * 1024 * 1024)
+        );
+        let cpu = resources.cpu().as_ref().expect("cpu resources");
+        assert_eq!(cpu.quota(), Some(-1));
+        assert_eq!(cpu.period(), None);
+        assert_eq!(cpu.cpus().as_deref(), Some("0-3"));
+        assert!(resources.pids().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_flags_conflict_with_resources() {
+        assert!(
+            Update::try_parse_from(&["update", "-r", "-", "--pids-limit", "10", "container"])
+                .is_err()
+        );
+        let update = Update::try_parse_from(&["update", "container"]).unwrap();
+        assert!(update.flag_resources().is_err());
+    }
+}
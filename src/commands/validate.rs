@@ -0,0 +1,27 @@
+//! Validates the runtime spec of a bundle without creating a container
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Clap;
+
+use crate::validation;
+
+/// Validate the config.json of a bundle and report all problems found in it
+#[derive(Clap, Debug)]
+pub struct Validate {
+    /// path to the bundle directory, containing config.json and root filesystem
+    #[clap(default_value = ".")]
+    pub bundle: PathBuf,
+    /// Only warn about an ociVersion youki does not implement
+    #[clap(long)]
+    pub ignore_oci_version: bool,
+}
+
+impl Validate {
+    pub fn exec(&self) -> Result<()> {
+        validation::validate_bundle(&self.bundle, self.ignore_oci_version)?;
+        println!("{} is valid", self.bundle.display());
+        Ok(())
+    }
+}
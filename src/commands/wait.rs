@@ -0,0 +1,55 @@
+//! Contains functionality of wait container command
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use clap::Clap;
+use nix::unistd::Pid;
+
+use crate::{commands::load_container, container, utils};
+
+/// Wait until the container or one of its processes exits and print the exit code
+#[derive(Clap, Debug)]
+pub struct Wait {
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+    /// Wait for a process of the container, e.g. started with youki exec,
+    /// instead of its init process
+    #[clap(long)]
+    pid: Option<i32>,
+    /// Give up after the timeout, e.g. 30s (default: wait forever)
+    #[clap(long, parse(try_from_str = utils::parse_duration))]
+    timeout: Option<Duration>,
+}
+
+impl Wait {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let mut container = load_container(root_path, &self.container_id)?;
+        let exit_code = match self.pid {
+            Some(pid) => {
+                // an exited process is no longer part of the cgroup, its exit
+                // code can still be read while it is a zombie
+                let pid = Pid::from_raw(pid);
+                if !container::is_zombie(pid)
+                    && !container.processes()?.iter().any(|p| p.pid == pid.as_raw())
+                {
+                    bail!(
+                        "process {} is not part of container {}",
+                        pid,
+                        self.container_id
+                    );
+                }
+                container::wait_process(pid, self.timeout)?
+            }
+            None => container.wait(self.timeout)?,
+        };
+
+        match exit_code {
+            Some(exit_code) => {
+                println!("{}", exit_code);
+                Ok(())
+            }
+            None => bail!("timed out waiting for container {}", self.container_id),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Async variants of the container operations for tokio, so that shims and
+//! agents can drive many containers concurrently. Creating, starting and
+//! killing a container fork processes and wait on them, so these operations
+//! run on the blocking thread pool of tokio. Waiting for the exit of the
+//! container does not occupy a thread, the pidfd of the init process is
+//! polled by the runtime instead.
+//!
+//! Waiting requires that the init process of the container is a child of the
+//! caller, which is only the case if the caller is a child subreaper
+//! (prctl(PR_SET_CHILD_SUBREAPER)), as the init process is re-parented when
+//! the intermediate process exits.
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{self, Pid};
+use tokio::io::unix::AsyncFd;
+use tokio::task;
+
+use super::container_wait::pidfd_open;
+use super::{Container, ContainerStatus};
+use crate::signal::ContainerSignal;
+use crate::utils;
+
+/// Creates a container on the blocking thread pool. The builder is passed as
+/// closure, as it borrows the syscall interface which cannot be sent to
+/// another thread.
+///
+/// # Example
+///
+/// ```no_run
+/// use youki::container::async_api;
+/// use youki::container::builder::ContainerBuilder;
+/// use youki::syscall::syscall::create_syscall;
+///
+/// # async fn create() -> anyhow::Result<()> {
+/// let container = async_api::create(|| {
+///     ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
+///         .as_init("/var/run/docker/bundle")
+///         .build()
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn create<F>(build: F) -> Result<Container>
+where
+    F: FnOnce() -> Result<Container> + Send + 'static,
+{
+    task::spawn_blocking(build)
+        .await
+        .context("failed to join container creation")?
+}
+
+/// Starts a previously created container on the blocking thread pool
+pub async fn start(mut container: Container) -> Result<Container> {
+    task::spawn_blocking(move || {
+        container.start()?;
+        Ok(container)
+    })
+    .await
+    .context("failed to join container start")?
+}
+
+/// Sends the signal to the container init process on the blocking thread pool
+pub async fn kill<S>(mut container: Container, signal: S) -> Result<Container>
+where
+    S: Into<ContainerSignal> + Send + 'static,
+{
+    task::spawn_blocking(move || {
+        container.kill(signal)?;
+        Ok(container)
+    })
+    .await
+    .context("failed to join container kill")?
+}
+
+/// Waits until the init process of the container exits, stores its exit code
+/// in the container state and returns it
+pub async fn wait(container: &mut Container) -> Result<i32> {
+    let pid = container
+        .pid()
+        .with_context(|| format!("container {} has no init process", container.id()))?;
+    let pidfd = PidFd::open(pid)?;
+    let exit_code = loop {
+        // the pidfd becomes readable once the process has exited
+        let mut guard = pidfd
+            .0
+            .readable()
+            .await
+            .with_context(|| format!("failed to poll pidfd of {}", pid))?;
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => guard.clear_ready(),
+            Ok(status) => {
+                if let Some(code) = utils::exit_code(status) {
+                    break code;
+                }
+            }
+            Err(Errno::EINTR) => continue,
+            Err(Errno::ECHILD) => bail!(
+                "init process {} of container {} is not a child, \
+                waiting requires to be a child subreaper",
+                pid,
+                container.id()
+            ),
+            Err(e) => bail!("waitpid on {} failed: {}", pid, e),
+        }
+    };
+
+    // a SIGKILL sent by kill may have stopped the container already
+    if container.status() != ContainerStatus::Stopped {
+        container.transition(ContainerStatus::Stopped)?;
+    }
+    container
+        .set_exit_code(exit_code)
+        .save()
+        .with_context(|| format!("failed to save state of container {}", container.id()))?;
+
+    Ok(exit_code)
+}
+
+struct PidFd(AsyncFd<RawFd>);
+
+impl PidFd {
+    fn open(pid: Pid) -> Result<Self> {
+        let fd = match pidfd_open(pid) {
+            Ok(fd) => fd,
+            Err(Errno::ENOSYS) => bail!("waiting for a container requires at least Linux 5.3"),
+            Err(e) => bail!("failed to open pidfd of {}: {}", pid, e),
+        };
+
+        match AsyncFd::new(fd) {
+            Ok(fd) => Ok(Self(fd)),
+            Err(e) => {
+                let _ = unistd::close(fd);
+                Err(e).context("failed to register pidfd")
+            }
+        }
+    }
+}
+
+impl Drop for PidFd {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.0.as_raw_fd());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use std::path::Path;
+    use std::process::Command;
+
+    #[test]
+    fn test_wait() -> Result<()> {
+        let tmp = create_temp_dir("test_async_wait")?;
+        let child = Command::new("sh").args(&["-c", "exit 3"]).spawn()?;
+        let mut container = Container::new(
+            "test",
+            ContainerStatus::Running,
+            Some(child.id() as i32),
+            Path::new("/bundle"),
+            &tmp,
+        )?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()?;
+        let exit_code = runtime.block_on(wait(&mut container))?;
+        assert_eq!(exit_code, 3);
+        assert_eq!(container.status(), ContainerStatus::Stopped);
+        assert_eq!(container.exit_code(), Some(3));
+        Ok(())
+    }
+}
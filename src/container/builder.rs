@@ -1,7 +1,11 @@
 use crate::syscall::Syscall;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use super::{init_builder::InitContainerBuilder, tenant_builder::TenantContainerBuilder};
+use super::{
+    init_builder::InitContainerBuilder, tenant_builder::TenantContainerBuilder, DirectoryStore,
+    StateStore,
+};
 pub struct ContainerBuilder<'a> {
     /// Id of the container
     pub(super) container_id: String,
@@ -16,6 +20,8 @@ pub struct ContainerBuilder<'a> {
     pub(super) console_socket: Option<PathBuf>,
     /// File descriptors to be passed into the container process
     pub(super) preserve_fds: i32,
+    /// Store the container state is persisted in
+    pub(super) state_store: Arc<dyn StateStore>,
 }
 
 /// Builder that can be used to configure the common properties of
@@ -56,6 +62,7 @@ impl<'a> ContainerBuilder<'a> {
             pid_file: None,
             console_socket: None,
             preserve_fds: 0,
+            state_store: Arc::new(DirectoryStore),
         }
     }
 
@@ -155,4 +162,22 @@ impl<'a> ContainerBuilder<'a> {
         self.preserve_fds = preserved_fds;
         self
     }
+
+    /// Sets the store in which the container state is persisted. By default
+    /// it is stored in the directory of the container below the root path.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::sync::Arc;
+    /// # use youki::container::builder::ContainerBuilder;
+    /// # use youki::container::MemoryStore;
+    /// # use youki::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
+    /// .with_state_store(Arc::new(MemoryStore::default()));
+    /// ```
+    pub fn with_state_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.state_store = store;
+        self
+    }
 }
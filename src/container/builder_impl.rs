@@ -1,19 +1,45 @@
 use crate::{
-    hooks,
+    exec_fifo, features, hooks,
+    idmap::IdmappedMounts,
+    landlock::Landlock,
     notify_socket::NotifyListener,
-    process::{args::ContainerArgs, channel, fork, intermediate},
+    process::{
+        args::{ContainerArgs, StartSync},
+        channel, fork, intermediate,
+    },
     rootless::Rootless,
+    signal::TerminationGuard,
+    stdio_log::StdioPipes,
     syscall::Syscall,
-    utils,
+    utils::{self, secure_fs},
 };
-use anyhow::{bail, Context, Result};
-use cgroups::{self, common::CgroupManager};
+use anyhow::{anyhow, bail, Context, Result};
+use cgroups::{
+    self,
+    common::{CgroupManager, MissingControllerPolicy, CGROUP_PROCS},
+};
+use nix::fcntl;
+use nix::sys::stat;
 use nix::unistd::Pid;
-use oci_spec::runtime::{LinuxResources, Spec};
-use std::{fs, io::Write, os::unix::prelude::RawFd, path::PathBuf};
+use oci_spec::runtime::{
+    LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType, LinuxResources, Mount, Spec,
+};
+use serde::Serialize;
+use std::{
+    env, fs,
+    os::unix::{fs::FileTypeExt, prelude::RawFd},
+    path::PathBuf,
+};
+
+#[cfg(feature = "seccomp")]
+use crate::seccomp::{self, SeccompCache};
 
 use super::{Container, ContainerStatus};
 
+// directory below the youki root in which compiled seccomp filters are cached
+#[cfg(feature = "seccomp")]
+const SECCOMP_CACHE_DIR: &str = "seccomp";
+
 pub(super) struct ContainerBuilderImpl<'a> {
     /// Flag indicating if an init or a tenant container should be created
     pub init: bool,
@@ -34,17 +60,40 @@ pub(super) struct ContainerBuilderImpl<'a> {
     pub console_socket: Option<RawFd>,
     /// Options for rootless containers
     pub rootless: Option<Rootless<'a>>,
-    /// Path to the Unix Domain Socket to communicate container start
+    /// Path to communicate the container start, the exec fifo of an init
+    /// container or the Unix Domain Socket of a tenant
     pub notify_path: PathBuf,
     /// Container state
     pub container: Option<Container>,
     /// File descriptos preserved/passed to the container init process.
     pub preserve_fds: i32,
+    /// Flag indicating if MS_MOVE and chroot should be used instead of pivot_root
+    pub no_pivot: bool,
+    /// Landlock ruleset to enforce on the container process
+    pub landlock: Option<Landlock>,
+    /// Cgroup directories a tenant process is placed in
+    pub cgroup_paths: Vec<PathBuf>,
+    /// Pipes the container process uses as stdout and stderr instead of the
+    /// stdio of youki
+    pub stdio_pipes: Option<StdioPipes>,
 }
 
 impl<'a> ContainerBuilderImpl<'a> {
     pub(super) fn create(&mut self) -> Result<()> {
-        if let Err(outer) = self.run_container().context("failed to create container") {
+        // a create which is cancelled is rolled back like a failed one, so
+        // that the next attempt is not blocked by the remains
+        let termination = if self.init {
+            Some(TerminationGuard::install()?)
+        } else {
+            None
+        };
+        let result = self.run_container().context("failed to create container");
+        let result = match termination.as_ref().and_then(|t| t.received()) {
+            Some(signal) if result.is_ok() => Err(anyhow!("create was interrupted by {}", signal)),
+            _ => result,
+        };
+
+        if let Err(outer) = result {
             if let Err(inner) = self.cleanup_container() {
                 return Err(outer.context(inner));
             }
@@ -56,14 +105,28 @@ impl<'a> ContainerBuilderImpl<'a> {
     }
 
     fn run_container(&mut self) -> Result<()> {
+        let listen_fds = listen_fds(
+            env::var("LISTEN_FDS").ok().as_deref(),
+            env::var("LISTEN_PID").ok().as_deref(),
+            std::process::id(),
+        );
+        validate_preserved_fds(self.preserve_fds, listen_fds)?;
         let linux = self.spec.linux().as_ref().context("no linux in spec")?;
         let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), &self.container_id);
-        let cmanager = cgroups::common::create_cgroup_manager(&cgroups_path, self.use_systemd)?;
+        let cmanager = cgroups::common::create_cgroup_manager_with_unit_properties(
+            &cgroups_path,
+            self.use_systemd,
+            systemd_unit_properties(self.spec),
+        )?;
         let process = self.spec.process().as_ref().context("No process in spec")?;
 
         if self.init {
             if let Some(hooks) = self.spec.hooks() {
-                hooks::run_hooks(hooks.create_runtime().as_ref(), self.container.as_ref())?
+                hooks::run_hooks(
+                    "createRuntime",
+                    hooks.create_runtime().as_ref(),
+                    self.container.as_ref(),
+                )?
             }
         }
 
@@ -71,11 +134,18 @@ impl<'a> ContainerBuilderImpl<'a> {
         let (sender_to_intermediate, receiver_from_main) = &mut channel::main_to_intermediate()?;
         let (sender_to_main, receiver_from_intermediate) = &mut channel::intermediate_to_main()?;
 
-        // Need to create the notify socket before we pivot root, since the unix
-        // domain socket used here is outside of the rootfs of container. During
-        // exec, need to create the socket before we enter into existing mount
-        // namespace.
-        let notify_socket: NotifyListener = NotifyListener::new(&self.notify_path)?;
+        // Need to create the exec fifo or notify socket before we pivot root,
+        // since they are outside of the rootfs of container. During exec, need
+        // to create the socket before we enter into existing mount namespace.
+        let start_sync = if self.init {
+            StartSync::ExecFifo(exec_fifo::create(&self.notify_path)?)
+        } else {
+            StartSync::NotifySocket(NotifyListener::new(&self.notify_path)?)
+        };
+        let exec_fifo_fd = match start_sync {
+            StartSync::ExecFifo(fd) => Some(fd),
+            StartSync::NotifySocket(_) => None,
+        };
 
         // If Out-of-memory score adjustment is set in specification.  set the score
         // value for the current process check
@@ -88,10 +158,53 @@ impl<'a> ContainerBuilderImpl<'a> {
         // fork(2) so this will always be propagated properly.
         if let Some(oom_score_adj) = process.oom_score_adj() {
             log::debug!("Set OOM score to {}", oom_score_adj);
-            let mut f = fs::File::create("/proc/self/oom_score_adj")?;
-            f.write_all(oom_score_adj.to_string().as_bytes())?;
+            let res =
+                secure_fs::write_proc_file("/proc/self/oom_score_adj", oom_score_adj.to_string());
+            match res {
+                // Nested in a container youki lacks CAP_SYS_RESOURCE in the
+                // initial user namespace, so the OOM score cannot be lowered
+                // below the one of the surrounding container
+                Err(e) if features::in_user_namespace() => {
+                    log::warn!("failed to set OOM score in user namespace: {:?}", e)
+                }
+                res => res?,
+            }
         }
 
+        // Idmapped mounts have to be prepared here, as the container init
+        // process lacks the privileges over the filesystems of the host. The
+        // detached mounts are inherited by the init process during fork.
+        let mut idmapped_mounts = if self.init {
+            IdmappedMounts::prepare(self.spec).context("failed to prepare idmapped mounts")?
+        } else {
+            IdmappedMounts::default()
+        };
+
+        // The seccomp filter is compiled here, so the filter of a profile is
+        // compiled only once and reused from the cache for other containers.
+        let seccomp_filter = match linux.seccomp() {
+            #[cfg(feature = "seccomp")]
+            Some(seccomp) => {
+                let _span = tracing::info_span!("seccomp_compile").entered();
+                let cache_dir = self
+                    .container
+                    .as_ref()
+                    .and_then(|c| c.root.parent())
+                    .map(|root_path| root_path.join(SECCOMP_CACHE_DIR));
+                let filter = match cache_dir {
+                    Some(cache_dir) => SeccompCache::new(cache_dir).get_or_compile(seccomp),
+                    None => seccomp::compile_seccomp(seccomp),
+                };
+                Some(filter.context("failed to compile seccomp filter")?)
+            }
+            #[cfg(not(feature = "seccomp"))]
+            Some(_) => bail!(
+                "the spec has a seccomp profile, \
+                but youki has been built without the seccomp feature"
+            ),
+            None => None,
+        };
+
         // Make the process non-dumpable, to avoid various race conditions that
         // could cause processes in namespaces we're joining to access host
         // resources (or potentially execute code).
@@ -113,11 +226,20 @@ impl<'a> ContainerBuilderImpl<'a> {
             spec: self.spec.clone(),
             rootfs: self.rootfs.clone(),
             console_socket: self.console_socket,
-            notify_socket,
+            start_sync,
             preserve_fds: self.preserve_fds,
+            listen_fds,
+            listen_fdnames: env::var("LISTEN_FDNAMES").ok(),
             container: self.container.clone(),
             rootless: self.rootless.clone(),
+            idmapped_mounts: idmapped_mounts.clone(),
+            no_pivot: self.no_pivot,
+            seccomp_filter,
+            landlock: self.landlock.clone(),
+            stdio_pipes: self.stdio_pipes,
         };
+        // covers the namespace setup and rootfs preparation in the init process
+        let init_span = tracing::info_span!("init").entered();
         let intermediate_pid = fork::container_fork(|| {
             // The fds in the pipe is duplicated during fork, so we first close
             // the unused fds. Note, this already runs in the child process.
@@ -128,11 +250,15 @@ impl<'a> ContainerBuilderImpl<'a> {
                 .close()
                 .context("Failed to close unused receiver")?;
 
-            intermediate::container_intermediate(
+            let result = intermediate::container_intermediate(
                 intermediate_args,
                 receiver_from_main,
                 sender_to_main,
-            )
+            );
+            if let Err(err) = &result {
+                let _ = sender_to_main.send_error(err);
+            }
+            result
         })?;
         // Close down unused fds. The corresponding fds are duplicated to the
         // child process during fork.
@@ -142,6 +268,11 @@ impl<'a> ContainerBuilderImpl<'a> {
         sender_to_main
             .close()
             .context("Failed to close child to parent sender")?;
+        idmapped_mounts.close()?;
+        // the monitor of the stdio log stops once the container closes them
+        if let Some(stdio_pipes) = self.stdio_pipes.take() {
+            stdio_pipes.close()?;
+        }
 
         // If creating a rootless container, the intermediate process will ask
         // the main process to set up uid and gid mapping, once the intermediate
@@ -152,24 +283,58 @@ impl<'a> ContainerBuilderImpl<'a> {
             sender_to_intermediate.mapping_written()?;
         }
 
-        let init_pid = receiver_from_intermediate.wait_for_intermediate_ready()?;
+        let (init_pid, timings) = receiver_from_intermediate.wait_for_intermediate_ready()?;
         log::debug!("init pid is {:?}", init_pid);
+        timings.record();
+        // the init process holds the exec fifo from here on
+        if let Some(fd) = exec_fifo_fd {
+            let _ = nix::unistd::close(fd);
+        }
+        drop(init_span);
 
-        if self.rootless.is_none() && linux.resources().is_some() && self.init {
-            if let Some(resources) = linux.resources() {
-                apply_cgroups(resources, init_pid, cmanager.as_ref())?;
+        if self.init {
+            // the container joins the cgroups even without resources, so that
+            // controllers without limits like freezer and perf_event can be used
+            let no_resources = LinuxResources::default();
+            let resources = linux.resources().as_ref().unwrap_or(&no_resources);
+            let resources = with_oom_group_annotation(
+                &with_bind_mounted_devices(resources, self.spec)?,
+                self.spec,
+            )?;
+            let resources = with_cpuset_partition_annotation(&resources, self.spec)?;
+            if self.rootless.is_some() {
+                apply_rootless_cgroups(&resources, init_pid, cmanager.as_ref());
+            } else {
+                apply_cgroups(
+                    &resources,
+                    init_pid,
+                    cmanager.as_ref(),
+                    missing_controller_policy(self.spec)?,
+                )?;
             }
         }
 
+        for cgroup_path in &self.cgroup_paths {
+            cgroups::common::write_cgroup_file(cgroup_path.join(CGROUP_PROCS), init_pid)
+                .with_context(|| {
+                    format!(
+                        "failed to add {} to cgroup {}",
+                        init_pid,
+                        cgroup_path.display()
+                    )
+                })?;
+        }
+
         // if file to write the pid to is specified, write pid of the child
         if let Some(pid_file) = &self.pid_file {
-            fs::write(&pid_file, format!("{}", init_pid)).context("Failed to write pid file")?;
+            utils::write_file_atomic(&pid_file, format!("{}", init_pid), false)
+                .context("Failed to write pid file")?;
         }
 
         if let Some(container) = &mut self.container {
             // update status and pid of the container process
             container
-                .set_status(ContainerStatus::Created)
+                .transition(ContainerStatus::Created)?
                 .set_creator(nix::unistd::geteuid().as_raw())
                 .set_pid(init_pid.as_raw())
                 .save()
@@ -185,6 +350,9 @@ impl<'a> ContainerBuilderImpl<'a> {
         let cmanager = cgroups::common::create_cgroup_manager(&cgroups_path, self.use_systemd)?;
 
         let mut errors = Vec::new();
+        if let Some(pid) = self.container.as_ref().and_then(|c| c.pid()) {
+            let _ = nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGKILL);
+        }
         if let Err(e) = cmanager.remove().context("failed to remove cgroup") {
             errors.push(e.to_string());
         }
@@ -207,12 +375,61 @@ impl<'a> ContainerBuilderImpl<'a> {
     }
 }
 
+/// Returns the number of sockets passed by systemd socket activation. The
+/// sockets are only meant for youki, if LISTEN_PID refers to the current
+/// process, otherwise they have been passed on by accident and are ignored.
+/// See sd_listen_fds(3) for details.
+fn listen_fds(listen_fds: Option<&str>, listen_pid: Option<&str>, pid: u32) -> i32 {
+    let listen_fds = match listen_fds {
+        Some(listen_fds) => listen_fds,
+        None => return 0,
+    };
+
+    if listen_pid.and_then(|p| p.parse::<u32>().ok()) != Some(pid) {
+        log::debug!("LISTEN_PID does not refer to youki, ignoring LISTEN_FDS");
+        return 0;
+    }
+
+    match listen_fds.parse::<i32>() {
+        Ok(v) if v >= 0 => v,
+        _ => {
+            log::warn!(
+                "LISTEN_FDS entered is not a number of fds: {}. Ignore the value.",
+                listen_fds
+            );
+            0
+        }
+    }
+}
+
+/// The preserved fds are passed down to the container process through fork
+/// and exec, which means that they have to be open in youki itself. They
+/// directly follow stdio (0, 1, 2) and the sockets passed by systemd socket
+/// activation.
+fn validate_preserved_fds(preserve_fds: i32, listen_fds: i32) -> Result<()> {
+    if preserve_fds < 0 {
+        bail!(
+            "number of preserved fds must not be negative: {}",
+            preserve_fds
+        );
+    }
+
+    for fd in 3..3 + listen_fds + preserve_fds {
+        if fcntl::fcntl(fd, fcntl::F_GETFD).is_err() {
+            bail!("fd {} should be preserved, but it is not open", fd);
+        }
+    }
+
+    Ok(())
+}
+
 fn setup_mapping(rootless: &Rootless, pid: Pid) -> Result<()> {
     log::debug!("write mapping for pid {:?}", pid);
-    if !rootless.privileged {
+    if !rootless.privileged && rootless.newgidmap.is_none() {
         // The main process is running as an unprivileged user and cannot write the mapping
-        // until "deny" has been written to setgroups. See CVE-2014-8989.
-        utils::write_file(format!("/proc/{}/setgroups", pid), "deny")?;
+        // until "deny" has been written to setgroups. See CVE-2014-8989. newgidmap
+        // checks the mapping against /etc/subgid instead.
+        secure_fs::write_proc_file(format!("/proc/{}/setgroups", pid), "deny")?;
     }
     rootless
         .write_uid_mapping(pid)
@@ -223,16 +440,19 @@ fn setup_mapping(rootless: &Rootless, pid: Pid) -> Result<()> {
     Ok(())
 }
 
-fn apply_cgroups<C: CgroupManager + ?Sized>(
+pub(super) fn apply_cgroups<C: CgroupManager + ?Sized>(
     resources: &LinuxResources,
     pid: Pid,
     cmanager: &C,
+    missing_controllers: MissingControllerPolicy,
 ) -> Result<()> {
+    let _span = tracing::info_span!("cgroup_apply", pid = pid.as_raw()).entered();
     let controller_opt = cgroups::common::ControllerOpt {
         resources,
         freezer_state: None,
         oom_score_adj: None,
-        disable_oom_killer: false,
+        disable_oom_killer: disable_oom_killer(resources),
+        missing_controllers,
     };
     cmanager
         .add_task(pid)
@@ -245,6 +465,224 @@ fn apply_cgroups<C: CgroupManager + ?Sized>(
     Ok(())
 }
 
+/// Device nodes of the host can be bind mounted into the container instead of
+/// being listed as devices in the spec. The device cgroup would deny the
+/// access to them, so allow rules are derived from the host device nodes.
+fn bind_mounted_device_rules(mounts: &[Mount]) -> Result<Vec<LinuxDeviceCgroup>> {
+    let mut rules = Vec::new();
+    for mount in mounts {
+        let is_bind = *mount.typ() == Some("bind".to_string())
+            || mount
+                .options()
+                .iter()
+                .flatten()
+                .any(|o| o == "bind" || o == "rbind");
+        let source = match (is_bind, mount.source()) {
+            (true, Some(source)) => source,
+            _ => continue,
+        };
+
+        let metadata = match fs::metadata(source) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let typ = if metadata.file_type().is_char_device() {
+            LinuxDeviceType::C
+        } else if metadata.file_type().is_block_device() {
+            LinuxDeviceType::B
+        } else {
+            continue;
+        };
+
+        let rdev = std::os::unix::fs::MetadataExt::rdev(&metadata);
+        log::debug!("allow access to bind mounted device {}", source.display());
+        rules.push(
+            LinuxDeviceCgroupBuilder::default()
+                .allow(true)
+                .typ(typ)
+                .major(stat::major(rdev) as i64)
+                .minor(stat::minor(rdev) as i64)
+                .access("rwm")
+                .build()?,
+        );
+    }
+
+    Ok(rules)
+}
+
+fn with_bind_mounted_devices(resources: &LinuxResources, spec: &Spec) -> Result<LinuxResources> {
+    let rules = bind_mounted_device_rules(spec.mounts().as_deref().unwrap_or_default())?;
+    if rules.is_empty() {
+        return Ok(resources.clone());
+    }
+
+    let mut devices = resources.devices().clone().unwrap_or_default();
+    devices.extend(rules);
+    with_resources_field(resources, "devices", devices)
+}
+
+/// Returns a copy of the resources with the field, given by its name in the
+/// runtime spec, replaced. LinuxResources has no setters, so the copy is
+/// made through json.
+fn with_resources_field<T: Serialize>(
+    resources: &LinuxResources,
+    field: &str,
+    value: T,
+) -> Result<LinuxResources> {
+    let mut resources = serde_json::to_value(resources)?;
+    resources[field] = serde_json::to_value(value)?;
+    Ok(serde_json::from_value(resources)?)
+}
+
+// annotation which enables the cgroup-wide OOM killing
+const OOM_GROUP_ANNOTATION: &str = "youki.memory.oom.group";
+
+/// Enables or disables the cgroup-wide OOM killing of memory.oom.group, if
+/// the container is annotated with it. The annotation takes "true" or "false",
+/// a value given in the unified map of the resources takes precedence.
+fn with_oom_group_annotation(resources: &LinuxResources, spec: &Spec) -> Result<LinuxResources> {
+    let oom_group = match spec
+        .annotations()
+        .as_ref()
+        .and_then(|a| a.get(OOM_GROUP_ANNOTATION))
+        .map(|v| v.as_str())
+    {
+        Some("true") => "1",
+        Some("false") => "0",
+        Some(value) => bail!(
+            "invalid value {} of annotation {}, expected true or false",
+            value,
+            OOM_GROUP_ANNOTATION
+        ),
+        None => return Ok(resources.clone()),
+    };
+
+    with_unified_default(resources, "memory.oom.group", oom_group)
+}
+
+// annotation which turns the cpuset into a partition
+const CPUSET_PARTITION_ANNOTATION: &str = "youki.cpuset.partition";
+
+/// Turns the cpuset of the container into a partition on cgroup v2, if the
+/// container is annotated with it. The annotation takes "root" or "isolated",
+/// the latter removes the cpus from the scheduling of other processes, e.g.
+/// for realtime workloads. A value in the unified map takes precedence.
+fn with_cpuset_partition_annotation(
+    resources: &LinuxResources,
+    spec: &Spec,
+) -> Result<LinuxResources> {
+    match spec
+        .annotations()
+        .as_ref()
+        .and_then(|a| a.get(CPUSET_PARTITION_ANNOTATION))
+        .map(|v| v.as_str())
+    {
+        Some(partition @ "root") | Some(partition @ "isolated") => {
+            with_unified_default(resources, "cpuset.cpus.partition", partition)
+        }
+        Some(value) => bail!(
+            "invalid value {} of annotation {}, expected root or isolated",
+            value,
+            CPUSET_PARTITION_ANNOTATION
+        ),
+        None => Ok(resources.clone()),
+    }
+}
+
+/// Sets the cgroup file in the unified map of the resources, unless the map
+/// contains it already
+fn with_unified_default(
+    resources: &LinuxResources,
+    cgroup_file: &str,
+    value: &str,
+) -> Result<LinuxResources> {
+    let mut unified = resources.unified().clone().unwrap_or_default();
+    unified
+        .entry(cgroup_file.to_owned())
+        .or_insert_with(|| value.to_owned());
+    with_resources_field(resources, "unified", unified)
+}
+
+// annotation which sets the policy for missing controllers
+const MISSING_CONTROLLERS_ANNOTATION: &str = "youki.cgroup.missing-controllers";
+
+/// Controllers which are not available to the cgroup of the container fail
+/// the creation by default. If the container is annotated with "warn", their
+/// restrictions are skipped with a warning instead.
+pub(super) fn missing_controller_policy(spec: &Spec) -> Result<MissingControllerPolicy> {
+    match spec
+        .annotations()
+        .as_ref()
+        .and_then(|a| a.get(MISSING_CONTROLLERS_ANNOTATION))
+    {
+        Some(value) => value
+            .parse()
+            .with_context(|| format!("invalid annotation {}", MISSING_CONTROLLERS_ANNOTATION)),
+        None => Ok(MissingControllerPolicy::default()),
+    }
+}
+
+/// Like crun, annotations of the form org.systemd.property.<name> set the
+/// property on the systemd unit of the container, e.g. RuntimeMaxSec.
+const SYSTEMD_PROPERTY_ANNOTATION_PREFIX: &str = "org.systemd.property.";
+
+pub(super) fn systemd_unit_properties(spec: &Spec) -> Vec<(String, String)> {
+    let mut properties: Vec<_> = spec
+        .annotations()
+        .iter()
+        .flatten()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(SYSTEMD_PROPERTY_ANNOTATION_PREFIX)
+                .filter(|name| !name.is_empty())
+                .map(|name| (name.to_owned(), value.clone()))
+        })
+        .collect();
+    // the order of the annotations is not defined
+    properties.sort();
+    properties
+}
+
+/// Whether the memory section of the resources disables the OOM killer
+pub(super) fn disable_oom_killer(resources: &LinuxResources) -> bool {
+    resources
+        .memory()
+        .as_ref()
+        .and_then(|memory| memory.disable_oom_killer())
+        .unwrap_or(false)
+}
+
+/// Unprivileged users can usually only manage cgroups which have been
+/// delegated to them, so like runc the cgroup configuration of rootless
+/// containers is best effort. Limits which cannot be applied are skipped
+/// with a warning instead of failing the container creation.
+fn apply_rootless_cgroups<C: CgroupManager + ?Sized>(
+    resources: &LinuxResources,
+    pid: Pid,
+    cmanager: &C,
+) {
+    if let Err(e) = cmanager.add_task(pid) {
+        log::warn!(
+            "cgroup cannot be used by rootless container, resource limits will not be applied: {:?}",
+            e
+        );
+        return;
+    }
+
+    let controller_opt = cgroups::common::ControllerOpt {
+        resources,
+        freezer_state: None,
+        oom_score_adj: None,
+        disable_oom_killer: disable_oom_killer(resources),
+        missing_controllers: Default::default(),
+    };
+    if let Err(e) = cmanager.apply(&controller_opt) {
+        log::warn!(
+            "failed to apply resource limits to cgroup of rootless container: {:?}",
+            e
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use cgroups::test_manager::TestManager;
@@ -252,7 +690,7 @@ mod tests {
         sched::{unshare, CloneFlags},
         unistd::{self, getgid, getuid},
     };
-    use oci_spec::runtime::LinuxIdMappingBuilder;
+    use oci_spec::runtime::{LinuxIdMappingBuilder, LinuxResourcesBuilder, MountBuilder};
     use serial_test::serial;
 
     use crate::process::channel::{intermediate_to_main, main_to_intermediate};
@@ -354,13 +792,200 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn validate_preserved_fds_should_fail_for_negative() {
+        assert!(validate_preserved_fds(-1, 0).is_err());
+    }
+
+    #[test]
+    fn validate_preserved_fds_should_succeed_without_fds() -> Result<()> {
+        validate_preserved_fds(0, 0)
+    }
+
+    #[test]
+    fn validate_preserved_fds_should_fail_for_closed_fds() {
+        // Nearly all of the fds up to this one would have to be open in the
+        // test process for this to pass
+        assert!(validate_preserved_fds(100_000, 0).is_err());
+    }
+
+    #[test]
+    fn listen_fds_should_be_used_for_matching_pid() {
+        assert_eq!(listen_fds(Some("2"), Some("42"), 42), 2);
+    }
+
+    #[test]
+    fn listen_fds_should_be_ignored_for_other_pid() {
+        assert_eq!(listen_fds(Some("2"), Some("1"), 42), 0);
+        assert_eq!(listen_fds(Some("2"), None, 42), 0);
+    }
+
+    #[test]
+    fn listen_fds_should_be_ignored_if_invalid() {
+        assert_eq!(listen_fds(None, Some("42"), 42), 0);
+        assert_eq!(listen_fds(Some("abc"), Some("42"), 42), 0);
+        assert_eq!(listen_fds(Some("-1"), Some("42"), 42), 0);
+    }
+
     #[test]
     fn apply_cgroup_successed() -> Result<()> {
         let cmanager = TestManager::default();
         let sample_pid = Pid::from_raw(1000);
         let resources = LinuxResources::default();
-        apply_cgroups(&resources, sample_pid, &cmanager)?;
+        apply_cgroups(
+            &resources,
+            sample_pid,
+            &cmanager,
+            MissingControllerPolicy::Fail,
+        )?;
         assert_eq!(cmanager.get_add_task_args(), vec![sample_pid]);
         Ok(())
     }
+
+    #[test]
+    fn bind_mounted_devices_should_be_allowed() -> Result<()> {
+        let mounts = vec![
+            MountBuilder::default()
+                .destination("/dev/null")
+                .typ("bind")
+                .source("/dev/null")
+                .options(vec!["rbind".to_string()])
+                .build()?,
+            MountBuilder::default()
+                .destination("/etc/hosts")
+                .typ("bind")
+                .source("/etc/hosts")
+                .build()?,
+            MountBuilder::default()
+                .destination("/dev/full")
+                .typ("tmpfs")
+                .source("/dev/full")
+                .build()?,
+        ];
+
+        let rules = bind_mounted_device_rules(&mounts)?;
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].typ(), Some(LinuxDeviceType::C));
+        assert_eq!(rules[0].major(), Some(1));
+        assert_eq!(rules[0].minor(), Some(3));
+        assert!(rules[0].allow());
+        Ok(())
+    }
+
+    #[test]
+    fn missing_controller_policy_should_be_read_from_annotation() -> Result<()> {
+        let annotated = |value: &str| -> Result<Spec> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "ociVersion": "1.0.2",
+                "annotations": { MISSING_CONTROLLERS_ANNOTATION: value }
+            }))?)
+        };
+
+        assert_eq!(
+            missing_controller_policy(&Spec::default())?,
+            MissingControllerPolicy::Fail
+        );
+        assert_eq!(
+            missing_controller_policy(&annotated("warn")?)?,
+            MissingControllerPolicy::Warn
+        );
+        assert_eq!(
+            missing_controller_policy(&annotated("fail")?)?,
+            MissingControllerPolicy::Fail
+        );
+        assert!(missing_controller_policy(&annotated("ignore")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn systemd_unit_properties_should_be_read_from_annotations() -> Result<()> {
+        let spec: Spec = serde_json::from_value(serde_json::json!({
+            "ociVersion": "1.0.2",
+            "annotations": {
+                "org.systemd.property.RuntimeMaxSec": "60",
+                "org.systemd.property.CPUWeight": "200",
+                "org.systemd.property.": "ignored",
+                "org.opencontainers.image.title": "ignored"
+            }
+        }))?;
+
+        assert_eq!(
+            systemd_unit_properties(&spec),
+            vec![
+                ("CPUWeight".to_owned(), "200".to_owned()),
+                ("RuntimeMaxSec".to_owned(), "60".to_owned()),
+            ]
+        );
+        assert!(systemd_unit_properties(&Spec::default()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn cpuset_partition_annotation_should_be_applied() -> Result<()> {
+        let annotated = |value: &str| -> Result<Spec> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "ociVersion": "1.0.2",
+                "annotations": { CPUSET_PARTITION_ANNOTATION: value }
+            }))?)
+        };
+
+        let resources = LinuxResources::default();
+        let updated = with_cpuset_partition_annotation(&resources, &annotated("isolated")?)?;
+        assert_eq!(
+            updated
+                .unified()
+                .as_ref()
+                .and_then(|u| u.get("cpuset.cpus.partition"))
+                .map(|p| p.as_str()),
+            Some("isolated")
+        );
+        assert!(with_cpuset_partition_annotation(&resources, &annotated("member")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn oom_group_annotation_should_be_applied() -> Result<()> {
+        let annotated = |value: &str| -> Result<Spec> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "ociVersion": "1.0.2",
+                "annotations": { OOM_GROUP_ANNOTATION: value }
+            }))?)
+        };
+        let oom_group = |resources: &LinuxResources| {
+            resources
+                .unified()
+                .as_ref()
+                .and_then(|u| u.get("memory.oom.group").cloned())
+        };
+
+        let resources = LinuxResources::default();
+        let updated = with_oom_group_annotation(&resources, &annotated("true")?)?;
+        assert_eq!(oom_group(&updated).as_deref(), Some("1"));
+        assert_eq!(
+            oom_group(&with_oom_group_annotation(&resources, &Spec::default())?),
+            None
+        );
+        assert!(with_oom_group_annotation(&resources, &annotated("yes")?).is_err());
+
+        // the unified map takes precedence over the annotation
+        let resources = LinuxResourcesBuilder::default()
+            .unified(
+                vec![("memory.oom.group".to_owned(), "1".to_owned())]
+                    .into_iter()
+                    .collect::<std::collections::HashMap<_, _>>(),
+            )
+            .build()?;
+        let updated = with_oom_group_annotation(&resources, &annotated("false")?)?;
+        assert_eq!(oom_group(&updated).as_deref(), Some("1"));
+        Ok(())
+    }
+
+    #[test]
+    fn apply_rootless_cgroup_successed() {
+        let cmanager = TestManager::default();
+        let sample_pid = Pid::from_raw(1000);
+        let resources = LinuxResources::default();
+        apply_rootless_cgroups(&resources, sample_pid, &cmanager);
+        assert_eq!(cmanager.get_add_task_args(), vec![sample_pid]);
+    }
 }
@@ -2,18 +2,22 @@ use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use chrono::DateTime;
+use nix::sys::wait::WaitStatus;
 use nix::unistd::Pid;
 
 use chrono::Utc;
 use oci_spec::runtime::Spec;
 use procfs::process::Process;
 
+use crate::error::YoukiError;
 use crate::syscall::syscall::create_syscall;
+use crate::utils;
 
-use crate::container::{ContainerStatus, State};
+use crate::container::{state::StateLock, ContainerStatus, DirectoryStore, State, StateStore};
 
 /// Structure representing the container data
 #[derive(Debug, Clone)]
@@ -22,6 +26,8 @@ pub struct Container {
     pub state: State,
     // indicated the directory for the root path in the container
     pub root: PathBuf,
+    // persists the state of the container
+    store: Arc<dyn StateStore>,
 }
 
 impl Default for Container {
@@ -29,6 +35,7 @@ impl Default for Container {
         Self {
             state: State::default(),
             root: PathBuf::from("/run/youki"),
+            store: Arc::new(DirectoryStore),
         }
     }
 }
@@ -46,9 +53,17 @@ impl Container {
         Ok(Self {
             state,
             root: container_root,
+            store: Arc::new(DirectoryStore),
         })
     }
 
+    /// Sets the store the state of the container is persisted in, instead of
+    /// the state file in the container root
+    pub fn with_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.store = store;
+        self
+    }
+
     pub fn id(&self) -> &str {
         &self.state.id
     }
@@ -77,10 +92,22 @@ impl Container {
         self.state.status.can_resume()
     }
 
+    pub fn can_checkpoint(&self) -> bool {
+        self.state.status.can_checkpoint()
+    }
+
+    pub fn can_update(&self) -> bool {
+        self.state.status.can_update()
+    }
+
     pub fn bundle(&self) -> &PathBuf {
         &self.state.bundle
     }
 
+    pub fn annotations(&self) -> Option<&HashMap<String, String>> {
+        self.state.annotations.as_ref()
+    }
+
     pub fn set_annotations(&mut self, annotations: Option<HashMap<String, String>>) -> &mut Self {
         self.state.annotations = annotations;
         self
@@ -125,6 +152,15 @@ impl Container {
         self
     }
 
+    pub fn exit_code(&self) -> Option<i32> {
+        self.state.exit_code
+    }
+
+    pub fn set_exit_code(&mut self, exit_code: i32) -> &mut Self {
+        self.state.exit_code = Some(exit_code);
+        self
+    }
+
     pub fn status(&self) -> ContainerStatus {
         self.state.status
     }
@@ -141,6 +177,38 @@ impl Container {
         self
     }
 
+    /// Changes the status of the container like set_status, but rejects
+    /// changes which are not part of the container lifecycle, e.g. starting
+    /// a stopped container
+    pub fn transition(&mut self, status: ContainerStatus) -> Result<&mut Self> {
+        self.check_transition(status)?;
+        Ok(self.set_status(status))
+    }
+
+    /// Fails if the container cannot change to the status. Operations check
+    /// this before their side effects, e.g. freezing the cgroup, so that they
+    /// are not left half done when the transition is rejected.
+    pub fn check_transition(&self, status: ContainerStatus) -> Result<()> {
+        if !self.status().can_transition_to(status) {
+            bail!(YoukiError::InvalidTransition {
+                id: self.id().to_owned(),
+                from: self.status(),
+                to: status,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Error for an operation which is not allowed in the current status
+    pub(super) fn invalid_state(&self, operation: &'static str) -> YoukiError {
+        YoukiError::InvalidState {
+            id: self.id().to_owned(),
+            status: self.status(),
+            operation,
+        }
+    }
+
     pub fn refresh_status(&mut self) -> Result<()> {
         let new_status = match self.pid() {
             Some(pid) => {
@@ -150,7 +218,19 @@ impl Container {
                 if let Ok(proc) = Process::new(pid.as_raw()) {
                     use procfs::process::ProcState;
                     match proc.stat.state().unwrap() {
-                        ProcState::Zombie | ProcState::Dead => ContainerStatus::Stopped,
+                        ProcState::Zombie | ProcState::Dead => {
+                            // The exit status stays available until the
+                            // zombie has been reaped by its parent
+                            if let Some(code) = proc
+                                .stat
+                                .exit_code
+                                .and_then(|status| WaitStatus::from_raw(pid, status).ok())
+                                .and_then(utils::exit_code)
+                            {
+                                self.state.exit_code = Some(code);
+                            }
+                            ContainerStatus::Stopped
+                        }
                         _ => match self.status() {
                             ContainerStatus::Creating
                             | ContainerStatus::Created
@@ -170,17 +250,32 @@ impl Container {
     }
 
     pub fn refresh_state(&mut self) -> Result<&mut Self> {
-        let state = State::load(&self.root)?;
+        let state = self.store.load(&self.root)?;
         self.state = state;
 
         Ok(self)
     }
 
+    /// Acquires an exclusive lock on the container state and reloads it, so
+    /// that concurrent invocations of youki do not act on stale state. The
+    /// lock is held until the returned guard is dropped.
+    pub fn lock(&mut self) -> Result<StateLock> {
+        let lock = self.store.lock(&self.root)?;
+        self.refresh_state()?;
+        Ok(lock)
+    }
+
     pub fn load(container_root: PathBuf) -> Result<Self> {
-        let state = State::load(&container_root)?;
+        Self::load_from(Arc::new(DirectoryStore), container_root)
+    }
+
+    /// Loads the container from the given state store
+    pub fn load_from(store: Arc<dyn StateStore>, container_root: PathBuf) -> Result<Self> {
+        let state = store.load(&container_root)?;
         let mut container = Self {
             state,
             root: container_root,
+            store,
         };
         container.refresh_status()?;
         Ok(container)
@@ -188,7 +283,7 @@ impl Container {
 
     pub fn save(&self) -> Result<()> {
         log::debug!("Save container status: {:?} in {:?}", self, self.root);
-        self.state.save(&self.root)
+        self.store.save(&self.root, &self.state)
     }
 
     pub fn spec(&self) -> Result<Spec> {
@@ -214,6 +309,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_transition() -> Result<()> {
+        let dir = env::temp_dir();
+        let mut container =
+            Container::new("container_id", ContainerStatus::Created, None, &dir, &dir)?;
+
+        assert!(container.check_transition(ContainerStatus::Paused).is_err());
+        assert!(container.transition(ContainerStatus::Paused).is_err());
+        assert_eq!(container.status(), ContainerStatus::Created);
+        container.check_transition(ContainerStatus::Running)?;
+        assert_eq!(container.status(), ContainerStatus::Created);
+        container.transition(ContainerStatus::Running)?;
+        container.transition(ContainerStatus::Stopped)?;
+        assert!(container.transition(ContainerStatus::Running).is_err());
+        assert_eq!(container.status(), ContainerStatus::Stopped);
+        Ok(())
+    }
+
     #[test]
     fn test_basic_getter() -> Result<()> {
         let container = Container::new(
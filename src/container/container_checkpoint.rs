@@ -0,0 +1,336 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::utils;
+
+use super::{Container, ContainerStatus};
+use anyhow::{bail, Context, Result};
+
+const CRIU: &str = "criu";
+const DUMP_LOG: &str = "dump.log";
+
+/// How CRIU handles the cgroups of the container on checkpoint and restore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManageCgroupsMode {
+    /// Restore the cgroup properties only if the cgroups are created by CRIU,
+    /// existing cgroups are reused as they are
+    Soft,
+    /// Always restore the cgroup properties, also into existing cgroups
+    Full,
+    /// Like full, but fail if the cgroups of the checkpoint do not match
+    /// the existing ones
+    Strict,
+    /// Neither dump nor restore the cgroups, the restored container is
+    /// placed into the cgroup created for it by youki
+    Ignore,
+}
+
+impl ManageCgroupsMode {
+    /// The cgroup handling of CRIU as flag, e.g. --manage-cgroups=soft
+    pub(super) fn criu_flag(&self) -> String {
+        format!("--manage-cgroups={}", self)
+    }
+}
+
+impl fmt::Display for ManageCgroupsMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self {
+            Self::Soft => "soft",
+            Self::Full => "full",
+            Self::Strict => "strict",
+            Self::Ignore => "ignore",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+impl FromStr for ManageCgroupsMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Self> {
+        match mode {
+            "soft" => Ok(Self::Soft),
+            "full" => Ok(Self::Full),
+            "strict" => Ok(Self::Strict),
+            "ignore" => Ok(Self::Ignore),
+            _ => bail!(
+                "invalid manage cgroups mode {}, expected one of soft, full, strict or ignore",
+                mode
+            ),
+        }
+    }
+}
+
+/// Options which are passed to CRIU to checkpoint a container
+#[derive(Debug, Clone, Default)]
+pub struct CheckpointOptions {
+    /// Directory the checkpoint images are written to
+    pub image_path: PathBuf,
+    /// Directory for the log and work files of CRIU, defaults to the image directory
+    pub work_path: Option<PathBuf>,
+    /// Keep the container running after it has been checkpointed
+    pub leave_running: bool,
+    /// Checkpoint established TCP connections
+    pub tcp_established: bool,
+    /// Checkpoint unix sockets connected to peers outside of the container
+    pub ext_unix_sk: bool,
+    /// Checkpoint file locks
+    pub file_locks: bool,
+    /// Only dump the memory of the container, which keeps running. Used to
+    /// iteratively dump the memory before the final checkpoint.
+    pub pre_dump: bool,
+    /// Path of the images of a previous pre-dump, relative to the image path.
+    /// Only the memory pages changed since the previous dump are written.
+    pub parent_path: Option<PathBuf>,
+    /// Leave the memory pages to be fetched lazily by the restored container
+    pub lazy_pages: bool,
+    /// Address (ADDRESS:PORT) of the page server the memory pages are sent to
+    pub page_server: Option<String>,
+    /// How CRIU dumps the cgroups, by default the mode configured for CRIU
+    pub manage_cgroups_mode: Option<ManageCgroupsMode>,
+}
+
+impl CheckpointOptions {
+    fn criu_action(&self) -> &'static str {
+        if self.pre_dump {
+            "pre-dump"
+        } else {
+            "dump"
+        }
+    }
+
+    fn criu_flags(&self) -> Result<Vec<String>> {
+        let mut flags = Vec::new();
+        if self.leave_running && !self.pre_dump {
+            flags.push("--leave-running".to_owned());
+        }
+        if self.tcp_established {
+            flags.push("--tcp-established".to_owned());
+        }
+        if self.ext_unix_sk {
+            flags.push("--ext-unix-sk".to_owned());
+        }
+        if self.file_locks {
+            flags.push("--file-locks".to_owned());
+        }
+        if self.pre_dump || self.parent_path.is_some() {
+            flags.push("--track-mem".to_owned());
+        }
+        if let Some(parent_path) = &self.parent_path {
+            flags.push("--prev-images-dir".to_owned());
+            flags.push(parent_path.display().to_string());
+        }
+        if self.lazy_pages {
+            if self.pre_dump {
+                bail!("lazy pages cannot be used for a pre-dump");
+            }
+            flags.push("--lazy-pages".to_owned());
+        }
+        if let Some(mode) = self.manage_cgroups_mode {
+            flags.push(mode.criu_flag());
+        }
+        if let Some(page_server) = &self.page_server {
+            let (address, port) = page_server.rsplit_once(':').with_context(|| {
+                format!("invalid page server {}, expected ADDRESS:PORT", page_server)
+            })?;
+            port.parse::<u16>()
+                .with_context(|| format!("invalid port of page server {}", page_server))?;
+            flags.push("--page-server".to_owned());
+            flags.push("--address".to_owned());
+            flags.push(address.to_owned());
+            flags.push("--port".to_owned());
+            flags.push(port.to_owned());
+        }
+
+        Ok(flags)
+    }
+}
+
+impl Container {
+    /// Checkpoints the processes of the container using CRIU, so that the
+    /// container can be restored later, e.g. on another host
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use youki::container::builder::ContainerBuilder;
+    /// use youki::container::CheckpointOptions;
+    /// use youki::syscall::syscall::create_syscall;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// container.checkpoint(&CheckpointOptions {
+    ///     image_path: "checkpoint".into(),
+    ///     ..Default::default()
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn checkpoint(&mut self, opts: &CheckpointOptions) -> Result<()> {
+        let _lock = self.lock().context("failed to lock container state")?;
+        self.refresh_status()
+            .context("failed to refresh container status")?;
+
+        if !self.can_checkpoint() {
+            bail!(self.invalid_state("checkpointed"));
+        }
+        let stops = !opts.leave_running && !opts.pre_dump;
+        if stops {
+            self.check_transition(ContainerStatus::Stopped)?;
+        }
+
+        let spec = self.spec()?;
+        let pid = self.pid().context("container has no pid")?;
+        let rootfs = self
+            .bundle()
+            .join(spec.root().as_ref().context("no root in spec")?.path());
+        let work_path = opts.work_path.as_ref().unwrap_or(&opts.image_path);
+        utils::create_dir_all(&opts.image_path)?;
+        utils::create_dir_all(work_path)?;
+
+        let mut criu = Command::new(CRIU);
+        criu.arg(opts.criu_action())
+            .arg("--tree")
+            .arg(pid.to_string())
+            .arg("--images-dir")
+            .arg(&opts.image_path)
+            .arg("--work-dir")
+            .arg(work_path)
+            .arg("--log-file")
+            .arg(DUMP_LOG)
+            .arg("--root")
+            .arg(&rootfs);
+        if opts.manage_cgroups_mode.is_none() {
+            criu.arg("--manage-cgroups");
+        }
+
+        // bind mounts are external to the mount namespace of the container,
+        // CRIU has to be told to expect them and where to find them on restore
+        for mount in spec.mounts().iter().flatten() {
+            if utils::is_bind_mount(mount) {
+                let destination = mount.destination().display();
+                criu.arg("--ext-mount-map")
+                    .arg(format!("{}:{}", destination, destination));
+            }
+        }
+        criu.args(opts.criu_flags()?);
+
+        log::debug!("checkpoint container {} with {:?}", self.id(), criu);
+        let status = criu
+            .status()
+            .with_context(|| format!("failed to execute {}", CRIU))?;
+        if !status.success() {
+            bail!(
+                "criu failed to checkpoint container {}, see {} for details",
+                self.id(),
+                work_path.join(DUMP_LOG).display()
+            );
+        }
+
+        if stops {
+            self.transition(ContainerStatus::Stopped)?.save()?;
+        }
+
+        log::debug!("container {} checkpointed", self.id());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_criu_flags() -> Result<()> {
+        assert!(CheckpointOptions::default().criu_flags()?.is_empty());
+
+        let opts = CheckpointOptions {
+            leave_running: true,
+            tcp_established: true,
+            ext_unix_sk: true,
+            file_locks: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            opts.criu_flags()?,
+            vec![
+                "--leave-running",
+                "--tcp-established",
+                "--ext-unix-sk",
+                "--file-locks"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pre_dump_flags() -> Result<()> {
+        let opts = CheckpointOptions {
+            pre_dump: true,
+            leave_running: true,
+            parent_path: Some(PathBuf::from("../pre-dump-1")),
+            ..Default::default()
+        };
+        assert_eq!(opts.criu_action(), "pre-dump");
+        assert_eq!(
+            opts.criu_flags()?,
+            vec!["--track-mem", "--prev-images-dir", "../pre-dump-1"]
+        );
+
+        let opts = CheckpointOptions {
+            pre_dump: true,
+            lazy_pages: true,
+            ..Default::default()
+        };
+        assert!(opts.criu_flags().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_pages_flags() -> Result<()> {
+        let opts = CheckpointOptions {
+            lazy_pages: true,
+            page_server: Some("192.168.0.2:27".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(opts.criu_action(), "dump");
+        assert_eq!(
+            opts.criu_flags()?,
+            vec![
+                "--lazy-pages",
+                "--page-server",
+                "--address",
+                "192.168.0.2",
+                "--port",
+                "27"
+            ]
+        );
+
+        let opts = CheckpointOptions {
+            page_server: Some("192.168.0.2".to_owned()),
+            ..Default::default()
+        };
+        assert!(opts.criu_flags().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_manage_cgroups_mode() -> Result<()> {
+        for mode in &["soft", "full", "strict", "ignore"] {
+            assert_eq!(mode.parse::<ManageCgroupsMode>()?.to_string(), *mode);
+        }
+        assert!("props".parse::<ManageCgroupsMode>().is_err());
+
+        let opts = CheckpointOptions {
+            manage_cgroups_mode: Some(ManageCgroupsMode::Ignore),
+            ..Default::default()
+        };
+        assert_eq!(opts.criu_flags()?, vec!["--manage-cgroups=ignore"]);
+        Ok(())
+    }
+}
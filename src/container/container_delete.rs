@@ -3,8 +3,11 @@ use crate::hooks;
 use crate::utils;
 use anyhow::{bail, Context, Result};
 use cgroups;
+use nix::mount::{umount2, MntFlags};
 use nix::sys::signal;
+use procfs::process::Process;
 use std::fs;
+use std::path::Path;
 
 impl Container {
     /// Deletes the container
@@ -25,13 +28,15 @@ impl Container {
     /// # }
     /// ```
     pub fn delete(&mut self, force: bool) -> Result<()> {
+        let _lock = self.lock().context("failed to lock container state")?;
         self.refresh_status()
             .context("failed to refresh container status")?;
         if self.can_kill() && force {
+            self.check_transition(ContainerStatus::Stopped)?;
             let sig = signal::Signal::SIGKILL;
             log::debug!("kill signal {} to {}", sig, self.pid().unwrap());
             signal::kill(self.pid().unwrap(), sig)?;
-            self.set_status(ContainerStatus::Stopped).save()?;
+            self.transition(ContainerStatus::Stopped)?.save()?;
         }
         log::debug!("container status: {:?}", self.status());
         if self.can_delete() {
@@ -41,12 +46,6 @@ impl Container {
                 })?;
                 log::debug!("spec: {:?}", spec);
 
-                // remove the directory storing container state
-                log::debug!("remove dir {:?}", self.root);
-                fs::remove_dir_all(&self.root).with_context(|| {
-                    format!("failed to remove container dir {}", self.root.display())
-                })?;
-
                 let cgroups_path = utils::get_cgroup_path(
                     spec.linux()
                         .as_ref()
@@ -67,18 +66,53 @@ impl Container {
                     format!("failed to remove cgroup {}", cgroups_path.display())
                 })?;
 
+                if let Some(root) = spec.root() {
+                    if let Ok(rootfs) = self.bundle().join(root.path()).canonicalize() {
+                        unmount_leftovers(&rootfs);
+                    }
+                }
+
+                // remove the directory storing container state. This is done
+                // after the cgroup is removed, so a failed delete can be retried
+                log::debug!("remove dir {:?}", self.root);
+                fs::remove_dir_all(&self.root).with_context(|| {
+                    format!("failed to remove container dir {}", self.root.display())
+                })?;
+
                 if let Some(hooks) = spec.hooks() {
-                    hooks::run_hooks(hooks.poststop().as_ref(), Some(self))
+                    hooks::run_hooks("poststop", hooks.poststop().as_ref(), Some(self))
                         .with_context(|| "failed to run post stop hooks")?;
                 }
             }
-            std::process::exit(0)
+
+            Ok(())
         } else {
-            bail!(
-                "{} could not be deleted because it was {:?}",
-                self.id(),
-                self.status()
-            )
+            bail!(self.invalid_state("deleted"))
+        }
+    }
+}
+
+/// Lazily unmounts everything which is still mounted below the rootfs in the
+/// mount namespace of youki, e.g. mounts which propagated back from the
+/// container. The rootfs itself is left alone, it is mounted by the caller.
+/// Mounts are detached in reverse order, so nested mounts go first.
+fn unmount_leftovers(rootfs: &Path) {
+    let mounts = match Process::myself().and_then(|p| p.mountinfo()) {
+        Ok(mounts) => mounts,
+        Err(e) => {
+            log::warn!("failed to read mounts, skip unmounting rootfs: {}", e);
+            return;
+        }
+    };
+
+    for mount in mounts
+        .iter()
+        .rev()
+        .filter(|m| m.mount_point != rootfs && m.mount_point.starts_with(rootfs))
+    {
+        log::debug!("unmount leftover {}", mount.mount_point.display());
+        if let Err(e) = umount2(&mount.mount_point, MntFlags::MNT_DETACH) {
+            log::warn!("failed to unmount {}: {}", mount.mount_point.display(), e);
         }
     }
 }
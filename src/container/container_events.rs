@@ -2,28 +2,45 @@ use std::{thread, time::Duration};
 
 use crate::utils;
 
+use super::container_processes::{self, ProcessDetails};
 use super::{Container, ContainerStatus};
 use anyhow::{bail, Context, Result};
+use cgroups::stats::Stats;
+use serde::Serialize;
+
+/// An event emitted by the events command, serialized as a single line of JSON
+#[derive(Serialize)]
+struct Event<'a> {
+    #[serde(rename = "type")]
+    typ: &'a str,
+    id: &'a str,
+    data: &'a Stats,
+    /// processes of the container with their memory usage
+    processes: &'a [ProcessDetails],
+}
 
 impl Container {
-    /// Displays container events
+    /// Displays container events. Unless only the stats are requested, the stats
+    /// are emitted at every interval as newline delimited JSON until the
+    /// container stops. Each event lists the processes of the container as well.
     ///
     /// # Example
     ///
     /// ```no_run
     /// use youki::container::builder::ContainerBuilder;
-    /// use youki::syscall::syscall::create_syscall;;
+    /// use youki::syscall::syscall::create_syscall;
+    /// use std::time::Duration;
     ///
     /// # fn main() -> anyhow::Result<()> {
     /// let mut container = ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
     /// .as_init("/var/run/docker/bundle")
     /// .build()?;
     ///
-    /// container.events(5000, false)?;
+    /// container.events(Duration::from_secs(5), false)?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn events(&mut self, interval: u32, stats: bool) -> Result<()> {
+    pub fn events(&mut self, interval: Duration, stats: bool) -> Result<()> {
         self.refresh_status()
             .context("failed to refresh container status")?;
         if !self.state.status.eq(&ContainerStatus::Running) {
@@ -50,8 +67,24 @@ impl Container {
             }
             false => loop {
                 let stats = cgroup_manager.stats()?;
-                println!("{}", serde_json::to_string_pretty(&stats)?);
-                thread::sleep(Duration::from_secs(interval as u64));
+                let processes = container_processes::cgroup_processes(cgroup_manager.as_ref())?;
+                let event = Event {
+                    typ: "stats",
+                    id: self.id(),
+                    data: &stats,
+                    processes: &processes,
+                };
+                println!("{}", serde_json::to_string(&event)?);
+                thread::sleep(interval);
+
+                self.refresh_status()
+                    .context("failed to refresh container status")?;
+                if !matches!(
+                    self.status(),
+                    ContainerStatus::Running | ContainerStatus::Paused
+                ) {
+                    break;
+                }
             },
         }
 
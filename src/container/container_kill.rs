@@ -1,6 +1,18 @@
+use std::thread;
+use std::time::Duration;
+
 use super::{Container, ContainerStatus};
+use crate::signal::ContainerSignal;
+use crate::utils;
 use anyhow::{bail, Context, Result};
-use nix::sys::signal::{self, Signal};
+use cgroups::common::{CgroupManager, CgroupSetup, FreezerState};
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+
+// how often and how long to check whether the processes exited after SIGKILL
+const EXIT_CHECK_ATTEMPTS: u32 = 100;
+const EXIT_CHECK_INTERVAL: Duration = Duration::from_millis(10);
 
 impl Container {
     /// Sends the specified signal to the container init process
@@ -21,20 +33,130 @@ impl Container {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn kill(&mut self, signal: Signal) -> Result<()> {
+    pub fn kill<S: Into<ContainerSignal>>(&mut self, signal: S) -> Result<()> {
+        let signal = signal.into();
+        let _lock = self.lock().context("failed to lock container state")?;
         self.refresh_status()
             .context("failed to refresh container status")?;
         if self.can_kill() {
-            log::debug!("kill signal {} to {}", signal, self.pid().unwrap());
-            signal::kill(self.pid().unwrap(), signal)?;
-            self.set_status(ContainerStatus::Stopped).save()?;
-            std::process::exit(0)
+            let pid = self.pid().unwrap();
+            log::debug!("kill signal {} to {}", signal, pid);
+            send_signal(pid, signal)
+                .with_context(|| format!("failed to send signal {} to {}", signal, pid))?;
+            self.update_status_after_kill(signal, || Ok(true))
+        } else {
+            bail!(self.invalid_state("killed"))
+        }
+    }
+
+    /// Sends the specified signal to all processes of the container. SIGKILL
+    /// is sent through cgroup.kill if the cgroup supports it, so that no
+    /// process can escape by forking while the processes are enumerated. On
+    /// cgroup v1 the container is frozen while its processes are signaled
+    /// instead.
+    pub fn kill_all<S: Into<ContainerSignal>>(&mut self, signal: S) -> Result<()> {
+        let signal = signal.into();
+        let _lock = self.lock().context("failed to lock container state")?;
+        self.refresh_status()
+            .context("failed to refresh container status")?;
+        if !self.can_kill() {
+            bail!(self.invalid_state("killed"));
+        }
+
+        let cgroups_path = utils::get_cgroup_path(
+            self.spec()?
+                .linux()
+                .as_ref()
+                .context("no linux in spec")?
+                .cgroups_path(),
+            self.id(),
+        );
+        let use_systemd = self
+            .systemd()
+            .context("container state does not contain cgroup manager")?;
+        let cmanager = cgroups::common::create_cgroup_manager(cgroups_path, use_systemd)?;
+
+        if signal == Signal::SIGKILL.into() && cmanager.kill_all()? {
+            log::debug!("killed all processes of {} through cgroup.kill", self.id());
         } else {
-            bail!(
-                "{} could not be killed because it was {:?}",
-                self.id(),
-                self.status()
-            )
+            // a paused container is already frozen and stays frozen. If the
+            // container cannot be frozen, it is still signaled, a process may
+            // escape by forking then.
+            let mut freeze = self.status() != ContainerStatus::Paused
+                && !matches!(cgroups::common::get_cgroup_setup()?, CgroupSetup::Unified);
+            if freeze {
+                if let Err(e) = cmanager.freeze(FreezerState::Frozen) {
+                    log::warn!(
+                        "failed to freeze {}, signaling its processes without freezing: {:#}",
+                        self.id(),
+                        e
+                    );
+                    freeze = false;
+                }
+            }
+            // the signals are delivered once the processes are thawed
+            let result = signal_all(cmanager.as_ref(), signal);
+            if freeze {
+                cmanager
+                    .freeze(FreezerState::Thawed)
+                    .context("failed to thaw container")?;
+            }
+            result?;
         }
+
+        self.update_status_after_kill(signal, || Ok(cmanager.get_all_pids()?.is_empty()))
     }
+
+    /// The container is stopped once its init process and the processes
+    /// reported by processes_exited are gone. Only SIGKILL is certain to end
+    /// them, other signals may be handled or ignored, which leaves the status
+    /// to the next refresh. The processes of a frozen container do not exit
+    /// before it is thawed, so that it stays paused.
+    fn update_status_after_kill<F>(
+        &mut self,
+        signal: ContainerSignal,
+        processes_exited: F,
+    ) -> Result<()>
+    where
+        F: Fn() -> Result<bool>,
+    {
+        if signal != Signal::SIGKILL.into() {
+            return Ok(());
+        }
+
+        for _ in 0..EXIT_CHECK_ATTEMPTS {
+            self.refresh_status()
+                .context("failed to refresh container status")?;
+            if self.status() == ContainerStatus::Stopped && processes_exited()? {
+                self.save()?;
+                return Ok(());
+            }
+            thread::sleep(EXIT_CHECK_INTERVAL);
+        }
+
+        log::debug!("processes of {} did not exit yet after SIGKILL", self.id());
+        Ok(())
+    }
+}
+
+fn signal_all(cmanager: &dyn CgroupManager, signal: ContainerSignal) -> Result<()> {
+    for pid in cmanager.get_all_pids()? {
+        log::debug!("kill signal {} to {}", signal, pid);
+        signal_process(pid, signal)?;
+    }
+
+    Ok(())
+}
+
+fn signal_process(pid: Pid, signal: ContainerSignal) -> Result<()> {
+    match send_signal(pid, signal) {
+        // the process exited after the pids have been read
+        Ok(()) | Err(Errno::ESRCH) => Ok(()),
+        Err(e) => bail!("failed to send signal {} to {}: {}", signal, pid, e),
+    }
+}
+
+fn send_signal(pid: Pid, signal: ContainerSignal) -> nix::Result<()> {
+    // nix::sys::signal::kill cannot send realtime signals
+    Errno::result(unsafe { libc::kill(pid.as_raw(), signal.as_raw()) }).map(drop)
 }
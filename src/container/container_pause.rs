@@ -23,16 +23,14 @@ impl Container {
     /// # }
     /// ```
     pub fn pause(&mut self) -> Result<()> {
+        let _lock = self.lock().context("failed to lock container state")?;
         self.refresh_status()
             .context("failed to refresh container status")?;
 
         if !self.can_pause() {
-            bail!(
-                "{} could not be paused because it was {:?}",
-                self.id(),
-                self.status()
-            );
+            bail!(self.invalid_state("paused"));
         }
+        self.check_transition(ContainerStatus::Paused)?;
 
         let spec = self.spec()?;
         let cgroups_path = utils::get_cgroup_path(
@@ -50,7 +48,7 @@ impl Container {
         cmanager.freeze(FreezerState::Frozen)?;
 
         log::debug!("saving paused status");
-        self.set_status(ContainerStatus::Paused).save()?;
+        self.transition(ContainerStatus::Paused)?.save()?;
 
         log::debug!("container {} paused", self.id());
         Ok(())
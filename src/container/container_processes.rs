@@ -0,0 +1,123 @@
+//! Details of the processes of a container, read from procfs instead of
+//! running the ps utility, which might not be installed on the host.
+use anyhow::{Context, Result};
+use cgroups::common::CgroupManager;
+use nix::unistd::Pid;
+use procfs::process::Process;
+use procfs::ProcError;
+use serde::Serialize;
+
+use crate::utils;
+
+use super::Container;
+
+/// A process of the container as seen from the host
+#[derive(Debug, Serialize)]
+pub struct ProcessDetails {
+    pub pid: i32,
+    pub ppid: i32,
+    /// state as in the STAT column of ps, e.g. R for running
+    pub state: String,
+    /// arguments of the process, empty for kernel threads and zombies
+    pub cmdline: Vec<String>,
+    /// resident set size in bytes
+    pub rss: u64,
+}
+
+/// Reads the details of a process. Returns None if the process does not
+/// exist (anymore).
+pub fn process_details(pid: Pid) -> Result<Option<ProcessDetails>> {
+    let process = match Process::new(pid.as_raw()) {
+        Ok(process) => process,
+        Err(ProcError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read process {}", pid)),
+    };
+    let cmdline = match process.cmdline() {
+        Ok(cmdline) => cmdline,
+        Err(ProcError::NotFound(_)) => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to read cmdline of process {}", pid))
+        }
+    };
+
+    Ok(Some(ProcessDetails {
+        pid: process.stat.pid,
+        ppid: process.stat.ppid,
+        state: process.stat.state.to_string(),
+        cmdline,
+        rss: process.stat.rss_bytes().max(0) as u64,
+    }))
+}
+
+/// Reads the details of all processes in the cgroup. Processes which exit in
+/// the meantime are left out.
+pub(super) fn cgroup_processes(cmanager: &dyn CgroupManager) -> Result<Vec<ProcessDetails>> {
+    let mut processes = Vec::new();
+    for pid in cmanager.get_all_pids()? {
+        if let Some(details) = process_details(pid)? {
+            processes.push(details);
+        }
+    }
+
+    Ok(processes)
+}
+
+impl Container {
+    /// Returns the details of all processes of the container
+    pub fn processes(&self) -> Result<Vec<ProcessDetails>> {
+        let cgroups_path = utils::get_cgroup_path(
+            self.spec()?
+                .linux()
+                .as_ref()
+                .context("no linux in spec")?
+                .cgroups_path(),
+            self.id(),
+        );
+        let use_systemd = self
+            .systemd()
+            .context("could not determine cgroup manager")?;
+        let cmanager = cgroups::common::create_cgroup_manager(cgroups_path, use_systemd)?;
+        cgroup_processes(cmanager.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::signal::{self, Signal};
+    use nix::sys::wait::{self, WaitPidFlag, WaitStatus};
+    use nix::unistd;
+    use std::process::Command;
+
+    #[test]
+    fn test_process_details() -> Result<()> {
+        let details = process_details(unistd::getpid())?.context("no details of test process")?;
+        assert_eq!(details.pid, unistd::getpid().as_raw());
+        assert_eq!(details.ppid, unistd::getppid().as_raw());
+        assert!(!details.cmdline.is_empty());
+        assert!(details.rss > 0);
+
+        // pids are limited to 2^22, so this one never exists
+        assert!(process_details(Pid::from_raw(i32::MAX))?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_details_of_stopped_child() -> Result<()> {
+        let child = Command::new("sleep").arg("100").spawn()?;
+        let pid = Pid::from_raw(child.id() as i32);
+        signal::kill(pid, Signal::SIGSTOP)?;
+        let status = wait::waitpid(pid, Some(WaitPidFlag::WUNTRACED))?;
+        let details = process_details(pid);
+        signal::kill(pid, Signal::SIGKILL)?;
+        wait::waitpid(pid, None)?;
+
+        assert_eq!(status, WaitStatus::Stopped(pid, Signal::SIGSTOP));
+        let details = details?.context("no details of child process")?;
+        assert_eq!(details.pid, pid.as_raw());
+        assert_eq!(details.ppid, unistd::getpid().as_raw());
+        assert_eq!(details.state, "T");
+        assert_eq!(details.cmdline, vec!["sleep", "100"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::utils;
+
+use super::{builder_impl, Container, ContainerStatus, ManageCgroupsMode};
+use anyhow::{bail, Context, Result};
+use nix::unistd::{self, Pid};
+use oci_spec::runtime::{LinuxResources, Spec};
+
+const CRIU: &str = "criu";
+const RESTORE_LOG: &str = "restore.log";
+const RESTORE_PID_FILE: &str = "restore.pid";
+
+/// Options which are passed to CRIU to restore a container from a checkpoint
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    /// Directory the checkpoint images are read from
+    pub image_path: PathBuf,
+    /// Directory for the log and work files of CRIU, defaults to the image directory
+    pub work_path: Option<PathBuf>,
+    /// Restore established TCP connections
+    pub tcp_established: bool,
+    /// Restore unix sockets connected to peers outside of the container
+    pub ext_unix_sk: bool,
+    /// Restore file locks
+    pub file_locks: bool,
+    /// Fetch the memory pages lazily from the page server of the checkpoint
+    pub lazy_pages: bool,
+    /// How CRIU restores the cgroups, by default the mode configured for CRIU.
+    /// With soft, full or strict the cgroup properties of the checkpoint are
+    /// restored into the cgroup of the new container, with ignore youki moves
+    /// the init process and all its descendants into it.
+    pub manage_cgroups_mode: Option<ManageCgroupsMode>,
+}
+
+impl RestoreOptions {
+    fn criu_flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if self.tcp_established {
+            flags.push("--tcp-established".to_owned());
+        }
+        if self.ext_unix_sk {
+            flags.push("--ext-unix-sk".to_owned());
+        }
+        if self.file_locks {
+            flags.push("--file-locks".to_owned());
+        }
+        if self.lazy_pages {
+            flags.push("--lazy-pages".to_owned());
+        }
+        if let Some(mode) = self.manage_cgroups_mode {
+            flags.push(mode.criu_flag());
+        }
+
+        flags
+    }
+}
+
+impl Container {
+    /// Restores the processes of a checkpoint into this container. The
+    /// container may have a different id and bundle than the checkpointed
+    /// one, the cgroup of the container is derived from the new id and the
+    /// resources of its spec are applied to it.
+    pub(super) fn restore(
+        &mut self,
+        spec: &Spec,
+        opts: &RestoreOptions,
+        use_systemd: bool,
+    ) -> Result<()> {
+        // the restored container is created and running at once
+        self.check_transition(ContainerStatus::Created)?;
+        let linux = spec.linux().as_ref().context("no linux in spec")?;
+        let rootfs = spec.root().as_ref().context("no root in spec")?.path();
+        let work_path = opts.work_path.as_ref().unwrap_or(&opts.image_path);
+        utils::create_dir_all(work_path)?;
+        let pid_file = self.root.join(RESTORE_PID_FILE);
+        let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), self.id());
+
+        let mut criu = Command::new(CRIU);
+        criu.arg("restore")
+            .arg("--images-dir")
+            .arg(&opts.image_path)
+            .arg("--work-dir")
+            .arg(work_path)
+            .arg("--log-file")
+            .arg(RESTORE_LOG)
+            .arg("--root")
+            .arg(rootfs)
+            .arg("--restore-detached")
+            .arg("--pidfile")
+            .arg(&pid_file);
+        if opts.manage_cgroups_mode.is_none() {
+            criu.arg("--manage-cgroups");
+        }
+
+        // place the restored processes into the cgroup of the new container,
+        // with systemd the process is moved into its unit after the restore.
+        // CRIU does not touch the cgroups at all if they are ignored.
+        if !use_systemd && opts.manage_cgroups_mode != Some(ManageCgroupsMode::Ignore) {
+            criu.arg("--cgroup-root")
+                .arg(Path::new("/").join(&cgroups_path));
+        }
+
+        // the bind mounts recorded in the checkpoint are mapped to the sources
+        // given in the spec of the restored container
+        for mount in spec.mounts().iter().flatten() {
+            if let (true, Some(source)) = (utils::is_bind_mount(mount), mount.source()) {
+                criu.arg("--ext-mount-map").arg(format!(
+                    "{}:{}",
+                    mount.destination().display(),
+                    source.display()
+                ));
+            }
+        }
+        criu.args(opts.criu_flags());
+
+        log::debug!("restore container {} with {:?}", self.id(), criu);
+        let status = criu
+            .status()
+            .with_context(|| format!("failed to execute {}", CRIU))?;
+        if !status.success() {
+            bail!(
+                "criu failed to restore container {}, see {} for details",
+                self.id(),
+                work_path.join(RESTORE_LOG).display()
+            );
+        }
+
+        let pid: i32 = fs::read_to_string(&pid_file)
+            .with_context(|| format!("failed to read {}", pid_file.display()))?
+            .trim()
+            .parse()
+            .context("invalid pid of restored process")?;
+        let _ = fs::remove_file(&pid_file);
+        log::debug!("restored pid is {}", pid);
+
+        // the cgroup is set up even without resources. CRIU has not placed
+        // the restored processes into it with systemd or if cgroups are
+        // ignored, so youki moves all of them.
+        let cmanager = cgroups::common::create_cgroup_manager_with_unit_properties(
+            &cgroups_path,
+            use_systemd,
+            builder_impl::systemd_unit_properties(spec),
+        )?;
+        let no_resources = LinuxResources::default();
+        builder_impl::apply_cgroups(
+            linux.resources().as_ref().unwrap_or(&no_resources),
+            Pid::from_raw(pid),
+            cmanager.as_ref(),
+            builder_impl::missing_controller_policy(spec)?,
+        )?;
+        if use_systemd || opts.manage_cgroups_mode == Some(ManageCgroupsMode::Ignore) {
+            for descendant in descendants(pid)? {
+                cmanager.add_task(descendant).with_context(|| {
+                    format!("failed to move restored process {} into cgroup", descendant)
+                })?;
+            }
+        }
+
+        self.transition(ContainerStatus::Created)?
+            .transition(ContainerStatus::Running)?
+            .set_creator(unistd::geteuid().as_raw())
+            .set_pid(pid)
+            .save()
+            .context("failed to save container state")?;
+
+        log::debug!("container {} restored", self.id());
+        Ok(())
+    }
+}
+
+// Returns the descendants of the process, the children of a process are
+// found by the parent pid of all processes
+fn descendants(pid: i32) -> Result<Vec<Pid>> {
+    let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+    for process in procfs::process::all_processes().context("failed to list processes")? {
+        children
+            .entry(process.stat.ppid)
+            .or_default()
+            .push(process.pid);
+    }
+
+    let mut pids = vec![pid];
+    let mut i = 0;
+    while i < pids.len() {
+        if let Some(children) = children.get(&pids[i]) {
+            pids.extend(children);
+        }
+        i += 1;
+    }
+    Ok(pids.into_iter().skip(1).map(Pid::from_raw).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_criu_flags() {
+        assert!(RestoreOptions::default().criu_flags().is_empty());
+
+        let opts = RestoreOptions {
+            tcp_established: true,
+            lazy_pages: true,
+            ..Default::default()
+        };
+        assert_eq!(opts.criu_flags(), vec!["--tcp-established", "--lazy-pages"]);
+
+        let opts = RestoreOptions {
+            manage_cgroups_mode: Some(ManageCgroupsMode::Soft),
+            ..Default::default()
+        };
+        assert_eq!(opts.criu_flags(), vec!["--manage-cgroups=soft"]);
+    }
+
+    #[test]
+    fn test_descendants() -> Result<()> {
+        let mut child = Command::new("sleep").arg("10").spawn()?;
+        let descendants = descendants(unistd::getpid().as_raw());
+        child.kill()?;
+        child.wait()?;
+
+        let descendants = descendants?;
+        assert!(descendants.contains(&Pid::from_raw(child.id() as i32)));
+        assert!(!descendants.contains(&unistd::getpid()));
+        Ok(())
+    }
+}
@@ -24,17 +24,15 @@ impl Container {
     /// # }
     /// ```
     pub fn resume(&mut self) -> Result<()> {
+        let _lock = self.lock().context("failed to lock container state")?;
         self.refresh_status()
             .context("failed to refresh container status")?;
         // check if container can be resumed :
         // for example, a running process cannot be resumed
         if !self.can_resume() {
-            bail!(
-                "{} could not be resumed because it was {:?}",
-                self.id(),
-                self.status()
-            );
+            bail!(self.invalid_state("resumed"));
         }
+        self.check_transition(ContainerStatus::Running)?;
 
         let spec = self.spec()?;
         let cgroups_path = utils::get_cgroup_path(
@@ -54,7 +52,7 @@ impl Container {
         cmanager.freeze(FreezerState::Thawed)?;
 
         log::debug!("saving running status");
-        self.set_status(ContainerStatus::Running).save()?;
+        self.transition(ContainerStatus::Running)?.save()?;
 
         log::debug!("container {} resumed", self.id());
         Ok(())
@@ -1,4 +1,5 @@
 use crate::{
+    exec_fifo::{self, EXEC_FIFO},
     hooks,
     notify_socket::{NotifySocket, NOTIFY_FILE},
 };
@@ -26,18 +27,17 @@ impl Container {
     /// # }
     /// ```
     pub fn start(&mut self) -> Result<()> {
+        let _span = tracing::info_span!("start", container_id = %self.id()).entered();
+        let _lock = self.lock().context("failed to lock container state")?;
         self.refresh_status()
             .context("failed to refresh container status")?;
 
         if !self.can_start() {
-            let err_msg = format!(
-                "{} could not be started because it was {:?}",
-                self.id(),
-                self.status()
-            );
-            log::error!("{}", err_msg);
-            bail!(err_msg);
+            let err = self.invalid_state("started");
+            log::error!("{}", err);
+            bail!(err);
         }
+        self.check_transition(ContainerStatus::Running)?;
 
         let spec = self
             .spec()
@@ -46,22 +46,32 @@ impl Container {
             // While prestart is marked as deprecated in the OCI spec, the docker and integration test still
             // uses it.
             #[allow(deprecated)]
-            hooks::run_hooks(hooks.prestart().as_ref(), Some(self))
+            hooks::run_hooks("prestart", hooks.prestart().as_ref(), Some(self))
                 .with_context(|| "failed to run pre start hooks")?;
         }
 
         unistd::chdir(self.root.as_os_str())?;
 
-        let mut notify_socket = NotifySocket::new(&self.root.join(NOTIFY_FILE));
-        notify_socket.notify_container_start()?;
-        self.set_status(ContainerStatus::Running)
+        let notify_path = self.root.join(NOTIFY_FILE);
+        tracing::info_span!("exec").in_scope(|| {
+            // containers created by earlier versions wait on a notify socket
+            if notify_path.exists() {
+                return NotifySocket::new(&notify_path).notify_container_start();
+            }
+
+            let init_pid = self
+                .pid()
+                .with_context(|| format!("container {} has no init process", self.id()))?;
+            exec_fifo::start(&self.root.join(EXEC_FIFO), init_pid)
+        })?;
+        self.transition(ContainerStatus::Running)?
             .save()
             .with_context(|| format!("could not save state for container {}", self.id()))?;
 
         // Run post start hooks. It runs after the container process is started.
         // It is called in the runtime namespace.
         if let Some(hooks) = spec.hooks() {
-            hooks::run_hooks(hooks.poststart().as_ref(), Some(self))
+            hooks::run_hooks("poststart", hooks.poststart().as_ref(), Some(self))
                 .with_context(|| "failed to run post start hooks")?;
         }
 
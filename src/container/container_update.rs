@@ -0,0 +1,147 @@
+use std::fs;
+
+use crate::utils;
+
+use super::{builder_impl, Container};
+use anyhow::{bail, Context, Result};
+use cgroups::common::ControllerOpt;
+use oci_spec::runtime::LinuxResources;
+use serde_json::Value;
+
+impl Container {
+    /// Updates the resource limits of the container. Same as runc, only the
+    /// limits which are set in the given resources are changed, all other
+    /// limits of the container are kept. The updated limits are stored in the
+    /// spec of the container, so that they are known to later commands.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use youki::container::builder::ContainerBuilder;
+    /// use youki::syscall::syscall::create_syscall;
+    /// use oci_spec::runtime::{LinuxPidsBuilder, LinuxResourcesBuilder};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// let resources = LinuxResourcesBuilder::default()
+    ///     .pids(LinuxPidsBuilder::default().limit(100).build()?)
+    ///     .build()?;
+    /// container.update(&resources)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn update(&mut self, resources: &LinuxResources) -> Result<()> {
+        let _lock = self.lock().context("failed to lock container state")?;
+        self.refresh_status()
+            .context("failed to refresh container status")?;
+
+        if !self.can_update() {
+            bail!(self.invalid_state("updated"));
+        }
+
+        let current_spec = self.spec()?;
+        let cgroups_path = utils::get_cgroup_path(
+            current_spec
+                .linux()
+                .as_ref()
+                .context("no linux in spec")?
+                .cgroups_path(),
+            self.id(),
+        );
+
+        // the spec is updated as json, so that only the resources are changed
+        let spec_path = self.root.join("config.json");
+        let mut spec: Value = serde_json::from_str(
+            &fs::read_to_string(&spec_path)
+                .with_context(|| format!("failed to read {}", spec_path.display()))?,
+        )
+        .context("failed to parse spec of container")?;
+        let current = spec
+            .get_mut("linux")
+            .and_then(Value::as_object_mut)
+            .context("no linux in spec")?
+            .entry("resources")
+            .or_insert_with(|| Value::Object(Default::default()));
+        merge_resources(current, serde_json::to_value(resources)?);
+        let updated: LinuxResources =
+            serde_json::from_value(current.clone()).context("failed to parse updated resources")?;
+
+        let use_systemd = self
+            .systemd()
+            .context("container state does not contain cgroup manager")?;
+        let cmanager = cgroups::common::create_cgroup_manager(cgroups_path, use_systemd)?;
+        cmanager
+            .apply(&ControllerOpt {
+                resources: &updated,
+                freezer_state: None,
+                oom_score_adj: None,
+                disable_oom_killer: builder_impl::disable_oom_killer(&updated),
+                missing_controllers: builder_impl::missing_controller_policy(&current_spec)?,
+            })
+            .context("failed to apply updated resources")?;
+
+        utils::write_file_atomic(&spec_path, serde_json::to_string(&spec)?, true)?;
+
+        log::debug!("container {} updated", self.id());
+        Ok(())
+    }
+}
+
+/// Merges the update into the current resources. Objects are merged
+/// recursively, all other values of the update replace the current ones.
+/// Values which are null in the update are not set and keep the current one.
+fn merge_resources(current: &mut Value, update: Value) {
+    match (current, update) {
+        (Value::Object(current), Value::Object(update)) => {
+            for (key, value) in update {
+                if value.is_null() {
+                    continue;
+                }
+                match current.get_mut(&key) {
+                    Some(existing) => merge_resources(existing, value),
+                    None => {
+                        current.insert(key, value);
+                    }
+                }
+            }
+        }
+        (current, update) => *current = update,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_resources() {
+        let mut current = json!({
+            "memory": { "limit": 1024, "swap": 2048 },
+            "pids": { "limit": 10 },
+            "devices": [{ "allow": false, "access": "rwm" }]
+        });
+        merge_resources(
+            &mut current,
+            json!({
+                "memory": { "limit": 4096 },
+                "cpu": { "quota": 50000 },
+                "pids": null,
+                "devices": []
+            }),
+        );
+
+        assert_eq!(
+            current,
+            json!({
+                "memory": { "limit": 4096, "swap": 2048 },
+                "pids": { "limit": 10 },
+                "cpu": { "quota": 50000 },
+                "devices": []
+            })
+        );
+    }
+}
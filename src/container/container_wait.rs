@@ -0,0 +1,268 @@
+use std::os::unix::io::RawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration as TimeDelta, Utc};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::signal;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{self, Pid};
+use procfs::process::{ProcState, Process};
+
+use super::{Container, ContainerStatus};
+use crate::utils;
+
+// interval in which the process is checked without pidfd
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Opens a pidfd of the process, which becomes readable once it has exited
+pub(super) fn pidfd_open(pid: Pid) -> std::result::Result<RawFd, Errno> {
+    let res = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) };
+    Errno::result(res).map(|fd| fd as RawFd)
+}
+
+impl Container {
+    /// Blocks until the init process of the container exits, stores its exit
+    /// code in the container state and returns it. Returns None if the
+    /// timeout expires first.
+    ///
+    /// Only the parent of the init process can reap it. youki run is the
+    /// parent as child subreaper and records the exit code in the state.
+    /// Other callers, like youki wait, get the exit code from the state or
+    /// from procfs while the init process is a zombie which has not been
+    /// reaped by its parent yet. Otherwise the exit code is unknown.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use youki::container::builder::ContainerBuilder;
+    /// use youki::syscall::syscall::create_syscall;
+    /// use std::time::Duration;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// container.start()?;
+    /// let exit_code = container.wait(Some(Duration::from_secs(10)))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Option<i32>> {
+        self.refresh_status()
+            .context("failed to refresh container status")?;
+        if self.status() != ContainerStatus::Stopped {
+            let pid = self
+                .pid()
+                .with_context(|| format!("container {} has no init process", self.id()))?;
+            // a process started after the container has been created reuses
+            // the pid of the init process, which has exited already
+            if !started_after(pid, self.created()) {
+                if !wait_for_exit(pid, timeout)? {
+                    return Ok(None);
+                }
+
+                if let Some(exit_code) = exit_code(pid)? {
+                    self.set_exit_code(exit_code);
+                }
+            }
+            self.transition(ContainerStatus::Stopped)?
+                .save()
+                .with_context(|| format!("failed to save state of container {}", self.id()))?;
+        }
+
+        match self.exit_code() {
+            Some(exit_code) => Ok(Some(exit_code)),
+            None => bail!(
+                "exit code of container {} is unknown, its init process has been reaped by another process",
+                self.id()
+            ),
+        }
+    }
+}
+
+/// Blocks until the process exits and returns its exit code, e.g. of a
+/// process started with youki exec. Returns None if the timeout expires
+/// first. Like for Container::wait, the exit code can only be determined by
+/// the parent of the process or while it is a zombie.
+pub fn wait_process(pid: Pid, timeout: Option<Duration>) -> Result<Option<i32>> {
+    if !wait_for_exit(pid, timeout)? {
+        return Ok(None);
+    }
+
+    match exit_code(pid)? {
+        Some(exit_code) => Ok(Some(exit_code)),
+        None => bail!(
+            "exit code of process {} is unknown, it has been reaped by another process",
+            pid
+        ),
+    }
+}
+
+/// Returns if the process has exited and is waiting to be reaped, in which
+/// case it is no longer listed as process of its cgroup
+pub fn is_zombie(pid: Pid) -> bool {
+    Process::new(pid.as_raw())
+        .ok()
+        .and_then(|process| process.stat.state().ok())
+        .map_or(false, |state| {
+            matches!(state, ProcState::Zombie | ProcState::Dead)
+        })
+}
+
+// The start time of processes on procfs is relative to the boot time, which
+// is only given in seconds
+fn started_after(pid: Pid, time: Option<DateTime<Utc>>) -> bool {
+    let time = match time {
+        Some(time) => time,
+        None => return false,
+    };
+    Process::new(pid.as_raw())
+        .ok()
+        .and_then(|process| process.stat.starttime().ok())
+        .map_or(false, |started| {
+            started.with_timezone(&Utc) > time + TimeDelta::seconds(1)
+        })
+}
+
+// Waits until the process is a zombie or does not exist anymore. Returns
+// false if the timeout expires first.
+fn wait_for_exit(pid: Pid, timeout: Option<Duration>) -> Result<bool> {
+    let pidfd = match pidfd_open(pid) {
+        Ok(fd) => fd,
+        Err(Errno::ESRCH) => return Ok(true),
+        // pidfds are available since Linux 5.3
+        Err(Errno::ENOSYS) => return poll_for_exit(pid, timeout),
+        Err(e) => bail!("failed to open pidfd of {}: {}", pid, e),
+    };
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let result = loop {
+        let timeout_ms = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                // round up, so that the deadline has passed after a timeout
+                ((remaining.as_nanos() + 999_999) / 1_000_000).min(i32::MAX as u128) as i32
+            }
+            None => -1,
+        };
+        let mut fds = [PollFd::new(pidfd, PollFlags::POLLIN)];
+        match poll(&mut fds, timeout_ms) {
+            Ok(0) => break Ok(false),
+            Ok(_) => break Ok(true),
+            Err(Errno::EINTR) => continue,
+            Err(e) => break Err(e).with_context(|| format!("failed to poll pidfd of {}", pid)),
+        }
+    };
+
+    let _ = unistd::close(pidfd);
+    result
+}
+
+fn poll_for_exit(pid: Pid, timeout: Option<Duration>) -> Result<bool> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let exited = match Process::new(pid.as_raw()) {
+            Ok(process) => matches!(
+                process.stat.state(),
+                Ok(ProcState::Zombie) | Ok(ProcState::Dead)
+            ),
+            Err(_) => signal::kill(pid, None) == Err(Errno::ESRCH),
+        };
+        if exited {
+            return Ok(true);
+        }
+        if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            return Ok(false);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Returns the exit code of an exited process. A child is reaped, for other
+// processes it is read from procfs while they are zombies.
+fn exit_code(pid: Pid) -> Result<Option<i32>> {
+    loop {
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => bail!("process {} has not exited", pid),
+            Ok(status) => {
+                if let Some(code) = utils::exit_code(status) {
+                    return Ok(Some(code));
+                }
+            }
+            Err(Errno::EINTR) => continue,
+            Err(Errno::ECHILD) => break,
+            Err(e) => bail!("waitpid on {} failed: {}", pid, e),
+        }
+    }
+
+    Ok(Process::new(pid.as_raw())
+        .ok()
+        .and_then(|process| process.stat.exit_code)
+        .and_then(|status| WaitStatus::from_raw(pid, status).ok())
+        .and_then(utils::exit_code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use std::path::Path;
+    use std::process::Command;
+
+    #[test]
+    fn test_wait() -> Result<()> {
+        let tmp = create_temp_dir("test_wait")?;
+        let child = Command::new("sh").args(&["-c", "exit 3"]).spawn()?;
+        let mut container = Container::new(
+            "test",
+            ContainerStatus::Running,
+            Some(child.id() as i32),
+            Path::new("/bundle"),
+            &tmp,
+        )?;
+
+        assert_eq!(container.wait(None)?, Some(3));
+        assert_eq!(container.status(), ContainerStatus::Stopped);
+        assert_eq!(container.exit_code(), Some(3));
+        // the exit code stays available from the state
+        assert_eq!(container.wait(None)?, Some(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_reused_pid() -> Result<()> {
+        let tmp = create_temp_dir("test_wait_reused_pid")?;
+        let mut child = Command::new("sleep").arg("10").spawn()?;
+        let mut container = Container::new(
+            "test",
+            ContainerStatus::Running,
+            Some(child.id() as i32),
+            Path::new("/bundle"),
+            &tmp,
+        )?;
+        container.state.created = Some(Utc::now() - TimeDelta::hours(1));
+
+        // the process is not the init process, which has exited long ago
+        assert!(container.wait(Some(Duration::from_millis(10))).is_err());
+        assert_eq!(container.status(), ContainerStatus::Stopped);
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_process_timeout() -> Result<()> {
+        let mut child = Command::new("sleep").arg("10").spawn()?;
+        let pid = Pid::from_raw(child.id() as i32);
+        assert_eq!(wait_process(pid, Some(Duration::from_millis(10)))?, None);
+
+        child.kill()?;
+        assert_eq!(wait_process(pid, None)?, Some(128 + 9));
+        Ok(())
+    }
+}
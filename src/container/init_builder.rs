@@ -7,8 +7,13 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::{apparmor, notify_socket::NOTIFY_FILE, rootless, tty, utils};
+use crate::{
+    apparmor, error::YoukiError, exec_fifo::EXEC_FIFO, landlock::Landlock, rootless,
+    stdio_log::StdioLog, tty, utils, validation,
+};
 
+#[cfg(feature = "criu")]
+use super::RestoreOptions;
 use super::{
     builder::ContainerBuilder, builder_impl::ContainerBuilderImpl, Container, ContainerStatus,
 };
@@ -18,6 +23,9 @@ pub struct InitContainerBuilder<'a> {
     base: ContainerBuilder<'a>,
     bundle: PathBuf,
     use_systemd: bool,
+    no_pivot: bool,
+    ignore_oci_version: bool,
+    stdio_log: Option<StdioLog>,
 }
 
 impl<'a> InitContainerBuilder<'a> {
@@ -28,6 +36,9 @@ impl<'a> InitContainerBuilder<'a> {
             base: builder,
             bundle,
             use_systemd: true,
+            no_pivot: false,
+            ignore_oci_version: false,
+            stdio_log: None,
         }
     }
 
@@ -37,8 +48,31 @@ impl<'a> InitContainerBuilder<'a> {
         self
     }
 
+    /// Sets if the container should be jailed into its rootfs with MS_MOVE
+    /// and chroot instead of pivot_root
+    pub fn with_no_pivot(mut self, no_pivot: bool) -> Self {
+        self.no_pivot = no_pivot;
+        self
+    }
+
+    /// Sets if a spec with an ociVersion youki does not implement is accepted
+    /// with a warning instead of being rejected
+    pub fn with_ignore_oci_version(mut self, ignore: bool) -> Self {
+        self.ignore_oci_version = ignore;
+        self
+    }
+
+    /// Sets a log file the stdout and stderr of the container are captured
+    /// into by a monitor process, instead of passing the stdio of youki to the
+    /// container. It cannot be used together with a terminal.
+    pub fn with_stdio_log(mut self, stdio_log: Option<StdioLog>) -> Self {
+        self.stdio_log = stdio_log;
+        self
+    }
+
     /// Creates a new container
     pub fn build(self) -> Result<Container> {
+        let _span = tracing::info_span!("create", container_id = %self.base.container_id).entered();
         let spec = self.load_spec()?;
         let container_dir = self.create_container_dir()?;
         self.save_spec(&spec, &container_dir)?;
@@ -48,24 +82,45 @@ impl<'a> InitContainerBuilder<'a> {
             .set_systemd(self.use_systemd)
             .set_annotations(spec.annotations().clone());
 
+        let terminal = spec
+            .process()
+            .as_ref()
+            .and_then(|p| p.terminal())
+            .unwrap_or(false);
+        let stdio_pipes = match &self.stdio_log {
+            Some(_) if terminal => bail!("stdio log cannot be used with process.terminal"),
+            Some(stdio_log) => Some(stdio_log.spawn()?),
+            None => None,
+        };
+
         unistd::chdir(&container_dir)?;
-        let notify_path = container_dir.join(NOTIFY_FILE);
+        let notify_path = container_dir.join(EXEC_FIFO);
         // convert path of root file system of the container to absolute path
         let rootfs = fs::canonicalize(&spec.root().as_ref().context("no root in spec")?.path())?;
 
         // if socket file path is given in commandline options,
-        // get file descriptors of console socket
+        // get file descriptors of console socket. Without a terminal the
+        // container process uses the stdio of youki directly.
         let csocketfd = if let Some(console_socket) = &self.base.console_socket {
-            Some(tty::setup_console_socket(
-                &container_dir,
-                console_socket,
-                "console-socket",
-            )?)
+            if !terminal {
+                log::warn!(
+                    "ignoring console socket {}, process.terminal is not set in the spec",
+                    console_socket.display()
+                );
+                None
+            } else {
+                Some(tty::setup_console_socket(
+                    &container_dir,
+                    console_socket,
+                    "console-socket",
+                )?)
+            }
         } else {
             None
         };
 
         let rootless = Rootless::new(&spec)?;
+        let landlock = Landlock::load(&self.bundle.join("config.json"))?;
         let mut builder_impl = ContainerBuilderImpl {
             init: true,
             syscall: self.base.syscall,
@@ -79,6 +134,10 @@ impl<'a> InitContainerBuilder<'a> {
             notify_path,
             container: Some(container.clone()),
             preserve_fds: self.base.preserve_fds,
+            no_pivot: self.no_pivot,
+            landlock,
+            cgroup_paths: Vec::new(),
+            stdio_pipes,
         };
 
         builder_impl.create()?;
@@ -87,12 +146,41 @@ impl<'a> InitContainerBuilder<'a> {
         Ok(container)
     }
 
+    /// Creates a new container by restoring a checkpoint with CRIU. The
+    /// checkpoint may have been taken of a container with another id and
+    /// bundle, e.g. on another host.
+    #[cfg(feature = "criu")]
+    pub fn restore(self, opts: &RestoreOptions) -> Result<Container> {
+        let spec = self.load_spec()?;
+        let container_dir = self.create_container_dir()?;
+        self.save_spec(&spec, &container_dir)?;
+
+        let mut container = self.create_container_state(&container_dir)?;
+        container
+            .set_systemd(self.use_systemd)
+            .set_annotations(spec.annotations().clone());
+
+        if let Err(e) = container.restore(&spec, opts, self.use_systemd) {
+            if let Err(e) = fs::remove_dir_all(&container_dir) {
+                log::warn!("failed to remove {}: {}", container_dir.display(), e);
+            }
+            return Err(e);
+        }
+
+        if let (Some(pid_file), Some(pid)) = (&self.base.pid_file, container.pid()) {
+            utils::write_file_atomic(pid_file, format!("{}", pid), false)
+                .context("Failed to write pid file")?;
+        }
+
+        Ok(container)
+    }
+
     fn create_container_dir(&self) -> Result<PathBuf> {
         let container_dir = self.base.root_path.join(&self.base.container_id);
         log::debug!("container directory will be {:?}", container_dir);
 
         if container_dir.exists() {
-            bail!("container {} already exists", self.base.container_id);
+            bail!(YoukiError::AlreadyExists(self.base.container_id.clone()));
         }
 
         utils::create_dir_all(&container_dir)?;
@@ -100,21 +188,19 @@ impl<'a> InitContainerBuilder<'a> {
     }
 
     fn load_spec(&self) -> Result<Spec> {
+        let _span = tracing::info_span!("load_spec").entered();
         let source_spec_path = self.bundle.join("config.json");
         let mut spec = Spec::load(&source_spec_path)?;
-        Self::validate_spec(&spec).context("failed to validate runtime spec")?;
+        Self::validate_spec(&spec, self.ignore_oci_version)
+            .context("failed to validate runtime spec")?;
+        validation::validate(&spec, &self.bundle)?;
 
         spec.canonicalize_rootfs(&self.bundle)?;
         Ok(spec)
     }
 
-    fn validate_spec(spec: &Spec) -> Result<()> {
-        if !spec.version().starts_with("1.0") {
-            bail!(
-                "runtime spec has incompatible version '{}'. Only 1.0.X is supported",
-                spec.version()
-            );
-        }
+    fn validate_spec(spec: &Spec, ignore_oci_version: bool) -> Result<()> {
+        validation::check_oci_version(spec.version(), ignore_oci_version)?;
 
         if let Some(process) = spec.process() {
             if let Some(profile) = process.apparmor_profile() {
@@ -144,7 +230,8 @@ impl<'a> InitContainerBuilder<'a> {
             None,
             &self.bundle,
             container_dir,
-        )?;
+        )?
+        .with_store(self.base.state_store.clone());
         container.save()?;
         Ok(container)
     }
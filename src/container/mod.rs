@@ -4,18 +4,35 @@
 /// namespaces and cgroups will be created (usually) and a tenant container process that will move
 /// into the existing namespaces and cgroups of the initial container process (e.g. used to implement
 /// the exec command).
+#[cfg(feature = "async")]
+pub mod async_api;
 pub mod builder;
 mod builder_impl;
 #[allow(clippy::module_inception)]
 mod container;
+#[cfg(feature = "criu")]
+mod container_checkpoint;
 mod container_delete;
 mod container_events;
 mod container_kill;
 mod container_pause;
+mod container_processes;
+#[cfg(feature = "criu")]
+mod container_restore;
 mod container_resume;
 mod container_start;
+mod container_update;
+mod container_wait;
 pub mod init_builder;
 pub mod state;
+pub mod store;
 pub mod tenant_builder;
 pub use container::Container;
+#[cfg(feature = "criu")]
+pub use container_checkpoint::{CheckpointOptions, ManageCgroupsMode};
+pub use container_processes::{process_details, ProcessDetails};
+#[cfg(feature = "criu")]
+pub use container_restore::RestoreOptions;
+pub use container_wait::{is_zombie, wait_process};
 pub use state::{ContainerStatus, State};
+pub use store::{DirectoryStore, MemoryStore, StateStore};
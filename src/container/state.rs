@@ -1,13 +1,18 @@
 //! Information about status and state of the container
 use std::collections::HashMap;
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
 use std::fs;
 use std::path::PathBuf;
 use std::{fs::File, path::Path};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use chrono::{DateTime, Utc};
+use nix::fcntl::{flock, FlockArg};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::os::unix::io::AsRawFd;
+
+use crate::utils;
 
 /// Indicates status of the container
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
@@ -55,6 +60,36 @@ impl ContainerStatus {
     pub fn can_resume(&self) -> bool {
         matches!(self, ContainerStatus::Paused)
     }
+
+    pub fn can_checkpoint(&self) -> bool {
+        matches!(self, ContainerStatus::Running | ContainerStatus::Paused)
+    }
+
+    /// Checks if the status may change to the next one, following the
+    /// lifecycle of the runtime spec. Once stopped, the status of a container
+    /// never changes again.
+    pub fn can_transition_to(&self, next: ContainerStatus) -> bool {
+        use ContainerStatus::*;
+        matches!(
+            (self, next),
+            (Creating, Created)
+                | (Created, Running)
+                | (Running, Paused)
+                | (Paused, Running)
+                | (Creating, Stopped)
+                | (Created, Stopped)
+                | (Running, Stopped)
+                | (Paused, Stopped)
+        )
+    }
+
+    pub fn can_update(&self) -> bool {
+        use ContainerStatus::*;
+        match self {
+            Creating | Stopped => false,
+            Created | Running | Paused => true,
+        }
+    }
 }
 
 impl Display for ContainerStatus {
@@ -71,10 +106,23 @@ impl Display for ContainerStatus {
     }
 }
 
+/// Version of the format of the persisted state. It has to be increased
+/// whenever the format changes incompatibly, together with a migration from
+/// the previous version.
+pub const STATE_VERSION: u32 = 1;
+
+/// Upgrades the persisted state from the version at its index to the next
+/// one, so that containers created by an older youki can still be managed.
+const MIGRATIONS: &[fn(&mut Value) -> Result<()>] = &[migrate_v0_to_v1];
+
 /// Stores the state information of the container
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct State {
+    // Version of the format of the persisted state, states written before the
+    // format was versioned have version 0
+    #[serde(default)]
+    pub state_version: u32,
     // Version is the version of the specification that is supported.
     pub oci_version: String,
     // ID is the container ID
@@ -97,10 +145,29 @@ pub struct State {
     pub creator: Option<u32>,
     // Specifies if systemd should be used to manage cgroups
     pub use_systemd: Option<bool>,
+    // Exit code of the container process, once it has exited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+/// Exclusive lock on the state of a container, which is released when dropped
+#[derive(Debug)]
+pub struct StateLock {
+    _guard: Box<dyn Debug + Send>,
+}
+
+impl StateLock {
+    /// Creates a lock which is held until the guard is dropped
+    pub fn new<G: Debug + Send + 'static>(guard: G) -> Self {
+        Self {
+            _guard: Box::new(guard),
+        }
+    }
 }
 
 impl State {
     const STATE_FILE_PATH: &'static str = "state.json";
+    const LOCK_FILE_PATH: &'static str = "state.lock";
 
     pub fn new(
         container_id: &str,
@@ -109,6 +176,7 @@ impl State {
         bundle: PathBuf,
     ) -> Self {
         Self {
+            state_version: STATE_VERSION,
             oci_version: "v1.0.2".to_string(),
             id: container_id.to_string(),
             status,
@@ -118,21 +186,30 @@ impl State {
             created: None,
             creator: None,
             use_systemd: None,
+            exit_code: None,
         }
     }
 
     pub fn save(&self, container_root: &Path) -> Result<()> {
-        let state_file_path = Self::file_path(container_root);
+        // concurrent readers see either the old or the new state, but never
+        // a partially written one
+        let state = serde_json::to_vec(self).context("failed to serialize state")?;
+        utils::write_file_atomic(Self::file_path(container_root), state, true)
+    }
+
+    /// Acquires an exclusive lock on the state of the container. Blocks until
+    /// concurrent holders of the lock have released it.
+    pub fn lock(container_root: &Path) -> Result<StateLock> {
+        let lock_file_path = container_root.join(Self::LOCK_FILE_PATH);
         let file = fs::OpenOptions::new()
-            .read(true)
             .write(true)
-            .append(false)
             .create(true)
-            .truncate(true)
-            .open(&state_file_path)
-            .with_context(|| format!("failed to open {}", state_file_path.display()))?;
-        serde_json::to_writer(&file, self)?;
-        Ok(())
+            .open(&lock_file_path)
+            .with_context(|| format!("failed to open {}", lock_file_path.display()))?;
+        flock(file.as_raw_fd(), FlockArg::LockExclusive)
+            .with_context(|| format!("failed to lock {}", lock_file_path.display()))?;
+
+        Ok(StateLock::new(file))
     }
 
     pub fn load(container_root: &Path) -> Result<Self> {
@@ -140,10 +217,40 @@ impl State {
         let file = File::open(&state_file_path).with_context(|| {
             format!("failed to open container state file {:?}", state_file_path)
         })?;
-        let state: Self = serde_json::from_reader(&file)?;
+        let mut state: Value = serde_json::from_reader(&file)
+            .with_context(|| format!("failed to parse {}", state_file_path.display()))?;
+        Self::migrate(&mut state)
+            .with_context(|| format!("failed to migrate {}", state_file_path.display()))?;
+        let state: Self = serde_json::from_value(state)?;
         Ok(state)
     }
 
+    /// Upgrades the persisted state to the current version. The upgraded
+    /// state is written back with the next save.
+    fn migrate(state: &mut Value) -> Result<()> {
+        let version = match state.get("stateVersion") {
+            Some(version) => version
+                .as_u64()
+                .with_context(|| format!("invalid state version {}", version))?,
+            None => 0,
+        };
+        if version > STATE_VERSION as u64 {
+            bail!(
+                "state has version {}, but this version of youki only supports up to {}",
+                version,
+                STATE_VERSION
+            );
+        }
+
+        for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            log::debug!("migrate container state from version {}", from);
+            migration(state)?;
+            state["stateVersion"] = Value::from(from + 1);
+        }
+
+        Ok(())
+    }
+
     /// Returns the path to the state JSON file for the provided `container_root`.
     ///
     /// ```
@@ -159,6 +266,15 @@ impl State {
     }
 }
 
+/// The first versioned format is the same as the unversioned one
+fn migrate_v0_to_v1(state: &mut Value) -> Result<()> {
+    if !state.is_object() {
+        bail!("state is not an object");
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -181,6 +297,7 @@ mod tests {
         assert!(cstatus.can_kill());
         assert!(!cstatus.can_pause());
         assert!(!cstatus.can_resume());
+        assert!(cstatus.can_update());
     }
 
     #[test]
@@ -191,6 +308,8 @@ mod tests {
         assert!(cstatus.can_kill());
         assert!(cstatus.can_pause());
         assert!(!cstatus.can_resume());
+        assert!(cstatus.can_checkpoint());
+        assert!(cstatus.can_update());
     }
 
     #[test]
@@ -201,6 +320,81 @@ mod tests {
         assert!(!cstatus.can_kill());
         assert!(!cstatus.can_pause());
         assert!(!cstatus.can_resume());
+        assert!(!cstatus.can_checkpoint());
+        assert!(!cstatus.can_update());
+    }
+
+    #[test]
+    fn test_status_transitions() {
+        use ContainerStatus::*;
+        assert!(Creating.can_transition_to(Created));
+        assert!(Created.can_transition_to(Running));
+        assert!(Running.can_transition_to(Paused));
+        assert!(Paused.can_transition_to(Running));
+        for status in &[Creating, Created, Running, Paused] {
+            assert!(status.can_transition_to(Stopped));
+        }
+
+        assert!(!Creating.can_transition_to(Running));
+        assert!(!Created.can_transition_to(Paused));
+        assert!(!Paused.can_transition_to(Created));
+        for status in &[Creating, Created, Running, Paused, Stopped] {
+            assert!(!Stopped.can_transition_to(*status));
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let dir = crate::utils::create_temp_dir("test_state_save_and_load")?;
+        let state = State::new(
+            "container",
+            ContainerStatus::Running,
+            Some(1),
+            dir.to_path_buf(),
+        );
+        state.save(&dir)?;
+        // saving twice replaces the state file
+        state.save(&dir)?;
+
+        let loaded = State::load(&dir)?;
+        assert_eq!(loaded.id, state.id);
+        assert_eq!(loaded.status, state.status);
+        assert_eq!(loaded.pid, state.pid);
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_and_migrate() -> Result<()> {
+        let dir = crate::utils::create_temp_dir("test_state_load_and_migrate")?;
+        fs::write(
+            State::file_path(&dir),
+            r#"{"ociVersion": "v1.0.2", "id": "container", "status": "running", "pid": 1, "bundle": "/bundle", "useSystemd": false}"#,
+        )?;
+        let loaded = State::load(&dir)?;
+        assert_eq!(loaded.state_version, STATE_VERSION);
+        assert_eq!(loaded.id, "container");
+        assert_eq!(loaded.status, ContainerStatus::Running);
+
+        fs::write(
+            State::file_path(&dir),
+            format!(
+                r#"{{"stateVersion": {}, "ociVersion": "v1.0.2", "id": "container", "status": "running", "bundle": "/bundle", "useSystemd": false}}"#,
+                STATE_VERSION + 1
+            ),
+        )?;
+        assert!(State::load(&dir).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock() -> Result<()> {
+        let dir = crate::utils::create_temp_dir("test_state_lock")?;
+        let lock = State::lock(&dir)?;
+        drop(lock);
+        // the lock can be acquired again once released
+        let _lock = State::lock(&dir)?;
+        Ok(())
     }
 
     #[test]
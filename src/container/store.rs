@@ -0,0 +1,168 @@
+//! Persistence of the container state. By default the state is stored in a
+//! file in the directory of the container, so that it is shared between
+//! invocations of youki. Embedders which manage containers from a single
+//! process, e.g. in tests, can keep the state in memory instead.
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+use anyhow::{bail, Result};
+
+use super::state::{State, StateLock};
+
+/// Backend the state of containers is persisted in. Containers are identified
+/// by their container root.
+pub trait StateStore: Debug + Send + Sync {
+    /// Loads the state of the container
+    fn load(&self, container_root: &Path) -> Result<State>;
+
+    /// Persists the state of the container, replacing the previous one
+    fn save(&self, container_root: &Path, state: &State) -> Result<()>;
+
+    /// Acquires an exclusive lock on the state of the container. Blocks until
+    /// concurrent holders of the lock have released it.
+    fn lock(&self, container_root: &Path) -> Result<StateLock>;
+}
+
+/// Stores the state in the state file of the container root
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DirectoryStore;
+
+impl StateStore for DirectoryStore {
+    fn load(&self, container_root: &Path) -> Result<State> {
+        State::load(container_root)
+    }
+
+    fn save(&self, container_root: &Path, state: &State) -> Result<()> {
+        state.save(container_root)
+    }
+
+    fn lock(&self, container_root: &Path) -> Result<StateLock> {
+        State::lock(container_root)
+    }
+}
+
+/// Keeps the state in memory. The state is only visible to the process
+/// which stores it and is lost when the store is dropped.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    states: Mutex<HashMap<PathBuf, State>>,
+    locks: Arc<MemoryLocks>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryLocks {
+    locked: Mutex<HashSet<PathBuf>>,
+    released: Condvar,
+}
+
+#[derive(Debug)]
+struct MemoryLockGuard {
+    locks: Arc<MemoryLocks>,
+    container_root: PathBuf,
+}
+
+impl Drop for MemoryLockGuard {
+    fn drop(&mut self) {
+        let mut locked = self
+            .locks
+            .locked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        locked.remove(&self.container_root);
+        self.locks.released.notify_all();
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn load(&self, container_root: &Path) -> Result<State> {
+        let states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+        match states.get(container_root) {
+            Some(state) => Ok(state.clone()),
+            None => bail!("no state stored for {}", container_root.display()),
+        }
+    }
+
+    fn save(&self, container_root: &Path, state: &State) -> Result<()> {
+        let mut states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+        states.insert(container_root.to_path_buf(), state.clone());
+        Ok(())
+    }
+
+    fn lock(&self, container_root: &Path) -> Result<StateLock> {
+        let mut locked = self
+            .locks
+            .locked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        while locked.contains(container_root) {
+            locked = self
+                .locks
+                .released
+                .wait(locked)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        locked.insert(container_root.to_path_buf());
+
+        Ok(StateLock::new(MemoryLockGuard {
+            locks: Arc::clone(&self.locks),
+            container_root: container_root.to_path_buf(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{Container, ContainerStatus};
+    use crate::utils::create_temp_dir;
+    use std::thread;
+
+    #[test]
+    fn test_memory_store() -> Result<()> {
+        let store = MemoryStore::default();
+        let root = Path::new("/run/youki/test");
+        assert!(store.load(root).is_err());
+
+        let state = State::new("test", ContainerStatus::Created, Some(1), "/bundle".into());
+        store.save(root, &state)?;
+        let loaded = store.load(root)?;
+        assert_eq!(loaded.id, "test");
+        assert_eq!(loaded.status, ContainerStatus::Created);
+        assert_eq!(loaded.pid, Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_store_lock() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let root = Path::new("/run/youki/test");
+        let lock = store.lock(root)?;
+
+        let waiter = {
+            let store = Arc::clone(&store);
+            thread::spawn(move || store.lock(root).map(|_| ()))
+        };
+        // locks of other containers are independent
+        let _other = store.lock(Path::new("/run/youki/other"))?;
+        drop(lock);
+        waiter.join().expect("lock thread panicked")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_with_memory_store() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let dir = create_temp_dir("test_container_with_memory_store")?;
+        let mut container = Container::new("test", ContainerStatus::Created, None, &dir, &dir)?
+            .with_store(store.clone());
+        container.save()?;
+        assert!(!State::file_path(&container.root).exists());
+
+        container.set_status(ContainerStatus::Stopped).save()?;
+        let loaded = Container::load_from(store, container.root.clone())?;
+        assert_eq!(loaded.status(), ContainerStatus::Stopped);
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::{bail, Context, Result};
 use caps::Capability;
-use nix::unistd;
+use cgroups::common::CGROUP_PROCS;
+use nix::unistd::{self, AccessFlags};
 use oci_spec::runtime::{
     Capabilities as SpecCapabilities, Capability as SpecCapability, LinuxBuilder,
     LinuxCapabilities, LinuxCapabilitiesBuilder, LinuxNamespace, LinuxNamespaceBuilder,
@@ -18,11 +19,16 @@ use std::{
 };
 
 use crate::{capabilities::CapabilityExt, container::builder_impl::ContainerBuilderImpl};
-use crate::{notify_socket::NotifySocket, rootless::Rootless, tty, utils};
+use crate::{
+    landlock::Landlock,
+    notify_socket::NotifySocket,
+    rootless::{rootless_required, Rootless},
+    tty, utils,
+};
 
 use super::{builder::ContainerBuilder, Container};
 
-const NAMESPACE_TYPES: &[&str] = &["ipc", "uts", "net", "pid", "mnt", "cgroup"];
+const NAMESPACE_TYPES: &[&str] = &["user", "ipc", "uts", "net", "pid", "mnt", "cgroup"];
 const TENANT_NOTIFY: &str = "tenant-notify-";
 const TENANT_TTY: &str = "tenant-tty-";
 
@@ -36,6 +42,9 @@ pub struct TenantContainerBuilder<'a> {
     no_new_privs: Option<bool>,
     capabilities: Vec<String>,
     process: Option<PathBuf>,
+    cgroup: Option<PathBuf>,
+    apparmor_profile: Option<String>,
+    process_label: Option<String>,
 }
 
 impl<'a> TenantContainerBuilder<'a> {
@@ -51,6 +60,9 @@ impl<'a> TenantContainerBuilder<'a> {
             no_new_privs: None,
             capabilities: Vec::new(),
             process: None,
+            cgroup: None,
+            apparmor_profile: None,
+            process_label: None,
         }
     }
 
@@ -87,6 +99,26 @@ impl<'a> TenantContainerBuilder<'a> {
         self
     }
 
+    /// Places the process in a sub-cgroup of the container, which must exist
+    pub fn with_cgroup<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
+        self.cgroup = path.map(|p| p.into());
+        self
+    }
+
+    /// Sets the AppArmor profile of the process, instead of the one of the
+    /// process document or of the init process
+    pub fn with_apparmor_profile<S: Into<String>>(mut self, profile: Option<S>) -> Self {
+        self.apparmor_profile = profile.map(|p| p.into());
+        self
+    }
+
+    /// Sets the SELinux label of the process, instead of the one of the
+    /// process document or of the init process
+    pub fn with_process_label<S: Into<String>>(mut self, label: Option<S>) -> Self {
+        self.process_label = label.map(|l| l.into());
+        self
+    }
+
     /// Joins an existing container
     pub fn build(self) -> Result<()> {
         let container_dir = self.lookup_container_dir()?;
@@ -108,6 +140,10 @@ impl<'a> TenantContainerBuilder<'a> {
 
         let use_systemd = self.should_use_systemd(&container);
         let rootless = Rootless::new(&spec)?;
+        let cgroup_paths = self.get_cgroup_paths(&container)?;
+        // the spec saved with the container state drops the landlock
+        // extension, so it is read from the bundle like for the init process
+        let landlock = Landlock::load(&container.bundle().join("config.json"))?;
 
         let mut builder_impl = ContainerBuilderImpl {
             init: false,
@@ -122,6 +158,10 @@ impl<'a> TenantContainerBuilder<'a> {
             notify_path: notify_path.clone(),
             container: None,
             preserve_fds: self.base.preserve_fds,
+            no_pivot: false,
+            landlock,
+            cgroup_paths,
+            stdio_pipes: None,
         };
 
         builder_impl.create()?;
@@ -148,7 +188,7 @@ impl<'a> TenantContainerBuilder<'a> {
     }
 
     fn load_container_state(&self, container_dir: PathBuf) -> Result<Container> {
-        let container = Container::load(container_dir)?;
+        let container = Container::load_from(self.base.state_store.clone(), container_dir)?;
         if !container.can_exec() {
             bail!(
                 "Cannot exec as container is in state {}",
@@ -183,6 +223,15 @@ impl<'a> TenantContainerBuilder<'a> {
                 None => process_builder,
             };
 
+            process_builder = match self.set_apparmor_profile(spec) {
+                Some(profile) => process_builder.apparmor_profile(profile),
+                None => process_builder,
+            };
+            process_builder = match self.set_process_label(spec) {
+                Some(label) => process_builder.selinux_label(label),
+                None => process_builder,
+            };
+
             process_builder.build()?
         };
 
@@ -191,7 +240,8 @@ impl<'a> TenantContainerBuilder<'a> {
         }
 
         let init_process = procfs::process::Process::new(container.pid().unwrap().as_raw())?;
-        let ns = self.set_namespaces(init_process.namespaces()?)?;
+        let own_namespaces = procfs::process::Process::myself()?.namespaces()?;
+        let ns = self.set_namespaces(init_process.namespaces()?, own_namespaces)?;
         let linux = LinuxBuilder::default().namespaces(ns).build()?;
 
         let mut spec_builder = SpecBuilder::default()
@@ -233,8 +283,18 @@ impl<'a> TenantContainerBuilder<'a> {
         }
 
         let process = utils::open(process)?;
-        let process_spec = serde_json::from_reader(process)?;
-        Ok(process_spec)
+        let mut process_spec: serde_json::Value = serde_json::from_reader(process)?;
+        // the profile and label given on the command line take precedence
+        if let Some(process_spec) = process_spec.as_object_mut() {
+            if let Some(profile) = &self.apparmor_profile {
+                process_spec.insert("apparmorProfile".to_owned(), profile.clone().into());
+            }
+            if let Some(label) = &self.process_label {
+                process_spec.insert("selinuxLabel".to_owned(), label.clone().into());
+            }
+        }
+
+        Ok(serde_json::from_value(process_spec)?)
     }
 
     fn set_working_dir(&self) -> Result<Option<PathBuf>> {
@@ -270,6 +330,24 @@ impl<'a> TenantContainerBuilder<'a> {
         self.no_new_privs
     }
 
+    // without a process document, the process is confined like the init
+    // process unless a profile is given
+    fn set_apparmor_profile(&self, spec: &Spec) -> Option<String> {
+        self.apparmor_profile.clone().or_else(|| {
+            spec.process()
+                .as_ref()
+                .and_then(|p| p.apparmor_profile().clone())
+        })
+    }
+
+    fn set_process_label(&self, spec: &Spec) -> Option<String> {
+        self.process_label.clone().or_else(|| {
+            spec.process()
+                .as_ref()
+                .and_then(|p| p.selinux_label().clone())
+        })
+    }
+
     fn set_capabilities(&self, spec: &Spec) -> Result<Option<LinuxCapabilities>> {
         if !self.capabilities.is_empty() {
             let mut caps: Vec<Capability> = Vec::with_capacity(self.capabilities.len());
@@ -342,11 +420,27 @@ impl<'a> TenantContainerBuilder<'a> {
         Ok(None)
     }
 
-    fn set_namespaces(&self, init_namespaces: Vec<Namespace>) -> Result<Vec<LinuxNamespace>> {
+    fn set_namespaces(
+        &self,
+        init_namespaces: Vec<Namespace>,
+        own_namespaces: Vec<Namespace>,
+    ) -> Result<Vec<LinuxNamespace>> {
         let mut tenant_namespaces = Vec::with_capacity(init_namespaces.len());
 
         for &ns_type in NAMESPACE_TYPES {
             if let Some(init_ns) = init_namespaces.iter().find(|n| n.ns_type == ns_type) {
+                // a process cannot join the user namespace it is already in,
+                // e.g. if the container has no user namespace of its own
+                if ns_type == "user"
+                    && own_namespaces.iter().any(|n| {
+                        n.ns_type == ns_type
+                            && n.identifier == init_ns.identifier
+                            && n.device_id == init_ns.device_id
+                    })
+                {
+                    continue;
+                }
+
                 let tenant_ns = LinuxNamespaceType::try_from(ns_type)?;
                 tenant_namespaces.push(
                     LinuxNamespaceBuilder::default()
@@ -360,6 +454,32 @@ impl<'a> TenantContainerBuilder<'a> {
         Ok(tenant_namespaces)
     }
 
+    fn get_cgroup_paths(&self, container: &Container) -> Result<Vec<PathBuf>> {
+        match &self.cgroup {
+            Some(sub_cgroup) => {
+                let init_pid = container
+                    .pid()
+                    .context("could not retrieve container init pid")?;
+                cgroups::common::get_sub_cgroup_paths(init_pid, sub_cgroup)
+            }
+            // like the init process, the process joins the cgroups of the
+            // container. Rootless containers may not be allowed to move
+            // processes into them, those cgroups are left alone.
+            None => {
+                let init_pid = container
+                    .pid()
+                    .context("could not retrieve container init pid")?;
+                let mut paths = cgroups::common::get_sub_cgroup_paths(init_pid, Path::new(""))?;
+                if rootless_required() {
+                    paths.retain(|path| {
+                        unistd::access(&path.join(CGROUP_PROCS), AccessFlags::W_OK).is_ok()
+                    });
+                }
+                Ok(paths)
+            }
+        }
+    }
+
     fn should_use_systemd(&self, container: &Container) -> bool {
         if let Some(use_systemd) = container.systemd() {
             return use_systemd;
@@ -400,3 +520,92 @@ impl<'a> TenantContainerBuilder<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syscall::test::TestHelperSyscall;
+    use crate::utils::create_temp_dir;
+
+    fn init_spec() -> Result<Spec> {
+        let process = ProcessBuilder::default()
+            .args(vec!["sleep".to_owned(), "1000".to_owned()])
+            .apparmor_profile("init-profile")
+            .selinux_label("system_u:system_r:init_t:s0")
+            .build()?;
+        Ok(SpecBuilder::default().process(process).build()?)
+    }
+
+    #[test]
+    fn test_set_process_overrides_confinement() -> Result<()> {
+        let tmp = create_temp_dir("test_set_process_overrides_confinement")?;
+        let process_path = tmp.join("process.json");
+        fs::write(
+            &process_path,
+            r#"{"cwd": "/", "args": ["sh"], "apparmorProfile": "document-profile", "selinuxLabel": "system_u:system_r:document_t:s0"}"#,
+        )?;
+        let syscall = TestHelperSyscall::default();
+
+        let builder = ContainerBuilder::new("test".to_owned(), &syscall).as_tenant();
+        let process = builder.set_process(&process_path)?;
+        assert_eq!(
+            process.apparmor_profile().as_deref(),
+            Some("document-profile")
+        );
+        assert_eq!(
+            process.selinux_label().as_deref(),
+            Some("system_u:system_r:document_t:s0")
+        );
+
+        let builder = ContainerBuilder::new("test".to_owned(), &syscall)
+            .as_tenant()
+            .with_apparmor_profile(Some("cli-profile"))
+            .with_process_label(Some("system_u:system_r:cli_t:s0"));
+        let process = builder.set_process(&process_path)?;
+        assert_eq!(process.apparmor_profile().as_deref(), Some("cli-profile"));
+        assert_eq!(
+            process.selinux_label().as_deref(),
+            Some("system_u:system_r:cli_t:s0")
+        );
+        assert_eq!(process.args().as_deref(), Some(&["sh".to_owned()][..]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_confinement_falls_back_to_init_process() -> Result<()> {
+        let spec = init_spec()?;
+        let syscall = TestHelperSyscall::default();
+
+        let builder = ContainerBuilder::new("test".to_owned(), &syscall).as_tenant();
+        assert_eq!(
+            builder.set_apparmor_profile(&spec).as_deref(),
+            Some("init-profile")
+        );
+        assert_eq!(
+            builder.set_process_label(&spec).as_deref(),
+            Some("system_u:system_r:init_t:s0")
+        );
+
+        let builder = ContainerBuilder::new("test".to_owned(), &syscall)
+            .as_tenant()
+            .with_apparmor_profile(Some("cli-profile"))
+            .with_process_label(Some("system_u:system_r:cli_t:s0"));
+        assert_eq!(
+            builder.set_apparmor_profile(&spec).as_deref(),
+            Some("cli-profile")
+        );
+        assert_eq!(
+            builder.set_process_label(&spec).as_deref(),
+            Some("system_u:system_r:cli_t:s0")
+        );
+
+        let process = ProcessBuilder::default()
+            .args(vec!["sleep".to_owned(), "1000".to_owned()])
+            .build()?;
+        let spec = SpecBuilder::default().process(process).build()?;
+        let builder = ContainerBuilder::new("test".to_owned(), &syscall).as_tenant();
+        assert_eq!(builder.set_apparmor_profile(&spec), None);
+        assert_eq!(builder.set_process_label(&spec), None);
+        Ok(())
+    }
+}
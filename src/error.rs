@@ -0,0 +1,125 @@
+//! Errors which are reported at the boundary of the command line. Internally
+//! youki uses anyhow, the errors here are attached to the anyhow chain where
+//! the failure can be classified, so that main can map them to the exit code
+//! of youki. All other failures exit with 1.
+use std::fmt;
+
+use crate::container::ContainerStatus;
+
+/// Exit code of failures which are not classified
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code if the container does not exist
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// Exit code if a container with the same id exists already
+pub const EXIT_ALREADY_EXISTS: i32 = 4;
+/// Exit code if the container is in a state which does not allow the operation
+pub const EXIT_INVALID_STATE: i32 = 5;
+/// Exit code if the runtime spec is invalid
+pub const EXIT_INVALID_SPEC: i32 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum YoukiError {
+    /// The container does not exist
+    NotFound(String),
+    /// A container with the id exists already
+    AlreadyExists(String),
+    /// The operation is not allowed in the current status of the container
+    InvalidState {
+        id: String,
+        status: ContainerStatus,
+        operation: &'static str,
+    },
+    /// The container cannot change from its current status to the next one
+    InvalidTransition {
+        id: String,
+        from: ContainerStatus,
+        to: ContainerStatus,
+    },
+    /// The runtime spec is invalid or not supported by youki
+    InvalidSpec(String),
+}
+
+impl YoukiError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::NotFound(_) => EXIT_NOT_FOUND,
+            Self::AlreadyExists(_) => EXIT_ALREADY_EXISTS,
+            Self::InvalidState { .. } | Self::InvalidTransition { .. } => EXIT_INVALID_STATE,
+            Self::InvalidSpec(_) => EXIT_INVALID_SPEC,
+        }
+    }
+}
+
+impl std::error::Error for YoukiError {}
+
+impl fmt::Display for YoukiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(id) => write!(f, "container {} does not exist", id),
+            Self::AlreadyExists(id) => write!(f, "container {} already exists", id),
+            Self::InvalidState {
+                id,
+                status,
+                operation,
+            } => write!(
+                f,
+                "{} could not be {} because it was {:?}",
+                id, operation, status
+            ),
+            Self::InvalidTransition { id, from, to } => {
+                write!(f, "container {} cannot change from {} to {}", id, from, to)
+            }
+            Self::InvalidSpec(reason) => write!(f, "runtime spec is invalid: {}", reason),
+        }
+    }
+}
+
+/// Returns the exit code for the error, which is determined by the first
+/// classified error in its chain
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<YoukiError>())
+        .map_or(EXIT_FAILURE, YoukiError::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, Result};
+
+    #[test]
+    fn test_exit_code() {
+        let err = anyhow::anyhow!("unclassified failure");
+        assert_eq!(exit_code(&err), EXIT_FAILURE);
+
+        let err = Err::<(), _>(YoukiError::NotFound("test".to_owned()))
+            .context("failed to load container")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_NOT_FOUND);
+        assert_eq!(
+            format!("{:#}", err),
+            "failed to load container: container test does not exist"
+        );
+
+        let err: anyhow::Error = YoukiError::InvalidState {
+            id: "test".to_owned(),
+            status: ContainerStatus::Stopped,
+            operation: "paused",
+        }
+        .into();
+        assert_eq!(exit_code(&err), EXIT_INVALID_STATE);
+        assert_eq!(
+            err.to_string(),
+            "test could not be paused because it was Stopped"
+        );
+    }
+
+    #[test]
+    fn test_exit_code_of_context_chain() -> Result<()> {
+        let result: Result<()> = Err(YoukiError::InvalidSpec("no root in spec".to_owned()))
+            .context("failed to validate runtime spec")
+            .context("failed to create container");
+        assert_eq!(exit_code(&result.unwrap_err()), EXIT_INVALID_SPEC);
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Synchronizes the start of an init container with a FIFO in the state
+//! directory of the container, the same way as the exec.fifo of runc.
+//!
+//! The FIFO is created by youki create and inherited by the init process as
+//! O_PATH fd. Once the init process has set up the container, it reopens the
+//! fd for writing, which blocks until youki start opens the FIFO for reading.
+//! The init process then writes a single byte and executes the payload. The
+//! FIFO stays open until the exec, which closes it, so youki start returns
+//! only once the payload runs. If the payload cannot be executed, the init
+//! process writes the error to the FIFO instead. youki start removes the FIFO
+//! afterwards, so a container can only be started once, even if youki start
+//! is run again before the state is updated.
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::{self, OFlag};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::signal;
+use nix::sys::stat::{self, FchmodatFlags, Mode};
+use nix::unistd::{self, Pid};
+
+pub const EXEC_FIFO: &str = "exec.fifo";
+
+// interval in which youki start checks whether the init process still exists
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Creates the FIFO and opens it as O_PATH fd for the init process. The user
+/// of the container may not be the user running youki, so everyone may write
+/// to it.
+pub fn create(path: &Path) -> Result<RawFd> {
+    unistd::mkfifo(path, Mode::from_bits_truncate(0o622))
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    // the mode of mkfifo is restricted by the umask
+    stat::fchmodat(
+        None,
+        path,
+        Mode::from_bits_truncate(0o622),
+        FchmodatFlags::FollowSymlink,
+    )
+    .with_context(|| format!("failed to change mode of {}", path.display()))?;
+
+    fcntl::open(path, OFlag::O_PATH | OFlag::O_CLOEXEC, Mode::empty())
+        .with_context(|| format!("failed to open {}", path.display()))
+}
+
+/// Writing end of the FIFO in the init process. It is opened with O_CLOEXEC
+/// and deliberately not closed on drop, as only the exec of the payload may
+/// close it.
+pub struct ExecFifoWriter {
+    fd: RawFd,
+}
+
+impl ExecFifoWriter {
+    /// Reports why the payload could not be executed to youki start
+    pub fn report_error(&self, err: &anyhow::Error) {
+        let _ = unistd::write(self.fd, format!("{:#}", err).as_bytes());
+    }
+}
+
+/// Blocks the init process until the container is started. Runs in the init
+/// process, which has to have the procfs of the container mounted.
+pub fn wait_for_start(fifo_fd: RawFd) -> Result<ExecFifoWriter> {
+    let fd_path = format!("/proc/self/fd/{}", fifo_fd);
+    let fd = loop {
+        match fcntl::open(
+            fd_path.as_str(),
+            OFlag::O_WRONLY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        ) {
+            Ok(fd) => break fd,
+            Err(Errno::EINTR) => continue,
+            Err(e) => bail!("failed to open exec fifo: {}", e),
+        }
+    };
+
+    let _ = unistd::close(fifo_fd);
+    if let Err(e) = unistd::write(fd, b"0") {
+        let _ = unistd::close(fd);
+        bail!("failed to write to exec fifo: {}", e);
+    }
+
+    Ok(ExecFifoWriter { fd })
+}
+
+/// Starts the container by unblocking its init process and waits until the
+/// payload has been executed. Fails if the init process exits instead of
+/// waiting on the FIFO or if the payload cannot be executed.
+pub fn start(path: &Path, init_pid: Pid) -> Result<()> {
+    // a non-blocking open of the reading end succeeds without a writer, so
+    // the init process can be checked while waiting for it
+    let fd = match fcntl::open(
+        path,
+        OFlag::O_RDONLY | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    ) {
+        Ok(fd) => fd,
+        Err(Errno::ENOENT) => bail!("exec fifo does not exist, the container has been started"),
+        Err(e) => bail!("failed to open {}: {}", path.display(), e),
+    };
+
+    let result = await_init(fd, init_pid);
+    let _ = unistd::close(fd);
+    result?;
+
+    std::fs::remove_file(path).with_context(|| format!("failed to remove {}", path.display()))
+}
+
+// Reads from the FIFO until the init process closes it. Before the init
+// process opened it, poll does not report the FIFO as hung up.
+fn await_init(fd: RawFd, init_pid: Pid) -> Result<()> {
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+        match poll(&mut fds, POLL_INTERVAL.as_millis() as i32) {
+            Ok(0) => {
+                if received.is_empty() {
+                    if let Err(Errno::ESRCH) = signal::kill(init_pid, None) {
+                        bail!("init process {} exited before the start", init_pid);
+                    }
+                }
+                continue;
+            }
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(e) => bail!("failed to poll exec fifo: {}", e),
+        }
+
+        match unistd::read(fd, &mut buf) {
+            Ok(0) => break,
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(Errno::EAGAIN) | Err(Errno::EINTR) => continue,
+            Err(e) => bail!("failed to read from exec fifo: {}", e),
+        }
+    }
+
+    match received.split_first() {
+        Some((b'0', [])) => Ok(()),
+        Some((b'0', err)) => bail!(
+            "failed to start container: {}",
+            String::from_utf8_lossy(err)
+        ),
+        // the init process closed the FIFO without writing to it
+        _ => bail!("init process {} exited before the start", init_pid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+    use std::thread;
+
+    #[test]
+    fn test_start() -> Result<()> {
+        let tmp = create_temp_dir("test_exec_fifo_start")?;
+        let path = tmp.join(EXEC_FIFO);
+        let fifo_fd = create(&path)?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o622);
+
+        // closing the writer stands in for the exec of the payload
+        let waiter = thread::spawn(move || -> Result<()> {
+            let writer = wait_for_start(fifo_fd)?;
+            unistd::close(writer.fd)?;
+            Ok(())
+        });
+        start(&path, unistd::getpid())?;
+        waiter.join().unwrap()?;
+
+        assert!(!path.exists());
+        assert!(start(&path, unistd::getpid()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_exec_error() -> Result<()> {
+        let tmp = create_temp_dir("test_exec_fifo_start_exec_error")?;
+        let path = tmp.join(EXEC_FIFO);
+        let fifo_fd = create(&path)?;
+
+        let waiter = thread::spawn(move || -> Result<()> {
+            let writer = wait_for_start(fifo_fd)?;
+            writer.report_error(&anyhow::anyhow!("failed to execute sleep"));
+            unistd::close(writer.fd)?;
+            Ok(())
+        });
+        let err = start(&path, unistd::getpid()).unwrap_err();
+        waiter.join().unwrap()?;
+        assert_eq!(
+            err.to_string(),
+            "failed to start container: failed to execute sleep"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_exited_init() -> Result<()> {
+        let tmp = create_temp_dir("test_exec_fifo_start_exited_init")?;
+        let path = tmp.join(EXEC_FIFO);
+        let fifo_fd = create(&path)?;
+        let _ = unistd::close(fifo_fd);
+
+        let mut child = Command::new("true").spawn()?;
+        child.wait()?;
+        assert!(start(&path, Pid::from_raw(child.id() as i32)).is_err());
+        assert!(path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+//! Probes for the features of the system youki depends on. They are used by
+//! the info command and allow embedders of the library to decide how to
+//! configure a container without probing the system themselves.
+use std::env;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::sys::statvfs::{self, FsFlags};
+
+use cgroups::common::CgroupSetup;
+
+use crate::apparmor;
+use crate::mount_api::{self, MountAttr};
+
+/// Features of the system which determine what a container can use
+#[derive(Debug)]
+pub struct Features {
+    pub seccomp: bool,
+    pub cgroup_setup: Option<CgroupSetup>,
+    pub idmap_mounts: bool,
+    pub apparmor: bool,
+    pub selinux: bool,
+    /// None if youki has been built without the criu feature
+    pub criu: Option<Criu>,
+    /// Whether youki runs in a user namespace, e.g. nested in a container, in
+    /// which device nodes cannot be created and privileges are limited
+    pub user_namespace: bool,
+    /// Whether /proc/sys is mounted read-only, as in most containers, so
+    /// that sysctls can only be set in network namespaces of containers
+    pub read_only_proc_sys: bool,
+}
+
+impl Features {
+    /// Probes all features of the system
+    pub fn detect() -> Self {
+        Self {
+            // a build without the seccomp feature cannot apply profiles
+            seccomp: cfg!(feature = "seccomp") && seccomp_available(),
+            cgroup_setup: cgroup_setup().ok(),
+            idmap_mounts: idmap_mounts_supported(),
+            apparmor: apparmor_enabled(),
+            selinux: selinux_enabled(),
+            criu: if cfg!(feature = "criu") {
+                Some(criu())
+            } else {
+                None
+            },
+            user_namespace: in_user_namespace(),
+            read_only_proc_sys: proc_sys_read_only(),
+        }
+    }
+}
+
+/// Socket the CRIU service listens on by default, see criu service --address
+pub const CRIU_SERVICE_SOCKET: &str = "/var/run/criu_service.socket";
+
+/// What is known about the CRIU installation, which is used to checkpoint
+/// and restore containers
+#[derive(Debug)]
+pub struct Criu {
+    /// Path of the criu binary, if it is found in PATH
+    pub binary: Option<PathBuf>,
+    /// Path of the socket of the CRIU service, if it is running
+    pub service_socket: Option<PathBuf>,
+    pub version: Option<CriuVersion>,
+    /// Whether criu check succeeded, i.e. the kernel has the features CRIU
+    /// requires. The check needs root, so it fails for rootless callers.
+    pub kernel_supported: bool,
+}
+
+impl Criu {
+    /// Checks if containers can be checkpointed and restored, which requires
+    /// the criu binary and a kernel supported by it
+    pub fn usable(&self) -> bool {
+        self.binary.is_some() && self.version.is_some() && self.kernel_supported
+    }
+}
+
+/// Version of CRIU, e.g. 3.16.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CriuVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub sublevel: u32,
+}
+
+impl fmt::Display for CriuVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.sublevel)
+    }
+}
+
+/// Probes the CRIU installation. Runs criu --version and criu check, which
+/// takes a moment, so the result should be kept by the caller.
+pub fn criu() -> Criu {
+    let binary = find_in_path("criu");
+    let (version, kernel_supported) = match &binary {
+        Some(binary) => (
+            criu_version(binary)
+                .map_err(|e| log::debug!("failed to get version of criu: {:?}", e))
+                .ok(),
+            criu_check(binary),
+        ),
+        None => (None, false),
+    };
+    let service_socket = fs::metadata(CRIU_SERVICE_SOCKET)
+        .map_or(false, |metadata| metadata.file_type().is_socket())
+        .then(|| PathBuf::from(CRIU_SERVICE_SOCKET));
+
+    Criu {
+        binary,
+        service_socket,
+        version,
+        kernel_supported,
+    }
+}
+
+fn criu_version(binary: &Path) -> Result<CriuVersion> {
+    let output = Command::new(binary)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("failed to execute {}", binary.display()))?;
+    if !output.status.success() {
+        bail!(
+            "{} --version failed with {}",
+            binary.display(),
+            output.status
+        );
+    }
+
+    parse_criu_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+// The output of criu --version looks like
+//   Version: 3.16.1
+//   GitID: v3.16.1
+// where the sublevel is left out if it is zero
+fn parse_criu_version(output: &str) -> Result<CriuVersion> {
+    let version = output
+        .lines()
+        .find_map(|line| line.strip_prefix("Version:"))
+        .with_context(|| format!("no version in output of criu: {}", output))?
+        .trim();
+
+    let mut parts = version.split('.').map(|part| {
+        part.parse::<u32>()
+            .with_context(|| format!("invalid criu version {}", version))
+    });
+    let major = parts
+        .next()
+        .with_context(|| format!("invalid criu version {}", version))??;
+    let minor = parts.next().transpose()?.unwrap_or(0);
+    let sublevel = parts.next().transpose()?.unwrap_or(0);
+    Ok(CriuVersion {
+        major,
+        minor,
+        sublevel,
+    })
+}
+
+fn criu_check(binary: &Path) -> bool {
+    match Command::new(binary)
+        .arg("check")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+    {
+        Ok(status) => status.success(),
+        Err(e) => {
+            log::debug!("failed to execute {} check: {}", binary.display(), e);
+            false
+        }
+    }
+}
+
+/// Looks up an executable in PATH like the shell does
+pub(crate) fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| {
+            fs::metadata(candidate).map_or(false, |metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+}
+
+/// Checks if the kernel has been built with seccomp support
+pub fn seccomp_available() -> bool {
+    // fails with EINVAL if the kernel lacks CONFIG_SECCOMP
+    let res = unsafe { libc::prctl(libc::PR_GET_SECCOMP, 0, 0, 0, 0) };
+    Errno::result(res).is_ok()
+}
+
+/// Determines the cgroup setup of the system
+pub fn cgroup_setup() -> Result<CgroupSetup> {
+    cgroups::common::get_cgroup_setup()
+}
+
+/// Checks if the kernel supports idmapped mounts, which are available since
+/// Linux 5.12. Whether the filesystem of a mount can be idmapped is only
+/// known once the mount is created.
+pub fn idmap_mounts_supported() -> bool {
+    // the invalid fd is rejected with EBADF only if the syscall exists
+    !matches!(
+        mount_api::mount_setattr(-1, 0, &MountAttr::default()),
+        Err(Errno::ENOSYS)
+    )
+}
+
+/// Checks if AppArmor is enabled, so that profiles can be applied to containers
+pub fn apparmor_enabled() -> bool {
+    apparmor::is_enabled().unwrap_or(false)
+}
+
+/// Checks if youki runs in a user namespace other than the initial one. The
+/// initial user namespace maps all ids to themselves.
+pub fn in_user_namespace() -> bool {
+    match fs::read_to_string("/proc/self/uid_map") {
+        Ok(uid_map) => !is_initial_uid_map(&uid_map),
+        // kernels without user namespaces lack the file
+        Err(_) => false,
+    }
+}
+
+fn is_initial_uid_map(uid_map: &str) -> bool {
+    let fields: Vec<&str> = uid_map.split_whitespace().collect();
+    fields == ["0", "0", "4294967295"]
+}
+
+/// Checks if /proc/sys is mounted read-only, which container runtimes do to
+/// keep containers from changing the sysctls of the host
+pub fn proc_sys_read_only() -> bool {
+    statvfs::statvfs("/proc/sys").map_or(false, |stat| stat.flags().contains(FsFlags::ST_RDONLY))
+}
+
+/// Checks if SELinux is enabled, so that labels can be applied to containers.
+/// It is enabled if selinuxfs is mounted, regardless of whether it enforces.
+pub fn selinux_enabled() -> bool {
+    fs::metadata("/sys/fs/selinux/enforce").is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_criu_version() -> Result<()> {
+        let version = |major, minor, sublevel| CriuVersion {
+            major,
+            minor,
+            sublevel,
+        };
+        assert_eq!(
+            parse_criu_version("Version: 3.16.1\nGitID: v3.16.1\n")?,
+            version(3, 16, 1)
+        );
+        assert_eq!(parse_criu_version("Version: 3.15\n")?, version(3, 15, 0));
+        assert!(parse_criu_version("3.15\n").is_err());
+        assert!(parse_criu_version("Version: 3.x\n").is_err());
+
+        assert!(version(3, 16, 1) > version(3, 15, 0));
+        assert_eq!(version(3, 15, 0).to_string(), "3.15.0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_initial_uid_map() {
+        assert!(is_initial_uid_map("         0          0 4294967295\n"));
+        assert!(!is_initial_uid_map("         0       1000          1\n"));
+        assert!(!is_initial_uid_map(
+            "         0       1000          1\n         1     100000      65536\n"
+        ));
+    }
+
+    #[test]
+    fn test_find_in_path() {
+        assert!(find_in_path("sh").is_some());
+        assert!(find_in_path("youki-test-no-such-binary").is_none());
+    }
+}
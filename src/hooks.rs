@@ -2,8 +2,12 @@ use anyhow::{bail, Context, Result};
 use nix::{sys::signal, unistd::Pid};
 use oci_spec::runtime::Hook;
 use std::{
-    collections::HashMap, fmt, io::ErrorKind, io::Write, os::unix::prelude::CommandExt, process,
-    thread, time,
+    collections::HashMap,
+    fmt, fs,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    os::unix::{fs::PermissionsExt, prelude::CommandExt},
+    path::{Path, PathBuf},
+    process, thread, time,
 };
 
 use crate::{container::Container, utils};
@@ -18,7 +22,73 @@ impl fmt::Display for HookTimeoutError {
     }
 }
 
-pub fn run_hooks(hooks: Option<&Vec<Hook>>, container: Option<&Container>) -> Result<()> {
+// How long to wait for the remaining output of a hook after it exited. The
+// output pipes stay open if the hook left a process running in background.
+const HOOK_OUTPUT_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+// Search path of execvpe(3) if PATH is not set
+const DEFAULT_PATH: &str = "/bin:/usr/bin";
+
+/// Resolves the program of a hook like execvpe(3). A path without slashes is
+/// searched in the PATH of the hook environment, never in the one of youki.
+fn resolve_program(path: &Path, envs: &HashMap<String, String>) -> Result<PathBuf> {
+    if path.to_string_lossy().contains('/') {
+        return Ok(path.to_path_buf());
+    }
+
+    let search_path = envs.get("PATH").map_or(DEFAULT_PATH, |p| p.as_str());
+    for dir in search_path.split(':') {
+        // an empty entry refers to the current directory
+        let candidate = Path::new(if dir.is_empty() { "." } else { dir }).join(path);
+        if let Ok(metadata) = fs::metadata(&candidate) {
+            if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0 {
+                return Ok(candidate);
+            }
+        }
+    }
+
+    bail!(
+        "hook program {} not found in {}",
+        path.display(),
+        search_path
+    )
+}
+
+/// Hooks run in the bundle of the container, as relative paths in its spec are
+/// relative to the bundle, and not in the working directory of youki
+fn hook_working_dir(bundle: &Path) -> &Path {
+    if bundle.is_dir() {
+        bundle
+    } else {
+        Path::new("/")
+    }
+}
+
+/// Forwards the output of a hook line by line to the log of youki, tagged with
+/// the type and program of the hook, so failing hooks can be debugged
+fn log_hook_output<R: Read + Send + 'static>(
+    output: R,
+    tag: String,
+    is_stderr: bool,
+    done: crossbeam_channel::Sender<()>,
+) {
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines() {
+            match line {
+                Ok(line) if is_stderr => log::warn!("{}: {}", tag, line),
+                Ok(line) => log::info!("{}: {}", tag, line),
+                Err(_) => break,
+            }
+        }
+        let _ = done.send(());
+    });
+}
+
+pub fn run_hooks(
+    hook_type: &str,
+    hooks: Option<&Vec<Hook>>,
+    container: Option<&Container>,
+) -> Result<()> {
     if container.is_none() {
         bail!("container state is required to run hook");
     }
@@ -27,7 +97,16 @@ pub fn run_hooks(hooks: Option<&Vec<Hook>>, container: Option<&Container>) -> Re
 
     if let Some(hooks) = hooks {
         for hook in hooks {
-            let mut hook_command = process::Command::new(&hook.path());
+            // only the environment given by the hook is passed to it
+            let envs: HashMap<String, String> = if let Some(env) = hook.env() {
+                utils::parse_env(env)
+            } else {
+                HashMap::new()
+            };
+            log::debug!("run_hooks envs: {:?}", envs);
+
+            let program = resolve_program(hook.path(), &envs)?;
+            let mut hook_command = process::Command::new(&program);
             // Based on OCI spec, the first arguement of the args vector is the
             // arg0, which can be different from the path.  For example, path
             // may be "/usr/bin/true" and arg0 is set to "true". However, rust
@@ -41,20 +120,28 @@ pub fn run_hooks(hooks: Option<&Vec<Hook>>, container: Option<&Container>) -> Re
                 hook_command.arg0(&hook.path().display().to_string())
             };
 
-            let envs: HashMap<String, String> = if let Some(env) = hook.env() {
-                utils::parse_env(env)
-            } else {
-                HashMap::new()
-            };
-            log::debug!("run_hooks envs: {:?}", envs);
-
             let mut hook_process = hook_command
                 .env_clear()
                 .envs(envs)
+                .current_dir(hook_working_dir(&state.bundle))
                 .stdin(process::Stdio::piped())
+                .stdout(process::Stdio::piped())
+                .stderr(process::Stdio::piped())
                 .spawn()
-                .with_context(|| "Failed to execute hook")?;
+                .with_context(|| format!("Failed to execute hook {}", program.display()))?;
             let hook_process_pid = Pid::from_raw(hook_process.id() as i32);
+
+            let tag = format!("{} hook {}", hook_type, hook.path().display());
+            let (output_done, outputs_done) = crossbeam_channel::unbounded();
+            let mut outputs = 0;
+            if let Some(stdout) = hook_process.stdout.take() {
+                log_hook_output(stdout, tag.clone(), false, output_done.clone());
+                outputs += 1;
+            }
+            if let Some(stderr) = hook_process.stderr.take() {
+                log_hook_output(stderr, tag, true, output_done);
+                outputs += 1;
+            }
             // Based on the OCI spec, we need to pipe the container state into
             // the hook command through stdin.
             if let Some(stdin) = &mut hook_process.stdin {
@@ -110,6 +197,13 @@ pub fn run_hooks(hooks: Option<&Vec<Hook>>, container: Option<&Container>) -> Re
                 hook_process.wait()
             };
 
+            // the output is logged before the result, so it precedes errors
+            for _ in 0..outputs {
+                if outputs_done.recv_timeout(HOOK_OUTPUT_TIMEOUT).is_err() {
+                    break;
+                }
+            }
+
             match res {
                 Ok(exit_status) => match exit_status.code() {
                     Some(0) => {}
@@ -165,7 +259,7 @@ mod test {
     fn test_run_hook() -> Result<()> {
         {
             let default_container: Container = Default::default();
-            run_hooks(None, Some(&default_container)).context("Failed simple test")?;
+            run_hooks("test", None, Some(&default_container)).context("Failed simple test")?;
         }
 
         {
@@ -174,7 +268,7 @@ mod test {
 
             let hook = HookBuilder::default().path("true").build()?;
             let hooks = Some(vec![hook]);
-            run_hooks(hooks.as_ref(), Some(&default_container)).context("Failed true")?;
+            run_hooks("test", hooks.as_ref(), Some(&default_container)).context("Failed true")?;
         }
 
         {
@@ -194,12 +288,54 @@ mod test {
                 .env(vec![String::from("key=value")])
                 .build()?;
             let hooks = Some(vec![hook]);
-            run_hooks(hooks.as_ref(), Some(&default_container)).context("Failed printenv test")?;
+            run_hooks("test", hooks.as_ref(), Some(&default_container))
+                .context("Failed printenv test")?;
         }
 
         Ok(())
     }
 
+    #[test]
+    fn test_resolve_program() -> Result<()> {
+        let tmp = crate::utils::create_temp_dir("test_resolve_program")?;
+        let program = tmp.join("hook");
+        fs::write(&program, "#!/bin/sh\n")?;
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755))?;
+        fs::write(tmp.join("data"), "")?;
+
+        let mut envs = HashMap::new();
+        envs.insert("PATH".to_owned(), format!("/nonexistent:{}", tmp.display()));
+        assert_eq!(resolve_program(Path::new("hook"), &envs)?, program);
+        // files which are not executable are skipped
+        assert!(resolve_program(Path::new("data"), &envs).is_err());
+        // paths with slashes are not searched
+        assert_eq!(
+            resolve_program(Path::new("./hook"), &envs)?,
+            PathBuf::from("./hook")
+        );
+        // the PATH of youki is not used
+        assert!(resolve_program(Path::new("hook"), &HashMap::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_run_hook_working_dir() -> Result<()> {
+        let default_container: Container = Default::default();
+        let hook = HookBuilder::default()
+            .path("bash")
+            .args(vec![
+                String::from("bash"),
+                String::from("-c"),
+                String::from("test \"$PWD\" = /"),
+            ])
+            .build()?;
+        let hooks = Some(vec![hook]);
+        run_hooks("test", hooks.as_ref(), Some(&default_container))
+            .context("hook does not run in /")?;
+        Ok(())
+    }
+
     #[test]
     #[serial]
     // This will test executing hook with a timeout. Since the timeout is set in
@@ -217,7 +353,7 @@ mod test {
             .timeout(1)
             .build()?;
         let hooks = Some(vec![hook]);
-        match run_hooks(hooks.as_ref(), Some(&default_container)) {
+        match run_hooks("test", hooks.as_ref(), Some(&default_container)) {
             Ok(_) => {
                 bail!("The test expects the hook to error out with timeout. Should not execute cleanly");
             }
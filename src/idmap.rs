@@ -0,0 +1,363 @@
+//! Idmapped mounts, available since Linux 5.12, allow to expose a volume to
+//! a container with the ownership of its files shifted according to an id
+//! mapping, so volumes can be shared with (rootless) containers without
+//! having to chown them. See https://lwn.net/Articles/837566/
+//!
+//! The mounts of the oci-spec revision in Cargo.toml have no
+//! uidMappings/gidMappings yet. Similar to crun,
+//! idmapping is therefore requested through the `idmap` mount option. Without
+//! a value the mappings of the container user namespace are used, otherwise
+//! the mappings can be given explicitly as
+//! `idmap=uids=<container>-<host>-<size>[#...];gids=<container>-<host>-<size>[#...]`.
+//!
+//! The detached idmapped mounts are prepared by the main process, as the
+//! container init process lacks the privileges over the host filesystems,
+//! and are inherited by the init process which attaches them into the rootfs.
+use crate::process::{channel, fork};
+use crate::utils::{self, secure_fs};
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::sched::{unshare, CloneFlags};
+use nix::sys::wait::waitpid;
+use nix::unistd::{self, Pid};
+use oci_spec::runtime::{LinuxIdMapping, LinuxIdMappingBuilder, Mount, Spec};
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use crate::mount_api::{self, MountAttr};
+
+const IDMAP_OPTION: &str = "idmap";
+
+/// Detached idmapped mounts, keyed by their destination in the container
+#[derive(Debug, Clone, Default)]
+pub struct IdmappedMounts {
+    mounts: HashMap<PathBuf, RawFd>,
+}
+
+impl IdmappedMounts {
+    /// Creates a detached idmapped mount for every mount in the spec which
+    /// requests it
+    pub fn prepare(spec: &Spec) -> Result<Self> {
+        let mut idmapped = Self::default();
+        let mounts = match spec.mounts() {
+            Some(mounts) => mounts,
+            None => return Ok(idmapped),
+        };
+
+        for mount in mounts {
+            let (uid_mappings, gid_mappings) = match idmap_option(mount)? {
+                Some(IdmapOption::Container) => {
+                    let linux = spec.linux().as_ref().context("no linux in spec")?;
+                    (
+                        linux.uid_mappings().clone().unwrap_or_default(),
+                        linux.gid_mappings().clone().unwrap_or_default(),
+                    )
+                }
+                Some(IdmapOption::Explicit { uids, gids }) => (uids, gids),
+                None => continue,
+            };
+
+            if uid_mappings.is_empty() || gid_mappings.is_empty() {
+                bail!(
+                    "idmapped mount {} requires uid and gid mappings",
+                    mount.destination().display()
+                );
+            }
+
+            let fd =
+                open_idmapped_tree(mount, &uid_mappings, &gid_mappings).with_context(|| {
+                    format!(
+                        "failed to create idmapped mount for {}",
+                        mount.destination().display()
+                    )
+                })?;
+            idmapped.mounts.insert(mount.destination().clone(), fd);
+        }
+
+        Ok(idmapped)
+    }
+
+    /// Returns the detached mount prepared for the destination, if any
+    pub fn get(&self, destination: &Path) -> Option<RawFd> {
+        self.mounts.get(destination).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mounts.is_empty()
+    }
+
+    /// Closes the detached mounts. Called by the main process once the
+    /// container processes have inherited them.
+    pub fn close(&mut self) -> Result<()> {
+        for (_, fd) in self.mounts.drain() {
+            unistd::close(fd).context("failed to close idmapped mount")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum IdmapOption {
+    /// Use the mappings of the container user namespace
+    Container,
+    /// Use the mappings given with the option
+    Explicit {
+        uids: Vec<LinuxIdMapping>,
+        gids: Vec<LinuxIdMapping>,
+    },
+}
+
+/// Checks if a mount option is used to request an idmapped mount
+pub fn is_idmap_option(option: &str) -> bool {
+    option == IDMAP_OPTION || option.starts_with("idmap=")
+}
+
+fn idmap_option(mount: &Mount) -> Result<Option<IdmapOption>> {
+    let option = match mount
+        .options()
+        .as_ref()
+        .and_then(|options| options.iter().find(|o| is_idmap_option(o)))
+    {
+        Some(option) => option,
+        None => return Ok(None),
+    };
+
+    if option == IDMAP_OPTION {
+        return Ok(Some(IdmapOption::Container));
+    }
+
+    let mut uids = Vec::new();
+    let mut gids = Vec::new();
+    for part in option[IDMAP_OPTION.len() + 1..].split(';') {
+        match part.split_once('=') {
+            Some(("uids", value)) => uids = parse_mappings(value)?,
+            Some(("gids", value)) => gids = parse_mappings(value)?,
+            _ => bail!("invalid idmap option {}", option),
+        }
+    }
+
+    Ok(Some(IdmapOption::Explicit { uids, gids }))
+}
+
+fn parse_mappings(value: &str) -> Result<Vec<LinuxIdMapping>> {
+    value
+        .split('#')
+        .map(|mapping| {
+            let ids: Vec<u32> = mapping
+                .split('-')
+                .map(|id| id.parse::<u32>())
+                .collect::<Result<_, _>>()
+                .with_context(|| format!("invalid id mapping {}", mapping))?;
+            if ids.len() != 3 {
+                bail!(
+                    "invalid id mapping {}, expected container-host-size",
+                    mapping
+                );
+            }
+
+            LinuxIdMappingBuilder::default()
+                .container_id(ids[0])
+                .host_id(ids[1])
+                .size(ids[2])
+                .build()
+                .with_context(|| format!("invalid id mapping {}", mapping))
+        })
+        .collect()
+}
+
+fn open_idmapped_tree(
+    mount: &Mount,
+    uid_mappings: &[LinuxIdMapping],
+    gid_mappings: &[LinuxIdMapping],
+) -> Result<RawFd> {
+    let options = mount.options().as_ref();
+    if !utils::is_bind_mount(mount) {
+        bail!("idmapped mounts are only supported for bind mounts");
+    }
+
+    let recursive = options.map_or(false, |o| o.iter().any(|o| o == "rbind"));
+    let source = mount.source().as_ref().context("no source in mount spec")?;
+    let source = source
+        .canonicalize()
+        .with_context(|| format!("failed to canonicalize {}", source.display()))?;
+
+    let userns = create_user_namespace(uid_mappings, gid_mappings)?;
+    let mut flags = mount_api::OPEN_TREE_CLONE | mount_api::OPEN_TREE_CLOEXEC;
+    if recursive {
+        flags |= mount_api::AT_RECURSIVE;
+    }
+    let fd = match mount_api::open_tree(&source, flags) {
+        Ok(fd) => fd,
+        Err(Errno::ENOSYS) => bail!("idmapped mounts require at least Linux 5.12"),
+        Err(e) => bail!("failed to open mount tree {}: {}", source.display(), e),
+    };
+
+    let attr = MountAttr {
+        attr_set: mount_api::MOUNT_ATTR_IDMAP,
+        userns_fd: userns.as_raw_fd() as u64,
+        ..Default::default()
+    };
+    let setattr_flags = if recursive {
+        mount_api::AT_RECURSIVE
+    } else {
+        0
+    };
+    if let Err(e) = mount_api::mount_setattr(fd, setattr_flags, &attr) {
+        let _ = unistd::close(fd);
+        match e {
+            Errno::ENOSYS => bail!("idmapped mounts require at least Linux 5.12"),
+            Errno::EINVAL => bail!(
+                "the filesystem of {} does not support idmapped mounts",
+                source.display()
+            ),
+            Errno::EPERM => bail!(
+                "not permitted to create an idmapped mount of {}",
+                source.display()
+            ),
+            e => bail!("failed to set idmapping on {}: {}", source.display(), e),
+        }
+    }
+
+    Ok(fd)
+}
+
+/// Creates a user namespace with the given mappings and returns a file
+/// referring to it. A short lived child process is used to unshare the
+/// namespace, the namespace stays alive as long as the file is open.
+fn create_user_namespace(
+    uid_mappings: &[LinuxIdMapping],
+    gid_mappings: &[LinuxIdMapping],
+) -> Result<File> {
+    let (sender_to_main, receiver_from_child) = &mut channel::intermediate_to_main()?;
+    let (sender_to_child, receiver_from_main) = &mut channel::main_to_intermediate()?;
+
+    let child = fork::container_fork(|| {
+        receiver_from_child.close()?;
+        sender_to_child.close()?;
+        prctl::set_dumpable(true)
+            .map_err(Errno::from_i32)
+            .context("failed to make the idmap process dumpable")?;
+        unshare(CloneFlags::CLONE_NEWUSER).context("failed to unshare user namespace")?;
+        sender_to_main.identifier_mapping_request()?;
+        receiver_from_main.wait_for_mapping_ack()?;
+        Ok(())
+    })?;
+    sender_to_main.close()?;
+    receiver_from_main.close()?;
+
+    let userns = receiver_from_child
+        .wait_for_mapping_request()
+        .and_then(|_| write_mappings(child, uid_mappings, gid_mappings))
+        .and_then(|_| {
+            File::open(format!("/proc/{}/ns/user", child)).context("failed to open user namespace")
+        });
+
+    // Let the child exit, regardless if the namespace could be set up
+    sender_to_child.mapping_written()?;
+    sender_to_child.close()?;
+    waitpid(child, None)?;
+
+    userns
+}
+
+/// Formats the mappings as content of a uid_map or gid_map file, one range
+/// per line. All ranges have to be written at once, as the file can only be
+/// written once.
+pub(crate) fn format_mappings(mappings: &[LinuxIdMapping]) -> String {
+    mappings
+        .iter()
+        .map(|m| format!("{} {} {}\n", m.container_id(), m.host_id(), m.size()))
+        .collect()
+}
+
+fn write_mappings(
+    pid: Pid,
+    uid_mappings: &[LinuxIdMapping],
+    gid_mappings: &[LinuxIdMapping],
+) -> Result<()> {
+    if !unistd::geteuid().is_root() {
+        // See CVE-2014-8989
+        secure_fs::write_proc_file(format!("/proc/{}/setgroups", pid), "deny")?;
+    }
+    secure_fs::write_proc_file(
+        format!("/proc/{}/uid_map", pid),
+        format_mappings(uid_mappings),
+    )?;
+    secure_fs::write_proc_file(
+        format!("/proc/{}/gid_map", pid),
+        format_mappings(gid_mappings),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::MountBuilder;
+
+    fn mount_with_options(options: &[&str]) -> Mount {
+        MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/tmp"))
+            .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_is_idmap_option() {
+        assert!(is_idmap_option("idmap"));
+        assert!(is_idmap_option("idmap=uids=0-1000-1;gids=0-1000-1"));
+        assert!(!is_idmap_option("idmapped"));
+        assert!(!is_idmap_option("rbind"));
+    }
+
+    #[test]
+    fn test_idmap_option_absent() -> Result<()> {
+        let mount = mount_with_options(&["rbind", "ro"]);
+        assert_eq!(idmap_option(&mount)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_idmap_option_container() -> Result<()> {
+        let mount = mount_with_options(&["rbind", "idmap"]);
+        assert_eq!(idmap_option(&mount)?, Some(IdmapOption::Container));
+        Ok(())
+    }
+
+    #[test]
+    fn test_idmap_option_explicit() -> Result<()> {
+        let mount = mount_with_options(&["bind", "idmap=uids=0-1000-10#10-2000-5;gids=0-1000-1"]);
+        let uid = |c: u32, h: u32, s: u32| {
+            LinuxIdMappingBuilder::default()
+                .container_id(c)
+                .host_id(h)
+                .size(s)
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(
+            idmap_option(&mount)?,
+            Some(IdmapOption::Explicit {
+                uids: vec![uid(0, 1000, 10), uid(10, 2000, 5)],
+                gids: vec![uid(0, 1000, 1)],
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_idmap_option_invalid() {
+        let mount = mount_with_options(&["bind", "idmap=uids=0-1000"]);
+        assert!(idmap_option(&mount).is_err());
+        let mount = mount_with_options(&["bind", "idmap=foo=0-1000-1"]);
+        assert!(idmap_option(&mount).is_err());
+    }
+}
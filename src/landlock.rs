@@ -0,0 +1,269 @@
+//! Landlock (https://docs.kernel.org/userspace-api/landlock.html), available
+//! since Linux 5.13, allows unprivileged processes to restrict their own
+//! access to the filesystem. It complements the access control of the
+//! container with a defense in depth, e.g. against writes to paths which
+//! are writable by the container user by accident.
+//!
+//! The proposed `linux.landlock` extension is not part of oci-spec, it is read
+//! from the config.json of the bundle separately, e.g.
+//!
+//! ```json
+//! "landlock": {
+//!     "ruleset": { "handledAccessFS": ["execute", "write_file", "read_file"] },
+//!     "rules": {
+//!         "pathBeneath": [
+//!             { "allowedAccess": ["execute", "read_file"], "paths": ["/usr", "/bin"] }
+//!         ]
+//!     },
+//!     "disableBestEffort": false
+//! }
+//! ```
+//!
+//! The accesses which are handled by the ruleset are denied, unless they are
+//! allowed beneath one of the paths of the rules. Without `disableBestEffort`,
+//! the container is started without the ruleset if the kernel does not
+//! support Landlock.
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::{self, OFlag};
+use nix::sys::stat::Mode;
+use nix::unistd;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+/// Access rights to the filesystem of the first version of the Landlock ABI
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessFs {
+    Execute,
+    WriteFile,
+    ReadFile,
+    ReadDir,
+    RemoveDir,
+    RemoveFile,
+    MakeChar,
+    MakeDir,
+    MakeReg,
+    MakeSock,
+    MakeFifo,
+    MakeBlock,
+    MakeSym,
+}
+
+impl AccessFs {
+    fn bits(accesses: &[AccessFs]) -> u64 {
+        accesses
+            .iter()
+            .fold(0, |bits, access| bits | (1u64 << *access as u64))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Ruleset {
+    #[serde(rename = "handledAccessFS", default)]
+    pub handled_access_fs: Vec<AccessFs>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PathBeneath {
+    pub allowed_access: Vec<AccessFs>,
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Rules {
+    #[serde(default)]
+    pub path_beneath: Vec<PathBeneath>,
+}
+
+/// Landlock configuration of the container
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Landlock {
+    #[serde(default)]
+    pub ruleset: Ruleset,
+    #[serde(default)]
+    pub rules: Rules,
+    /// Fail instead of starting the container unrestricted if Landlock is
+    /// not supported by the kernel
+    #[serde(default)]
+    pub disable_best_effort: bool,
+}
+
+/// Mirrors struct landlock_ruleset_attr from linux/landlock.h
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+/// Mirrors struct landlock_path_beneath_attr from linux/landlock.h
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+impl Landlock {
+    /// Reads the landlock configuration from the linux section of the spec
+    pub fn load(spec_path: &Path) -> Result<Option<Self>> {
+        let spec: Value = serde_json::from_str(
+            &fs::read_to_string(spec_path)
+                .with_context(|| format!("failed to read {}", spec_path.display()))?,
+        )
+        .with_context(|| format!("failed to parse {}", spec_path.display()))?;
+
+        match spec.get("linux").and_then(|linux| linux.get("landlock")) {
+            Some(landlock) => {
+                let landlock: Self = serde_json::from_value(landlock.clone())
+                    .context("invalid landlock configuration")?;
+                landlock.validate()?;
+                Ok(Some(landlock))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let handled = AccessFs::bits(&self.ruleset.handled_access_fs);
+        if handled == 0 {
+            bail!("landlock ruleset does not handle any access");
+        }
+
+        for rule in &self.rules.path_beneath {
+            if AccessFs::bits(&rule.allowed_access) & !handled != 0 {
+                bail!(
+                    "landlock rule for {:?} allows accesses which are not handled by the ruleset",
+                    rule.paths
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Enforces the ruleset on the calling process and all processes it
+    /// executes. Requires no_new_privs or CAP_SYS_ADMIN.
+    pub fn apply(&self) -> Result<()> {
+        let attr = RulesetAttr {
+            handled_access_fs: AccessFs::bits(&self.ruleset.handled_access_fs),
+        };
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        let ruleset_fd = match Errno::result(res) {
+            Ok(fd) => fd as RawFd,
+            Err(Errno::ENOSYS) | Err(Errno::EOPNOTSUPP) if !self.disable_best_effort => {
+                log::warn!("landlock is not supported by the kernel, the ruleset is not applied");
+                return Ok(());
+            }
+            Err(Errno::ENOSYS) | Err(Errno::EOPNOTSUPP) => {
+                bail!("landlock requires at least Linux 5.13 with landlock enabled")
+            }
+            Err(e) => bail!("failed to create landlock ruleset: {}", e),
+        };
+
+        let result = self.restrict(ruleset_fd);
+        let _ = unistd::close(ruleset_fd);
+        result
+    }
+
+    fn restrict(&self, ruleset_fd: RawFd) -> Result<()> {
+        for rule in &self.rules.path_beneath {
+            for path in &rule.paths {
+                let parent_fd = fcntl::open(path, OFlag::O_PATH | OFlag::O_CLOEXEC, Mode::empty())
+                    .with_context(|| format!("failed to open {}", path.display()))?;
+                let attr = PathBeneathAttr {
+                    allowed_access: AccessFs::bits(&rule.allowed_access),
+                    parent_fd,
+                };
+                let res = unsafe {
+                    libc::syscall(
+                        libc::SYS_landlock_add_rule,
+                        ruleset_fd,
+                        LANDLOCK_RULE_PATH_BENEATH,
+                        &attr as *const PathBeneathAttr,
+                        0,
+                    )
+                };
+                let _ = unistd::close(parent_fd);
+                Errno::result(res).with_context(|| {
+                    format!("failed to add landlock rule for {}", path.display())
+                })?;
+            }
+        }
+
+        let res = unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset_fd, 0) };
+        match Errno::result(res) {
+            Ok(_) => Ok(()),
+            Err(Errno::EPERM) => bail!(
+                "landlock requires noNewPrivileges or CAP_SYS_ADMIN to restrict the container process"
+            ),
+            Err(e) => bail!("failed to enforce landlock ruleset: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_access_bits() {
+        assert_eq!(AccessFs::bits(&[]), 0);
+        assert_eq!(AccessFs::bits(&[AccessFs::Execute]), 1);
+        assert_eq!(
+            AccessFs::bits(&[AccessFs::ReadFile, AccessFs::MakeSym]),
+            1 << 2 | 1 << 12
+        );
+    }
+
+    #[test]
+    fn test_load() -> Result<()> {
+        let tmp = create_temp_dir("test_landlock_load")?;
+        let spec_path = tmp.join("config.json");
+
+        fs::write(&spec_path, r#"{"ociVersion": "1.0.2", "linux": {}}"#)?;
+        assert_eq!(Landlock::load(&spec_path)?, None);
+
+        fs::write(
+            &spec_path,
+            r#"{"linux": {"landlock": {
+                "ruleset": {"handledAccessFS": ["execute", "write_file"]},
+                "rules": {"pathBeneath": [{"allowedAccess": ["execute"], "paths": ["/usr"]}]}
+            }}}"#,
+        )?;
+        let landlock = Landlock::load(&spec_path)?.expect("landlock configuration");
+        assert_eq!(
+            landlock.ruleset.handled_access_fs,
+            vec![AccessFs::Execute, AccessFs::WriteFile]
+        );
+        assert_eq!(
+            landlock.rules.path_beneath[0].paths,
+            vec![PathBuf::from("/usr")]
+        );
+        assert!(!landlock.disable_best_effort);
+
+        fs::write(
+            &spec_path,
+            r#"{"linux": {"landlock": {
+                "ruleset": {"handledAccessFS": ["execute"]},
+                "rules": {"pathBeneath": [{"allowedAccess": ["read_file"], "paths": ["/usr"]}]}
+            }}}"#,
+        )?;
+        assert!(Landlock::load(&spec_path).is_err());
+        Ok(())
+    }
+}
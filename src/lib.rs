@@ -2,16 +2,26 @@ pub mod apparmor;
 pub mod capabilities;
 pub mod commands;
 pub mod container;
-pub mod dbus;
+pub mod error;
+pub mod exec_fifo;
+pub mod features;
 pub mod hooks;
+pub mod idmap;
+pub mod landlock;
 pub mod logger;
+pub mod mount_api;
 pub mod namespaces;
 pub mod notify_socket;
 pub mod process;
 pub mod rootfs;
 pub mod rootless;
+#[cfg(feature = "seccomp")]
 pub mod seccomp;
+pub mod selinux;
 pub mod signal;
+pub mod stdio_log;
 pub mod syscall;
+pub mod telemetry;
 pub mod tty;
 pub mod utils;
+pub mod validation;
@@ -3,18 +3,24 @@
 use std::env;
 use std::io::{stderr, Write};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::{
     fs::{File, OpenOptions},
     str::FromStr,
 };
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::{LevelFilter, Log, Metadata, Record};
 use once_cell::sync::OnceCell;
 
 /// Public global variables to access logger and logfile
 pub static YOUKI_LOGGER: OnceCell<YoukiLogger> = OnceCell::new();
 pub static LOG_FILE: OnceCell<Option<File>> = OnceCell::new();
+static LOG_CONTEXT: OnceCell<LogContext> = OnceCell::new();
+// pid of the process which initialized the logger. Processes forked from it
+// do not log, as formatting and writing the records could take locks which
+// threads of the parent held at the time of the fork.
+static LOGGER_PID: AtomicI32 = AtomicI32::new(0);
 
 /// If in debug mode, default level is debug to get maximum logging
 #[cfg(debug_assertions)]
@@ -24,10 +30,75 @@ const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Debug;
 #[cfg(not(debug_assertions))]
 const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Warn;
 
+/// Format of the log messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Human readable messages
+    Text,
+    /// One json object per message. Used by higher level runtimes to parse
+    /// the errors of youki from the log file.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("unknown log format {}, expected text or json", format),
+        }
+    }
+}
+
+/// Operation youki carries out, which is attached to every log record so that
+/// the records of concurrent invocations on one node can be told apart
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogContext {
+    /// Subcommand youki has been invoked with
+    pub command: String,
+    /// Container the subcommand operates on
+    pub container_id: Option<String>,
+}
+
+impl LogContext {
+    fn prefix(&self) -> String {
+        match &self.container_id {
+            Some(id) => format!("[{} {}] ", self.command, id),
+            None => format!("[{}] ", self.command),
+        }
+    }
+}
+
+/// Sets the context of the operation for all following log records. It is
+/// inherited by the processes youki forks. The context can only be set once.
+pub fn set_context(command: &str, container_id: Option<&str>) {
+    let _ = LOG_CONTEXT.set(LogContext {
+        command: command.to_owned(),
+        container_id: container_id.map(|id| id.to_owned()),
+    });
+}
+
+// getpid is a plain syscall, so this is safe to call in forked processes
+fn in_logger_process() -> bool {
+    let pid = LOGGER_PID.load(Ordering::Relaxed);
+    pid == 0 || pid == unsafe { libc::getpid() }
+}
+
+fn now() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
 /// Initialize the logger, must be called before accessing the logger
 /// Multiple parts might call this at once, but the actual initialization
 /// is done only once due to use of OnceCell
-pub fn init(log_file: Option<PathBuf>) -> Result<()> {
+pub fn init(log_file: Option<PathBuf>, log_format: Option<String>) -> Result<()> {
+    let log_format = match log_format {
+        Some(format) => format.parse()?,
+        None => LogFormat::Text,
+    };
+
     // If file exists, ignore, else create and open the file
     let _log_file = LOG_FILE.get_or_init(|| -> Option<File> {
         // set the log level if specified in env variable or set to default
@@ -38,11 +109,13 @@ pub fn init(log_file: Option<PathBuf>) -> Result<()> {
         };
 
         // Create a new logger, or get existing if already created
-        let logger = YOUKI_LOGGER.get_or_init(|| YoukiLogger::new(level_filter.to_level()));
+        let logger =
+            YOUKI_LOGGER.get_or_init(|| YoukiLogger::new(level_filter.to_level(), log_format));
 
         log::set_logger(logger)
             .map(|()| log::set_max_level(level_filter))
             .expect("set logger failed");
+        LOGGER_PID.store(unsafe { libc::getpid() }, Ordering::Relaxed);
 
         // Create and open log file
         log_file.as_ref().map(|log_file_path| {
@@ -61,12 +134,53 @@ pub fn init(log_file: Option<PathBuf>) -> Result<()> {
 pub struct YoukiLogger {
     /// Indicates level up to which logs are to be printed
     level: Option<log::Level>,
+    /// Format of the log messages
+    format: LogFormat,
 }
 
 impl YoukiLogger {
     /// Create new logger
-    pub fn new(level: Option<log::Level>) -> Self {
-        Self { level }
+    pub fn new(level: Option<log::Level>, format: LogFormat) -> Self {
+        Self { level, format }
+    }
+}
+
+/// Formats the record as single line json object
+fn json_message(record: &Record, context: Option<&LogContext>) -> String {
+    let mut message = serde_json::json!({
+        "level": record.level().to_string().to_lowercase(),
+        "msg": record.args().to_string(),
+        "time": now(),
+    });
+    if let Some(context) = context {
+        message["command"] = context.command.clone().into();
+        if let Some(id) = &context.container_id {
+            message["container"] = id.clone().into();
+        }
+    }
+    message.to_string()
+}
+
+/// Formats the record as human readable message
+fn text_message(record: &Record, context: Option<&LogContext>) -> String {
+    let prefix = context.map(LogContext::prefix).unwrap_or_default();
+    match (record.file(), record.line()) {
+        (Some(file), Some(line)) => format!(
+            "[{} {}:{}] {} {}{}\r",
+            record.level(),
+            file,
+            line,
+            now(),
+            prefix,
+            record.args()
+        ),
+        (_, _) => format!(
+            "[{}] {} {}{}\r",
+            record.level(),
+            now(),
+            prefix,
+            record.args()
+        ),
     }
 }
 
@@ -74,6 +188,10 @@ impl YoukiLogger {
 impl Log for YoukiLogger {
     /// Check if level of given log is enabled or not
     fn enabled(&self, metadata: &Metadata) -> bool {
+        if !in_logger_process() {
+            return false;
+        }
+
         if let Some(level) = self.level {
             metadata.level() <= level
         } else {
@@ -84,28 +202,20 @@ impl Log for YoukiLogger {
     /// Function to carry out logging
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            let log_msg = match (record.file(), record.line()) {
-                (Some(file), Some(line)) => format!(
-                    "[{} {}:{}] {} {}\r",
-                    record.level(),
-                    file,
-                    line,
-                    chrono::Local::now().to_rfc3339(),
-                    record.args()
-                ),
-                (_, _) => format!(
-                    "[{}] {} {}\r",
-                    record.level(),
-                    chrono::Local::now().to_rfc3339(),
-                    record.args()
-                ),
+            let context = LOG_CONTEXT.get();
+            let log_msg = match self.format {
+                LogFormat::Json => json_message(record, context),
+                LogFormat::Text => text_message(record, context),
             };
 
-            // if log file is set, write to it, else write to stderr
+            // if log file is set, write to it, else write to stderr. The
+            // record is written at once, so records of the processes of
+            // youki do not interleave.
+            let log_msg = format!("{}\n", log_msg);
             if let Some(mut log_file) = LOG_FILE.get().unwrap().as_ref() {
-                let _ = writeln!(log_file, "{}", log_msg);
+                let _ = log_file.write_all(log_msg.as_bytes());
             } else {
-                let _ = writeln!(stderr(), "{}", log_msg);
+                let _ = stderr().write_all(log_msg.as_bytes());
             }
         }
     }
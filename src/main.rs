@@ -3,15 +3,17 @@
 //! This crate provides a container runtime which can be used by a high-level container runtime to run containers.
 
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
-use clap::{crate_version, Clap};
+use clap::{crate_version, Clap, IntoApp};
 
 use nix::sys::stat::Mode;
-use nix::unistd::getuid;
+use nix::unistd::geteuid;
+use youki::commands::checkpoint;
+use youki::commands::completion;
 use youki::commands::create;
 use youki::commands::delete;
 use youki::commands::events;
@@ -19,14 +21,22 @@ use youki::commands::exec;
 use youki::commands::info;
 use youki::commands::kill;
 use youki::commands::list;
+use youki::commands::migrate_state;
 use youki::commands::pause;
 use youki::commands::ps;
+use youki::commands::restore;
 use youki::commands::resume;
 use youki::commands::run;
 use youki::commands::spec_json;
 use youki::commands::start;
 use youki::commands::state;
+use youki::commands::update;
+use youki::commands::validate;
+use youki::commands::wait;
+use youki::container::State;
+use youki::error;
 use youki::rootless::rootless_required;
+use youki::telemetry;
 use youki::utils::{self, create_dir_all_with_mode};
 
 // High-level commandline option definition
@@ -35,16 +45,22 @@ use youki::utils::{self, create_dir_all_with_mode};
 #[derive(Clap, Debug)]
 #[clap(version = crate_version!(), author = "youki team")]
 struct Opts {
-    /// root directory to store container state
+    /// root directory to store container state, created if it does not exist
+    /// (default: /run/youki, or $XDG_RUNTIME_DIR/youki for rootless users)
     #[clap(short, long)]
     root: Option<PathBuf>,
+    /// file to write the log to (default: stderr)
     #[clap(short, long)]
     log: Option<PathBuf>,
+    /// format of the log: text or json (default: "text")
     #[clap(long)]
     log_format: Option<String>,
     /// Enable systemd cgroup manager, rather then use the cgroupfs directly.
     #[clap(short, long)]
     systemd_cgroup: bool,
+    /// print how long the phases of the command took to stderr
+    #[clap(long)]
+    debug_timing: bool,
     /// command to actually manage container
     #[clap(subcommand)]
     subcmd: SubCommand,
@@ -82,11 +98,55 @@ enum SubCommand {
     Events(events::Events),
     #[clap(version = crate_version!(), author = "youki team", setting=clap::AppSettings::AllowLeadingHyphen)]
     Ps(ps::Ps),
+    #[clap(version = crate_version!(), author = "youki team")]
+    Checkpoint(checkpoint::Checkpoint),
+    #[clap(version = crate_version!(), author = "youki team")]
+    Restore(restore::Restore),
+    #[clap(version = crate_version!(), author = "youki team")]
+    Update(update::Update),
+    #[clap(version = crate_version!(), author = "youki team")]
+    Validate(validate::Validate),
+    #[clap(version = crate_version!(), author = "youki team")]
+    Wait(wait::Wait),
+    #[clap(version = crate_version!(), author = "youki team")]
+    MigrateState(migrate_state::MigrateState),
+    #[clap(version = crate_version!(), author = "youki team")]
+    Completion(completion::Completion),
+}
+
+impl SubCommand {
+    /// Returns the name of the subcommand and the container it operates on,
+    /// which are attached to the log records
+    fn log_context(&self) -> (&'static str, Option<&str>) {
+        match self {
+            SubCommand::Create(create) => ("create", Some(&create.container_id)),
+            SubCommand::Start(start) => ("start", Some(&start.container_id)),
+            SubCommand::Run(run) => ("run", Some(&run.container_id)),
+            SubCommand::Exec(exec) => ("exec", Some(&exec.container_id)),
+            SubCommand::Kill(kill) => ("kill", Some(&kill.container_id)),
+            SubCommand::Delete(delete) => ("delete", Some(&delete.container_id)),
+            SubCommand::State(state) => ("state", Some(&state.container_id)),
+            SubCommand::Info(_) => ("info", None),
+            SubCommand::Spec(_) => ("spec", None),
+            SubCommand::List(_) => ("list", None),
+            SubCommand::Pause(pause) => ("pause", Some(&pause.container_id)),
+            SubCommand::Resume(resume) => ("resume", Some(&resume.container_id)),
+            SubCommand::Events(events) => ("events", Some(&events.container_id)),
+            SubCommand::Ps(ps) => ("ps", Some(&ps.container_id)),
+            SubCommand::Checkpoint(checkpoint) => ("checkpoint", Some(&checkpoint.container_id)),
+            SubCommand::Restore(restore) => ("restore", Some(&restore.container_id)),
+            SubCommand::Update(update) => ("update", Some(&update.container_id)),
+            SubCommand::Validate(_) => ("validate", None),
+            SubCommand::Wait(wait) => ("wait", Some(&wait.container_id)),
+            SubCommand::MigrateState(_) => ("migrate-state", None),
+            SubCommand::Completion(_) => ("completion", None),
+        }
+    }
 }
 
 /// This is the entry point in the container runtime. The binary is run by a high-level container runtime,
 /// with various flags passed. This parses the flags, creates and manages appropriate resources.
-fn main() -> Result<()> {
+fn main() {
     // A malicious container can gain access to the host machine by modifying youki's host
     // binary and infect it with malicious code. This vulnerability was first discovered
     // in runc and was assigned as CVE-2019-5736, but it also affects youki.
@@ -97,37 +157,84 @@ fn main() -> Result<()> {
     //
     // Ref: https://github.com/opencontainers/runc/commit/0a8e4117e7f715d5fbeef398405813ce8e88558b
     // Ref: https://github.com/lxc/lxc/commit/6400238d08cdf1ca20d49bafb85f4e224348bf9d
-    pentacle::ensure_sealed().context("Failed to seal /proc/self/exe")?;
+    // The logger is not set up yet, the error can only be reported on stderr.
+    if let Err(e) = pentacle::ensure_sealed().context("Failed to seal /proc/self/exe") {
+        eprintln!("{:#}", e);
+        std::process::exit(error::exit_code(&e));
+    }
 
     let opts = Opts::parse();
 
-    if let Err(e) = youki::logger::init(opts.log) {
+    let log_to_file = opts.log.is_some();
+    let (command, container_id) = opts.subcmd.log_context();
+    youki::logger::set_context(command, container_id);
+    if let Err(e) = youki::logger::init(opts.log, opts.log_format) {
         eprintln!("log init failed: {:?}", e);
     }
 
-    let root_path = determine_root_path(opts.root)?;
-    let systemd_cgroup = opts.systemd_cgroup;
+    let telemetry = match telemetry::init(opts.debug_timing) {
+        Ok(telemetry) => telemetry,
+        Err(e) => {
+            log::warn!("failed to initialize telemetry: {:#}", e);
+            None
+        }
+    };
 
-    match opts.subcmd {
+    let systemd_cgroup = opts.systemd_cgroup;
+    let subcmd = opts.subcmd;
+    let mut exit_code = 0;
+    let result = determine_root_path(opts.root).and_then(|root_path| match subcmd {
         SubCommand::Create(create) => create.exec(root_path, systemd_cgroup),
         SubCommand::Start(start) => start.exec(root_path),
-        SubCommand::Run(run) => run.exec(root_path, systemd_cgroup),
+        SubCommand::Run(run) => run
+            .exec(root_path, systemd_cgroup)
+            .map(|code| exit_code = code),
         SubCommand::Exec(exec) => exec.exec(root_path),
         SubCommand::Kill(kill) => kill.exec(root_path),
         SubCommand::Delete(delete) => delete.exec(root_path),
         SubCommand::State(state) => state.exec(root_path),
-        SubCommand::Info(info) => info.exec(),
+        SubCommand::Info(info) => info.exec(root_path),
         SubCommand::List(list) => list.exec(root_path),
         SubCommand::Spec(spec) => spec.exec(),
         SubCommand::Pause(pause) => pause.exec(root_path),
         SubCommand::Resume(resume) => resume.exec(root_path),
         SubCommand::Events(events) => events.exec(root_path),
         SubCommand::Ps(ps) => ps.exec(root_path),
+        SubCommand::Checkpoint(checkpoint) => checkpoint.exec(root_path),
+        SubCommand::Restore(restore) => restore.exec(root_path, systemd_cgroup),
+        SubCommand::Update(update) => update.exec(root_path),
+        SubCommand::Validate(validate) => validate.exec(),
+        SubCommand::Wait(wait) => wait.exec(root_path),
+        SubCommand::MigrateState(migrate_state) => migrate_state.exec(),
+        SubCommand::Completion(completion) => completion.exec(Opts::into_app()),
+    });
+
+    // The error is reported as a single line including all causes, as higher
+    // level runtimes surface it verbatim. Classified errors determine the
+    // exit code, so that failures can be told apart by automation.
+    if let Err(e) = result {
+        log::error!("{:#}", e);
+        if log_to_file {
+            eprintln!("{:#}", e);
+        }
+        // exit skips the destructors, pending spans have to be flushed before
+        drop(telemetry);
+        std::process::exit(error::exit_code(&e));
+    }
+
+    // youki run exits with the exit code of the container
+    if exit_code != 0 {
+        drop(telemetry);
+        std::process::exit(exit_code);
     }
 }
 
 fn determine_root_path(root_path: Option<PathBuf>) -> Result<PathBuf> {
+    let uid = geteuid().as_raw();
     if let Some(path) = root_path {
+        if !path.exists() {
+            create_dir_all_with_mode(&path, uid, Mode::S_IRWXU)?;
+        }
         return Ok(path);
     }
 
@@ -139,13 +246,15 @@ fn determine_root_path(root_path: Option<PathBuf>) -> Result<PathBuf> {
 
     // see https://specifications.freedesktop.org/basedir-spec/basedir-spec-latest.html
     if let Ok(path) = std::env::var("XDG_RUNTIME_DIR") {
-        return Ok(PathBuf::from(path));
+        if let Some(runtime_dir) = rootless_runtime_dir(PathBuf::from(path), uid) {
+            return Ok(runtime_dir);
+        }
     }
 
     // XDG_RUNTIME_DIR is not set, try the usual location
-    let uid = getuid().as_raw();
-    let runtime_dir = PathBuf::from(format!("/run/user/{}", uid));
-    if create_dir_all_with_mode(&runtime_dir, uid, Mode::S_IRWXU).is_ok() {
+    if let Some(runtime_dir) =
+        rootless_runtime_dir(PathBuf::from(format!("/run/user/{}", uid)), uid)
+    {
         return Ok(runtime_dir);
     }
 
@@ -165,3 +274,27 @@ fn determine_root_path(root_path: Option<PathBuf>) -> Result<PathBuf> {
 
     bail!("could not find a storage location with suitable permissions for the current user");
 }
+
+/// Returns the youki subdirectory of the runtime directory. Older versions kept
+/// the container state directly in the runtime directory, which is still used
+/// as long as it holds containers, so that they can be managed after an upgrade.
+fn rootless_runtime_dir(runtime_dir: PathBuf, uid: u32) -> Option<PathBuf> {
+    if has_container_state(&runtime_dir) {
+        return Some(runtime_dir);
+    }
+
+    let youki_dir = runtime_dir.join("youki");
+    create_dir_all_with_mode(&youki_dir, uid, Mode::S_IRWXU)
+        .ok()
+        .map(|_| youki_dir)
+}
+
+fn has_container_state(dir: &Path) -> bool {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .any(|entry| State::file_path(&entry.path()).is_file())
+        })
+        .unwrap_or(false)
+}
@@ -0,0 +1,131 @@
+//! Thin wrappers around the syscalls of the new mount API (open_tree,
+//! move_mount, fsopen, fsconfig, fsmount and mount_setattr), which are not
+//! exposed by nix yet.
+//! See https://lwn.net/Articles/759499/ for an overview.
+use nix::errno::Errno;
+use nix::NixPath;
+use std::ffi::CString;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+
+/// Create a detached clone of the mount tree instead of opening it as O_PATH
+pub const OPEN_TREE_CLONE: u32 = 1;
+pub const OPEN_TREE_CLOEXEC: u32 = libc::O_CLOEXEC as u32;
+/// Apply the operation to the whole mount tree
+pub const AT_RECURSIVE: u32 = 0x8000;
+const MOVE_MOUNT_F_EMPTY_PATH: u32 = 0x4;
+const MOVE_MOUNT_T_SYMLINKS: u32 = 0x10;
+
+pub const FSOPEN_CLOEXEC: u32 = 0x1;
+pub const FSMOUNT_CLOEXEC: u32 = 0x1;
+
+/// Commands of fsconfig
+pub const FSCONFIG_SET_FLAG: u32 = 0;
+pub const FSCONFIG_SET_STRING: u32 = 1;
+pub const FSCONFIG_CMD_CREATE: u32 = 6;
+
+pub const MOUNT_ATTR_RDONLY: u64 = 0x0000_0001;
+pub const MOUNT_ATTR_NOSUID: u64 = 0x0000_0002;
+pub const MOUNT_ATTR_NODEV: u64 = 0x0000_0004;
+pub const MOUNT_ATTR_NOEXEC: u64 = 0x0000_0008;
+pub const MOUNT_ATTR_NOATIME: u64 = 0x0000_0010;
+pub const MOUNT_ATTR_STRICTATIME: u64 = 0x0000_0020;
+pub const MOUNT_ATTR_NODIRATIME: u64 = 0x0000_0080;
+pub const MOUNT_ATTR_IDMAP: u64 = 0x0010_0000;
+
+/// Mirrors struct mount_attr from linux/mount.h
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MountAttr {
+    pub attr_set: u64,
+    pub attr_clr: u64,
+    pub propagation: u64,
+    pub userns_fd: u64,
+}
+
+/// Opens the mount at path. If OPEN_TREE_CLONE is passed, the returned fd
+/// refers to a detached copy of the mount which can be attached somewhere
+/// else with move_mount.
+pub fn open_tree(path: &Path, flags: u32) -> Result<RawFd, Errno> {
+    let res = path.with_nix_path(|p| unsafe {
+        libc::syscall(libc::SYS_open_tree, libc::AT_FDCWD, p.as_ptr(), flags)
+    })?;
+
+    Errno::result(res).map(|fd| fd as RawFd)
+}
+
+/// Attaches the mount referred to by fd at the given path. Symlinks are
+/// followed, so that the path can be a magic link in /proc/self/fd.
+pub fn move_mount(fd: RawFd, to: &Path) -> Result<(), Errno> {
+    let empty = b"\0";
+    let res = to.with_nix_path(|p| unsafe {
+        libc::syscall(
+            libc::SYS_move_mount,
+            fd,
+            empty.as_ptr(),
+            libc::AT_FDCWD,
+            p.as_ptr(),
+            MOVE_MOUNT_F_EMPTY_PATH | MOVE_MOUNT_T_SYMLINKS,
+        )
+    })?;
+
+    Errno::result(res).map(drop)
+}
+
+/// Opens a context to configure a new filesystem of the given type
+pub fn fsopen(fs_name: &str, flags: u32) -> Result<RawFd, Errno> {
+    let fs_name = CString::new(fs_name).map_err(|_| Errno::EINVAL)?;
+    let res = unsafe { libc::syscall(libc::SYS_fsopen, fs_name.as_ptr(), flags) };
+
+    Errno::result(res).map(|fd| fd as RawFd)
+}
+
+/// Sets a parameter of the filesystem context or runs a command on it. The
+/// value is only passed for FSCONFIG_SET_STRING.
+pub fn fsconfig(fd: RawFd, cmd: u32, key: Option<&str>, value: Option<&str>) -> Result<(), Errno> {
+    let key = key
+        .map(CString::new)
+        .transpose()
+        .map_err(|_| Errno::EINVAL)?;
+    let value = value
+        .map(CString::new)
+        .transpose()
+        .map_err(|_| Errno::EINVAL)?;
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_fsconfig,
+            fd,
+            cmd,
+            key.as_ref().map_or(std::ptr::null(), |k| k.as_ptr()),
+            value.as_ref().map_or(std::ptr::null(), |v| v.as_ptr()),
+            0,
+        )
+    };
+
+    Errno::result(res).map(drop)
+}
+
+/// Creates a detached mount of the filesystem created in the context, the
+/// attributes are MOUNT_ATTR_* flags
+pub fn fsmount(fd: RawFd, flags: u32, attr_flags: u64) -> Result<RawFd, Errno> {
+    let res = unsafe { libc::syscall(libc::SYS_fsmount, fd, flags, attr_flags as libc::c_uint) };
+
+    Errno::result(res).map(|fd| fd as RawFd)
+}
+
+/// Changes the properties of the mount referred to by fd
+pub fn mount_setattr(fd: RawFd, flags: u32, attr: &MountAttr) -> Result<(), Errno> {
+    let empty = b"\0";
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            fd,
+            empty.as_ptr(),
+            flags | libc::AT_EMPTY_PATH as u32,
+            attr as *const MountAttr,
+            std::mem::size_of::<MountAttr>(),
+        )
+    };
+
+    Errno::result(res).map(drop)
+}
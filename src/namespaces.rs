@@ -13,6 +13,19 @@ use nix::{fcntl, sched::CloneFlags, sys::stat, unistd};
 use oci_spec::runtime::{LinuxNamespace, LinuxNamespaceType};
 use std::collections;
 
+// Order in which the namespaces are entered. The user namespace comes first,
+// as joining the others requires the capabilities in it, and the mount
+// namespace last, as the paths of the others may be on the host /proc.
+const NAMESPACE_ORDER: &[CloneFlags] = &[
+    CloneFlags::CLONE_NEWUSER,
+    CloneFlags::CLONE_NEWPID,
+    CloneFlags::CLONE_NEWUTS,
+    CloneFlags::CLONE_NEWIPC,
+    CloneFlags::CLONE_NEWNET,
+    CloneFlags::CLONE_NEWCGROUP,
+    CloneFlags::CLONE_NEWNS,
+];
+
 /// Holds information about namespaces
 pub struct Namespaces {
     command: Box<dyn Syscall>,
@@ -48,12 +61,13 @@ impl From<Option<&Vec<LinuxNamespace>>> for Namespaces {
 }
 
 impl Namespaces {
+    /// Enters the namespaces which pass the filter, in the order the kernel
+    /// requires
     pub fn apply_namespaces<F: Fn(CloneFlags) -> bool>(&self, filter: F) -> Result<()> {
-        let to_enter: collections::HashMap<&CloneFlags, &LinuxNamespace> = self
-            .namespace_map
+        let to_enter = NAMESPACE_ORDER
             .iter()
-            .filter(|(k, _)| filter(**k))
-            .collect();
+            .filter(|ns_type| filter(**ns_type))
+            .filter_map(|ns_type| self.namespace_map.get(ns_type).map(|ns| (ns_type, ns)));
         for (ns_type, ns) in to_enter {
             self.unshare_or_setns(ns)
                 .with_context(|| format!("Failed to enter {:?} namespace: {:?}", ns_type, ns))?;
@@ -143,4 +157,31 @@ mod tests {
         expect.sort();
         assert_eq!(unshare_args, expect)
     }
+
+    #[test]
+    #[serial]
+    fn test_apply_namespaces_order() {
+        let sample_linux_namespaces = gen_sample_linux_namespaces();
+        let namespaces = Namespaces::from(Some(&sample_linux_namespaces));
+        let test_command: &TestHelperSyscall = namespaces.command.as_any().downcast_ref().unwrap();
+        assert!(namespaces.apply_namespaces(|_| true).is_ok());
+
+        let setns_args: Vec<_> = test_command
+            .get_setns_args()
+            .into_iter()
+            .map(|(_fd, cf)| cf)
+            .collect();
+        assert_eq!(
+            setns_args,
+            vec![CloneFlags::CLONE_NEWNET, CloneFlags::CLONE_NEWNS]
+        );
+        assert_eq!(
+            test_command.get_unshare_args(),
+            vec![
+                CloneFlags::CLONE_NEWUSER,
+                CloneFlags::CLONE_NEWPID,
+                CloneFlags::CLONE_NEWIPC
+            ]
+        );
+    }
 }
@@ -2,9 +2,20 @@ use oci_spec::runtime::Spec;
 use std::os::unix::prelude::RawFd;
 use std::path::PathBuf;
 
+use crate::idmap::IdmappedMounts;
+use crate::landlock::Landlock;
 use crate::rootless::Rootless;
+use crate::stdio_log::StdioPipes;
 use crate::{container::Container, notify_socket::NotifyListener, syscall::Syscall};
 
+/// Waits in the container process until the container is started
+pub enum StartSync {
+    /// O_PATH fd of the exec fifo of an init container
+    ExecFifo(RawFd),
+    /// Socket a tenant process is notified on by youki exec
+    NotifySocket(NotifyListener),
+}
+
 pub struct ContainerArgs<'a> {
     /// Flag indicating if an init or a tenant container should be created
     pub init: bool,
@@ -16,12 +27,26 @@ pub struct ContainerArgs<'a> {
     pub rootfs: PathBuf,
     /// Socket to communicate the file descriptor of the ptty
     pub console_socket: Option<RawFd>,
-    /// The Unix Domain Socket to communicate container start
-    pub notify_socket: NotifyListener,
+    /// Communicates the container start
+    pub start_sync: StartSync,
     /// File descriptos preserved/passed to the container init process.
     pub preserve_fds: i32,
+    /// Number of sockets passed to youki by systemd socket activation
+    pub listen_fds: i32,
+    /// Names of the sockets passed by systemd socket activation
+    pub listen_fdnames: Option<String>,
     /// Container state
     pub container: Option<Container>,
     /// Options for rootless containers
     pub rootless: Option<Rootless<'a>>,
+    /// Detached idmapped mounts to attach into the rootfs
+    pub idmapped_mounts: IdmappedMounts,
+    /// Flag indicating if MS_MOVE and chroot should be used instead of pivot_root
+    pub no_pivot: bool,
+    /// Compiled seccomp filter of the seccomp profile in the spec
+    pub seccomp_filter: Option<Vec<u8>>,
+    /// Landlock ruleset to enforce before the payload is executed
+    pub landlock: Option<Landlock>,
+    /// Pipes to use as stdout and stderr of the container process
+    pub stdio_pipes: Option<StdioPipes>,
 }
@@ -1,4 +1,5 @@
 use crate::process::message::Message;
+use crate::telemetry::Timings;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
@@ -12,6 +13,7 @@ use std::os::unix::io::AsRawFd;
 
 trait SenderExt {
     fn write_message(&mut self, msg: Message) -> Result<()>;
+    fn write_error(&mut self, err: &anyhow::Error) -> Result<()>;
 }
 
 impl SenderExt for Sender {
@@ -22,6 +24,25 @@ impl SenderExt for Sender {
             .with_context(|| format!("Failed to write message {:?} to the pipe", bytes))?;
         Ok(())
     }
+
+    fn write_error(&mut self, err: &anyhow::Error) -> Result<()> {
+        let text = format!("{:#}", err);
+        self.write_message(Message::Error)?;
+        self.write_all(&(text.len() as u32).to_be_bytes())?;
+        self.write_all(text.as_bytes())
+            .context("Failed to write error to the pipe")?;
+        Ok(())
+    }
+}
+
+// Reads the text of an error sent by a child process. Logging is disabled in
+// the child processes, so this is the only way their errors reach the user.
+fn read_error(receiver: &mut Receiver) -> Result<String> {
+    let mut buf = [0; 4];
+    receiver.read_exact(&mut buf)?;
+    let mut text = vec![0; u32::from_be_bytes(buf) as usize];
+    receiver.read_exact(&mut text)?;
+    Ok(String::from_utf8_lossy(&text).into_owned())
 }
 
 pub fn main_to_intermediate() -> Result<(SenderMainToIntermediate, ReceiverFromMain)> {
@@ -99,14 +120,23 @@ impl SenderIntermediateToMain {
         Ok(())
     }
 
-    pub fn intermediate_ready(&mut self, pid: Pid) -> Result<()> {
-        // Send over the IntermediateReady follow by the pid.
+    pub fn intermediate_ready(&mut self, pid: Pid, timings: &Timings) -> Result<()> {
+        // Send over the IntermediateReady follow by the pid and the timings
+        // of the intermediate and init process.
         log::debug!("sending init pid ({:?})", pid);
         self.sender.write_message(Message::IntermediateReady)?;
         self.sender.write_all(&(pid.as_raw()).to_be_bytes())?;
+        timings
+            .write_to(&mut self.sender)
+            .context("Failed to write timings to the pipe")?;
         Ok(())
     }
 
+    // reports why the intermediate process failed to the main process
+    pub fn send_error(&mut self, err: &anyhow::Error) -> Result<()> {
+        self.sender.write_error(err)
+    }
+
     pub fn close(&self) -> Result<()> {
         unistd::close(self.sender.as_raw_fd())?;
         Ok(())
@@ -127,6 +157,7 @@ impl ReceiverFromIntermediate {
         // convert to Message wrapper
         match Message::from(u8::from_be_bytes(buf)) {
             Message::WriteMapping => Ok(()),
+            Message::Error => bail!("{}", read_error(&mut self.receiver)?),
             msg => bail!(
                 "receive unexpected message {:?} waiting for mapping request",
                 msg
@@ -136,7 +167,8 @@ impl ReceiverFromIntermediate {
 
     /// Waits for associated intermediate process to send ready message
     /// and return the pid of init process which is forked by intermediate process
-    pub fn wait_for_intermediate_ready(&mut self) -> Result<Pid> {
+    /// together with the timings of both processes
+    pub fn wait_for_intermediate_ready(&mut self) -> Result<(Pid, Timings)> {
         let mut buf = [0; 1];
         self.receiver
             .read_exact(&mut buf)
@@ -150,9 +182,12 @@ impl ReceiverFromIntermediate {
                 self.receiver.read_exact(&mut buf).with_context(|| {
                     "Failed to receive a message from the intermediate process."
                 })?;
+                let timings = Timings::read_from(&mut self.receiver)
+                    .context("Failed to receive timings from the intermediate process.")?;
 
-                Ok(Pid::from_raw(i32::from_be_bytes(buf)))
+                Ok((Pid::from_raw(i32::from_be_bytes(buf)), timings))
             }
+            Message::Error => bail!("{}", read_error(&mut self.receiver)?),
             msg => bail!(
                 "receive unexpected message {:?} waiting for intermediate ready",
                 msg
@@ -179,11 +214,19 @@ pub struct SenderInitToIntermediate {
 }
 
 impl SenderInitToIntermediate {
-    pub fn init_ready(&mut self) -> Result<()> {
+    pub fn init_ready(&mut self, timings: &Timings) -> Result<()> {
         self.sender.write_message(Message::InitReady)?;
+        timings
+            .write_to(&mut self.sender)
+            .context("Failed to write timings to the pipe")?;
         Ok(())
     }
 
+    // reports why the init process failed to the intermediate process
+    pub fn send_error(&mut self, err: &anyhow::Error) -> Result<()> {
+        self.sender.write_error(err)
+    }
+
     pub fn close(&self) -> Result<()> {
         unistd::close(self.sender.as_raw_fd())?;
         Ok(())
@@ -196,15 +239,17 @@ pub struct ReceiverFromInit {
 
 impl ReceiverFromInit {
     /// Waits for associated init process to send ready message
-    /// and return the pid of init process which is forked by init process
-    pub fn wait_for_init_ready(&mut self) -> Result<()> {
+    /// and return the timings of the init process
+    pub fn wait_for_init_ready(&mut self) -> Result<Timings> {
         let mut buf = [0; 1];
         self.receiver
             .read_exact(&mut buf)
             .with_context(|| "Failed to receive a message from the init process.")?;
 
         match Message::from(u8::from_be_bytes(buf)) {
-            Message::InitReady => Ok(()),
+            Message::InitReady => Timings::read_from(&mut self.receiver)
+                .context("Failed to receive timings from the init process."),
+            Message::Error => bail!("{}", read_error(&mut self.receiver)?),
             msg => bail!(
                 "receive unexpected message {:?} waiting for init ready",
                 msg
@@ -252,7 +297,7 @@ mod tests {
         match unsafe { unistd::fork()? } {
             unistd::ForkResult::Parent { child } => {
                 wait::waitpid(child, None)?;
-                let pid = receiver
+                let (pid, _) = receiver
                     .wait_for_intermediate_ready()
                     .with_context(|| "Failed to wait for intermadiate ready")?;
                 receiver.close()?;
@@ -260,7 +305,7 @@ mod tests {
             }
             unistd::ForkResult::Child => {
                 let pid = unistd::getpid();
-                sender.intermediate_ready(pid)?;
+                sender.intermediate_ready(pid, &Timings::default())?;
                 sender.close()?;
                 std::process::exit(0);
             }
@@ -323,7 +368,7 @@ mod tests {
             }
             unistd::ForkResult::Child => {
                 sender
-                    .init_ready()
+                    .init_ready(&Timings::default())
                     .with_context(|| "Failed to send init ready")?;
                 sender.close()?;
                 std::process::exit(0);
@@ -333,6 +378,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_channel_init_error() -> Result<()> {
+        let (sender, receiver) = &mut init_to_intermediate()?;
+        match unsafe { unistd::fork()? } {
+            unistd::ForkResult::Parent { child } => {
+                wait::waitpid(child, None)?;
+                let err = receiver.wait_for_init_ready().unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    "Failed to mount rootfs: No such file or directory"
+                );
+                receiver.close()?;
+            }
+            unistd::ForkResult::Child => {
+                let err =
+                    anyhow::anyhow!("No such file or directory").context("Failed to mount rootfs");
+                sender.send_error(&err)?;
+                sender.close()?;
+                std::process::exit(0);
+            }
+        };
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_channel_intermedaite_graceful_exit() -> Result<()> {
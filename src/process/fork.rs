@@ -8,17 +8,27 @@ use nix::unistd::Pid;
 // using clone, we would have to manually make sure all the variables are
 // correctly send to the new process, especially Rust borrow checker will be a
 // lot of hassel to deal with every details.
+//
+// Only the forking thread exists in the child. Locks held by other threads of
+// the parent at the time of the fork stay locked forever, so container_fork
+// itself does no work in the child which could take them. Log records and
+// tracing spans are only emitted by the process which set up the logger and
+// the telemetry, which is recognized by its pid without any setup in the
+// child. The termination handlers are reset with plain sigaction calls. The
+// child exits with _exit, which unlike exit does not run atexit handlers of
+// the parent. The callbacks still allocate and format, so they rely on youki
+// not running other threads while it forks. As the child cannot log, the
+// callbacks send their errors to the parent through their channel.
 pub fn container_fork<F: FnOnce() -> Result<()>>(cb: F) -> Result<Pid> {
     match unsafe { unistd::fork()? } {
         unistd::ForkResult::Parent { child } => Ok(child),
         unistd::ForkResult::Child => {
-            let ret = if let Err(error) = cb() {
-                log::debug!("failed to run fork: {:?}", error);
-                -1
-            } else {
-                0
+            crate::signal::reset_termination_handlers();
+            let ret = match cb() {
+                Ok(()) => 0,
+                Err(_) => -1,
             };
-            std::process::exit(ret);
+            unsafe { libc::_exit(ret) }
         }
     }
 }
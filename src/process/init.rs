@@ -1,24 +1,61 @@
-use super::args::ContainerArgs;
+use super::{
+    args::{ContainerArgs, StartSync},
+    user,
+};
 use crate::apparmor;
+use crate::features;
 use crate::{
-    capabilities, hooks, namespaces::Namespaces, process::channel, rootfs, rootless::Rootless,
-    seccomp, tty, utils,
+    capabilities, exec_fifo, hooks,
+    namespaces::Namespaces,
+    process::channel,
+    rootfs,
+    rootless::Rootless,
+    selinux,
+    syscall::{PivotRootError, Syscall},
+    telemetry::Timings,
+    tty,
+    utils::{self, secure_fs::Dir},
 };
+#[cfg(feature = "seccomp")]
+use crate::{container::Container, seccomp};
 use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
 use nix::mount::mount as nix_mount;
 use nix::mount::MsFlags;
 use nix::sched::CloneFlags;
 use nix::{
     fcntl,
+    sys::stat,
     unistd::{self, Gid, Uid},
 };
+#[cfg(feature = "seccomp")]
+use oci_spec::runtime::LinuxSeccomp;
 use oci_spec::runtime::{LinuxNamespaceType, User};
 use std::collections::HashMap;
 use std::{
-    env, fs,
+    fs,
+    io::Read,
     path::{Path, PathBuf},
 };
 
+// close_range was added in Linux 5.9, CLOSE_RANGE_CLOEXEC in Linux 5.11.
+const CLOSE_RANGE_CLOEXEC: libc::c_uint = 1 << 2;
+
+// Sets CLOEXEC on all fds starting at first_fd which are open at the time of
+// the call, without having to look them up in procfs.
+fn close_range_cloexec(first_fd: i32) -> Result<(), Errno> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_close_range,
+            first_fd as libc::c_uint,
+            libc::c_uint::MAX,
+            CLOSE_RANGE_CLOEXEC,
+        )
+    };
+
+    Errno::result(res).map(drop)
+}
+
 // Get a list of open fds for the calling process.
 fn get_open_fds() -> Result<Vec<i32>> {
     const PROCFS_FD_PATH: &str = "/proc/self/fd";
@@ -52,11 +89,21 @@ fn get_open_fds() -> Result<Vec<i32>> {
 // "preserve_fds" number of fds. Set the rest of fd with CLOEXEC flag, so they
 // will be closed after execve into the container payload. We can't close the
 // fds immediatly since we at least still need it for the pipe used to wait on
-// starting the container.
+// starting the container. Fds leaked into the container, e.g. sockets of the
+// host or a directory fd of the state directory, allow it to escape, so the
+// list of fds from procfs is only used if the kernel lacks close_range.
 fn cleanup_file_descriptors(preserve_fds: i32) -> Result<()> {
-    let open_fds = get_open_fds().with_context(|| "Failed to obtain opened fds")?;
     // Include stdin, stdout, and stderr for fd 0, 1, and 2 respectively.
     let min_fd = preserve_fds + 3;
+    match close_range_cloexec(min_fd) {
+        Ok(()) => return Ok(()),
+        Err(Errno::ENOSYS) | Err(Errno::EINVAL) => {
+            log::debug!("close_range is not supported, falling back to procfs");
+        }
+        Err(e) => bail!("failed to set CLOEXEC with close_range: {}", e),
+    }
+
+    let open_fds = get_open_fds().with_context(|| "Failed to obtain opened fds")?;
     let to_be_cleaned_up_fds: Vec<i32> = open_fds
         .iter()
         .filter_map(|&fd| if fd >= min_fd { Some(fd) } else { None })
@@ -72,21 +119,45 @@ fn cleanup_file_descriptors(preserve_fds: i32) -> Result<()> {
 }
 
 fn sysctl(kernel_params: &HashMap<String, String>) -> Result<()> {
-    let sys = PathBuf::from("/proc/sys");
+    let sys = Dir::open_procfs("/proc/sys")?;
+    let read_only = features::proc_sys_read_only();
     for (kernel_param, value) in kernel_params {
-        let path = sys.join(kernel_param.replace(".", "/"));
+        let path = kernel_param.replace(".", "/");
         log::debug!(
             "apply value {} to kernel parameter {}.",
             value,
             kernel_param
         );
-        fs::write(path, value.as_bytes())
-            .with_context(|| format!("failed to set sysctl {}={}", kernel_param, value))?;
+        // Nested in a container /proc/sys is usually read-only, which is
+        // tolerated as long as the sysctl already has the requested value
+        if read_only && sysctl_value(&sys, &path).as_deref() == Some(value.trim()) {
+            log::debug!("sysctl {} already is {}", kernel_param, value);
+            continue;
+        }
+        sys.write_file(&path, value).with_context(|| {
+            if read_only {
+                format!(
+                    "failed to set sysctl {}={}, /proc/sys is read-only",
+                    kernel_param, value
+                )
+            } else {
+                format!("failed to set sysctl {}={}", kernel_param, value)
+            }
+        })?;
     }
 
     Ok(())
 }
 
+fn sysctl_value(sys: &Dir, path: &str) -> Option<String> {
+    let mut value = String::new();
+    sys.open_file(path, fcntl::OFlag::O_RDONLY, stat::Mode::empty())
+        .ok()?
+        .read_to_string(&mut value)
+        .ok()?;
+    Some(value.trim().to_owned())
+}
+
 // make a read only path
 // The first time we bind mount, other flags are ignored,
 // so we need to mount it once and then remount it with the necessary flags specified.
@@ -172,11 +243,29 @@ pub fn container_init(
     let container = args.container.as_ref();
     let namespaces = Namespaces::from(linux.namespaces().as_ref());
 
+    if let Some(stdio_pipes) = args.stdio_pipes {
+        unistd::dup2(stdio_pipes.stdout, 1).context("failed to redirect stdout")?;
+        unistd::dup2(stdio_pipes.stderr, 2).context("failed to redirect stderr")?;
+    }
+
     // set up tty if specified
     if let Some(csocketfd) = args.console_socket {
         tty::setup_console(&csocketfd).with_context(|| "Failed to set up tty")?;
     }
 
+    // the agent is connected to while its socket is still reachable in the
+    // mount namespace of youki
+    #[cfg(feature = "seccomp")]
+    let seccomp_listener = match linux.seccomp() {
+        Some(seccomp) if args.seccomp_filter.is_some() => seccomp
+            .listener_path()
+            .as_ref()
+            .map(seccomp::SeccompListener::connect)
+            .transpose()?,
+        _ => None,
+    };
+
+    let namespace_span = tracing::info_span!("namespace_setup").entered();
     // Enter into rest of namespace. Note, we already entered into user and pid
     // namespace. We also have to enter into mount namespace last since
     // namespace may be bind to /proc path. The /proc path will need to be
@@ -202,6 +291,7 @@ pub fn container_init(
             }
         }
     }
+    drop(namespace_span);
 
     if let Some(true) = proc.no_new_privileges() {
         let _ = prctl::set_no_new_privileges(true);
@@ -211,12 +301,20 @@ pub fn container_init(
         // create_container hook needs to be called after the namespace setup, but
         // before pivot_root is called. This runs in the container namespaces.
         if let Some(hooks) = hooks {
-            hooks::run_hooks(hooks.create_container().as_ref(), container)
-                .context("Failed to run create container hooks")?;
+            hooks::run_hooks(
+                "createContainer",
+                hooks.create_container().as_ref(),
+                container,
+            )
+            .context("Failed to run create container hooks")?;
         }
 
-        let bind_service = namespaces.get(LinuxNamespaceType::User).is_some();
-        rootfs::prepare_rootfs(spec, rootfs, bind_service)
+        let _span = tracing::info_span!("rootfs_prep").entered();
+        // device nodes cannot be created in a user namespace, which includes
+        // running nested in a container with a user namespace of its own
+        let bind_service =
+            namespaces.get(LinuxNamespaceType::User).is_some() || features::in_user_namespace();
+        rootfs::prepare_rootfs(spec, rootfs, bind_service, &args.idmapped_mounts)
             .with_context(|| "Failed to prepare rootfs")?;
 
         // Entering into the rootfs jail. If mount namespace is specified, then
@@ -224,10 +322,7 @@ pub fn container_init(
         // use simple chroot. Scary things will happen if you try to pivot_root
         // in the host mount namespace...
         if namespaces.get(LinuxNamespaceType::Mount).is_some() {
-            // change the root of filesystem of the process to the rootfs
-            command
-                .pivot_rootfs(rootfs)
-                .with_context(|| format!("Failed to pivot root to {:?}", rootfs))?;
+            enter_rootfs(command, rootfs, args.no_pivot)?;
         } else {
             command
                 .chroot(rootfs)
@@ -286,22 +381,64 @@ pub fn container_init(
         }
     };
 
-    set_supplementary_gids(proc.user(), &args.rootless)
-        .context("failed to set supplementary gids")?;
+    // The user name can only be resolved once the root of the container has
+    // been entered, as it refers to the passwd and group files of the image.
+    // Unprivileged rootless containers cannot set supplementary groups, so
+    // the groups the user is a member of are not added for them.
+    let user = user::resolve_user(
+        proc.user(),
+        Path::new(user::PASSWD_PATH),
+        Path::new(user::GROUP_PATH),
+        !matches!(&args.rootless, Some(rootless) if !rootless.privileged),
+    )
+    .context("failed to resolve container user")?;
+
+    // Without a pty the container process inherits the stdio of youki, which
+    // may not be accessible for the container user.
+    if args.console_socket.is_none() {
+        fix_stdio_permissions(Uid::from_raw(user.uid()))
+            .context("failed to fix permissions of stdio")?;
+    } else if user.uid() != 0 {
+        // the tty group of the image may differ from the convention
+        let tty_gid = user::group_gid(Path::new(user::GROUP_PATH), "tty")
+            .unwrap_or_default()
+            .unwrap_or(tty::TTY_GID);
+        tty::chown_pty_slave(Uid::from_raw(user.uid()), Some(Gid::from_raw(tty_gid)))
+            .context("failed to hand pty over to container user")?;
+    }
+
+    // Many images rely on HOME, so like runc it is set to the home directory
+    // of the container user if the spec does not set it. The passwd of the
+    // container has to be read before the user is switched.
+    if !envs.iter().any(|env| env.starts_with("HOME=")) {
+        let home = user::home_dir(Path::new(user::PASSWD_PATH), user.uid()).unwrap_or_else(|e| {
+            log::debug!(
+                "failed to look up home directory in {}: {}",
+                user::PASSWD_PATH,
+                e
+            );
+            None
+        });
+        envs.push(format!(
+            "HOME={}",
+            home.unwrap_or_else(|| PathBuf::from("/")).display()
+        ));
+    }
+
+    set_supplementary_gids(&user, &args.rootless).context("failed to set supplementary gids")?;
 
     command
-        .set_id(
-            Uid::from_raw(proc.user().uid()),
-            Gid::from_raw(proc.user().gid()),
-        )
+        .set_id(Uid::from_raw(user.uid()), Gid::from_raw(user.gid()))
         .context("Failed to configure uid and gid")?;
 
     // Without no new privileges, seccomp is a privileged operation. We have to
     // do this before dropping capabilities. Otherwise, we should do it later,
     // as close to exec as possible.
-    if linux.seccomp().is_some() && proc.no_new_privileges().is_none() {
-        seccomp::initialize_seccomp(linux.seccomp().as_ref().unwrap())
-            .context("Failed to execute seccomp")?;
+    #[cfg(feature = "seccomp")]
+    if let (Some(seccomp), Some(filter)) = (linux.seccomp(), &args.seccomp_filter) {
+        if proc.no_new_privileges().is_none() {
+            load_seccomp(filter, seccomp, seccomp_listener.as_ref(), container)?;
+        }
     }
 
     capabilities::reset_effective(command).context("Failed to reset effective capabilities")?;
@@ -311,43 +448,21 @@ pub fn container_init(
 
     // Take care of LISTEN_FDS used for systemd-active-socket. If the value is
     // not 0, then we have to preserve those fds as well, and set up the correct
-    // environment variables.
-    let preserve_fds: i32 = match env::var("LISTEN_FDS") {
-        Ok(listen_fds_str) => {
-            let listen_fds = match listen_fds_str.parse::<i32>() {
-                Ok(v) => v,
-                Err(error) => {
-                    log::warn!(
-                        "LISTEN_FDS entered is not a fd. Ignore the value. {:?}",
-                        error
-                    );
-
-                    0
-                }
-            };
-
-            // The LISTEN_FDS will have to be passed to container init process.
-            // The LISTEN_PID will be set to PID 1. Based on the spec, if
-            // LISTEN_FDS is 0, the variable should be unset, so we just ignore
-            // it here, if it is 0.
-            if listen_fds > 0 {
-                envs.append(&mut vec![
-                    format!("LISTEN_FDS={}", listen_fds),
-                    "LISTEN_PID=1".to_string(),
-                ]);
-            }
-
-            args.preserve_fds + listen_fds
+    // environment variables. Based on the spec, if LISTEN_FDS is 0, the
+    // variables should be unset, so we just ignore it here, if it is 0.
+    if args.listen_fds > 0 {
+        // LISTEN_PID has to refer to the container process. As exec keeps
+        // the pid, this is our own pid in the pid namespace of the container,
+        // which is 1 for the init process of a new pid namespace.
+        envs.append(&mut vec![
+            format!("LISTEN_FDS={}", args.listen_fds),
+            format!("LISTEN_PID={}", unistd::getpid()),
+        ]);
+        if let Some(names) = &args.listen_fdnames {
+            envs.push(format!("LISTEN_FDNAMES={}", names));
         }
-        Err(env::VarError::NotPresent) => args.preserve_fds,
-        Err(env::VarError::NotUnicode(value)) => {
-            log::warn!(
-                "LISTEN_FDS entered is malformed: {:?}. Ignore the value.",
-                &value
-            );
-            args.preserve_fds
-        }
-    };
+    }
+    let preserve_fds = args.preserve_fds + args.listen_fds;
 
     // clean up and handle perserved fds.
     cleanup_file_descriptors(preserve_fds).with_context(|| "Failed to clean up extra fds")?;
@@ -359,45 +474,160 @@ pub fn container_init(
     }
 
     // Reset the process env based on oci spec.
-    env::vars().for_each(|(key, _value)| std::env::remove_var(key));
-    utils::parse_env(&envs)
-        .iter()
-        .for_each(|(key, value)| env::set_var(key, value));
+    utils::reset_env(&utils::parse_env(&envs)).context("failed to set up the environment")?;
 
     // notify parents that the init process is ready to execute the payload.
     // Note, we pass -1 here because we are already inside the pid namespace.
     // The pid outside the pid namespace should be recorded by the intermediate
     // process.
-    sender_to_intermediate.init_ready()?;
+    sender_to_intermediate.init_ready(&Timings::collect())?;
+
+    // wait for the container start command
+    let exec_fifo = match &args.start_sync {
+        StartSync::ExecFifo(fd) => Some(exec_fifo::wait_for_start(*fd)?),
+        StartSync::NotifySocket(notify_socket) => {
+            notify_socket.wait_for_container_start()?;
+            None
+        }
+    };
 
-    // listing on the notify socket for container start command
-    let notify_socket = args.notify_socket;
-    notify_socket.wait_for_container_start()?;
+    let result = (|| -> Result<()> {
+        // create_container hook needs to be called after the namespace setup, but
+        // before pivot_root is called. This runs in the container namespaces.
+        if args.init {
+            if let Some(hooks) = hooks {
+                hooks::run_hooks(
+                    "startContainer",
+                    hooks.start_container().as_ref(),
+                    container,
+                )?
+            }
+        }
 
-    // create_container hook needs to be called after the namespace setup, but
-    // before pivot_root is called. This runs in the container namespaces.
-    if args.init {
-        if let Some(hooks) = hooks {
-            hooks::run_hooks(hooks.start_container().as_ref(), container)?
+        // the label is applied by the execve of the payload, the hooks above
+        // still run with the label of youki
+        if let Some(label) = proc.selinux_label() {
+            selinux::set_exec_label(label)
+                .with_context(|| format!("failed to set selinux label {}", label))?;
+        }
+
+        // Landlock restricts the accesses of the process itself, so it is enforced
+        // after the start container hooks, right before the payload is executed.
+        if let Some(landlock) = &args.landlock {
+            landlock
+                .apply()
+                .context("failed to enforce landlock ruleset")?;
+        }
+
+        #[cfg(feature = "seccomp")]
+        if let (Some(seccomp), Some(filter)) = (linux.seccomp(), &args.seccomp_filter) {
+            if proc.no_new_privileges().is_some() {
+                // Initialize seccomp profile right before we are ready to execute the
+                // payload. Waiting for the start still needs syscalls a profile may deny.
+                load_seccomp(filter, seccomp, seccomp_listener.as_ref(), container)?;
+            }
         }
+
+        if let Some(args) = proc.args() {
+            utils::do_exec(&args[0], args).with_context(|| format!("failed to execute {}", args[0]))
+        } else {
+            bail!("On non-Windows, at least one process arg entry is required.")
+        }
+    })();
+
+    // After do_exec is called, the process is replaced with the container
+    // payload through execvp, so it only gets here if starting it failed.
+    // youki start waits for the exec and reports the error.
+    let err = match result {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+    if let Some(exec_fifo) = &exec_fifo {
+        exec_fifo.report_error(&err);
     }
+    Err(err)
+}
 
-    if linux.seccomp().is_some() && proc.no_new_privileges().is_some() {
-        // Initialize seccomp profile right before we are ready to execute the
-        // payload. The notify socket will still need network related syscalls.
-        seccomp::initialize_seccomp(linux.seccomp().as_ref().unwrap())
-            .context("Failed to execute seccomp")?;
+// Makes the rootfs the root of the mount namespace with pivot_root, or by
+// moving it on top of the original root if no_pivot is set. pivot_root
+// fails with EINVAL if the current root can not be unmounted, which is the
+// case for ramdisk roots (initramfs), then the rootfs is moved as well.
+fn enter_rootfs(command: &dyn Syscall, rootfs: &Path, no_pivot: bool) -> Result<()> {
+    if !no_pivot {
+        let err = match command.pivot_rootfs(rootfs) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        if err.downcast_ref::<PivotRootError>() != Some(&PivotRootError(Errno::EINVAL)) {
+            return Err(err.context(format!("Failed to pivot root to {:?}", rootfs)));
+        }
+
+        log::warn!("pivot_root is not possible, falling back to MS_MOVE and chroot");
     }
 
-    if let Some(args) = proc.args() {
-        utils::do_exec(&args[0], args)?;
-    } else {
-        bail!("On non-Windows, at least one process arg entry is required.")
+    command
+        .move_rootfs(rootfs)
+        .with_context(|| format!("Failed to move root to {:?}", rootfs))
+}
+
+// Loads the compiled seccomp filter and passes its notify fd to the agent
+#[cfg(feature = "seccomp")]
+fn load_seccomp(
+    filter: &[u8],
+    seccomp: &LinuxSeccomp,
+    listener: Option<&seccomp::SeccompListener>,
+    container: Option<&Container>,
+) -> Result<()> {
+    let _span = tracing::info_span!("seccomp_load").entered();
+    let notify_fd =
+        seccomp::load_seccomp_filter(filter, seccomp).context("Failed to execute seccomp")?;
+    match (notify_fd, listener) {
+        (Some(fd), Some(listener)) => {
+            let state = container
+                .map(|c| c.state.clone())
+                .context("container state is required by the seccomp agent")?;
+            listener
+                .send_notify_fd(
+                    fd,
+                    seccomp.listener_metadata().as_deref().unwrap_or_default(),
+                    &state,
+                )
+                .context("failed to pass seccomp notify fd to the agent")?;
+        }
+        // the fd must not be left to the container process
+        (Some(fd), None) => {
+            let _ = unistd::close(fd);
+        }
+        (None, _) => {}
     }
 
-    // After do_exec is called, the process is replaced with the container
-    // payload through execvp, so it should never reach here.
-    unreachable!();
+    Ok(())
+}
+
+// Changes the owner of the stdio fds to the container user, so that the
+// process can still use them after the user has been switched. Only the uid
+// is changed, as there is no reason to change the gid. Stdio pointing to
+// /dev/null is left alone, as it is accessible to everyone anyway.
+fn fix_stdio_permissions(uid: Uid) -> Result<()> {
+    let null = stat::stat("/dev/null").context("failed to stat /dev/null")?;
+    for fd in 0..=2 {
+        let fd_stat = stat::fstat(fd).with_context(|| format!("failed to stat fd {}", fd))?;
+        if fd_stat.st_rdev == null.st_rdev || fd_stat.st_uid == uid.as_raw() {
+            continue;
+        }
+
+        match unistd::fchown(fd, Some(uid), None) {
+            // Rootless containers can not chown stdio owned by other users,
+            // and some kinds of fds cannot be chowned at all.
+            Err(nix::Error::EINVAL) | Err(nix::Error::EPERM) => {
+                log::debug!("could not change owner of fd {}", fd);
+            }
+            Err(e) => bail!("failed to change owner of fd {}: {}", fd, e),
+            Ok(_) => {}
+        }
+    }
+
+    Ok(())
 }
 
 // Before 3.19 it was possible for an unprivileged user to enter an user namespace,
@@ -410,10 +640,10 @@ pub fn container_init(
 // Unprivileged user starting a rootless container: The main process is running as an
 // unprivileged user and therefore cannot write the mapping until "deny" has been written
 // to /proc/{pid}/setgroups. Once written /proc/{pid}/setgroups cannot be reset and the
-// setgroups system call will be disabled for all processes in this user namespace. This
-// also means that we should detect if the user is unprivileged and additional gids have
-// been specified and bail out early as this can never work. This is not handled here,
-// but during the validation for rootless containers.
+// setgroups system call will be disabled for all processes in this user namespace, so it
+// is skipped. This also means that we should detect if the user is unprivileged and
+// additional gids have been specified and bail out early as this can never work. This is
+// not handled here, but during the validation for rootless containers.
 //
 // Privileged user starting a rootless container: It is not necessary to write "deny" to
 // /proc/setgroups in order to create the gid mapping and therefore we don't. This means
@@ -422,42 +652,45 @@ pub fn container_init(
 // We already have checked during validation if the specified supplemental groups fall into
 // the range that are specified in the gid mapping and bail out early if they do not.
 //
-// Privileged user starting a normal container: Just add the supplementary groups.
+// Privileged user starting a normal container: Just set the supplementary groups. Without
+// additional gids the groups inherited from youki are dropped.
 //
 fn set_supplementary_gids(user: &User, rootless: &Option<Rootless>) -> Result<()> {
-    if let Some(additional_gids) = user.additional_gids() {
-        if additional_gids.is_empty() {
-            return Ok(());
-        }
+    let gids: Vec<Gid> = user
+        .additional_gids()
+        .iter()
+        .flatten()
+        .map(|gid| Gid::from_raw(*gid))
+        .collect();
 
-        let setgroups =
-            fs::read_to_string("/proc/self/setgroups").context("failed to read setgroups")?;
-        if setgroups.trim() == "deny" {
+    if setgroups_denied()? {
+        if !gids.is_empty() {
             bail!("cannot set supplementary gids, setgroup is disabled");
         }
+        log::debug!("setgroups is disabled in the user namespace, keeping the groups");
+        return Ok(());
+    }
 
-        let gids: Vec<Gid> = additional_gids
-            .iter()
-            .map(|gid| Gid::from_raw(*gid))
-            .collect();
-
-        match rootless {
-            Some(r) if r.privileged => {
-                nix::unistd::setgroups(&gids).context("failed to set supplementary gids")?;
-            }
-            None => {
-                nix::unistd::setgroups(&gids).context("failed to set supplementary gids")?;
-            }
-            // this should have been detected during validation
-            _ => unreachable!(
-                "unprivileged users cannot set supplementary gids in rootless container"
-            ),
+    match rootless {
+        // this should have been detected during validation
+        Some(r) if !r.privileged && !gids.is_empty() => {
+            unreachable!("unprivileged users cannot set supplementary gids in rootless container")
         }
+        _ => nix::unistd::setgroups(&gids).context("failed to set supplementary gids")?,
     }
 
     Ok(())
 }
 
+fn setgroups_denied() -> Result<bool> {
+    match fs::read_to_string("/proc/self/setgroups") {
+        Ok(setgroups) => Ok(setgroups.trim() == "deny"),
+        // kernels before 3.19 lack the file and always allow setgroups
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).context("failed to read setgroups"),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -466,6 +699,44 @@ mod tests {
     use serial_test::serial;
     use std::{fs, os::unix::prelude::AsRawFd};
 
+    use crate::syscall::test::TestHelperSyscall;
+
+    #[test]
+    fn test_enter_rootfs() -> Result<()> {
+        let rootfs = Path::new("/rootfs");
+        let syscall = TestHelperSyscall::default();
+        enter_rootfs(&syscall, rootfs, false)?;
+        assert_eq!(syscall.get_pivot_rootfs_args(), vec![PathBuf::from(rootfs)]);
+        assert!(syscall.get_move_rootfs_args().is_empty());
+
+        let syscall = TestHelperSyscall::default();
+        enter_rootfs(&syscall, rootfs, true)?;
+        assert!(syscall.get_pivot_rootfs_args().is_empty());
+        assert_eq!(syscall.get_move_rootfs_args(), vec![PathBuf::from(rootfs)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_rootfs_fallback() -> Result<()> {
+        let rootfs = Path::new("/rootfs");
+        let syscall = TestHelperSyscall::default();
+        syscall.set_pivot_rootfs_error(|| PivotRootError(Errno::EINVAL).into());
+        enter_rootfs(&syscall, rootfs, false)?;
+        assert_eq!(syscall.get_move_rootfs_args(), vec![PathBuf::from(rootfs)]);
+
+        // only EINVAL of pivot_root itself means that the root cannot be pivoted
+        let syscall = TestHelperSyscall::default();
+        syscall.set_pivot_rootfs_error(|| PivotRootError(Errno::EPERM).into());
+        assert!(enter_rootfs(&syscall, rootfs, false).is_err());
+        assert!(syscall.get_move_rootfs_args().is_empty());
+
+        let syscall = TestHelperSyscall::default();
+        syscall.set_pivot_rootfs_error(|| Errno::EINVAL.into());
+        assert!(enter_rootfs(&syscall, rootfs, false).is_err());
+        assert!(syscall.get_move_rootfs_args().is_empty());
+        Ok(())
+    }
+
     // Note: We have to run these tests here as serial. The main issue is that
     // these tests has a dependency on the system state. The
     // cleanup_file_descriptors test is especially evil when running with other
@@ -500,14 +771,21 @@ mod tests {
     #[serial]
     fn test_cleanup_file_descriptors() -> Result<()> {
         // Open a fd without the CLOEXEC flag. Rust automatically adds the flag,
-        // so we use fcntl::open here for more control.
+        // so we use fcntl::open here for more control. The fd is preserved,
+        // its duplicate above it is not.
         let fd = fcntl::open("/dev/null", fcntl::OFlag::O_RDWR, sys::stat::Mode::empty())?;
-        cleanup_file_descriptors(fd - 1).with_context(|| "Failed to clean up the fds")?;
+        let dup_fd = fcntl::fcntl(fd, fcntl::FcntlArg::F_DUPFD(fd + 1))?;
+        cleanup_file_descriptors(fd - 2).with_context(|| "Failed to clean up the fds")?;
         let fd_flag = fcntl::fcntl(fd, fcntl::F_GETFD)?;
         if (fd_flag & fcntl::FdFlag::FD_CLOEXEC.bits()) != 0 {
             bail!("CLOEXEC flag is not set correctly");
         }
+        let dup_fd_flag = fcntl::fcntl(dup_fd, fcntl::F_GETFD)?;
+        if (dup_fd_flag & fcntl::FdFlag::FD_CLOEXEC.bits()) == 0 {
+            bail!("CLOEXEC flag is not set on fd {}", dup_fd);
+        }
 
+        unistd::close(dup_fd)?;
         unistd::close(fd)?;
         Ok(())
     }
@@ -1,5 +1,6 @@
-use crate::{namespaces::Namespaces, process::channel, process::fork};
+use crate::{namespaces::Namespaces, process::channel, process::fork, telemetry::Timings};
 use anyhow::{Context, Result};
+use nix::errno::Errno;
 use nix::unistd::{Gid, Uid};
 use oci_spec::runtime::LinuxNamespaceType;
 
@@ -23,15 +24,19 @@ pub fn container_intermediate(
     if let Some(user_namespace) = namespaces.get(LinuxNamespaceType::User) {
         namespaces
             .unshare_or_setns(user_namespace)
-            .with_context(|| format!("Failed to enter pid namespace: {:?}", user_namespace))?;
+            .with_context(|| format!("Failed to enter user namespace: {:?}", user_namespace))?;
         if user_namespace.path().is_none() {
             log::debug!("creating new user namespace");
             // child needs to be dumpable, otherwise the non root parent is not
             // allowed to write the uid/gid maps
-            prctl::set_dumpable(true).unwrap();
+            prctl::set_dumpable(true)
+                .map_err(Errno::from_i32)
+                .context("failed to make the intermediate process dumpable")?;
             sender_to_main.identifier_mapping_request()?;
             receiver_from_main.wait_for_mapping_ack()?;
-            prctl::set_dumpable(false).unwrap();
+            prctl::set_dumpable(false)
+                .map_err(Errno::from_i32)
+                .context("failed to make the intermediate process non-dumpable")?;
         }
 
         // After UID and GID mapping is configured correctly in the Youki main
@@ -71,7 +76,13 @@ pub fn container_intermediate(
         receiver_from_init
             .close()
             .context("Failed to close receiver in init process")?;
-        container_init(args, sender_to_intermediate)
+        let result = container_init(args, sender_to_intermediate);
+        if let Err(err) = &result {
+            // the intermediate process may be gone already if the init
+            // process failed after it became ready
+            let _ = sender_to_intermediate.send_error(err);
+        }
+        result
     })?;
     // Close unused fds in the parent process.
     sender_to_intermediate
@@ -79,13 +90,15 @@ pub fn container_intermediate(
         .context("Failed to close sender in the intermediate process")?;
     // There is no point using the pid returned here, since the child will be
     // inside the pid namespace already.
-    receiver_from_init
+    let init_timings = receiver_from_init
         .wait_for_init_ready()
         .context("Failed to wait for the child")?;
     // After the child (the container init process) becomes ready, we can signal
     // the parent (the main process) that we are ready.
+    let mut timings = Timings::collect();
+    timings.append(&init_timings);
     sender_to_main
-        .intermediate_ready(pid)
+        .intermediate_ready(pid, &timings)
         .context("Failed to send child ready from intermediate process")?;
 
     Ok(())
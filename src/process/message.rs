@@ -5,6 +5,8 @@ pub enum Message {
     InitReady = 0x01,
     WriteMapping = 0x02,
     MappingWritten = 0x03,
+    // followed by the length and the text of the error
+    Error = 0x04,
 }
 
 impl From<u8> for Message {
@@ -14,6 +16,7 @@ impl From<u8> for Message {
             0x01 => Message::InitReady,
             0x02 => Message::WriteMapping,
             0x03 => Message::MappingWritten,
+            0x04 => Message::Error,
             _ => panic!("unknown message: {:?}.", from),
         }
     }
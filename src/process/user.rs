@@ -0,0 +1,264 @@
+//! Looks up users and groups in the passwd and group files of the container.
+//! Engines pass the USER of an image as user name of the process, which is
+//! resolved to ids the same way as the tools inside of the image would.
+use anyhow::{Context, Result};
+use oci_spec::runtime::{User, UserBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub(crate) const PASSWD_PATH: &str = "/etc/passwd";
+pub(crate) const GROUP_PATH: &str = "/etc/group";
+
+// A line of the passwd file, name:password:uid:gid:gecos:home:shell
+struct PasswdEntry {
+    name: String,
+    uid: u32,
+    gid: u32,
+    home: String,
+}
+
+// A line of the group file, name:password:gid:member,member
+struct GroupEntry {
+    name: String,
+    gid: u32,
+    members: Vec<String>,
+}
+
+// Reads the lines of a colon separated file, a file which does not exist has
+// no entries. Lines with too few fields or invalid ids are skipped.
+fn read_entries<T, F>(path: &Path, parse: F) -> Result<Vec<T>>
+where
+    F: Fn(&[&str]) -> Option<T>,
+{
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| parse(&line.split(':').collect::<Vec<_>>()))
+        .collect())
+}
+
+fn passwd_entries(passwd: &Path) -> Result<Vec<PasswdEntry>> {
+    read_entries(passwd, |fields| {
+        if fields.len() < 6 {
+            return None;
+        }
+
+        Some(PasswdEntry {
+            name: fields[0].to_owned(),
+            uid: fields[2].parse().ok()?,
+            gid: fields[3].parse().ok()?,
+            home: fields[5].to_owned(),
+        })
+    })
+}
+
+fn group_entries(group: &Path) -> Result<Vec<GroupEntry>> {
+    read_entries(group, |fields| {
+        if fields.len() < 4 {
+            return None;
+        }
+
+        Some(GroupEntry {
+            name: fields[0].to_owned(),
+            gid: fields[2].parse().ok()?,
+            members: fields[3]
+                .split(',')
+                .filter(|m| !m.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        })
+    })
+}
+
+/// Looks up the home directory of the user in a passwd file. Returns None if
+/// the file does not exist or has no entry for the user.
+pub(crate) fn home_dir(passwd: &Path, uid: u32) -> Result<Option<PathBuf>> {
+    Ok(passwd_entries(passwd)?
+        .into_iter()
+        .find(|entry| entry.uid == uid)
+        .map(|entry| entry.home)
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from))
+}
+
+/// Looks up the gid of a group by name in a group file. Returns None if the
+/// file does not exist or has no entry for the group.
+pub(crate) fn group_gid(group: &Path, name: &str) -> Result<Option<u32>> {
+    Ok(group_entries(group)?
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .map(|entry| entry.gid))
+}
+
+/// Resolves the user name of the process, which has the form user[:group]
+/// like the USER of an image. Both parts can be a name or a numeric id. The
+/// group defaults to the primary group of the user. If member_groups is set,
+/// the groups which list the user as member are added as supplementary
+/// groups. Without a user name, the user is returned as is.
+pub(crate) fn resolve_user(
+    user: &User,
+    passwd: &Path,
+    group: &Path,
+    member_groups: bool,
+) -> Result<User> {
+    let username = match user.username() {
+        Some(username) if !username.is_empty() => username,
+        _ => return Ok(user.clone()),
+    };
+
+    let (user_part, group_part) = match username.split_once(':') {
+        Some((user_part, group_part)) => (user_part, Some(group_part)),
+        None => (username.as_str(), None),
+    };
+
+    let passwd_entries = passwd_entries(passwd)?;
+    let passwd_entry = match user_part.parse::<u32>() {
+        Ok(uid) => passwd_entries.iter().find(|entry| entry.uid == uid),
+        Err(_) => Some(
+            passwd_entries
+                .iter()
+                .find(|entry| entry.name == user_part)
+                .with_context(|| format!("no user {} in {}", user_part, passwd.display()))?,
+        ),
+    };
+    let uid = match passwd_entry {
+        Some(entry) => entry.uid,
+        None => user_part.parse()?,
+    };
+
+    let group_entries = group_entries(group)?;
+    let gid = match group_part {
+        Some(group_part) => match group_part.parse::<u32>() {
+            Ok(gid) => gid,
+            Err(_) => {
+                group_entries
+                    .iter()
+                    .find(|entry| entry.name == group_part)
+                    .with_context(|| format!("no group {} in {}", group_part, group.display()))?
+                    .gid
+            }
+        },
+        None => passwd_entry.map_or(user.gid(), |entry| entry.gid),
+    };
+
+    let mut additional_gids = user.additional_gids().clone().unwrap_or_default();
+    if let Some(entry) = passwd_entry.filter(|_| member_groups) {
+        for group_entry in &group_entries {
+            if group_entry.gid != gid
+                && group_entry.members.contains(&entry.name)
+                && !additional_gids.contains(&group_entry.gid)
+            {
+                additional_gids.push(group_entry.gid);
+            }
+        }
+    }
+
+    let mut builder = UserBuilder::default()
+        .uid(uid)
+        .gid(gid)
+        .additional_gids(additional_gids)
+        .username(username.clone());
+    if let Some(umask) = user.umask() {
+        builder = builder.umask(umask);
+    }
+
+    log::debug!("resolved user {} to {}:{}", username, uid, gid);
+    builder
+        .build()
+        .with_context(|| format!("invalid user {}", username))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    const PASSWD: &str = "root:x:0:0:root:/root:/bin/sh\n\
+                          nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin\n\
+                          app:x:1000:1000:::/bin/sh\n";
+    const GROUP: &str = "root:x:0:\n\
+                         video:x:44:app\n\
+                         audio:x:29:root,app\n\
+                         app:x:1000:\n";
+
+    fn user(username: &str) -> Result<User> {
+        Ok(UserBuilder::default()
+            .uid(5u32)
+            .gid(5u32)
+            .username(username.to_owned())
+            .build()?)
+    }
+
+    #[test]
+    fn test_home_dir() -> Result<()> {
+        let tmp = create_temp_dir("test_home_dir")?;
+        let passwd = tmp.join("passwd");
+        assert_eq!(home_dir(&passwd, 0)?, None);
+
+        fs::write(&passwd, PASSWD)?;
+        assert_eq!(home_dir(&passwd, 0)?, Some(PathBuf::from("/root")));
+        assert_eq!(
+            home_dir(&passwd, 65534)?,
+            Some(PathBuf::from("/nonexistent"))
+        );
+        assert_eq!(home_dir(&passwd, 1000)?, None);
+        assert_eq!(home_dir(&passwd, 1001)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_gid() -> Result<()> {
+        let tmp = create_temp_dir("test_group_gid")?;
+        let group = tmp.join("group");
+        assert_eq!(group_gid(&group, "audio")?, None);
+
+        fs::write(&group, GROUP)?;
+        assert_eq!(group_gid(&group, "audio")?, Some(29));
+        assert_eq!(group_gid(&group, "tty")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_user() -> Result<()> {
+        let tmp = create_temp_dir("test_resolve_user")?;
+        let (passwd, group) = (tmp.join("passwd"), tmp.join("group"));
+        fs::write(&passwd, PASSWD)?;
+        fs::write(&group, GROUP)?;
+
+        let without_name = UserBuilder::default().uid(5u32).gid(5u32).build()?;
+        assert_eq!(
+            resolve_user(&without_name, &passwd, &group, true)?,
+            without_name
+        );
+
+        let resolved = resolve_user(&user("app")?, &passwd, &group, true)?;
+        assert_eq!((resolved.uid(), resolved.gid()), (1000, 1000));
+        assert_eq!(resolved.additional_gids(), &Some(vec![44, 29]));
+
+        let resolved = resolve_user(&user("app:audio")?, &passwd, &group, true)?;
+        assert_eq!((resolved.uid(), resolved.gid()), (1000, 29));
+        assert_eq!(resolved.additional_gids(), &Some(vec![44]));
+
+        let resolved = resolve_user(&user("1234:44")?, &passwd, &group, true)?;
+        assert_eq!((resolved.uid(), resolved.gid()), (1234, 44));
+        assert_eq!(resolved.additional_gids(), &Some(vec![]));
+
+        let resolved = resolve_user(&user("0")?, &passwd, &group, true)?;
+        assert_eq!((resolved.uid(), resolved.gid()), (0, 0));
+        assert_eq!(resolved.additional_gids(), &Some(vec![29]));
+
+        let resolved = resolve_user(&user("app")?, &passwd, &group, false)?;
+        assert_eq!((resolved.uid(), resolved.gid()), (1000, 1000));
+        assert_eq!(resolved.additional_gids(), &Some(vec![]));
+
+        assert!(resolve_user(&user("unknown")?, &passwd, &group, true).is_err());
+        assert!(resolve_user(&user("app:unknown")?, &passwd, &group, true).is_err());
+        Ok(())
+    }
+}
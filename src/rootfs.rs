@@ -1,25 +1,32 @@
 //! During kernel initialization, a minimal replica of the ramfs filesystem is loaded, called rootfs.
 //! Most systems mount another filesystem over it
 
-use crate::utils::PathBufExt;
+use crate::features;
+use crate::idmap::{self, IdmappedMounts};
+use crate::mount_api;
+use crate::utils::secure_fs::Dir;
 use anyhow::{anyhow, bail, Context, Result};
 use nix::errno::Errno;
-use nix::fcntl::{open, OFlag};
+use nix::fcntl::OFlag;
 use nix::mount::mount as nix_mount;
 use nix::mount::MsFlags;
-use nix::sys::stat::{mknod, umask};
+use nix::sys::stat::umask;
 use nix::sys::stat::{Mode, SFlag};
-use nix::unistd::{chown, close};
+use nix::unistd::close;
 use nix::unistd::{Gid, Uid};
 use nix::NixPath;
 use oci_spec::runtime::{Linux, LinuxDevice, LinuxDeviceBuilder, LinuxDeviceType, Mount, Spec};
 use procfs::process::{MountInfo, MountOptFields, Process};
-use std::fs::OpenOptions;
-use std::fs::{canonicalize, create_dir_all, remove_file};
-use std::os::unix::fs::symlink;
-use std::path::{Path, PathBuf};
+use std::fs::{canonicalize, File};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Component, Path, PathBuf};
 
-pub fn prepare_rootfs(spec: &Spec, rootfs: &Path, bind_devices: bool) -> Result<()> {
+pub fn prepare_rootfs(
+    spec: &Spec,
+    rootfs: &Path,
+    bind_devices: bool,
+    idmapped_mounts: &IdmappedMounts,
+) -> Result<()> {
     log::debug!("Prepare rootfs: {:?}", rootfs);
     let mut flags = MsFlags::MS_REC;
     let linux = spec.linux().as_ref().context("no linux in spec")?;
@@ -45,59 +52,67 @@ pub fn prepare_rootfs(spec: &Spec, rootfs: &Path, bind_devices: bool) -> Result<
         None::<&str>,
     )?;
 
+    // The mount points and the files below are created relative to the
+    // opened rootfs without following symlinks, so that the rootfs cannot
+    // redirect them to the host
+    let root = Dir::open(rootfs)?;
     if let Some(mounts) = spec.mounts() {
         for mount in mounts {
             log::debug!("Mount... {:?}", mount);
-            let (flags, data) = parse_mount(mount);
+            let (flags, mut data) = parse_mount(mount);
+            // relative destinations are relative to the root of the container
+            let destination = Path::new("/").join(mount.destination());
+            if destination == Path::new("/dev/shm") && *mount.typ() == Some("tmpfs".to_string()) {
+                data = with_default_shm_size(&data);
+            }
             let mount_label = linux.mount_label().as_ref();
             if *mount.typ() == Some("cgroup".to_string()) {
                 // skip
                 log::warn!("A feature of cgroup is unimplemented.");
-            } else if *mount.destination() == PathBuf::from("/dev") {
+            } else if destination == Path::new("/dev") {
                 mount_to_container(
                     mount,
-                    rootfs,
+                    &root,
                     flags & !MsFlags::MS_RDONLY,
                     &data,
                     mount_label,
+                    idmapped_mounts,
                 )
                 .with_context(|| format!("Failed to mount /dev: {:?}", mount))?;
             } else {
-                mount_to_container(mount, rootfs, flags, &data, mount_label)
+                mount_to_container(mount, &root, flags, &data, mount_label, idmapped_mounts)
                     .with_context(|| format!("Failed to mount: {:?}", mount))?;
             }
         }
     }
 
-    setup_default_symlinks(rootfs).context("Failed to setup default symlinks")?;
+    setup_default_symlinks(&root).context("Failed to setup default symlinks")?;
     if let Some(added_devices) = linux.devices() {
         create_devices(
-            rootfs,
+            &root,
             default_devices().iter().chain(added_devices),
             bind_devices,
         )
     } else {
-        create_devices(rootfs, &default_devices(), bind_devices)
+        create_devices(&root, &default_devices(), bind_devices)
     }?;
 
-    setup_ptmx(rootfs)?;
+    setup_ptmx(&root)?;
     Ok(())
 }
 
-fn setup_ptmx(rootfs: &Path) -> Result<()> {
-    if let Err(e) = remove_file(rootfs.join("dev/ptmx")) {
-        if e.kind() != ::std::io::ErrorKind::NotFound {
-            bail!("could not delete /dev/ptmx")
-        }
-    }
-
-    symlink("pts/ptmx", rootfs.join("dev/ptmx")).context("failed to symlink ptmx")?;
+fn setup_ptmx(root: &Dir) -> Result<()> {
+    root.remove_file("dev/ptmx")
+        .context("could not delete /dev/ptmx")?;
+    root.symlink("pts/ptmx", "dev/ptmx")
+        .context("failed to symlink ptmx")?;
     Ok(())
 }
 
-fn setup_default_symlinks(rootfs: &Path) -> Result<()> {
+fn setup_default_symlinks(root: &Dir) -> Result<()> {
     if Path::new("/proc/kcore").exists() {
-        symlink("/proc/kcore", rootfs.join("dev/kcore")).context("Failed to symlink kcore")?;
+        root.symlink("/proc/kcore", "dev/kcore")
+            .context("Failed to symlink kcore")?;
     }
 
     let defaults = [
@@ -107,7 +122,7 @@ fn setup_default_symlinks(rootfs: &Path) -> Result<()> {
         ("/proc/self/fd/2", "dev/stderr"),
     ];
     for (src, dst) in defaults {
-        symlink(src, rootfs.join(dst)).context("Fail to symlink defaults")?;
+        root.symlink(src, dst).context("Fail to symlink defaults")?;
     }
 
     Ok(())
@@ -166,7 +181,7 @@ pub fn default_devices() -> Vec<LinuxDevice> {
     ]
 }
 
-fn create_devices<'a, I>(rootfs: &Path, devices: I, bind: bool) -> Result<()>
+fn create_devices<'a, I>(root: &Dir, devices: I, bind: bool) -> Result<()>
 where
     I: IntoIterator<Item = &'a LinuxDevice>,
 {
@@ -179,7 +194,7 @@ where
                     panic!("{} is not a valid device path", dev.path().display());
                 }
 
-                bind_dev(rootfs, dev)
+                bind_dev(root, dev)
             })
             .collect::<Result<Vec<_>>>()?;
     } else {
@@ -190,7 +205,7 @@ where
                     panic!("{} is not a valid device path", dev.path().display());
                 }
 
-                mknod_dev(rootfs, dev)
+                mknod_dev(root, dev)
             })
             .collect::<Result<Vec<_>>>()?;
     }
@@ -199,18 +214,15 @@ where
     Ok(())
 }
 
-fn bind_dev(rootfs: &Path, dev: &LinuxDevice) -> Result<()> {
-    let full_container_path = rootfs.join(dev.path().as_in_container()?);
-
-    let fd = open(
-        &full_container_path,
+fn bind_dev(root: &Dir, dev: &LinuxDevice) -> Result<()> {
+    let file = root.open_file(
+        dev.path(),
         OFlag::O_RDWR | OFlag::O_CREAT,
         Mode::from_bits_truncate(0o644),
     )?;
-    close(fd)?;
     nix_mount(
         Some(dev.path()),
-        &full_container_path,
+        &proc_fd_path(file.as_raw_fd()),
         Some("bind"),
         MsFlags::MS_BIND,
         None::<&str>,
@@ -219,6 +231,12 @@ fn bind_dev(rootfs: &Path, dev: &LinuxDevice) -> Result<()> {
     Ok(())
 }
 
+// The magic link of procfs refers to exactly the opened file, so mounting on
+// it cannot be redirected by a symlink swapped into the path in the meantime
+fn proc_fd_path(fd: RawFd) -> PathBuf {
+    PathBuf::from(format!("/proc/self/fd/{}", fd))
+}
+
 fn to_sflag(dev_type: LinuxDeviceType) -> SFlag {
     match dev_type {
         LinuxDeviceType::A => SFlag::S_IFBLK | SFlag::S_IFCHR | SFlag::S_IFIFO,
@@ -228,7 +246,7 @@ fn to_sflag(dev_type: LinuxDeviceType) -> SFlag {
     }
 }
 
-fn mknod_dev(rootfs: &Path, dev: &LinuxDevice) -> Result<()> {
+fn mknod_dev(root: &Dir, dev: &LinuxDevice) -> Result<()> {
     fn makedev(major: i64, minor: i64) -> u64 {
         ((minor & 0xff)
             | ((major & 0xfff) << 8)
@@ -236,15 +254,14 @@ fn mknod_dev(rootfs: &Path, dev: &LinuxDevice) -> Result<()> {
             | ((major & !0xfff) << 32)) as u64
     }
 
-    let full_container_path = rootfs.join(dev.path().as_in_container()?);
-    mknod(
-        &full_container_path,
+    root.mknod(
+        dev.path(),
         to_sflag(dev.typ()),
         Mode::from_bits_truncate(dev.file_mode().unwrap_or(0)),
         makedev(dev.major(), dev.minor()),
     )?;
-    chown(
-        &full_container_path,
+    root.chown(
+        dev.path(),
         dev.uid().map(Uid::from_raw),
         dev.gid().map(Gid::from_raw),
     )?;
@@ -254,62 +271,51 @@ fn mknod_dev(rootfs: &Path, dev: &LinuxDevice) -> Result<()> {
 
 fn mount_to_container(
     m: &Mount,
-    rootfs: &Path,
+    root: &Dir,
     flags: MsFlags,
     data: &str,
     label: Option<&String>,
+    idmapped_mounts: &IdmappedMounts,
 ) -> Result<()> {
     let typ = m.typ().as_deref();
-    let d = if let Some(l) = label {
-        if typ != Some("proc") && typ != Some("sysfs") {
-            if data.is_empty() {
-                format!("context=\"{}\"", l)
-            } else {
-                format!("{},context=\"{}\"", data, l)
-            }
-        } else {
-            data.to_string()
-        }
-    } else {
-        data.to_string()
+    let d = match label {
+        Some(label) if features::selinux_enabled() => label_mount_data(typ, data, label),
+        _ => data.to_string(),
     };
-    let dest_for_host = format!(
-        "{}{}",
-        rootfs.to_string_lossy().into_owned(),
-        m.destination().display()
-    );
-    let dest = Path::new(&dest_for_host);
     let source = m.source().as_ref().context("no source in mount spec")?;
     let src = if typ == Some("bind") {
-        let src = canonicalize(source)?;
-        let dir = if src.is_file() {
-            Path::new(&dest).parent().unwrap()
-        } else {
-            Path::new(&dest)
-        };
-        create_dir_all(&dir)
-            .with_context(|| format!("Failed to create dir for bind mount: {:?}", dir))?;
-        if src.is_file() {
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .open(&dest)
-                .unwrap();
-        }
-
-        src
+        canonicalize(source)?
     } else {
-        create_dir_all(&dest).with_context(|| format!("Failed to create device: {:?}", dest))?;
         PathBuf::from(source)
     };
 
-    if let Err(errno) = nix_mount(Some(&*src), dest, typ, flags, Some(&*d)) {
+    // The mount point is held open while it is mounted on, the mount goes to
+    // the opened file or directory even if the path is changed in between
+    let mount_point = open_mount_point(root, m.destination(), typ == Some("bind") && src.is_file())
+        .with_context(|| format!("failed to create mount point {:?}", m.destination()))?;
+    let dest = &proc_fd_path(mount_point.as_raw_fd());
+
+    if let Some(fd) = idmapped_mounts.get(m.destination()) {
+        // The idmapped mount has already been prepared as a detached mount
+        // by the main process, it only needs to be attached.
+        mount_api::move_mount(fd, dest)
+            .with_context(|| format!("failed to attach idmapped mount {:?}", m.destination()))?;
+    } else if let Err(errno) = mount_fs(&src, dest, typ, flags, &d) {
         if !matches!(errno, Errno::EINVAL) {
             bail!("mount of {:?} failed", m.destination());
         }
+        log::warn!(
+            "mount of {:?} failed with the SELinux mount label, retrying without",
+            m.destination()
+        );
         nix_mount(Some(&*src), dest, typ, flags, Some(data))?;
     }
 
+    // The mount point is opened again, so that the remounts below change the
+    // mount which has just been made and not the one below it
+    let mount_point = root.open_file(m.destination(), OFlag::O_PATH, Mode::empty())?;
+    let dest = &proc_fd_path(mount_point.as_raw_fd());
+
     if flags.contains(MsFlags::MS_BIND)
         && flags.intersects(
             !(MsFlags::MS_REC
@@ -328,9 +334,241 @@ fn mount_to_container(
             None::<&str>,
         )?;
     }
+
+    if m.options()
+        .as_ref()
+        .map_or(false, |o| o.iter().any(|o| o == RRO_OPTION))
+    {
+        make_recursively_read_only(dest)
+            .with_context(|| format!("failed to make {:?} read-only", m.destination()))?;
+    }
     Ok(())
 }
 
+// Opens the destination of a mount below the rootfs as O_PATH. It is created
+// together with its parents as directory, or as file if a file is bind mounted.
+fn open_mount_point(root: &Dir, dest: &Path, is_file: bool) -> Result<File> {
+    let mode = Mode::from_bits_truncate(0o755);
+    if is_file {
+        if let Some(parent) = dest.parent().filter(|parent| {
+            parent
+                .components()
+                .any(|c| matches!(c, Component::Normal(_)))
+        }) {
+            root.create_dir_all(parent, mode)?;
+        }
+        root.open_file(
+            dest,
+            OFlag::O_CREAT | OFlag::O_WRONLY,
+            Mode::from_bits_truncate(0o644),
+        )?;
+    } else {
+        root.create_dir_all(dest, mode)?;
+    }
+
+    root.open_file(dest, OFlag::O_PATH, Mode::empty())
+}
+
+// mount option which makes a bind mount recursively read-only
+const RRO_OPTION: &str = "rro";
+
+/// Makes a bind mount and all mounts below it read-only, which MS_RDONLY
+/// cannot do for the submounts of a recursive bind mount
+fn make_recursively_read_only(dest: &Path) -> Result<()> {
+    let fd = match mount_api::open_tree(dest, mount_api::OPEN_TREE_CLOEXEC) {
+        Ok(fd) => fd,
+        Err(Errno::ENOSYS) => bail!("recursively read-only mounts require at least Linux 5.12"),
+        Err(e) => bail!("failed to open mount {}: {}", dest.display(), e),
+    };
+
+    let attr = mount_api::MountAttr {
+        attr_set: mount_api::MOUNT_ATTR_RDONLY,
+        ..Default::default()
+    };
+    let res = mount_api::mount_setattr(fd, mount_api::AT_RECURSIVE, &attr);
+    let _ = close(fd);
+    match res {
+        Ok(()) => Ok(()),
+        Err(Errno::ENOSYS) => bail!("recursively read-only mounts require at least Linux 5.12"),
+        Err(e) => bail!("failed to set mount attributes: {}", e),
+    }
+}
+
+/// Mounts a filesystem which is not a bind mount with the new mount API, so
+/// it is fully configured as detached mount before it is attached to the
+/// rootfs. Falls back to mount(2) on kernels before Linux 5.2, if the new
+/// mount API is blocked, e.g. by the seccomp profile of an outer container,
+/// and for flags which have no equivalent in the new mount API.
+fn mount_fs(
+    src: &Path,
+    dest: &Path,
+    typ: Option<&str>,
+    flags: MsFlags,
+    data: &str,
+) -> Result<(), Errno> {
+    if let (Some(typ), Some(attr_flags)) = (
+        typ.filter(|typ| *typ != "bind" && !flags.contains(MsFlags::MS_BIND)),
+        mount_attr_flags(flags),
+    ) {
+        match open_fs(typ, src, flags, data, attr_flags) {
+            Ok(fd) => {
+                let res = mount_api::move_mount(fd, dest);
+                let _ = close(fd);
+                return res;
+            }
+            Err(e @ Errno::ENOSYS) | Err(e @ Errno::EPERM) => {
+                log::debug!("new mount API is not usable ({}), using mount(2)", e)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    nix_mount(Some(src), dest, typ, flags, Some(data))
+}
+
+/// Creates a detached mount of a new filesystem, the mount options are set
+/// as parameters of the filesystem context
+fn open_fs(
+    typ: &str,
+    src: &Path,
+    flags: MsFlags,
+    data: &str,
+    attr_flags: u64,
+) -> Result<RawFd, Errno> {
+    let fs_fd = mount_api::fsopen(typ, mount_api::FSOPEN_CLOEXEC)?;
+    let res = (|| -> Result<RawFd, Errno> {
+        let src = src.to_str().ok_or(Errno::EINVAL)?;
+        mount_api::fsconfig(
+            fs_fd,
+            mount_api::FSCONFIG_SET_STRING,
+            Some("source"),
+            Some(src),
+        )?;
+        for (key, value) in split_mount_data(data) {
+            match value {
+                Some(value) => mount_api::fsconfig(
+                    fs_fd,
+                    mount_api::FSCONFIG_SET_STRING,
+                    Some(key),
+                    Some(value),
+                )?,
+                None => mount_api::fsconfig(fs_fd, mount_api::FSCONFIG_SET_FLAG, Some(key), None)?,
+            }
+        }
+        // MOUNT_ATTR_RDONLY only makes the mount read-only, the superblock
+        // is made read-only as mount(2) does with MS_RDONLY
+        for &(flag, key) in &[
+            (MsFlags::MS_RDONLY, "ro"),
+            (MsFlags::MS_SYNCHRONOUS, "sync"),
+            (MsFlags::MS_DIRSYNC, "dirsync"),
+            (MsFlags::MS_MANDLOCK, "mand"),
+        ] {
+            if flags.contains(flag) {
+                mount_api::fsconfig(fs_fd, mount_api::FSCONFIG_SET_FLAG, Some(key), None)?;
+            }
+        }
+
+        mount_api::fsconfig(fs_fd, mount_api::FSCONFIG_CMD_CREATE, None, None)?;
+        mount_api::fsmount(fs_fd, mount_api::FSMOUNT_CLOEXEC, attr_flags)
+    })();
+    let _ = close(fs_fd);
+    res
+}
+
+/// Translates the mount flags into the attributes of fsmount. Returns None
+/// if a flag has no equivalent, e.g. a propagation flag.
+fn mount_attr_flags(flags: MsFlags) -> Option<u64> {
+    // set on the filesystem context instead, relatime is the default
+    let superblock_flags =
+        MsFlags::MS_SYNCHRONOUS | MsFlags::MS_DIRSYNC | MsFlags::MS_MANDLOCK | MsFlags::MS_RELATIME;
+    let mut remaining = flags & !superblock_flags;
+    let mut attr_flags = 0;
+    for &(flag, attr) in &[
+        (MsFlags::MS_RDONLY, mount_api::MOUNT_ATTR_RDONLY),
+        (MsFlags::MS_NOSUID, mount_api::MOUNT_ATTR_NOSUID),
+        (MsFlags::MS_NODEV, mount_api::MOUNT_ATTR_NODEV),
+        (MsFlags::MS_NOEXEC, mount_api::MOUNT_ATTR_NOEXEC),
+        (MsFlags::MS_NOATIME, mount_api::MOUNT_ATTR_NOATIME),
+        (MsFlags::MS_NODIRATIME, mount_api::MOUNT_ATTR_NODIRATIME),
+        (MsFlags::MS_STRICTATIME, mount_api::MOUNT_ATTR_STRICTATIME),
+    ] {
+        if flags.contains(flag) {
+            attr_flags |= attr;
+            remaining.remove(flag);
+        }
+    }
+
+    if remaining.is_empty() {
+        Some(attr_flags)
+    } else {
+        None
+    }
+}
+
+/// Splits the comma separated mount data into keys and optional values.
+/// Commas inside of quotes, e.g. in the categories of an SELinux context,
+/// do not separate options and the quotes are removed from the value.
+fn split_mount_data(data: &str) -> Vec<(&str, Option<&str>)> {
+    let mut options = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in data
+        .char_indices()
+        .chain(std::iter::once((data.len(), ',')))
+    {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                let option = &data[start..i];
+                start = i + 1;
+                if option.is_empty() {
+                    continue;
+                }
+                options.push(match option.split_once('=') {
+                    Some((key, value)) => (key, Some(value.trim_matches('"'))),
+                    None => (option, None),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    options
+}
+
+/// Size of /dev/shm if the spec doesn't specify one, the same default as docker
+const DEFAULT_SHM_SIZE: &str = "64m";
+
+/// Limits the size of the /dev/shm tmpfs, otherwise the kernel default of
+/// half of the memory of the host would be used
+fn with_default_shm_size(data: &str) -> String {
+    if data.split(',').any(|o| o.starts_with("size=")) {
+        return data.to_string();
+    }
+
+    if data.is_empty() {
+        format!("size={}", DEFAULT_SHM_SIZE)
+    } else {
+        format!("{},size={}", data, DEFAULT_SHM_SIZE)
+    }
+}
+
+/// Adds the mount label as SELinux context to the mount data, so the files
+/// created in filesystems like tmpfs get the label of the container. The
+/// context option is not supported for bind mounts, which keep the labels of
+/// their source, and for proc and sysfs, which have their own labeling.
+fn label_mount_data(typ: Option<&str>, data: &str, label: &str) -> String {
+    if matches!(typ, Some("bind" | "proc" | "sysfs")) || data.contains("context=") {
+        return data.to_string();
+    }
+
+    if data.is_empty() {
+        format!("context=\"{}\"", label)
+    } else {
+        format!("{},context=\"{}\"", data, label)
+    }
+}
+
 fn parse_mount(m: &Mount) -> (MsFlags, String) {
     let mut flags = MsFlags::empty();
     let mut data = Vec::new();
@@ -370,6 +608,8 @@ fn parse_mount(m: &Mount) -> (MsFlags, String) {
                 "norelatime" => Some((true, MsFlags::MS_RELATIME)),
                 "strictatime" => Some((true, MsFlags::MS_STRICTATIME)),
                 "nostrictatime" => Some((true, MsFlags::MS_STRICTATIME)),
+                // applied after the mount with mount_setattr
+                RRO_OPTION => Some((false, MsFlags::empty())),
                 _ => None,
             } {
                 if is_clear {
@@ -377,7 +617,7 @@ fn parse_mount(m: &Mount) -> (MsFlags, String) {
                 } else {
                     flags |= flag;
                 }
-            } else {
+            } else if !idmap::is_idmap_option(s) {
                 data.push(s.as_str());
             };
         }
@@ -439,10 +679,114 @@ pub fn adjust_root_mount_propagation(linux: &Linux) -> Result<()> {
 
 #[cfg(test)]
 mod tests {
+    use crate::mount_api;
     use anyhow::{Context, Result};
+    use nix::mount::MsFlags;
     use procfs::process::MountInfo;
     use std::path::{Path, PathBuf};
 
+    #[test]
+    fn test_default_shm_size() {
+        assert_eq!(super::with_default_shm_size(""), "size=64m");
+        assert_eq!(
+            super::with_default_shm_size("mode=1777"),
+            "mode=1777,size=64m"
+        );
+        assert_eq!(
+            super::with_default_shm_size("mode=1777,size=65536k"),
+            "mode=1777,size=65536k"
+        );
+    }
+
+    #[test]
+    fn test_label_mount_data() {
+        let label = "system_u:object_r:container_file_t:s0:c1,c2";
+        assert_eq!(
+            super::label_mount_data(Some("tmpfs"), "", label),
+            format!("context=\"{}\"", label)
+        );
+        assert_eq!(
+            super::label_mount_data(Some("tmpfs"), "mode=755,size=65536k", label),
+            format!("mode=755,size=65536k,context=\"{}\"", label)
+        );
+        assert_eq!(
+            super::label_mount_data(Some("mqueue"), "", label),
+            format!("context=\"{}\"", label)
+        );
+        for typ in &["bind", "proc", "sysfs"] {
+            assert_eq!(
+                super::label_mount_data(Some(*typ), "mode=755", label),
+                "mode=755"
+            );
+        }
+        // an explicit context in the spec takes precedence
+        assert_eq!(
+            super::label_mount_data(Some("tmpfs"), "context=\"foo\"", label),
+            "context=\"foo\""
+        );
+    }
+
+    #[test]
+    fn test_parse_mount_rro() -> Result<()> {
+        let mount = oci_spec::runtime::MountBuilder::default()
+            .destination("/data")
+            .typ("bind")
+            .source("/data")
+            .options(vec!["rbind".to_string(), "rro".to_string()])
+            .build()?;
+        let (flags, data) = super::parse_mount(&mount);
+        assert_eq!(flags, MsFlags::MS_BIND | MsFlags::MS_REC);
+        assert_eq!(data, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_attr_flags() {
+        assert_eq!(super::mount_attr_flags(MsFlags::empty()), Some(0));
+        assert_eq!(
+            super::mount_attr_flags(
+                MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC | MsFlags::MS_NODEV | MsFlags::MS_RDONLY
+            ),
+            Some(
+                mount_api::MOUNT_ATTR_NOSUID
+                    | mount_api::MOUNT_ATTR_NOEXEC
+                    | mount_api::MOUNT_ATTR_NODEV
+                    | mount_api::MOUNT_ATTR_RDONLY
+            )
+        );
+        assert_eq!(
+            super::mount_attr_flags(MsFlags::MS_NOATIME | MsFlags::MS_SYNCHRONOUS),
+            Some(mount_api::MOUNT_ATTR_NOATIME)
+        );
+        assert_eq!(super::mount_attr_flags(MsFlags::MS_SLAVE), None);
+        assert_eq!(super::mount_attr_flags(MsFlags::MS_REMOUNT), None);
+    }
+
+    #[test]
+    fn test_split_mount_data() {
+        assert!(super::split_mount_data("").is_empty());
+        assert_eq!(
+            super::split_mount_data("mode=755,size=65536k,,nr_inodes"),
+            vec![
+                ("mode", Some("755")),
+                ("size", Some("65536k")),
+                ("nr_inodes", None)
+            ]
+        );
+        assert_eq!(
+            super::split_mount_data(
+                "size=64m,context=\"system_u:object_r:container_file_t:s0:c1,c2\""
+            ),
+            vec![
+                ("size", Some("64m")),
+                (
+                    "context",
+                    Some("system_u:object_r:container_file_t:s0:c1,c2")
+                )
+            ]
+        );
+    }
+
     #[test]
     fn test_find_parent_mount() -> Result<()> {
         let mount_infos = vec![
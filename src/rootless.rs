@@ -1,10 +1,9 @@
-use crate::{namespaces::Namespaces, utils};
+use crate::{features, idmap, namespaces::Namespaces, utils::secure_fs};
 use anyhow::{bail, Context, Result};
 use nix::unistd::Pid;
 use oci_spec::runtime::{Linux, LinuxIdMapping, LinuxNamespace, LinuxNamespaceType, Mount, Spec};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::{env, path::PathBuf};
 
 #[derive(Debug, Clone, Default)]
 pub struct Rootless<'a> {
@@ -36,15 +35,14 @@ impl<'a> Rootless<'a> {
 
         if user_namespace.is_some() && user_namespace.unwrap().path().is_none() {
             log::debug!("rootless container should be created");
-            log::warn!(
-                "resource constraints and multi id mapping is unimplemented for rootless containers"
-            );
 
             validate(spec).context("The spec failed to comply to rootless requirement")?;
             let mut rootless = Rootless::from(linux);
-            if let Some((uid_binary, gid_binary)) = lookup_map_binaries(linux)? {
-                rootless.newuidmap = Some(uid_binary);
-                rootless.newgidmap = Some(gid_binary);
+            // a privileged user can write any mapping itself
+            if !rootless.privileged {
+                let (newuidmap, newgidmap) = lookup_map_binaries(linux)?;
+                rootless.newuidmap = newuidmap;
+                rootless.newgidmap = newgidmap;
             }
 
             Ok(Some(rootless))
@@ -58,7 +56,8 @@ impl<'a> Rootless<'a> {
         log::debug!("Write UID mapping for {:?}", target_pid);
         if let Some(uid_mappings) = self.uid_mappings {
             write_id_mapping(
-                &format!("/proc/{}/uid_map", target_pid),
+                target_pid,
+                "uid_map",
                 uid_mappings,
                 self.newuidmap.as_deref(),
             )
@@ -71,7 +70,8 @@ impl<'a> Rootless<'a> {
         log::debug!("Write GID mapping for {:?}", target_pid);
         if let Some(gid_mappings) = self.gid_mappings {
             return write_id_mapping(
-                &format!("/proc/{}/gid_map", target_pid),
+                target_pid,
+                "gid_map",
                 gid_mappings,
                 self.newgidmap.as_deref(),
             );
@@ -130,6 +130,8 @@ fn validate(spec: &Spec) -> Result<()> {
     if gid_mappings.is_empty() {
         bail!("rootless containers require at least one gid mapping")
     }
+    validate_mappings(uid_mappings).context("invalid uidMappings")?;
+    validate_mappings(gid_mappings).context("invalid gidMappings")?;
 
     validate_mounts(
         spec.mounts().as_ref().context("no mounts in spec")?,
@@ -188,58 +190,188 @@ fn validate_mounts(
     Ok(())
 }
 
+// The kernel accepts ranges which neither overlap in the container nor on the
+// host, up to 340 of them since Linux 4.15
+fn validate_mappings(mappings: &[LinuxIdMapping]) -> Result<()> {
+    const MAX_MAPPINGS: usize = 340;
+    if mappings.len() > MAX_MAPPINGS {
+        bail!(
+            "{} mappings are given, at most {} are supported",
+            mappings.len(),
+            MAX_MAPPINGS
+        );
+    }
+
+    let overlaps = |start_a: u32, start_b: u32, size_a: u32, size_b: u32| {
+        (start_a as u64) < start_b as u64 + size_b as u64
+            && (start_b as u64) < start_a as u64 + size_a as u64
+    };
+    for (i, a) in mappings.iter().enumerate() {
+        if a.size() == 0 {
+            bail!("mapping of container id {} has size 0", a.container_id());
+        }
+        for b in &mappings[i + 1..] {
+            if overlaps(a.container_id(), b.container_id(), a.size(), b.size()) {
+                bail!(
+                    "container ids of mappings {}-{} overlap",
+                    a.container_id(),
+                    b.container_id()
+                );
+            }
+            if overlaps(a.host_id(), b.host_id(), a.size(), b.size()) {
+                bail!(
+                    "host ids of mappings {}-{} overlap",
+                    a.host_id(),
+                    b.host_id()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn is_id_mapped(id: u32, mappings: &[LinuxIdMapping]) -> bool {
     mappings
         .iter()
-        .any(|m| id >= m.container_id() && id <= m.container_id() + m.size())
+        .any(|m| id >= m.container_id() && (id as u64) < m.container_id() as u64 + m.size() as u64)
 }
 
-/// Looks up the location of the newuidmap and newgidmap binaries which
-/// are required to write multiple user/group mappings
-pub fn lookup_map_binaries(spec: &Linux) -> Result<Option<(PathBuf, PathBuf)>> {
-    if let Some(uid_mappings) = spec.uid_mappings() {
-        if uid_mappings.len() == 1 && uid_mappings.len() == 1 {
-            return Ok(None);
+/// Looks up the location of the newuidmap and newgidmap binaries in PATH,
+/// which an unprivileged user requires for any mapping but the one of its own
+/// uid or gid. None is returned for a map which can be written directly.
+pub fn lookup_map_binaries(spec: &Linux) -> Result<(Option<PathBuf>, Option<PathBuf>)> {
+    let newuidmap = match spec.uid_mappings() {
+        Some(mappings) if requires_map_binary(mappings, nix::unistd::geteuid().as_raw()) => {
+            Some(lookup_map_binary("newuidmap")?)
+        }
+        _ => None,
+    };
+    let newgidmap = match spec.gid_mappings() {
+        Some(mappings) if requires_map_binary(mappings, nix::unistd::getegid().as_raw()) => {
+            Some(lookup_map_binary("newgidmap")?)
         }
+        _ => None,
+    };
 
-        let uidmap = lookup_map_binary("newuidmap")?;
-        let gidmap = lookup_map_binary("newgidmap")?;
+    Ok((newuidmap, newgidmap))
+}
 
-        match (uidmap, gidmap) {
-            (Some(newuidmap), Some(newgidmap)) => Ok(Some((newuidmap, newgidmap))),
-            _ => bail!("newuidmap/newgidmap binaries could not be found in path. This is required if multiple id mappings are specified"),
-        }
-    } else {
-        Ok(None)
+// An unprivileged user may only map its own id to a single id of the user
+// namespace. The setuid helpers also allow the ranges delegated to the user.
+fn requires_map_binary(mappings: &[LinuxIdMapping], own_id: u32) -> bool {
+    match mappings {
+        [] => false,
+        [mapping] => mapping.host_id() != own_id || mapping.size() != 1,
+        _ => true,
     }
 }
 
-fn lookup_map_binary(binary: &str) -> Result<Option<PathBuf>> {
-    let paths = env::var("PATH")?;
-    Ok(paths
-        .split_terminator(':')
-        .find(|p| PathBuf::from(p).join(binary).exists())
-        .map(PathBuf::from))
+fn lookup_map_binary(binary: &str) -> Result<PathBuf> {
+    features::find_in_path(binary).with_context(|| {
+        format!(
+            "{} could not be found in PATH, it is required to map ids other than the own one",
+            binary
+        )
+    })
 }
 
 fn write_id_mapping(
+    pid: Pid,
     map_file: &str,
     mappings: &[LinuxIdMapping],
     map_binary: Option<&Path>,
 ) -> Result<()> {
-    let mappings: Vec<String> = mappings
-        .iter()
-        .map(|m| format!("{} {} {}", m.container_id(), m.host_id(), m.size()))
-        .collect();
     log::debug!("Write ID mapping: {:?}", mappings);
-    if mappings.len() == 1 {
-        utils::write_file(map_file, mappings.first().unwrap())?;
-    } else {
-        Command::new(map_binary.unwrap())
-            .args(mappings)
-            .output()
-            .with_context(|| format!("failed to execute {:?}", map_binary))?;
+    match map_binary {
+        // newuidmap and newgidmap are setuid binaries, which may map the
+        // ranges delegated to the user in /etc/subuid and /etc/subgid
+        Some(map_binary) => {
+            let output = Command::new(map_binary)
+                .arg(pid.to_string())
+                .args(map_binary_args(mappings))
+                .output()
+                .with_context(|| format!("failed to execute {:?}", map_binary))?;
+            if !output.status.success() {
+                bail!(
+                    "{:?} failed with {}: {}",
+                    map_binary,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+        }
+        None => secure_fs::write_proc_file(
+            format!("/proc/{}/{}", pid, map_file),
+            idmap::format_mappings(mappings),
+        )?,
     }
 
     Ok(())
 }
+
+// newuidmap and newgidmap take the ranges as triples of arguments
+fn map_binary_args(mappings: &[LinuxIdMapping]) -> Vec<String> {
+    mappings
+        .iter()
+        .flat_map(|m| {
+            vec![
+                m.container_id().to_string(),
+                m.host_id().to_string(),
+                m.size().to_string(),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::LinuxIdMappingBuilder;
+
+    fn mapping(container_id: u32, host_id: u32, size: u32) -> LinuxIdMapping {
+        LinuxIdMappingBuilder::default()
+            .container_id(container_id)
+            .host_id(host_id)
+            .size(size)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate_mappings() {
+        // podman maps root to the user and the rest to the subuid range
+        assert!(validate_mappings(&[mapping(0, 1000, 1), mapping(1, 100000, 65536)]).is_ok());
+        assert!(validate_mappings(&[mapping(0, 1000, 2), mapping(1, 100000, 65536)]).is_err());
+        assert!(validate_mappings(&[mapping(0, 100000, 10), mapping(10, 100005, 10)]).is_err());
+        assert!(validate_mappings(&[mapping(0, 1000, 0)]).is_err());
+    }
+
+    #[test]
+    fn test_is_id_mapped() {
+        let mappings = [mapping(0, 1000, 1), mapping(1, 100000, 65536)];
+        assert!(is_id_mapped(0, &mappings));
+        assert!(is_id_mapped(65536, &mappings));
+        assert!(!is_id_mapped(65537, &mappings));
+    }
+
+    #[test]
+    fn test_requires_map_binary() {
+        assert!(!requires_map_binary(&[], 1000));
+        assert!(!requires_map_binary(&[mapping(0, 1000, 1)], 1000));
+        assert!(requires_map_binary(&[mapping(0, 100000, 1)], 1000));
+        assert!(requires_map_binary(&[mapping(0, 1000, 2)], 1000));
+        assert!(requires_map_binary(
+            &[mapping(0, 1000, 1), mapping(1, 100000, 65536)],
+            1000
+        ));
+    }
+
+    #[test]
+    fn test_map_binary_args() {
+        assert_eq!(
+            map_binary_args(&[mapping(0, 1000, 1), mapping(1, 100000, 65536)]),
+            vec!["0", "1000", "1", "1", "100000", "65536"]
+        );
+    }
+}
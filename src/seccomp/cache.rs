@@ -0,0 +1,132 @@
+//! Generating the BPF program of large seccomp profiles, like the default
+//! profile of docker, takes a considerable part of the container start time.
+//! Similar to runc, the compiled programs are therefore cached, keyed by a
+//! hash of the seccomp profile, and reused for every container with the same
+//! profile.
+use anyhow::{Context, Result};
+use oci_spec::runtime::LinuxSeccomp;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::utils;
+
+/// Cache of compiled seccomp filters stored in a directory
+pub struct SeccompCache {
+    dir: PathBuf,
+}
+
+impl SeccompCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the compiled filter of the seccomp profile from the cache, or
+    /// compiles and caches it if the profile has not been seen before
+    pub fn get_or_compile(&self, seccomp: &LinuxSeccomp) -> Result<Vec<u8>> {
+        let path = self.dir.join(format!("{}.bpf", cache_key(seccomp)?));
+        match fs::read(&path) {
+            Ok(filter) => {
+                log::debug!("use cached seccomp filter {}", path.display());
+                return Ok(filter);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => log::warn!(
+                "failed to read cached seccomp filter {}: {}",
+                path.display(),
+                e
+            ),
+        }
+
+        let filter = super::compile_seccomp(seccomp)?;
+        // a failure to cache the filter only costs time on the next start
+        if let Err(e) = self.store(&path, &filter) {
+            log::warn!("failed to cache seccomp filter: {:?}", e);
+        }
+
+        Ok(filter)
+    }
+
+    fn store(&self, path: &Path, filter: &[u8]) -> Result<()> {
+        utils::create_dir_all(&self.dir)?;
+        // concurrently starting containers never read a partially written filter
+        utils::write_file_atomic(path, filter, false)
+    }
+}
+
+/// Returns the sha256 of the serialized profile together with everything else
+/// the compiled program depends on: the version of youki, the version of the
+/// dynamically linked libseccomp and the architecture
+fn cache_key(seccomp: &LinuxSeccomp) -> Result<String> {
+    let profile = serde_json::to_vec(seccomp).context("failed to serialize seccomp profile")?;
+    let libseccomp_version = libseccomp_version();
+    let mut hasher = Sha256::new();
+    for part in &[
+        env!("CARGO_PKG_VERSION"),
+        libseccomp_version.as_str(),
+        std::env::consts::ARCH,
+    ] {
+        hasher.update(part);
+        hasher.update(b"\0");
+    }
+    hasher.update(profile);
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// libseccomp is loaded at runtime, so an upgrade changes the compiled
+// program without changing youki
+fn libseccomp_version() -> String {
+    let version = unsafe { seccomp::seccomp_version() };
+    match unsafe { version.as_ref() } {
+        Some(v) => format!("{}.{}.{}", v.major, v.minor, v.micro),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::{LinuxSeccompAction, LinuxSeccompBuilder, LinuxSyscallBuilder};
+
+    fn profile(syscall: &str) -> LinuxSeccomp {
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![syscall.to_string()])
+            .action(LinuxSeccompAction::ScmpActErrno)
+            .build()
+            .unwrap();
+        LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .syscalls(vec![syscall])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cache_key() -> Result<()> {
+        assert_eq!(
+            cache_key(&profile("getcwd"))?,
+            cache_key(&profile("getcwd"))?
+        );
+        assert_ne!(
+            cache_key(&profile("getcwd"))?,
+            cache_key(&profile("mount"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_or_compile() -> Result<()> {
+        let tmp = utils::create_temp_dir("test_seccomp_cache")?;
+        let cache = SeccompCache::new(tmp.join("seccomp"));
+        let seccomp = profile("getcwd");
+
+        let filter = cache.get_or_compile(&seccomp)?;
+        let cached = tmp
+            .join("seccomp")
+            .join(format!("{}.bpf", cache_key(&seccomp)?));
+        assert_eq!(fs::read(&cached)?, filter);
+        assert_eq!(cache.get_or_compile(&seccomp)?, filter);
+        Ok(())
+    }
+}
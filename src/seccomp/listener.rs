@@ -0,0 +1,140 @@
+//! Passes the seccomp notify fd of the container to the agent listening on
+//! the listenerPath of the seccomp profile, which handles the notifications
+//! of the syscalls with the SCMP_ACT_NOTIFY action.
+//! Ref: https://github.com/opencontainers/runtime-spec/blob/master/config-linux.md#seccomp
+use std::fs;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use nix::sys::socket::{self, ControlMessage, MsgFlags};
+use nix::sys::uio::IoVec;
+use nix::unistd;
+use serde::Serialize;
+
+use crate::container::State;
+
+// name of the notify fd in the fds of the container process state
+const SECCOMP_FD_NAME: &str = "seccompFd";
+
+/// Message sent to the agent together with the notify fd
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ContainerProcessState<'a> {
+    oci_version: &'a str,
+    fds: Vec<&'a str>,
+    pid: i32,
+    metadata: &'a str,
+    state: &'a State,
+}
+
+/// Connection to the seccomp agent. It has to be opened before the container
+/// process enters its mount namespace, in which the path of the agent usually
+/// does not exist.
+#[derive(Debug)]
+pub struct SeccompListener {
+    stream: UnixStream,
+    // pid of the container process in the pid namespace of the agent
+    pid: i32,
+}
+
+impl SeccompListener {
+    pub fn connect<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("failed to connect to seccomp agent {}", path.display()))?;
+        // while the procfs of the host is still mounted, it resolves the
+        // link to the pid outside of the pid namespace of the container
+        let pid = fs::read_link("/proc/self")
+            .context("failed to read /proc/self")?
+            .to_string_lossy()
+            .parse()
+            .context("failed to parse pid of /proc/self")?;
+
+        Ok(Self { stream, pid })
+    }
+
+    /// Sends the notify fd with the container state and the listenerMetadata
+    /// of the profile to the agent. The fd is closed afterwards, it must not
+    /// be left to the container process.
+    pub fn send_notify_fd(&self, fd: RawFd, metadata: &str, state: &State) -> Result<()> {
+        let result = self.send(fd, metadata, state);
+        let _ = unistd::close(fd);
+        result
+    }
+
+    fn send(&self, fd: RawFd, metadata: &str, state: &State) -> Result<()> {
+        let mut state = state.clone();
+        state.pid = Some(self.pid);
+        let message = serde_json::to_vec(&ContainerProcessState {
+            oci_version: &state.oci_version,
+            fds: vec![SECCOMP_FD_NAME],
+            pid: self.pid,
+            metadata,
+            state: &state,
+        })
+        .context("failed to encode container process state")?;
+
+        let fds = [fd];
+        socket::sendmsg(
+            self.stream.as_raw_fd(),
+            &[IoVec::from_slice(&message)],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )
+        .context("failed to send seccomp notify fd to the agent")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::ContainerStatus;
+    use crate::utils::create_temp_dir;
+    use nix::sys::socket::ControlMessageOwned;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_send_notify_fd() -> Result<()> {
+        let tmp = create_temp_dir("test_send_notify_fd")?;
+        let listener = UnixListener::bind(tmp.join("agent.sock"))?;
+        let seccomp_listener = SeccompListener::connect(tmp.join("agent.sock"))?;
+        let (agent, _) = listener.accept()?;
+
+        let state = State::new(
+            "test",
+            ContainerStatus::Creating,
+            None,
+            PathBuf::from("/bundle"),
+        );
+        let (notify_fd, write_fd) = unistd::pipe()?;
+        seccomp_listener.send_notify_fd(notify_fd, "profile=default", &state)?;
+        unistd::close(write_fd)?;
+
+        let mut buf = [0u8; 4096];
+        let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+        let msg = socket::recvmsg(
+            agent.as_raw_fd(),
+            &[IoVec::from_mut_slice(&mut buf)],
+            Some(&mut cmsg_buf),
+            MsgFlags::empty(),
+        )?;
+        let received = msg
+            .cmsgs()
+            .any(|cmsg| matches!(cmsg, ControlMessageOwned::ScmRights(fds) if fds.len() == 1));
+        let bytes = msg.bytes;
+        assert!(received);
+
+        let message: serde_json::Value = serde_json::from_slice(&buf[..bytes])?;
+        assert_eq!(message["fds"], serde_json::json!(["seccompFd"]));
+        assert_eq!(message["metadata"], "profile=default");
+        assert_eq!(message["pid"], unistd::getpid().as_raw());
+        assert_eq!(message["state"]["id"], "test");
+        assert_eq!(message["state"]["pid"], unistd::getpid().as_raw());
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use nix::errno::Errno;
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
 use oci_spec::runtime::Arch;
 use oci_spec::runtime::LinuxSeccomp;
 use oci_spec::runtime::LinuxSeccompAction;
@@ -9,7 +10,17 @@ use oci_spec::runtime::LinuxSeccompOperator;
 use seccomp::scmp_compare::*;
 use seccomp::*;
 use std::ffi::CString;
-use std::os::unix::io;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::io::{self, FromRawFd};
+
+mod cache;
+mod listener;
+pub use cache::SeccompCache;
+pub use listener::SeccompListener;
+
+const SECCOMP_SET_MODE_FILTER: libc::c_ulong = 1;
+const SECCOMP_FILTER_FLAG_NEW_LISTENER: libc::c_ulong = 1 << 3;
 
 #[derive(Debug)]
 struct Compare {
@@ -96,7 +107,12 @@ impl FilterContext {
     pub fn default(default_action: u32) -> Result<FilterContext> {
         let filter_ctx = unsafe { seccomp_init(default_action) };
         if filter_ctx.is_null() {
-            bail!("Failed to initialized seccomp profile")
+            // libseccomp fails to initialize if it was built without support
+            // for the architecture of the host, e.g. riscv64 before 2.5
+            bail!(
+                "Failed to initialized seccomp profile, libseccomp may not support {}",
+                nix::sys::utsname::uname().machine()
+            )
         }
 
         Ok(FilterContext { ctx: filter_ctx })
@@ -124,10 +140,15 @@ impl FilterContext {
 
     pub fn add_arch(&mut self, arch: u32) -> Result<()> {
         let res = unsafe { seccomp_arch_add(self.ctx, arch) };
-        if res != 0 && nix::Error::from_i32(res.abs()) != nix::Error::EEXIST {
+        match nix::Error::from_i32(res.abs()) {
+            _ if res == 0 => {}
             // The architecture already existed in the profile, so we can
             // safely ignore the error here. Otherwise, error out.
-            bail!("Failed to add architecture {}. Errno: {}", arch, res);
+            nix::Error::EEXIST => {}
+            // libseccomp does not know the architecture, it was built before
+            // the architecture was added or without it
+            nix::Error::EINVAL => bail!("architecture {} is not supported by libseccomp", arch),
+            _ => bail!("Failed to add architecture {}. Errno: {}", arch, res),
         }
 
         Ok(())
@@ -142,6 +163,22 @@ impl FilterContext {
         Ok(())
     }
 
+    /// Generates the BPF program of the filter
+    pub fn export_bpf(&self) -> Result<Vec<u8>> {
+        let fd = memfd_create(&CString::new("seccomp")?, MemFdCreateFlag::MFD_CLOEXEC)
+            .context("failed to create memfd for seccomp filter")?;
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        let res = unsafe { seccomp_export_bpf(self.ctx, fd) };
+        if res != 0 {
+            bail!("Failed to export seccomp profile: {}", res);
+        }
+
+        let mut bpf = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut bpf)?;
+        Ok(bpf)
+    }
+
     pub fn notify_fd(&self) -> Result<Option<i32>> {
         let res = unsafe { seccomp_notify_fd(self.ctx) };
         if res > 0 {
@@ -209,6 +246,9 @@ fn translate_op(op: LinuxSeccompOperator) -> scmp_compare {
     }
 }
 
+// The Arch of oci-spec has no token for riscv64 (and the other architectures
+// libseccomp added after s390x) yet, so profiles for riscv64 have to use
+// SCMP_ARCH_NATIVE or leave the architectures empty.
 fn translate_arch(arch: Arch) -> scmp_arch {
     match arch {
         Arch::ScmpArchNative => SCMP_ARCH_NATIVE,
@@ -246,6 +286,12 @@ fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
         bail!("SCMP_ACT_NOTIFY cannot be used as default action");
     }
 
+    // Without an agent, nobody would answer the notifications and the
+    // notified syscalls would block forever. runc rejects this as well.
+    if is_seccomp_notify(seccomp) && seccomp.listener_path().is_none() {
+        bail!("SCMP_ACT_NOTIFY requires a listenerPath");
+    }
+
     if let Some(syscalls) = seccomp.syscalls() {
         for syscall in syscalls {
             if syscall.action() == LinuxSeccompAction::ScmpActNotify {
@@ -261,7 +307,7 @@ fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
     Ok(())
 }
 
-pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
+fn build_filter(seccomp: &LinuxSeccomp) -> Result<FilterContext> {
     if seccomp.flags().is_some() {
         // runc did not support this, so let's skip it for now.
         bail!("seccomp flags are not yet supported");
@@ -277,7 +323,7 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
         for &arch in architectures {
             let arch_token = translate_arch(arch);
             ctx.add_arch(arch_token as u32)
-                .context("failed to add arch to seccomp")?;
+                .with_context(|| format!("failed to add arch {:?} to seccomp", arch))?;
         }
     }
 
@@ -358,19 +404,27 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
         }
     }
 
+    Ok(ctx)
+}
+
+fn is_seccomp_notify(seccomp: &LinuxSeccomp) -> bool {
+    seccomp
+        .syscalls()
+        .iter()
+        .flatten()
+        .any(|syscall| syscall.action() == LinuxSeccompAction::ScmpActNotify)
+}
+
+pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
+    let ctx = build_filter(seccomp)?;
+
     // In order to use the SECCOMP_SET_MODE_FILTER operation, either the calling
     // thread must have the CAP_SYS_ADMIN capability in its user namespace, or
     // the thread must already have the no_new_privs bit set.
     // Ref: https://man7.org/linux/man-pages/man2/seccomp.2.html
     ctx.load().context("failed to load seccomp context")?;
 
-    let is_seccomp_notify = seccomp
-        .syscalls()
-        .iter()
-        .flatten()
-        .any(|syscall| syscall.action() == LinuxSeccompAction::ScmpActNotify);
-
-    let fd = if is_seccomp_notify {
+    let fd = if is_seccomp_notify(seccomp) {
         ctx.notify_fd().context("failed to get seccomp notify fd")?
     } else {
         None
@@ -379,6 +433,61 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
     Ok(fd)
 }
 
+/// Compiles the seccomp profile into a BPF program, which can be loaded with
+/// load_seccomp_filter. Generating the program of large profiles is
+/// expensive, so the compiled programs are cached, see SeccompCache.
+pub fn compile_seccomp(seccomp: &LinuxSeccomp) -> Result<Vec<u8>> {
+    build_filter(seccomp)?
+        .export_bpf()
+        .context("failed to compile seccomp profile")
+}
+
+/// Loads a BPF program compiled from the seccomp profile by compile_seccomp.
+/// Returns the notify fd if the profile contains notify actions.
+pub fn load_seccomp_filter(filter: &[u8], seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
+    const INSTRUCTION_SIZE: usize = std::mem::size_of::<libc::sock_filter>();
+    if filter.is_empty() || filter.len() % INSTRUCTION_SIZE != 0 {
+        bail!("invalid seccomp filter of {} bytes", filter.len());
+    }
+
+    let mut instructions: Vec<libc::sock_filter> = filter
+        .chunks_exact(INSTRUCTION_SIZE)
+        .map(|i| libc::sock_filter {
+            code: u16::from_ne_bytes([i[0], i[1]]),
+            jt: i[2],
+            jf: i[3],
+            k: u32::from_ne_bytes([i[4], i[5], i[6], i[7]]),
+        })
+        .collect();
+    let prog = libc::sock_fprog {
+        len: instructions.len() as libc::c_ushort,
+        filter: instructions.as_mut_ptr(),
+    };
+
+    let is_notify = is_seccomp_notify(seccomp);
+    let flags = if is_notify {
+        SECCOMP_FILTER_FLAG_NEW_LISTENER
+    } else {
+        0
+    };
+    // See the comment on privileges in initialize_seccomp
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            flags,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    let res = Errno::result(res).context("failed to load seccomp filter")?;
+
+    Ok(if is_notify {
+        Some(res as io::RawFd)
+    } else {
+        None
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -457,6 +566,62 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_compiled_filter() -> Result<()> {
+        let expect_error = libc::EAGAIN;
+
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getcwd")])
+            .action(LinuxSeccompAction::ScmpActErrno)
+            .errno_ret(expect_error as u32)
+            .build()?;
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(vec![syscall])
+            .build()?;
+
+        let filter = compile_seccomp(&seccomp_profile)?;
+        test_utils::test_in_child_process(|| {
+            let _ = prctl::set_no_new_privileges(true);
+            load_seccomp_filter(&filter, &seccomp_profile)?;
+            match nix::unistd::getcwd() {
+                Err(errno) if errno == nix::errno::from_i32(expect_error) => Ok(()),
+                ret => bail!(
+                    "getcwd didn't fail as the compiled filter specified: {:?}",
+                    ret
+                ),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_invalid_filter() {
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .build()
+            .unwrap();
+        assert!(load_seccomp_filter(&[], &seccomp_profile).is_err());
+        assert!(load_seccomp_filter(&[0; 7], &seccomp_profile).is_err());
+    }
+
+    #[test]
+    fn test_notify_requires_listener() -> Result<()> {
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getcwd")])
+            .action(LinuxSeccompAction::ScmpActNotify)
+            .build()?;
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .syscalls(vec![syscall])
+            .build()?;
+        assert!(check_seccomp(&seccomp_profile).is_err());
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_seccomp_notify() -> Result<()> {
@@ -468,6 +633,7 @@ mod tests {
             .default_action(LinuxSeccompAction::ScmpActAllow)
             .architectures(vec![Arch::ScmpArchNative])
             .syscalls(vec![syscall])
+            .listener_path(path::PathBuf::from("/run/seccomp-agent.sock"))
             .build()?;
         test_utils::test_in_child_process(|| {
             let _ = prctl::set_no_new_privileges(true);
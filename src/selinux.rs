@@ -0,0 +1,14 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::{features, utils::secure_fs};
+
+/// Sets the SELinux label the process gets on its next execve, i.e. the label
+/// of the container process. Nothing is done if SELinux is not enabled.
+pub fn set_exec_label(label: &str) -> Result<()> {
+    if label.is_empty() || !features::selinux_enabled() {
+        return Ok(());
+    }
+
+    secure_fs::write_proc_file(Path::new("/proc/self/attr/exec"), label)
+}
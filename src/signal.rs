@@ -1,7 +1,72 @@
 //! Returns *nix signal enum value from passed string
 
-use anyhow::{bail, Result};
-use nix::sys::signal::Signal;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use anyhow::{bail, Context, Result};
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+/// A signal which can be sent to the processes of a container. Unlike
+/// nix::sys::signal::Signal, it can hold the realtime signals as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContainerSignal(i32);
+
+impl ContainerSignal {
+    pub fn as_raw(self) -> i32 {
+        self.0
+    }
+}
+
+impl From<Signal> for ContainerSignal {
+    fn from(signal: Signal) -> Self {
+        ContainerSignal(signal as i32)
+    }
+}
+
+impl Display for ContainerSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Signal::try_from(self.0) {
+            Ok(signal) => write!(f, "{}", signal),
+            Err(_) => write!(f, "SIGRTMIN+{}", self.0 - libc::SIGRTMIN()),
+        }
+    }
+}
+
+/// Parses a signal given by its name with or without the SIG prefix, by its
+/// number or as realtime signal relative to RTMIN or RTMAX, e.g. SIGTERM,
+/// TERM, 15, RTMIN+3 or SIGRTMAX-1
+pub fn parse_signal(signal: &str) -> Result<ContainerSignal> {
+    let (rtmin, rtmax) = (libc::SIGRTMIN(), libc::SIGRTMAX());
+    let upper = signal.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+
+    let realtime = |base: i32, offset: &str, sign: i32| -> Result<i32> {
+        let offset: i32 = offset
+            .parse()
+            .with_context(|| format!("{} is not a valid signal", signal))?;
+        Ok(base + sign * offset)
+    };
+    let number = if let Ok(number) = name.parse::<i32>() {
+        number
+    } else if name == "RTMIN" {
+        rtmin
+    } else if name == "RTMAX" {
+        rtmax
+    } else if let Some(offset) = name.strip_prefix("RTMIN+") {
+        realtime(rtmin, offset, 1)?
+    } else if let Some(offset) = name.strip_prefix("RTMAX-") {
+        realtime(rtmax, offset, -1)?
+    } else {
+        return Ok(upper.to_signal()?.into());
+    };
+
+    if (rtmin..=rtmax).contains(&number) || Signal::try_from(number).is_ok() {
+        Ok(ContainerSignal(number))
+    } else {
+        bail!("{} is not a valid signal", signal)
+    }
+}
 
 pub trait ToSignal<From = Self> {
     fn to_signal(&self) -> Result<Signal>;
@@ -47,10 +112,107 @@ impl ToSignal for String {
     }
 }
 
+// Signals which terminate youki, e.g. when the create is cancelled
+const TERMINATION_SIGNALS: [Signal; 2] = [Signal::SIGTERM, Signal::SIGINT];
+
+static TERMINATION_RECEIVED: AtomicI32 = AtomicI32::new(0);
+static TERMINATION_HANDLED: AtomicBool = AtomicBool::new(false);
+// The handlers which were installed before the guard. They are only written
+// while TERMINATION_HANDLED is false and only read while it is true, so that
+// a forked process can restore them without taking a lock.
+static mut PREVIOUS_HANDLERS: [Option<SigAction>; 2] = [None, None];
+
+extern "C" fn record_termination(signal: libc::c_int) {
+    TERMINATION_RECEIVED.store(signal, Ordering::SeqCst);
+}
+
+/// Records SIGTERM and SIGINT instead of terminating youki while it exists,
+/// so that an operation which is cancelled can roll back what it created
+/// before exiting. The previous handlers are restored when it is dropped.
+/// Only one guard can exist at a time.
+pub struct TerminationGuard {
+    _private: (),
+}
+
+impl TerminationGuard {
+    pub fn install() -> Result<Self> {
+        if TERMINATION_HANDLED.load(Ordering::SeqCst) {
+            bail!("termination handlers are installed already");
+        }
+
+        TERMINATION_RECEIVED.store(0, Ordering::SeqCst);
+        unsafe { PREVIOUS_HANDLERS = [None, None] };
+        // interrupted syscalls are restarted, the operation checks for the
+        // signal once it is done
+        let action = SigAction::new(
+            SigHandler::Handler(record_termination),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+
+        let guard = Self { _private: () };
+        for (i, signal) in TERMINATION_SIGNALS.iter().enumerate() {
+            let previous = unsafe { signal::sigaction(*signal, &action) };
+            let previous = match previous {
+                Ok(previous) => previous,
+                Err(e) => {
+                    let _ = restore_previous_handlers();
+                    bail!("failed to install handler for {}: {}", signal, e);
+                }
+            };
+            unsafe { PREVIOUS_HANDLERS[i] = Some(previous) };
+        }
+        TERMINATION_HANDLED.store(true, Ordering::SeqCst);
+
+        Ok(guard)
+    }
+
+    /// Returns the termination signal received since the guard was installed
+    pub fn received(&self) -> Option<Signal> {
+        match TERMINATION_RECEIVED.load(Ordering::SeqCst) {
+            0 => None,
+            signal => Signal::try_from(signal).ok(),
+        }
+    }
+}
+
+impl Drop for TerminationGuard {
+    fn drop(&mut self) {
+        for (signal, result) in TERMINATION_SIGNALS.iter().zip(restore_previous_handlers()) {
+            if let Err(e) = result {
+                log::warn!("failed to restore handler for {}: {}", signal, e);
+            }
+        }
+        TERMINATION_HANDLED.store(false, Ordering::SeqCst);
+    }
+}
+
+// Reinstalls the handlers which were replaced by the guard. It neither
+// allocates nor locks, so that it can be called in a forked process.
+fn restore_previous_handlers() -> [nix::Result<()>; 2] {
+    let mut results = [Ok(()), Ok(())];
+    for (i, signal) in TERMINATION_SIGNALS.iter().enumerate() {
+        if let Some(previous) = unsafe { PREVIOUS_HANDLERS[i].as_ref() } {
+            results[i] = unsafe { signal::sigaction(*signal, previous) }.map(drop);
+        }
+    }
+    results
+}
+
+/// Restores the previous handlers of the termination signals in a process
+/// forked while a TerminationGuard exists, otherwise the container processes
+/// could not be terminated before they exec. Async-signal-safe.
+pub fn reset_termination_handlers() {
+    if TERMINATION_HANDLED.load(Ordering::SeqCst) {
+        let _ = restore_previous_handlers();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use nix::sys::signal::Signal::*;
+    use serial_test::serial;
     use std::collections::HashMap;
 
     #[test]
@@ -98,4 +260,48 @@ mod tests {
     fn test_conversion_from_string_should_be_failed() {
         assert!("invalid".to_string().to_signal().is_err())
     }
+
+    #[test]
+    fn test_parse_signal() -> Result<()> {
+        for s in &["SIGTERM", "TERM", "term", "15"] {
+            assert_eq!(parse_signal(s)?, SIGTERM.into());
+        }
+
+        let rtmin = libc::SIGRTMIN();
+        let rtmax = libc::SIGRTMAX();
+        assert_eq!(parse_signal("RTMIN")?.as_raw(), rtmin);
+        assert_eq!(parse_signal("SIGRTMIN+3")?.as_raw(), rtmin + 3);
+        assert_eq!(parse_signal("RTMAX-1")?.as_raw(), rtmax - 1);
+        assert_eq!(parse_signal(&rtmax.to_string())?.as_raw(), rtmax);
+        assert_eq!(parse_signal("RTMIN+3")?.to_string(), "SIGRTMIN+3");
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_termination_guard() -> Result<()> {
+        let guard = TerminationGuard::install()?;
+        assert_eq!(guard.received(), None);
+
+        signal::raise(SIGTERM)?;
+        assert_eq!(guard.received(), Some(SIGTERM));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_signal_should_be_failed() {
+        let rtmax = libc::SIGRTMAX();
+        let invalid = vec![
+            "0".to_owned(),
+            "-1".to_owned(),
+            (rtmax + 1).to_string(),
+            format!("RTMIN+{}", rtmax),
+            "RTMIN+".to_owned(),
+            "RTMAX-x".to_owned(),
+            "SIGFOO".to_owned(),
+        ];
+        for s in invalid {
+            assert!(parse_signal(&s).is_err(), "{} should be invalid", s);
+        }
+    }
 }
@@ -0,0 +1,413 @@
+//! Captures the stdout and stderr of a container into a log file in the CRI
+//! format of kubernetes, so that simple deployments do not need conmon. The
+//! container writes to pipes, which are read by a monitor process detached
+//! from youki create. Each line of the log is written as
+//!
+//! ```text
+//! 2021-10-14T12:10:01.234567890Z stdout F hello world
+//! ```
+//!
+//! with the tag P for parts of lines which exceed the maximum line length.
+//! Once the log grows beyond its maximum size, it is rotated to <path>.1.
+//! The monitor exits when the container and all of its processes have
+//! closed the pipes.
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use nix::errno::Errno;
+use nix::fcntl::{self, OFlag};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::stat::Mode;
+use nix::unistd;
+
+use crate::process::fork;
+
+/// Default size at which the log is rotated
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+// lines are split into parts of this length, like containerd does
+const MAX_LINE_LENGTH: usize = 16 * 1024;
+
+/// Log file the stdout and stderr of a container are captured into
+#[derive(Clone, Debug)]
+pub struct StdioLog {
+    path: PathBuf,
+    max_size: u64,
+}
+
+/// Writing ends of the pipes the container uses as stdout and stderr
+#[derive(Clone, Copy, Debug)]
+pub struct StdioPipes {
+    pub stdout: RawFd,
+    pub stderr: RawFd,
+}
+
+impl StdioPipes {
+    /// Closes the pipes in youki, once they have been passed to the container
+    pub fn close(&self) -> Result<()> {
+        unistd::close(self.stdout).context("failed to close stdout pipe")?;
+        unistd::close(self.stderr).context("failed to close stderr pipe")?;
+        Ok(())
+    }
+}
+
+impl StdioLog {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Sets the size in bytes at which the log is rotated
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Opens the log and starts the monitor process which writes to it.
+    /// Returns the pipes the container has to use as stdout and stderr.
+    pub(crate) fn spawn(&self) -> Result<StdioPipes> {
+        // the monitor runs in another working directory
+        let path = if self.path.is_absolute() {
+            self.path.clone()
+        } else {
+            env::current_dir()?.join(&self.path)
+        };
+        let mut writer = LogWriter::open(&path, self.max_size)?;
+
+        let (stdout_read, stdout_write) =
+            unistd::pipe2(OFlag::O_CLOEXEC).context("failed to create stdout pipe")?;
+        let (stderr_read, stderr_write) =
+            unistd::pipe2(OFlag::O_CLOEXEC).context("failed to create stderr pipe")?;
+
+        let monitor = fork::container_fork(|| {
+            unistd::close(stdout_write)?;
+            unistd::close(stderr_write)?;
+            // detach from youki create and its caller, which may wait for
+            // the end of the output of youki
+            unistd::setsid()?;
+            unistd::chdir("/")?;
+            let null = fcntl::open("/dev/null", OFlag::O_RDWR, Mode::empty())?;
+            for fd in 0..=2 {
+                unistd::dup2(null, fd)?;
+            }
+            unistd::close(null)?;
+
+            capture(
+                &mut writer,
+                [
+                    Stream::new("stdout", stdout_read),
+                    Stream::new("stderr", stderr_read),
+                ],
+            )
+        })?;
+        log::debug!("stdio of container is logged to {:?} by {}", path, monitor);
+
+        unistd::close(stdout_read)?;
+        unistd::close(stderr_read)?;
+        Ok(StdioPipes {
+            stdout: stdout_write,
+            stderr: stderr_write,
+        })
+    }
+}
+
+// Reads from the pipes until both are closed and writes their lines to the log
+fn capture(writer: &mut LogWriter, mut streams: [Stream; 2]) -> Result<()> {
+    let mut buf = [0u8; 32 * 1024];
+    while streams.iter().any(|s| s.fd.is_some()) {
+        let open: Vec<RawFd> = streams.iter().filter_map(|s| s.fd).collect();
+        let mut fds: Vec<PollFd> = open
+            .iter()
+            .map(|fd| PollFd::new(*fd, PollFlags::POLLIN))
+            .collect();
+        match poll(&mut fds, -1) {
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e).context("failed to poll pipes"),
+        }
+
+        // POLLHUP is reported for closed pipes, which are read until EOF
+        let ready: Vec<RawFd> = open
+            .iter()
+            .zip(fds.iter())
+            .filter(|(_, fd)| fd.revents().map_or(false, |r| !r.is_empty()))
+            .map(|(raw_fd, _)| *raw_fd)
+            .collect();
+        for stream in streams.iter_mut() {
+            let fd = match stream.fd {
+                Some(fd) if ready.contains(&fd) => fd,
+                _ => continue,
+            };
+
+            let eof = match unistd::read(fd, &mut buf) {
+                Ok(0) => true,
+                Ok(n) => {
+                    stream.pending.extend_from_slice(&buf[..n]);
+                    false
+                }
+                Err(Errno::EINTR) | Err(Errno::EAGAIN) => continue,
+                Err(e) => return Err(e).with_context(|| format!("failed to read {}", stream.name)),
+            };
+            for (content, partial) in take_entries(&mut stream.pending, eof) {
+                writer.log_entry(&Utc::now(), stream.name, partial, &content);
+            }
+            if eof {
+                let _ = unistd::close(fd);
+                stream.fd = None;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Stream {
+    name: &'static str,
+    fd: Option<RawFd>,
+    // output which does not end with a newline yet
+    pending: Vec<u8>,
+}
+
+impl Stream {
+    fn new(name: &'static str, fd: RawFd) -> Self {
+        Self {
+            name,
+            fd: Some(fd),
+            pending: Vec::new(),
+        }
+    }
+}
+
+/// Takes the complete lines out of the pending output, as content without the
+/// newline and whether it is only a part of a line. Lines which exceed the
+/// maximum length are split into parts. At the end of the stream the rest is
+/// taken as last line.
+fn take_entries(pending: &mut Vec<u8>, eof: bool) -> Vec<(Vec<u8>, bool)> {
+    let mut entries = Vec::new();
+    loop {
+        match pending.iter().position(|b| *b == b'\n') {
+            Some(end) if end <= MAX_LINE_LENGTH => {
+                let mut line: Vec<u8> = pending.drain(..=end).collect();
+                line.pop();
+                entries.push((line, false));
+            }
+            _ if pending.len() > MAX_LINE_LENGTH => {
+                entries.push((pending.drain(..MAX_LINE_LENGTH).collect(), true));
+            }
+            _ => break,
+        }
+    }
+
+    if eof && !pending.is_empty() {
+        entries.push((pending.drain(..).collect(), false));
+    }
+    entries
+}
+
+fn format_entry(timestamp: &DateTime<Utc>, stream: &str, partial: bool, content: &[u8]) -> Vec<u8> {
+    let mut entry = format!(
+        "{} {} {} ",
+        timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        stream,
+        if partial { "P" } else { "F" }
+    )
+    .into_bytes();
+    entry.extend_from_slice(content);
+    entry.push(b'\n');
+    entry
+}
+
+struct LogWriter {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    // entries which could not be written and the last error
+    dropped: u64,
+    error: Option<String>,
+}
+
+impl LogWriter {
+    fn open(path: &Path, max_size: u64) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            dropped: 0,
+            error: None,
+        })
+    }
+
+    /// Writes the entry to the log. If the log cannot be written or rotated,
+    /// e.g. because the disk is full, the entry is dropped instead of ending
+    /// the monitor, as the container would block on the full pipes. The
+    /// dropped entries are reported in the log once writing succeeds again.
+    fn log_entry(
+        &mut self,
+        timestamp: &DateTime<Utc>,
+        stream: &str,
+        partial: bool,
+        content: &[u8],
+    ) {
+        let result = self
+            .write_dropped()
+            .and_then(|_| self.write_entry(timestamp, stream, partial, content));
+        if let Err(e) = result {
+            self.dropped += 1;
+            self.error = Some(format!("{:#}", e));
+        }
+    }
+
+    fn write_dropped(&mut self) -> Result<()> {
+        if let Some(error) = self.error.clone() {
+            let message = format!("youki dropped {} log entries: {}", self.dropped, error);
+            self.write_entry(&Utc::now(), "stderr", false, message.as_bytes())?;
+            self.dropped = 0;
+            self.error = None;
+        }
+        Ok(())
+    }
+
+    fn write_entry(
+        &mut self,
+        timestamp: &DateTime<Utc>,
+        stream: &str,
+        partial: bool,
+        content: &[u8],
+    ) -> Result<()> {
+        let entry = format_entry(timestamp, stream, partial, content);
+        if self.size > 0 && self.size + entry.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        self.file
+            .write_all(&entry)
+            .with_context(|| format!("failed to write to {}", self.path.display()))?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+
+    // keeps a single rotated log, the previous one is replaced
+    fn rotate(&mut self) -> Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        match fs::rename(&self.path, &rotated) {
+            Ok(()) => {}
+            // the log has been rotated before reopening it failed
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to rotate {}", self.path.display()))
+            }
+        }
+        let reopened = Self::open(&self.path, self.max_size)?;
+        self.file = reopened.file;
+        self.size = reopened.size;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_take_entries() {
+        let mut pending = b"first\nsecond\nthi".to_vec();
+        assert_eq!(
+            take_entries(&mut pending, false),
+            vec![(b"first".to_vec(), false), (b"second".to_vec(), false)]
+        );
+        assert_eq!(pending, b"thi");
+        assert_eq!(
+            take_entries(&mut pending, true),
+            vec![(b"thi".to_vec(), false)]
+        );
+        assert!(pending.is_empty());
+
+        let mut pending = vec![b'a'; MAX_LINE_LENGTH + 1];
+        pending.push(b'\n');
+        assert_eq!(
+            take_entries(&mut pending, false),
+            vec![(vec![b'a'; MAX_LINE_LENGTH], true), (b"a".to_vec(), false)]
+        );
+    }
+
+    #[test]
+    fn test_format_entry() {
+        let timestamp = Utc.ymd(2021, 10, 14).and_hms_nano(12, 10, 1, 234567890);
+        assert_eq!(
+            format_entry(&timestamp, "stdout", false, b"hello world"),
+            b"2021-10-14T12:10:01.234567890Z stdout F hello world\n"
+        );
+        assert_eq!(
+            format_entry(&timestamp, "stderr", true, b"hello"),
+            b"2021-10-14T12:10:01.234567890Z stderr P hello\n"
+        );
+    }
+
+    #[test]
+    fn test_log_writer_rotation() -> Result<()> {
+        let tmp = create_temp_dir("test_log_writer_rotation")?;
+        let path = tmp.join("container.log");
+        let timestamp = Utc::now();
+        let entry_len = format_entry(&timestamp, "stdout", false, b"line").len() as u64;
+
+        let mut writer = LogWriter::open(&path, entry_len * 2)?;
+        for _ in 0..3 {
+            writer.write_entry(&timestamp, "stdout", false, b"line")?;
+        }
+
+        assert_eq!(
+            fs::metadata(tmp.join("container.log.1"))?.len(),
+            entry_len * 2
+        );
+        assert_eq!(fs::metadata(&path)?.len(), entry_len);
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_writer_drops_entries() -> Result<()> {
+        let tmp = create_temp_dir("test_log_writer_drops_entries")?;
+        let dir = tmp.join("logs");
+        fs::create_dir(&dir)?;
+        let path = dir.join("container.log");
+        let timestamp = Utc::now();
+        let entry_len = format_entry(&timestamp, "stdout", false, b"line").len() as u64;
+
+        // the log cannot be rotated while its directory is missing
+        let mut writer = LogWriter::open(&path, entry_len)?;
+        writer.log_entry(&timestamp, "stdout", false, b"line");
+        fs::remove_dir_all(&dir)?;
+        writer.log_entry(&timestamp, "stdout", false, b"lost");
+        writer.log_entry(&timestamp, "stdout", false, b"lost");
+        assert_eq!(writer.dropped, 2);
+
+        fs::create_dir(&dir)?;
+        writer.log_entry(&timestamp, "stdout", false, b"line");
+        assert_eq!(writer.dropped, 0);
+        // each entry fills the log, the notice has been rotated already
+        let rotated = fs::read_to_string(dir.join("container.log.1"))?;
+        assert!(
+            rotated.contains("stderr F youki dropped 2 log entries"),
+            "{}",
+            rotated
+        );
+        assert!(fs::read_to_string(&path)?.ends_with("stdout F line\n"));
+        Ok(())
+    }
+}
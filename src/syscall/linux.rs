@@ -22,9 +22,9 @@ use nix::{
 };
 use nix::{sched::unshare, sys::stat::Mode};
 
-use oci_spec::runtime::LinuxRlimit;
+use oci_spec::runtime::{LinuxRlimit, LinuxRlimitType};
 
-use super::Syscall;
+use super::{PivotRootError, Syscall};
 use crate::capabilities;
 
 /// Empty structure to implement Command trait for
@@ -66,7 +66,7 @@ impl Syscall for LinuxSyscall {
         // this path. This is done, as otherwise, we will need to create a separate temporary directory under the new root path
         // so we can move the original root there, and then unmount that. This way saves the creation of the temporary
         // directory to put original root directory.
-        pivot_root(path, path)?;
+        pivot_root(path, path).map_err(PivotRootError)?;
 
         // Make the original root directory rslave to avoid propagating unmount event to the host mount namespace.
         // We should use MS_SLAVE not MS_PRIVATE according to https://github.com/opencontainers/runc/pull/1500.
@@ -88,6 +88,26 @@ impl Syscall for LinuxSyscall {
         Ok(())
     }
 
+    /// Function to move the given path to / and chroot into it. This is used
+    /// where pivot_root is not possible, e.g. when the current root is
+    /// a ramdisk, which can not be unmounted.
+    fn move_rootfs(&self, path: &Path) -> Result<()> {
+        unistd::chdir(path)?;
+        // Move the rootfs on top of the original root directory. Contrary to
+        // pivot_root, the original root directory stays mounted below the
+        // rootfs, but it is not reachable anymore after the chroot.
+        mount(
+            Some(path),
+            "/",
+            None::<&str>,
+            MsFlags::MS_MOVE,
+            None::<&str>,
+        )?;
+        unistd::chroot(".")?;
+        unistd::chdir("/")?;
+        Ok(())
+    }
+
     /// Set namespace for process
     fn set_ns(&self, rawfd: i32, nstype: CloneFlags) -> Result<()> {
         nix::sched::setns(rawfd, nstype)?;
@@ -96,6 +116,9 @@ impl Syscall for LinuxSyscall {
 
     /// set uid and gid for process
     fn set_id(&self, uid: Uid, gid: Gid) -> Result<()> {
+        // keep the permitted capabilities across the switch to a non-root
+        // user, so that the capabilities of the spec can still be applied
+        // and raised to ambient afterwards
         if let Err(e) = prctl::set_keep_capabilities(true) {
             bail!("set keep capabilities returned {}", e);
         };
@@ -165,7 +188,27 @@ impl Syscall for LinuxSyscall {
             rlim_cur: rlimit.soft(),
             rlim_max: rlimit.hard(),
         };
-        let res = unsafe { libc::setrlimit(rlimit.typ() as u32, rlim) };
+        // the numbers of the resources differ between architectures, e.g. on
+        // mips and sparc, so the enum cannot be passed as number directly
+        let resource = match rlimit.typ() {
+            LinuxRlimitType::RlimitCpu => libc::RLIMIT_CPU,
+            LinuxRlimitType::RlimitFsize => libc::RLIMIT_FSIZE,
+            LinuxRlimitType::RlimitData => libc::RLIMIT_DATA,
+            LinuxRlimitType::RlimitStack => libc::RLIMIT_STACK,
+            LinuxRlimitType::RlimitCore => libc::RLIMIT_CORE,
+            LinuxRlimitType::RlimitRss => libc::RLIMIT_RSS,
+            LinuxRlimitType::RlimitNproc => libc::RLIMIT_NPROC,
+            LinuxRlimitType::RlimitNofile => libc::RLIMIT_NOFILE,
+            LinuxRlimitType::RlimitMemlock => libc::RLIMIT_MEMLOCK,
+            LinuxRlimitType::RlimitAs => libc::RLIMIT_AS,
+            LinuxRlimitType::RlimitLocks => libc::RLIMIT_LOCKS,
+            LinuxRlimitType::RlimitSigpending => libc::RLIMIT_SIGPENDING,
+            LinuxRlimitType::RlimitMsgqueue => libc::RLIMIT_MSGQUEUE,
+            LinuxRlimitType::RlimitNice => libc::RLIMIT_NICE,
+            LinuxRlimitType::RlimitRtprio => libc::RLIMIT_RTPRIO,
+            LinuxRlimitType::RlimitRttime => libc::RLIMIT_RTTIME,
+        };
+        let res = unsafe { libc::setrlimit(resource, rlim) };
         if let Err(e) = Errno::result(res).map(drop) {
             bail!("Failed to set {:?}. {:?}", rlimit.typ(), e)
         }
@@ -7,4 +7,4 @@ pub mod linux;
 pub mod syscall;
 pub mod test;
 
-pub use syscall::Syscall;
+pub use syscall::{PivotRootError, Syscall};
@@ -1,7 +1,7 @@
 //! An interface trait so that rest of Youki can call
 //! necessary functions without having to worry about their
 //! implementation details
-use std::{any::Any, ffi::OsStr, path::Path, sync::Arc};
+use std::{any::Any, ffi::OsStr, fmt, path::Path, sync::Arc};
 
 use anyhow::Result;
 use caps::{errors::CapsError, CapSet, CapsHashSet};
@@ -14,11 +14,25 @@ use oci_spec::runtime::LinuxRlimit;
 
 use crate::syscall::{linux::LinuxSyscall, test::TestHelperSyscall};
 
+/// Failure of the pivot_root syscall itself, which pivot_rootfs reports
+/// separately from the failures of the steps around it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PivotRootError(pub nix::Error);
+
+impl std::error::Error for PivotRootError {}
+
+impl fmt::Display for PivotRootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pivot_root failed: {}", self.0)
+    }
+}
+
 /// This specifies various kernel/other functionalities required for
 /// container management
 pub trait Syscall {
     fn as_any(&self) -> &dyn Any;
     fn pivot_rootfs(&self, path: &Path) -> Result<()>;
+    fn move_rootfs(&self, path: &Path) -> Result<()>;
     fn chroot(&self, path: &Path) -> Result<()>;
     fn set_ns(&self, rawfd: i32, nstype: CloneFlags) -> Result<()>;
     fn set_id(&self, uid: Uid, gid: Gid) -> Result<()>;
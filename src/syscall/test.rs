@@ -1,4 +1,10 @@
-use std::{any::Any, cell::RefCell, ffi::OsStr, sync::Arc};
+use std::{
+    any::Any,
+    cell::RefCell,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use caps::{errors::CapsError, CapSet, CapsHashSet};
 use nix::sched::CloneFlags;
@@ -11,6 +17,9 @@ pub struct TestHelperSyscall {
     set_ns_args: RefCell<Vec<(i32, CloneFlags)>>,
     unshare_args: RefCell<Vec<CloneFlags>>,
     set_capability_args: RefCell<Vec<(CapSet, CapsHashSet)>>,
+    pivot_rootfs_error: RefCell<Option<fn() -> anyhow::Error>>,
+    pivot_rootfs_args: RefCell<Vec<PathBuf>>,
+    move_rootfs_args: RefCell<Vec<PathBuf>>,
 }
 
 impl Default for TestHelperSyscall {
@@ -19,6 +28,9 @@ impl Default for TestHelperSyscall {
             set_ns_args: RefCell::new(vec![]),
             unshare_args: RefCell::new(vec![]),
             set_capability_args: RefCell::new(vec![]),
+            pivot_rootfs_error: RefCell::new(None),
+            pivot_rootfs_args: RefCell::new(vec![]),
+            move_rootfs_args: RefCell::new(vec![]),
         }
     }
 }
@@ -28,8 +40,17 @@ impl Syscall for TestHelperSyscall {
         self
     }
 
-    fn pivot_rootfs(&self, _path: &std::path::Path) -> anyhow::Result<()> {
-        unimplemented!()
+    fn pivot_rootfs(&self, path: &Path) -> anyhow::Result<()> {
+        self.pivot_rootfs_args.borrow_mut().push(path.to_owned());
+        match *self.pivot_rootfs_error.borrow() {
+            Some(error) => Err(error()),
+            None => Ok(()),
+        }
+    }
+
+    fn move_rootfs(&self, path: &Path) -> anyhow::Result<()> {
+        self.move_rootfs_args.borrow_mut().push(path.to_owned());
+        Ok(())
     }
 
     fn set_ns(&self, rawfd: i32, nstype: CloneFlags) -> anyhow::Result<()> {
@@ -82,4 +103,17 @@ impl TestHelperSyscall {
     pub fn get_set_capability_args(&self) -> Vec<(CapSet, CapsHashSet)> {
         self.set_capability_args.borrow_mut().clone()
     }
+
+    /// Makes pivot_rootfs fail with the error returned by the function
+    pub fn set_pivot_rootfs_error(&self, error: fn() -> anyhow::Error) {
+        *self.pivot_rootfs_error.borrow_mut() = Some(error);
+    }
+
+    pub fn get_pivot_rootfs_args(&self) -> Vec<PathBuf> {
+        self.pivot_rootfs_args.borrow_mut().clone()
+    }
+
+    pub fn get_move_rootfs_args(&self) -> Vec<PathBuf> {
+        self.move_rootfs_args.borrow_mut().clone()
+    }
 }
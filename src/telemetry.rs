@@ -0,0 +1,390 @@
+//! The create and start path of containers is instrumented with tracing spans,
+//! to find out where the startup latency of containers goes. If youki is
+//! built with the `otel` feature and OTEL_EXPORTER_OTLP_ENDPOINT is set, the
+//! spans are exported to an OpenTelemetry collector with OTLP over http.
+//!
+//! Only the main process of youki exports spans. The intermediate and init
+//! processes are forked while the exporter is running and could not reach the
+//! collector anymore once they entered the network namespace of the container,
+//! so exporting is disabled in them. Their work is covered by the spans of the
+//! main process waiting for them.
+//!
+//! With --debug-timing, the durations of the spans are recorded and printed
+//! as a summary to stderr once the command finishes, so regressions of the
+//! startup latency can be measured without a collector. The intermediate and
+//! init processes record the durations of their spans, e.g. of the namespace
+//! and rootfs setup, in space reserved before the fork and send them to the
+//! main process together with their ready messages.
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicI32, Ordering};
+use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+// pid of the process which set up the telemetry. The forked processes of
+// youki are recognized by their pid, so nothing has to be done in them after
+// the fork, where locks held by other threads of the parent stay locked.
+static TELEMETRY_PID: AtomicI32 = AtomicI32::new(0);
+
+fn in_main_process() -> bool {
+    let pid = TELEMETRY_PID.load(Ordering::Relaxed);
+    pid == 0 || pid == unsafe { libc::getpid() }
+}
+
+/// Keeps the exporter of the spans running. Pending spans are flushed and
+/// the timing summary is printed when it is dropped.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    export: bool,
+    debug_timing: bool,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if self.export {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+        if self.debug_timing {
+            eprint!("{}", timing::summary());
+        }
+    }
+}
+
+/// Starts the exporter of the spans if it is configured and the recording
+/// of their durations if debug_timing is set
+pub fn init(debug_timing: bool) -> Result<Option<Telemetry>> {
+    TELEMETRY_PID.store(unsafe { libc::getpid() }, Ordering::Relaxed);
+    let timing_layer = if debug_timing {
+        timing::reserve();
+        Some(timing::TimingLayer)
+    } else {
+        None
+    };
+
+    #[cfg(feature = "otel")]
+    if let Some(export_layer) = otel::init()? {
+        tracing::subscriber::set_global_default(
+            Registry::default().with(export_layer).with(timing_layer),
+        )
+        .context("failed to set tracing subscriber")?;
+        return Ok(Some(Telemetry {
+            export: true,
+            debug_timing,
+        }));
+    }
+
+    if !debug_timing {
+        return Ok(None);
+    }
+
+    tracing::subscriber::set_global_default(Registry::default().with(timing_layer))
+        .context("failed to set tracing subscriber")?;
+    Ok(Some(Telemetry {
+        #[cfg(feature = "otel")]
+        export: false,
+        debug_timing,
+    }))
+}
+
+pub use timing::Timings;
+
+mod timing {
+    use once_cell::sync::Lazy;
+    use std::borrow::Cow;
+    use std::convert::TryInto;
+    use std::fmt::Write as _;
+    use std::io::{self, Read, Write};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+    use tracing::span;
+    use tracing::Subscriber;
+    use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+    // number of phases the forked processes can record without allocating
+    const RESERVED_PHASES: usize = 32;
+    // size of the encoded phases sent to the parent process
+    const TIMINGS_SIZE: usize = 1024;
+
+    static PHASES: Lazy<Mutex<Vec<Phase>>> = Lazy::new(|| Mutex::new(Vec::new()));
+    // the phases sent by forked processes start relative to it
+    static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+    struct Phase {
+        name: Cow<'static, str>,
+        depth: usize,
+        started: Instant,
+        duration: Duration,
+        // process which recorded the phase, the forked processes inherit
+        // the phases recorded by their parent
+        pid: i32,
+    }
+
+    pub(super) fn reserve() {
+        Lazy::force(&EPOCH);
+        if let Ok(mut phases) = PHASES.lock() {
+            phases.reserve(RESERVED_PHASES);
+        }
+    }
+
+    /// Phases recorded by a forked process, encoded into a fixed buffer so
+    /// that they can be sent over the channel to the parent without allocating
+    pub struct Timings {
+        buf: [u8; TIMINGS_SIZE],
+        len: usize,
+    }
+
+    impl Default for Timings {
+        fn default() -> Self {
+            Self {
+                buf: [0; TIMINGS_SIZE],
+                len: 0,
+            }
+        }
+    }
+
+    impl Timings {
+        /// Encodes the phases recorded by the calling process. Each phase is
+        /// the length and bytes of its name, its depth, start and duration.
+        /// Phases which do not fit into the buffer are left out.
+        pub fn collect() -> Self {
+            let mut timings = Self::default();
+            let pid = unsafe { libc::getpid() };
+            let phases = match PHASES.lock() {
+                Ok(phases) => phases,
+                Err(_) => return timings,
+            };
+            for phase in phases.iter().filter(|phase| phase.pid == pid) {
+                let name = phase.name.as_bytes();
+                let name = &name[..name.len().min(u8::MAX as usize)];
+                let size = 1 + name.len() + 1 + 8 + 8;
+                if timings.len + size > TIMINGS_SIZE {
+                    break;
+                }
+                let started = phase.started.saturating_duration_since(*EPOCH);
+                let buf = &mut timings.buf[timings.len..timings.len + size];
+                buf[0] = name.len() as u8;
+                buf[1..=name.len()].copy_from_slice(name);
+                let buf = &mut buf[1 + name.len()..];
+                buf[0] = phase.depth.min(u8::MAX as usize) as u8;
+                buf[1..9].copy_from_slice(&(started.as_nanos() as u64).to_be_bytes());
+                buf[9..17].copy_from_slice(&(phase.duration.as_nanos() as u64).to_be_bytes());
+                timings.len += size;
+            }
+            timings
+        }
+
+        /// Appends the phases a forked process received from its own child
+        pub fn append(&mut self, other: &Timings) {
+            if self.len + other.len <= TIMINGS_SIZE {
+                self.buf[self.len..self.len + other.len].copy_from_slice(other.as_bytes());
+                self.len += other.len;
+            }
+        }
+
+        /// Adds the phases to the summary of the main process
+        pub fn record(&self) {
+            let mut phases = match PHASES.lock() {
+                Ok(phases) => phases,
+                Err(_) => return,
+            };
+            let mut buf = self.as_bytes();
+            while let Some((&name_len, rest)) = buf.split_first() {
+                let name_len = name_len as usize;
+                if rest.len() < name_len + 17 {
+                    break;
+                }
+                let (name, rest) = rest.split_at(name_len);
+                let started = u64::from_be_bytes(rest[1..9].try_into().unwrap());
+                let duration = u64::from_be_bytes(rest[9..17].try_into().unwrap());
+                phases.push(Phase {
+                    name: Cow::Owned(String::from_utf8_lossy(name).into_owned()),
+                    depth: rest[0] as usize,
+                    started: *EPOCH + Duration::from_nanos(started),
+                    duration: Duration::from_nanos(duration),
+                    pid: 0,
+                });
+                buf = &rest[17..];
+            }
+        }
+
+        pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+            writer.write_all(&(self.len as u16).to_be_bytes())?;
+            writer.write_all(self.as_bytes())
+        }
+
+        pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            let len = u16::from_be_bytes(len) as usize;
+            if len > TIMINGS_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "timings exceed the buffer",
+                ));
+            }
+            let mut timings = Self::default();
+            reader.read_exact(&mut timings.buf[..len])?;
+            timings.len = len;
+            Ok(timings)
+        }
+
+        fn as_bytes(&self) -> &[u8] {
+            &self.buf[..self.len]
+        }
+    }
+
+    // stored in the extensions of a span when it is created
+    struct Started(Instant);
+
+    pub(super) struct TimingLayer;
+
+    impl<S> Layer<S> for TimingLayer
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, _: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(Started(Instant::now()));
+            }
+        }
+
+        fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+            let span = match ctx.span(&id) {
+                Some(span) => span,
+                None => return,
+            };
+            let started = match span.extensions().get::<Started>() {
+                Some(started) => started.0,
+                None => return,
+            };
+
+            if let Ok(mut phases) = PHASES.lock() {
+                // forked processes only use the space reserved before the fork
+                if !super::in_main_process() && phases.len() == phases.capacity() {
+                    return;
+                }
+                phases.push(Phase {
+                    name: Cow::Borrowed(span.name()),
+                    depth: span.scope().count() - 1,
+                    started,
+                    duration: started.elapsed(),
+                    pid: unsafe { libc::getpid() },
+                });
+            }
+        }
+    }
+
+    /// Formats the recorded phases in the order they started, nested phases
+    /// are indented below the phase they are part of
+    pub(super) fn summary() -> String {
+        let mut phases = match PHASES.lock() {
+            Ok(phases) => phases,
+            Err(_) => return String::new(),
+        };
+        phases.sort_by_key(|phase| phase.started);
+
+        let mut summary = String::from("phase durations:\n");
+        for phase in phases.iter() {
+            let name = format!("{}{}", "  ".repeat(phase.depth + 1), phase.name);
+            let _ = writeln!(
+                summary,
+                "{:<24} {:>10}",
+                name,
+                format!("{:.1?}", phase.duration)
+            );
+        }
+        summary
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        #[test]
+        fn test_summary() {
+            let subscriber = tracing_subscriber::Registry::default().with(TimingLayer);
+            tracing::subscriber::with_default(subscriber, || {
+                let _create = tracing::info_span!("test_create").entered();
+                tracing::info_span!("test_load_spec").in_scope(|| {});
+            });
+
+            let summary = summary();
+            let create = summary.find("\n  test_create ").unwrap();
+            let load_spec = summary.find("\n    test_load_spec ").unwrap();
+            assert!(create < load_spec);
+        }
+
+        #[test]
+        fn test_timings() -> io::Result<()> {
+            let subscriber = tracing_subscriber::Registry::default().with(TimingLayer);
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info_span!("test_rootfs_prep").in_scope(|| {});
+            });
+
+            let mut buf = Vec::new();
+            Timings::collect().write_to(&mut buf)?;
+            let timings = Timings::read_from(&mut buf.as_slice())?;
+            assert!(timings
+                .as_bytes()
+                .windows(16)
+                .any(|w| w == b"test_rootfs_prep"));
+
+            timings.record();
+            let count = summary().matches("  test_rootfs_prep ").count();
+            assert_eq!(count, 2);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use anyhow::{Context, Result};
+    use opentelemetry::sdk::{trace, Resource};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use tracing::Metadata;
+    use tracing_opentelemetry::OpenTelemetryLayer;
+    use tracing_subscriber::filter::{FilterFn, Filtered};
+    use tracing_subscriber::{Layer, Registry};
+
+    const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+    type ExportLayer = Filtered<OpenTelemetryLayer<Registry, trace::Tracer>, FilterFn, Registry>;
+
+    /// The returned layer only exports spans of the main process. The
+    /// exporter runs on a thread which does not exist in forked processes.
+    pub(super) fn init() -> Result<Option<ExportLayer>> {
+        let endpoint = match std::env::var(ENDPOINT_ENV) {
+            Ok(endpoint) => endpoint,
+            Err(_) => return Ok(None),
+        };
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(&endpoint),
+            )
+            .with_trace_config(
+                trace::config()
+                    .with_resource(Resource::new(vec![KeyValue::new("service.name", "youki")])),
+            )
+            .install_simple()
+            .with_context(|| format!("failed to install span exporter for {}", endpoint))?;
+
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(FilterFn::new(
+                main_process_only as fn(&Metadata<'_>) -> bool,
+            ));
+
+        log::debug!("export spans to {}", endpoint);
+        Ok(Some(layer))
+    }
+
+    fn main_process_only(_: &Metadata<'_>) -> bool {
+        super::in_main_process()
+    }
+}
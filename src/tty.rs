@@ -1,22 +1,45 @@
 //! tty (teletype) for user-system interaction
+//!
+//! The pty master of a container is handed to the terminal broker listening
+//! on the --console-socket with the same protocol as runc, which is what
+//! runc's recvtty, conmon and containerd expect: a single message on a unix
+//! stream socket, carrying the path the master was opened from ("/dev/ptmx")
+//! as payload and exactly one fd, the pty master, as SCM_RIGHTS. There is no
+//! further handshake, the runtime closes its end of the socket afterwards.
 
-use std::os::unix::fs::symlink;
-use std::os::unix::io::AsRawFd;
+use std::fs::{DirBuilder, File};
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::{symlink, DirBuilderExt};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::UnixListener;
 use std::os::unix::prelude::RawFd;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
 
 use anyhow::Context;
 use anyhow::{bail, Result};
 use nix::errno::Errno;
+use nix::sys::signal::{SigSet, Signal};
 use nix::sys::socket;
+use nix::sys::stat::{self, Mode};
+use nix::sys::termios::{self, SetArg, Termios};
 use nix::sys::uio;
 use nix::unistd::dup2;
-use nix::unistd::{close, setsid};
+use nix::unistd::{close, dup, fchown, setsid, Gid, Uid};
+
+use crate::utils::TempDir;
 
 const STDIN: i32 = 0;
 const STDOUT: i32 = 1;
 const STDERR: i32 = 2;
 
+/// Name the pty master is sent with, runc sends the path it was opened from
+const PTY_MASTER_NAME: &str = "/dev/ptmx";
+/// Maximum length of the name, same as MaxNameLen of runc
+const MAX_NAME_LEN: usize = 4096;
+/// Group of terminals by convention, same as the gid devpts is mounted with
+pub const TTY_GID: u32 = 5;
+
 // TODO: Handling when there isn't console-socket.
 pub fn setup_console_socket(
     container_dir: &Path,
@@ -52,26 +75,73 @@ pub fn setup_console(console_fd: &RawFd) -> Result<()> {
     // ref. https://github.com/containerd/containerd/blob/261c107ffc4ff681bc73988f64e3f60c32233b37/vendor/github.com/containerd/go-runc/console.go#L139-L154
     let openpty_result =
         nix::pty::openpty(None, None).context("could not create pseudo terminal")?;
-    let pty_name: &[u8] = b"/dev/ptmx";
-    let iov = [uio::IoVec::from_slice(pty_name)];
-    let fds = [openpty_result.master];
+    // Same as runc, output is not translated from \n to \r\n, brokers
+    // expect the output of the container as is
+    clear_onlcr(openpty_result.slave)?;
+    send_pty_master(console_fd.as_raw_fd(), openpty_result.master)?;
+    // the container must not hold the master, otherwise the broker is not
+    // notified once the container closes the terminal
+    close(openpty_result.master).context("could not close pty master")?;
+
+    setsid()?;
+    if unsafe { libc::ioctl(openpty_result.slave, libc::TIOCSCTTY) } < 0 {
+        log::warn!("could not TIOCSCTTY");
+    };
+    let slave = openpty_result.slave;
+    connect_stdio(&slave, &slave, &slave).context("could not dup tty to stderr")?;
+    if slave > STDERR {
+        close(slave).context("could not close pty slave")?;
+    }
+    close(console_fd.as_raw_fd()).context("could not close console socket")?;
+    Ok(())
+}
+
+/// Hands the pty slave, which is the stdio of the container process after
+/// setup_console, over to the container user. Like a login terminal it is
+/// owned by the user and the tty group, readable and writable by the user and
+/// writable by the group. Without this the process cannot use terminal ioctls
+/// once it runs as a non-root user.
+pub fn chown_pty_slave(uid: Uid, gid: Option<Gid>) -> Result<()> {
+    match fchown(STDIN, Some(uid), gid) {
+        Ok(()) => {}
+        // the ids may not be mapped in the user namespace of the container,
+        // fall back to changing only the owner
+        Err(Errno::EINVAL) if gid.is_some() => {
+            fchown(STDIN, Some(uid), None).context("failed to change owner of pty slave")?
+        }
+        // rootless containers cannot chown the pty of the user running youki
+        Err(Errno::EPERM) => {
+            log::warn!("could not change owner of pty slave to {}", uid);
+            return Ok(());
+        }
+        Err(e) => bail!("failed to change owner of pty slave: {}", e),
+    }
+
+    stat::fchmod(STDIN, Mode::from_bits_truncate(0o620))
+        .context("failed to change mode of pty slave")?;
+    Ok(())
+}
+
+/// Sends the pty master over the console socket
+fn send_pty_master(console_socket: RawFd, master: RawFd) -> Result<()> {
+    let iov = [uio::IoVec::from_slice(PTY_MASTER_NAME.as_bytes())];
+    let fds = [master];
     let cmsg = socket::ControlMessage::ScmRights(&fds);
     socket::sendmsg(
-        console_fd.as_raw_fd(),
+        console_socket,
         &iov,
         &[cmsg],
         socket::MsgFlags::empty(),
         None,
     )
     .context("failed to send pty master")?;
+    Ok(())
+}
 
-    setsid()?;
-    if unsafe { libc::ioctl(openpty_result.slave, libc::TIOCSCTTY) } < 0 {
-        log::warn!("could not TIOCSCTTY");
-    };
-    let slave = openpty_result.slave;
-    connect_stdio(&slave, &slave, &slave).context("could not dup tty to stderr")?;
-    close(console_fd.as_raw_fd()).context("could not close console socket")?;
+fn clear_onlcr(fd: RawFd) -> Result<()> {
+    let mut attrs = termios::tcgetattr(fd).context("failed to get terminal attributes")?;
+    attrs.output_flags.remove(termios::OutputFlags::ONLCR);
+    termios::tcsetattr(fd, SetArg::TCSANOW, &attrs).context("failed to set terminal attributes")?;
     Ok(())
 }
 
@@ -84,13 +154,202 @@ fn connect_stdio(stdin: &RawFd, stdout: &RawFd, stderr: &RawFd) -> Result<()> {
     Ok(())
 }
 
+/// Receives the pty master which the container init process sends over the
+/// console socket
+pub fn receive_pty_master(console_socket: RawFd) -> Result<RawFd> {
+    receive_console(console_socket).map(|(_, fd)| fd)
+}
+
+/// Receives the name and the fd of a pty master sent with the protocol of
+/// runc, like recvtty does
+pub fn receive_console(console_socket: RawFd) -> Result<(String, RawFd)> {
+    let mut buf = [0u8; MAX_NAME_LEN];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+    let (len, fds) = {
+        let iov = [uio::IoVec::from_mut_slice(&mut buf)];
+        let msg = socket::recvmsg(
+            console_socket,
+            &iov,
+            Some(&mut cmsg_buf),
+            socket::MsgFlags::empty(),
+        )
+        .context("failed to receive pty master")?;
+
+        let fds: Vec<RawFd> = msg
+            .cmsgs()
+            .filter_map(|cmsg| match cmsg {
+                socket::ControlMessageOwned::ScmRights(fds) => Some(fds),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        (msg.bytes, fds)
+    };
+
+    if fds.len() != 1 {
+        for fd in &fds {
+            let _ = close(*fd);
+        }
+        bail!(
+            "expected exactly one fd on console socket, received {}",
+            fds.len()
+        );
+    }
+    let name = String::from_utf8_lossy(&buf[..len]).into_owned();
+
+    Ok((name, fds[0]))
+}
+
+/// Applies the window size of the terminal `from` to the pty master
+pub fn resize_pty(from: RawFd, master: RawFd) -> Result<()> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(from, libc::TIOCGWINSZ, &mut size) } < 0 {
+        bail!("failed to get window size: {}", Errno::last());
+    }
+    if unsafe { libc::ioctl(master, libc::TIOCSWINSZ, &size) } < 0 {
+        bail!("failed to set window size: {}", Errno::last());
+    }
+
+    Ok(())
+}
+
+/// Connects the terminal of youki to the pty of the container, when running
+/// a container with a terminal in the foreground. The pty master is received
+/// over a console socket owned by youki.
+pub struct ForegroundConsole {
+    dir: TempDir,
+    listener: UnixListener,
+    termios: Option<Termios>,
+    output: Option<JoinHandle<()>>,
+}
+
+impl ForegroundConsole {
+    pub fn new(container_id: &str) -> Result<Self> {
+        let dir = create_socket_dir(container_id)?;
+        let listener = UnixListener::bind(dir.join("console.sock"))
+            .context("failed to bind console socket")?;
+
+        Ok(Self {
+            dir,
+            listener,
+            termios: None,
+            output: None,
+        })
+    }
+
+    /// Path of the console socket, which has to be passed to the container
+    pub fn socket_path(&self) -> PathBuf {
+        self.dir.join("console.sock")
+    }
+
+    /// Receives the pty master from the container and starts to forward
+    /// stdio and window size changes of youki's terminal to it
+    pub fn attach(&mut self) -> Result<()> {
+        let (stream, _) = self
+            .listener
+            .accept()
+            .context("failed to accept console socket connection")?;
+        let master = receive_pty_master(stream.as_raw_fd())?;
+
+        // The container terminal takes care of line editing and echoing, so
+        // youki's terminal has to pass through everything as is
+        if let Ok(original) = termios::tcgetattr(STDIN) {
+            let mut raw = original.clone();
+            termios::cfmakeraw(&mut raw);
+            termios::tcsetattr(STDIN, SetArg::TCSANOW, &raw)
+                .context("failed to set terminal into raw mode")?;
+            self.termios = Some(original);
+        }
+
+        if let Err(e) = resize_pty(STDIN, master) {
+            log::warn!("failed to set initial window size: {}", e);
+        }
+        forward_window_resize(dup(master)?)?;
+
+        let mut master_in = unsafe { File::from_raw_fd(dup(master)?) };
+        thread::spawn(move || {
+            let _ = io::copy(&mut io::stdin(), &mut master_in);
+        });
+        let mut master_out = unsafe { File::from_raw_fd(master) };
+        self.output = Some(thread::spawn(move || {
+            // reading from the pty master fails with EIO, once all processes
+            // in the container have closed the pty slave
+            let _ = io::copy(&mut master_out, &mut io::stdout());
+        }));
+
+        Ok(())
+    }
+
+    /// Waits until all output of the container has been forwarded and
+    /// restores youki's terminal
+    pub fn detach(&mut self) -> Result<()> {
+        if let Some(output) = self.output.take() {
+            let _ = output.join();
+        }
+
+        if let Some(original) = self.termios.take() {
+            termios::tcsetattr(STDIN, SetArg::TCSANOW, &original)
+                .context("failed to restore terminal")?;
+        }
+        self.dir.remove();
+
+        Ok(())
+    }
+}
+
+// The directory of the console socket gets a random name and is created
+// exclusively, so that concurrent runs of containers with the same id do not
+// collide and other users cannot prepare it. Only the owner can access it.
+fn create_socket_dir(container_id: &str) -> Result<TempDir> {
+    loop {
+        let path =
+            std::env::temp_dir().join(format!("youki-{}-{:08x}", container_id, fastrand::u32(..)));
+        match DirBuilder::new().mode(0o700).create(&path) {
+            Ok(()) => return TempDir::new(path),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to create {}", path.display()))
+            }
+        }
+    }
+}
+
+impl Drop for ForegroundConsole {
+    // restores youki's terminal if the container fails before it is detached
+    fn drop(&mut self) {
+        if let Some(original) = self.termios.take() {
+            let _ = termios::tcsetattr(STDIN, SetArg::TCSANOW, &original);
+        }
+    }
+}
+
+/// Forwards window size changes of youki's terminal (SIGWINCH) to the pty master
+fn forward_window_resize(master: RawFd) -> Result<()> {
+    let mut sigset = SigSet::empty();
+    sigset.add(Signal::SIGWINCH);
+    // SIGWINCH has to be blocked so that it can be received with sigwait. The
+    // mask is inherited by threads spawned from here on.
+    sigset.thread_block().context("failed to block SIGWINCH")?;
+
+    thread::spawn(move || {
+        while sigset.wait().is_ok() {
+            if let Err(e) = resize_pty(STDIN, master) {
+                log::warn!("failed to forward window size: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use std::env;
     use std::fs::{self, File};
-    use std::os::unix::net::UnixListener;
+    use std::io::Write;
+    use std::os::unix::net::{UnixListener, UnixStream};
     use std::path::PathBuf;
 
     use serial_test::serial;
@@ -157,4 +416,31 @@ mod tests {
         let status = setup_console(&fd.unwrap());
         assert!(status.is_ok());
     }
+
+    #[test]
+    fn test_send_and_receive_pty_master() -> Result<()> {
+        let (sender, receiver) = UnixStream::pair()?;
+        let pty = nix::pty::openpty(None, None)?;
+        clear_onlcr(pty.slave)?;
+        send_pty_master(sender.as_raw_fd(), pty.master)?;
+
+        let (name, master) = receive_console(receiver.as_raw_fd())?;
+        assert_eq!(name, PTY_MASTER_NAME);
+        assert_ne!(master, pty.master);
+        let attrs = termios::tcgetattr(master)?;
+        assert!(!attrs.output_flags.contains(termios::OutputFlags::ONLCR));
+
+        for fd in [master, pty.master, pty.slave] {
+            close(fd)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_console_without_fd() -> Result<()> {
+        let (mut sender, receiver) = UnixStream::pair()?;
+        sender.write_all(PTY_MASTER_NAME.as_bytes())?;
+        assert!(receive_console(receiver.as_raw_fd()).is_err());
+        Ok(())
+    }
 }
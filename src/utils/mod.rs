@@ -0,0 +1,524 @@
+//! Utility functionality
+
+pub mod secure_fs;
+
+use anyhow::Context;
+use anyhow::{bail, Result};
+use nix::sys::stat::Mode;
+use nix::sys::statfs;
+use nix::sys::wait::WaitStatus;
+use nix::unistd;
+use oci_spec::runtime::Mount;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::Write;
+use std::ops::Deref;
+use std::os::linux::fs::MetadataExt;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::prelude::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub trait PathBufExt {
+    fn as_in_container(&self) -> Result<PathBuf>;
+    fn join_absolute_path(&self, p: &Path) -> Result<PathBuf>;
+}
+
+impl PathBufExt for PathBuf {
+    fn as_in_container(&self) -> Result<PathBuf> {
+        if self.is_relative() {
+            bail!("Relative path cannot be converted to the path in the container.")
+        } else {
+            let path_string = self.to_string_lossy().into_owned();
+            Ok(PathBuf::from(path_string[1..].to_string()))
+        }
+    }
+
+    fn join_absolute_path(&self, p: &Path) -> Result<PathBuf> {
+        if !p.is_absolute() && !p.as_os_str().is_empty() {
+            bail!(
+                "cannot join {:?} because it is not the absolute path.",
+                p.display()
+            )
+        }
+        Ok(PathBuf::from(format!("{}{}", self.display(), p.display())))
+    }
+}
+
+pub fn parse_env(envs: &[String]) -> HashMap<String, String> {
+    envs.iter()
+        .filter_map(|e| {
+            let mut split = e.split('=');
+
+            split.next().map(|key| {
+                let value = split.collect::<Vec<&str>>().join("=");
+                (key.into(), value)
+            })
+        })
+        .collect()
+}
+
+/// Replaces the environment of the process. libc is used directly, as the
+/// lock std::env takes could be held forever after a fork by a thread of the
+/// parent which no longer exists in the child.
+pub fn reset_env(envs: &HashMap<String, String>) -> Result<()> {
+    if unsafe { libc::clearenv() } != 0 {
+        bail!("failed to clear the environment");
+    }
+
+    for (key, value) in envs {
+        let c_key = CString::new(key.as_str())?;
+        let c_value = CString::new(value.as_str())?;
+        if unsafe { libc::setenv(c_key.as_ptr(), c_value.as_ptr(), 1) } != 0 {
+            bail!(
+                "failed to set environment variable {}: {}",
+                key,
+                nix::errno::Errno::last()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn do_exec(path: impl AsRef<Path>, args: &[String]) -> Result<()> {
+    let p = CString::new(path.as_ref().to_string_lossy().to_string())?;
+    let a: Vec<CString> = args
+        .iter()
+        .map(|s| CString::new(s.to_string()).unwrap_or_default())
+        .collect();
+    unistd::execvp(&p, &a)?;
+    Ok(())
+}
+
+/// Converts the wait status of a terminated process into an exit code. Following
+/// the shell convention, a process killed by a signal is reported as
+/// 128 + signal number. Returns None if the process has not terminated.
+pub fn exit_code(status: WaitStatus) -> Option<i32> {
+    match status {
+        WaitStatus::Exited(_, code) => Some(code),
+        WaitStatus::Signaled(_, signal, _) => Some(128 + signal as i32),
+        _ => None,
+    }
+}
+
+/// Parses a duration consisting of a number and an optional unit (ms, s, m, h),
+/// e.g. 500ms or 5s. A number without unit is interpreted as seconds.
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let position = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or_else(|| duration.len());
+    let (value, unit) = duration.split_at(position);
+    let value: u64 = value
+        .parse()
+        .with_context(|| format!("invalid duration {}", duration))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        "h" => Ok(Duration::from_secs(value * 60 * 60)),
+        _ => bail!("invalid unit {} in duration {}", unit, duration),
+    }
+}
+
+/// Parses a size in bytes consisting of a number and an optional unit (k, m,
+/// g, t, each optionally followed by b) like docker and runc, e.g. 512m.
+/// The units are powers of 1024, -1 is passed through and means unlimited.
+pub fn parse_size(size: &str) -> Result<i64> {
+    if size == "-1" {
+        return Ok(-1);
+    }
+
+    let position = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or_else(|| size.len());
+    let (value, unit) = size.split_at(position);
+    let value: i64 = value
+        .parse()
+        .with_context(|| format!("invalid size {}", size))?;
+
+    let unit_lower = unit.to_ascii_lowercase();
+    let multiplier: i64 = match unit_lower.strip_suffix('b').unwrap_or(&unit_lower) {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => bail!("invalid unit {} in size {}", unit, size),
+    };
+    value
+        .checked_mul(multiplier)
+        .with_context(|| format!("size {} is too large", size))
+}
+
+/// If None, it will generate a default path for cgroups.
+pub fn get_cgroup_path(cgroups_path: &Option<PathBuf>, container_id: &str) -> PathBuf {
+    match cgroups_path {
+        Some(cpath) => cpath.clone(),
+        None => PathBuf::from(container_id),
+    }
+}
+
+/// A mount is a bind mount if its type is bind, or if one of its options is
+/// bind or rbind, which is how mounts without a type are bind mounted
+pub fn is_bind_mount(mount: &Mount) -> bool {
+    *mount.typ() == Some("bind".to_string())
+        || mount
+            .options()
+            .iter()
+            .flatten()
+            .any(|o| o == "bind" || o == "rbind")
+}
+
+pub fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
+    let path = path.as_ref();
+    fs::write(path, contents).with_context(|| format!("failed to write to {:?}", path))?;
+    Ok(())
+}
+
+/// Writes the file atomically: the contents are written to a temporary file
+/// next to it, which then replaces the file. Readers see either the old or
+/// the new contents, and a crash never leaves a truncated file behind. With
+/// sync the contents are flushed to disk before the rename and the rename
+/// is flushed by syncing the directory, so that they survive a power loss.
+pub fn write_file_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(
+    path: P,
+    contents: C,
+    sync: bool,
+) -> Result<()> {
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("invalid file path {:?}", path))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    // the random suffix keeps concurrent writers of the same process apart
+    tmp_name.push(format!(".{}.{:08x}", std::process::id(), fastrand::u32(..)));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+            .with_context(|| format!("failed to create {:?}", tmp_path))?;
+        file.write_all(contents.as_ref())
+            .with_context(|| format!("failed to write to {:?}", tmp_path))?;
+        if sync {
+            file.sync_all()
+                .with_context(|| format!("failed to sync {:?}", tmp_path))?;
+        }
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to rename {:?} to {:?}", tmp_path, path))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
+
+    if sync {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .with_context(|| format!("failed to sync {:?}", dir))?;
+    }
+    Ok(())
+}
+
+pub fn create_dir_all<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+    fs::create_dir_all(path).with_context(|| format!("failed to create directory {:?}", path))
+}
+
+pub fn open<P: AsRef<Path>>(path: P) -> Result<File> {
+    let path = path.as_ref();
+    File::open(path).with_context(|| format!("failed to open {:?}", path))
+}
+
+/// Creates the specified directory and all parent directories with the specified mode. Ensures
+/// that the directory has been created with the correct mode and that the owner of the directory
+/// is the owner that has been specified
+/// # Example
+/// ``` no_run
+/// use youki::utils::create_dir_all_with_mode;
+/// use nix::sys::stat::Mode;
+/// use std::path::Path;
+///
+/// let path = Path::new("/tmp/youki");
+/// create_dir_all_with_mode(&path, 1000, Mode::S_IRWXU).unwrap();
+/// assert!(path.exists())
+/// ```
+pub fn create_dir_all_with_mode<P: AsRef<Path>>(path: P, owner: u32, mode: Mode) -> Result<()> {
+    let path = path.as_ref();
+    if !path.exists() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(mode.bits())
+            .create(path)
+            .with_context(|| format!("failed to create directory {}", path.display()))?;
+    }
+
+    let metadata = path
+        .metadata()
+        .with_context(|| format!("failed to get metadata for {}", path.display()))?;
+
+    if metadata.is_dir()
+        && metadata.st_uid() == owner
+        && metadata.st_mode() & mode.bits() == mode.bits()
+    {
+        Ok(())
+    } else {
+        bail!(
+            "metadata for {} does not possess the expected attributes",
+            path.display()
+        );
+    }
+}
+
+// Make sure a given path is on procfs. This is to avoid the security risk that
+// /proc path is mounted over. Ref: CVE-2019-16884
+pub fn ensure_procfs(path: &Path) -> Result<()> {
+    let procfs_fd = fs::File::open(path)?;
+    let fstat_info = statfs::fstatfs(&procfs_fd.as_raw_fd())?;
+
+    if fstat_info.filesystem_type() != statfs::PROC_SUPER_MAGIC {
+        bail!(format!("{:?} is not on the procfs", path));
+    }
+
+    Ok(())
+}
+
+pub struct TempDir {
+    path: Option<PathBuf>,
+}
+
+impl TempDir {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let p = path.into();
+        std::fs::create_dir_all(&p)
+            .with_context(|| format!("failed to create directory {}", p.display()))?;
+        Ok(Self { path: Some(p) })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path
+            .as_ref()
+            .expect("temp dir has already been removed")
+    }
+
+    pub fn remove(&mut self) {
+        if let Some(p) = &self.path {
+            let _ = fs::remove_dir_all(p);
+            self.path = None;
+        }
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        self.path()
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Self::Target {
+        self.path()
+    }
+}
+
+pub fn create_temp_dir(test_name: &str) -> Result<TempDir> {
+    let dir = TempDir::new(std::env::temp_dir().join(test_name))?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use anyhow::Context;
+    use anyhow::{bail, Result};
+    use ipc_channel::ipc;
+    use nix::sys::wait;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TestResult {
+        success: bool,
+        message: String,
+    }
+
+    pub fn test_in_child_process<F: FnOnce() -> Result<()>>(cb: F) -> Result<()> {
+        let (sender, receiver) = ipc::channel::<TestResult>()?;
+        match unsafe { nix::unistd::fork()? } {
+            nix::unistd::ForkResult::Parent { child } => {
+                let res = receiver.recv().unwrap();
+                wait::waitpid(child, None)?;
+
+                if !res.success {
+                    bail!("child process failed: {}", res.message);
+                }
+            }
+            nix::unistd::ForkResult::Child => {
+                let test_result = match cb() {
+                    Ok(_) => TestResult {
+                        success: true,
+                        message: String::new(),
+                    },
+                    Err(err) => TestResult {
+                        success: false,
+                        message: err.to_string(),
+                    },
+                };
+                sender
+                    .send(test_result)
+                    .context("failed to send from the child process")?;
+                std::process::exit(0);
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_absolute_path() {
+        assert_eq!(
+            PathBuf::from("sample/a/")
+                .join_absolute_path(&PathBuf::from("/b"))
+                .unwrap(),
+            PathBuf::from("sample/a/b")
+        );
+    }
+
+    #[test]
+    fn test_join_absolute_path_error() {
+        assert!(PathBuf::from("sample/a/")
+            .join_absolute_path(&PathBuf::from("b/c"))
+            .is_err(),);
+    }
+
+    #[test]
+    fn test_get_cgroup_path() {
+        let cid = "sample_container_id";
+        assert_eq!(
+            get_cgroup_path(&None, cid),
+            PathBuf::from("sample_container_id")
+        );
+        assert_eq!(
+            get_cgroup_path(&Some(PathBuf::from("/youki")), cid),
+            PathBuf::from("/youki")
+        );
+    }
+
+    #[test]
+    fn test_is_bind_mount() -> Result<()> {
+        use oci_spec::runtime::MountBuilder;
+
+        let bind = MountBuilder::default()
+            .destination("/data")
+            .typ("bind")
+            .build()?;
+        assert!(is_bind_mount(&bind));
+        let rbind = MountBuilder::default()
+            .destination("/data")
+            .options(vec!["rbind".to_string(), "ro".to_string()])
+            .build()?;
+        assert!(is_bind_mount(&rbind));
+        let tmpfs = MountBuilder::default()
+            .destination("/tmp")
+            .typ("tmpfs")
+            .options(vec!["nosuid".to_string()])
+            .build()?;
+        assert!(!is_bind_mount(&tmpfs));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exit_code() {
+        use nix::sys::signal::Signal;
+        use nix::unistd::Pid;
+
+        let pid = Pid::from_raw(1);
+        assert_eq!(exit_code(WaitStatus::Exited(pid, 0)), Some(0));
+        assert_eq!(exit_code(WaitStatus::Exited(pid, 3)), Some(3));
+        assert_eq!(
+            exit_code(WaitStatus::Signaled(pid, Signal::SIGKILL, false)),
+            Some(137)
+        );
+        assert_eq!(exit_code(WaitStatus::Stopped(pid, Signal::SIGSTOP)), None);
+    }
+
+    #[test]
+    fn test_write_file_atomic() -> Result<()> {
+        let tmp = create_temp_dir("test_write_file_atomic")?;
+        let path = tmp.join("state.json");
+        write_file_atomic(&path, "old", false)?;
+        write_file_atomic(&path, "new", true)?;
+
+        assert_eq!(fs::read_to_string(&path)?, "new");
+        // the temporary file has been renamed
+        assert_eq!(fs::read_dir(tmp.path())?.count(), 1);
+        assert!(write_file_atomic(tmp.join("missing/state.json"), "new", false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_duration() -> Result<()> {
+        assert_eq!(parse_duration("5")?, Duration::from_secs(5));
+        assert_eq!(parse_duration("5s")?, Duration::from_secs(5));
+        assert_eq!(parse_duration("500ms")?, Duration::from_millis(500));
+        assert_eq!(parse_duration("2m")?, Duration::from_secs(120));
+        assert_eq!(parse_duration("1h")?, Duration::from_secs(3600));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("-5s").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_size() -> Result<()> {
+        assert_eq!(parse_size("1024")?, 1024);
+        assert_eq!(parse_size("1024b")?, 1024);
+        assert_eq!(parse_size("4k")?, 4096);
+        assert_eq!(parse_size("512m")?, 512 * 1024 * 1024);
+        assert_eq!(parse_size("2GB")?, 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("-1")?, -1);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("m").is_err());
+        assert!(parse_size("5mm").is_err());
+        assert!(parse_size("99999999999t").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_env() -> Result<()> {
+        let key = "key".to_string();
+        let value = "value".to_string();
+        let env_input = vec![format!("{}={}", key, value)];
+        let env_output = parse_env(&env_input);
+        assert_eq!(
+            env_output.len(),
+            1,
+            "There should be exactly one entry inside"
+        );
+        assert_eq!(env_output.get_key_value(&key), Some((&key, &value)));
+
+        Ok(())
+    }
+}
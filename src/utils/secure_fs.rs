@@ -0,0 +1,355 @@
+//! File operations relative to a directory which is held open by a file
+//! descriptor. Symlinks are not followed in any component of the paths
+//! relative to the directory, so the operations cannot be redirected out of
+//! it, e.g. by a container which swaps a directory of its rootfs for a
+//! symlink to the host while youki prepares the rootfs.
+use std::ffi::{CString, OsStr};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::{self, OFlag};
+use nix::sys::stat::{self, FchmodatFlags, Mode, SFlag};
+use nix::sys::statfs;
+use nix::unistd::{self, FchownatFlags, Gid, Uid, UnlinkatFlags};
+
+/// A directory held open by a file descriptor, which paths are resolved in
+#[derive(Debug)]
+pub struct Dir {
+    fd: RawFd,
+    path: PathBuf,
+}
+
+impl Dir {
+    /// Opens the directory. The path of the directory itself is trusted,
+    /// symlinks in it are followed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let fd = fcntl::open(
+            path,
+            OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )
+        .with_context(|| format!("failed to open directory {}", path.display()))?;
+
+        Ok(Self {
+            fd,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Opens a directory on procfs, e.g. /proc/self. Fails if the directory
+    /// is not on procfs, as a container could have mounted over /proc.
+    /// Ref: CVE-2019-16884
+    pub fn open_procfs<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let dir = Self::open(path)?;
+        let stat = statfs::fstatfs(&dir.fd)
+            .with_context(|| format!("failed to stat filesystem of {}", dir.path.display()))?;
+        if stat.filesystem_type() != statfs::PROC_SUPER_MAGIC {
+            bail!("{} is not on procfs", dir.path.display());
+        }
+
+        Ok(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens a directory below this one
+    pub fn open_dir<P: AsRef<Path>>(&self, path: P) -> Result<Dir> {
+        let path = path.as_ref();
+        let fd = self
+            .at(path, |dirfd, name| open_dir_at(dirfd, name))
+            .with_context(|| format!("failed to open directory {}", self.display(path)))?;
+
+        Ok(Dir {
+            fd,
+            path: self.full_path(path),
+        })
+    }
+
+    /// Creates a directory below this one together with its missing parents
+    /// and opens it. Existing components must be directories, not symlinks.
+    pub fn create_dir_all<P: AsRef<Path>>(&self, path: P, mode: Mode) -> Result<Dir> {
+        let path = path.as_ref();
+        let mut dir: Option<Dir> = None;
+        for component in path.components() {
+            let name = match component {
+                Component::RootDir | Component::CurDir => continue,
+                Component::Normal(name) => name,
+                Component::ParentDir | Component::Prefix(_) => {
+                    bail!("{} leaves {}", path.display(), self.path.display())
+                }
+            };
+            let dirfd = dir.as_ref().map_or(self.fd, |dir| dir.fd);
+            match stat::mkdirat(dirfd, name, mode) {
+                Ok(()) | Err(Errno::EEXIST) => {}
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("failed to create {}", self.display(path)))
+                }
+            }
+            let fd = open_dir_at(dirfd, name)
+                .with_context(|| format!("failed to open directory {}", self.display(path)))?;
+            dir = Some(Dir {
+                fd,
+                path: PathBuf::new(),
+            });
+        }
+
+        let mut dir =
+            dir.with_context(|| format!("{} does not name a directory", path.display()))?;
+        dir.path = self.full_path(path);
+        Ok(dir)
+    }
+
+    /// Opens a file below this directory. The file itself must not be a
+    /// symlink either.
+    pub fn open_file<P: AsRef<Path>>(&self, path: P, flags: OFlag, mode: Mode) -> Result<File> {
+        let path = path.as_ref();
+        let fd = self
+            .at(path, |dirfd, name| {
+                fcntl::openat(
+                    dirfd,
+                    name,
+                    flags | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
+                    mode,
+                )
+            })
+            .with_context(|| format!("failed to open {}", self.display(path)))?;
+
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Replaces the contents of an existing file, e.g. of a file on procfs
+    pub fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> Result<()> {
+        let path = path.as_ref();
+        let mut file = self.open_file(path, OFlag::O_WRONLY | OFlag::O_TRUNC, Mode::empty())?;
+        file.write_all(contents.as_ref())
+            .with_context(|| format!("failed to write to {}", self.display(path)))
+    }
+
+    /// Changes the owner of the file, without following it if it is a symlink
+    pub fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<Uid>, gid: Option<Gid>) -> Result<()> {
+        let path = path.as_ref();
+        self.at(path, |dirfd, name| {
+            unistd::fchownat(Some(dirfd), name, uid, gid, FchownatFlags::NoFollowSymlink)
+        })
+        .with_context(|| format!("failed to chown {}", self.display(path)))
+    }
+
+    /// Changes the mode of the file. Fails if it is a symlink, as the mode of
+    /// symlinks cannot be changed on Linux.
+    pub fn chmod<P: AsRef<Path>>(&self, path: P, mode: Mode) -> Result<()> {
+        let path = path.as_ref();
+        let file = self.open_file(path, OFlag::O_PATH, Mode::empty())?;
+        let file_stat = stat::fstat(file.as_raw_fd())
+            .with_context(|| format!("failed to stat {}", self.display(path)))?;
+        if SFlag::from_bits_truncate(file_stat.st_mode) & SFlag::S_IFMT == SFlag::S_IFLNK {
+            bail!("cannot chmod symlink {}", self.display(path));
+        }
+
+        // an O_PATH descriptor cannot be used with fchmod, but the magic link
+        // of procfs refers to exactly the opened file
+        stat::fchmodat(
+            None,
+            &PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd())),
+            mode,
+            FchmodatFlags::FollowSymlink,
+        )
+        .with_context(|| format!("failed to chmod {}", self.display(path)))
+    }
+
+    /// Creates a device node, fifo or socket
+    pub fn mknod<P: AsRef<Path>>(&self, path: P, kind: SFlag, perm: Mode, dev: u64) -> Result<()> {
+        let path = path.as_ref();
+        self.at(path, |dirfd, name| {
+            let name = CString::new(name.as_bytes()).map_err(|_| Errno::EINVAL)?;
+            let res =
+                unsafe { libc::mknodat(dirfd, name.as_ptr(), kind.bits() | perm.bits(), dev) };
+            Errno::result(res).map(drop)
+        })
+        .with_context(|| format!("failed to create node {}", self.display(path)))
+    }
+
+    /// Creates a symlink at path, which points to target
+    pub fn symlink<T: AsRef<Path>, P: AsRef<Path>>(&self, target: T, path: P) -> Result<()> {
+        let (target, path) = (target.as_ref(), path.as_ref());
+        self.at(path, |dirfd, name| {
+            unistd::symlinkat(target, Some(dirfd), name)
+        })
+        .with_context(|| {
+            format!(
+                "failed to symlink {} to {}",
+                self.display(path),
+                target.display()
+            )
+        })
+    }
+
+    /// Removes the file, a missing file is not an error
+    pub fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        self.at(path, |dirfd, name| {
+            match unistd::unlinkat(Some(dirfd), name, UnlinkatFlags::NoRemoveDir) {
+                Err(Errno::ENOENT) => Ok(()),
+                res => res,
+            }
+        })
+        .with_context(|| format!("failed to remove {}", self.display(path)))
+    }
+
+    // Opens the parent of the path one component at a time, so that none of
+    // them is followed if it is a symlink, and calls f with the parent and
+    // the last component of the path. Absolute paths are taken as relative
+    // to this directory.
+    fn at<T, F>(&self, path: &Path, f: F) -> Result<T>
+    where
+        F: FnOnce(RawFd, &OsStr) -> nix::Result<T>,
+    {
+        let mut components = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::RootDir | Component::CurDir => {}
+                Component::Normal(name) => components.push(name),
+                Component::ParentDir | Component::Prefix(_) => {
+                    bail!("{} leaves {}", path.display(), self.path.display())
+                }
+            }
+        }
+        let name = components
+            .pop()
+            .with_context(|| format!("{} does not name a file", path.display()))?;
+
+        let mut parent: Option<Dir> = None;
+        for component in components {
+            let dirfd = parent.as_ref().map_or(self.fd, |dir| dir.fd);
+            let fd = open_dir_at(dirfd, component)?;
+            parent = Some(Dir {
+                fd,
+                path: PathBuf::new(),
+            });
+        }
+        let dirfd = parent.as_ref().map_or(self.fd, |dir| dir.fd);
+        Ok(f(dirfd, name)?)
+    }
+
+    fn full_path(&self, path: &Path) -> PathBuf {
+        self.path.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    fn display(&self, path: &Path) -> String {
+        self.full_path(path).display().to_string()
+    }
+}
+
+impl AsRawFd for Dir {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.fd);
+    }
+}
+
+fn open_dir_at(dirfd: RawFd, name: &OsStr) -> nix::Result<RawFd> {
+    fcntl::openat(
+        dirfd,
+        name,
+        OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )
+}
+
+/// Writes to an existing file on procfs, e.g. /proc/self/oom_score_adj.
+/// Fails if the directory of the file is not on procfs or if the file is a
+/// symlink.
+pub fn write_proc_file<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
+    let path = path.as_ref();
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => Dir::open_procfs(parent)?.write_file(name, contents),
+        _ => bail!("{} is not a file on procfs", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use std::fs;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    fn test_symlinks_are_not_followed() -> Result<()> {
+        let tmp = create_temp_dir("test_symlinks_are_not_followed")?;
+        let root = tmp.join("root");
+        let outside = tmp.join("outside");
+        fs::create_dir_all(root.join("dev"))?;
+        fs::create_dir(&outside)?;
+        fs::write(outside.join("passwd"), "host")?;
+        symlink(&outside, root.join("etc"))?;
+        symlink(outside.join("passwd"), root.join("dev/passwd"))?;
+
+        let dir = Dir::open(&root)?;
+        assert!(dir.write_file("/etc/passwd", "container").is_err());
+        assert!(dir.write_file("dev/passwd", "container").is_err());
+        assert!(dir.open_dir("etc").is_err());
+        assert!(dir
+            .open_file("../outside/passwd", OFlag::O_RDONLY, Mode::empty())
+            .is_err());
+        assert!(dir.chmod("dev/passwd", Mode::S_IRWXU).is_err());
+        assert!(dir.create_dir_all("etc/ssl", Mode::S_IRWXU).is_err());
+        assert!(!outside.join("ssl").exists());
+        assert_eq!(fs::read_to_string(outside.join("passwd"))?, "host");
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_operations() -> Result<()> {
+        let tmp = create_temp_dir("test_file_operations")?;
+        fs::create_dir(tmp.join("dev"))?;
+        let dir = Dir::open(tmp.path())?;
+
+        dir.open_file("/dev/null", OFlag::O_CREAT | OFlag::O_WRONLY, Mode::S_IRUSR)?;
+        dir.chmod("/dev/null", Mode::from_bits_truncate(0o640))?;
+        assert_eq!(
+            fs::metadata(tmp.join("dev/null"))?.permissions().mode() & 0o777,
+            0o640
+        );
+        dir.chown("/dev/null", Some(unistd::geteuid()), None)?;
+
+        dir.mknod("dev/fifo", SFlag::S_IFIFO, Mode::S_IRUSR, 0)?;
+        dir.symlink("null", "dev/stdin")?;
+        assert_eq!(fs::read_link(tmp.join("dev/stdin"))?, Path::new("null"));
+
+        dir.remove_file("dev/stdin")?;
+        dir.remove_file("dev/stdin")?;
+        assert!(!tmp.join("dev/stdin").exists());
+        assert_eq!(dir.open_dir("dev")?.path(), tmp.join("dev"));
+
+        let created = dir.create_dir_all("/dev/shm/a", Mode::S_IRWXU)?;
+        assert_eq!(created.path(), tmp.join("dev/shm/a"));
+        assert!(tmp.join("dev/shm/a").is_dir());
+        dir.create_dir_all("dev/shm/a", Mode::S_IRWXU)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_proc_file() -> Result<()> {
+        let tmp = create_temp_dir("test_write_proc_file")?;
+        fs::write(tmp.join("oom_score_adj"), "0")?;
+        assert!(write_proc_file(tmp.join("oom_score_adj"), "0").is_err());
+
+        let oom_score_adj = fs::read_to_string("/proc/self/oom_score_adj")?;
+        write_proc_file("/proc/self/oom_score_adj", oom_score_adj.trim())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+//! Validation of the runtime spec of a bundle before a container is created
+//! from it. All problems of the spec are collected and reported at once, so
+//! a broken config.json does not have to be fixed one error at a time.
+use crate::error::YoukiError;
+use anyhow::{bail, Context, Result};
+use oci_spec::runtime::{Linux, LinuxIdMapping, LinuxNamespaceType, Spec};
+use std::path::{Component, Path};
+
+/// Version of the runtime spec implemented by youki
+const SUPPORTED_OCI_VERSION: (u64, u64) = (1, 0);
+
+/// Checks that youki implements the ociVersion of the spec. Fields added by a
+/// newer version of the spec would be silently ignored, so these specs are
+/// rejected, unless the check is overridden in which case only a warning is
+/// logged.
+pub fn check_oci_version(version: &str, ignore: bool) -> Result<()> {
+    let (major, minor) = parse_oci_version(version)?;
+    if (major, minor) == SUPPORTED_OCI_VERSION {
+        return Ok(());
+    }
+
+    if ignore {
+        log::warn!(
+            "runtime spec has version {}, youki only implements {}.{}.x",
+            version,
+            SUPPORTED_OCI_VERSION.0,
+            SUPPORTED_OCI_VERSION.1
+        );
+        return Ok(());
+    }
+
+    bail!(YoukiError::InvalidSpec(format!(
+        "incompatible version {}, only {}.{}.x is supported. \
+        Use --ignore-oci-version to create the container anyway",
+        version, SUPPORTED_OCI_VERSION.0, SUPPORTED_OCI_VERSION.1
+    )));
+}
+
+/// Returns the major and minor version of a semver version like 1.0.2-dev
+fn parse_oci_version(version: &str) -> Result<(u64, u64)> {
+    let numbers = version.split(&['-', '+'][..]).next().unwrap_or_default();
+    let parts: Vec<u64> = numbers
+        .split('.')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("invalid ociVersion {}", version))?;
+    if parts.len() != 3 {
+        bail!("invalid ociVersion {}, expected MAJOR.MINOR.PATCH", version);
+    }
+
+    Ok((parts[0], parts[1]))
+}
+
+/// Validates the spec of the bundle and fails with all problems found in it
+pub fn validate(spec: &Spec, bundle: &Path) -> Result<()> {
+    let problems = problems(spec, bundle);
+    if !problems.is_empty() {
+        bail!(YoukiError::InvalidSpec(problems.join("; ")));
+    }
+
+    Ok(())
+}
+
+/// Validates the config.json of the bundle
+pub fn validate_bundle(bundle: &Path, ignore_oci_version: bool) -> Result<()> {
+    let spec_path = bundle.join("config.json");
+    let spec = Spec::load(&spec_path)
+        .with_context(|| format!("failed to load {}", spec_path.display()))?;
+    check_oci_version(spec.version(), ignore_oci_version)?;
+    validate(&spec, bundle)
+}
+
+/// Returns the problems found in the spec, rootfs paths are relative to the bundle
+pub fn problems(spec: &Spec, bundle: &Path) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if spec.solaris().is_some() || spec.windows().is_some() || spec.vm().is_some() {
+        problems.push("only the linux platform is supported".to_owned());
+    }
+    match spec.linux() {
+        Some(linux) => {
+            validate_namespaces(spec, linux, &mut problems);
+            validate_id_mappings("uid", linux.uid_mappings().as_deref(), &mut problems);
+            validate_id_mappings("gid", linux.gid_mappings().as_deref(), &mut problems);
+        }
+        None => problems.push("no linux section in spec".to_owned()),
+    }
+
+    for mount in spec.mounts().iter().flatten() {
+        let destination = mount.destination();
+        // same as runc, relative destinations are accepted for compatibility
+        // with older specs and are relative to the root of the container
+        if !destination.is_absolute() {
+            log::warn!(
+                "mount destination {} is not an absolute path, it is treated as relative to /",
+                destination.display()
+            );
+        }
+        if destination.components().any(|c| c == Component::ParentDir) {
+            problems.push(format!(
+                "mount destination {} must not contain '..'",
+                destination.display()
+            ));
+        }
+    }
+
+    match spec.root() {
+        Some(root) => {
+            let rootfs = bundle.join(root.path());
+            if !rootfs.is_dir() {
+                problems.push(format!("rootfs {} does not exist", rootfs.display()));
+            }
+        }
+        None => problems.push("no root in spec".to_owned()),
+    }
+
+    problems
+}
+
+fn validate_namespaces(spec: &Spec, linux: &Linux, problems: &mut Vec<String>) {
+    let namespaces = linux.namespaces().as_deref().unwrap_or_default();
+    let mut seen = Vec::new();
+    for namespace in namespaces {
+        if seen.contains(&namespace.typ()) {
+            problems.push(format!(
+                "namespace {:?} is specified more than once",
+                namespace.typ()
+            ));
+        } else {
+            seen.push(namespace.typ());
+        }
+    }
+
+    let user_namespace = namespaces
+        .iter()
+        .find(|ns| ns.typ() == LinuxNamespaceType::User);
+    let has_mappings = linux.uid_mappings().is_some() || linux.gid_mappings().is_some();
+    match user_namespace {
+        None if has_mappings => problems
+            .push("uid/gid mappings are specified, but there is no user namespace".to_owned()),
+        Some(ns) if ns.path().is_none() => {
+            if linux.uid_mappings().as_ref().map_or(true, |m| m.is_empty()) {
+                problems
+                    .push("user namespace is created, but there are no uid mappings".to_owned());
+            }
+            if linux.gid_mappings().as_ref().map_or(true, |m| m.is_empty()) {
+                problems
+                    .push("user namespace is created, but there are no gid mappings".to_owned());
+            }
+        }
+        _ => {}
+    }
+
+    if spec.hostname().is_some() && !seen.contains(&LinuxNamespaceType::Uts) {
+        problems.push("hostname is specified, but there is no uts namespace".to_owned());
+    }
+}
+
+fn validate_id_mappings(
+    kind: &str,
+    mappings: Option<&[LinuxIdMapping]>,
+    problems: &mut Vec<String>,
+) {
+    let mappings = mappings.unwrap_or_default();
+    for (i, mapping) in mappings.iter().enumerate() {
+        if mapping.size() == 0 {
+            problems.push(format!(
+                "{} mapping {} has a size of 0",
+                kind,
+                mapping.container_id()
+            ));
+            continue;
+        }
+        if mapping.container_id().checked_add(mapping.size()).is_none()
+            || mapping.host_id().checked_add(mapping.size()).is_none()
+        {
+            problems.push(format!(
+                "{} mapping {} exceeds the range of ids",
+                kind,
+                mapping.container_id()
+            ));
+            continue;
+        }
+
+        let overlaps = mappings[..i].iter().any(|other| {
+            mapping.container_id() < other.container_id().saturating_add(other.size())
+                && other.container_id() < mapping.container_id() + mapping.size()
+        });
+        if overlaps {
+            problems.push(format!(
+                "{} mapping {} overlaps with another mapping",
+                kind,
+                mapping.container_id()
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_oci_version() -> Result<()> {
+        assert_eq!(parse_oci_version("1.0.2")?, (1, 0));
+        assert_eq!(parse_oci_version("1.1.0-rc.1")?, (1, 1));
+        assert_eq!(parse_oci_version("1.0.2-dev+build")?, (1, 0));
+        assert!(parse_oci_version("1.0").is_err());
+        assert!(parse_oci_version("v1.0.0").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_oci_version() -> Result<()> {
+        check_oci_version("1.0.2-dev", false)?;
+        assert!(check_oci_version("1.1.0", false).is_err());
+        assert!(check_oci_version("2.0.0", false).is_err());
+        assert!(check_oci_version("0.5.0", false).is_err());
+        check_oci_version("1.1.0", true)?;
+        assert!(check_oci_version("latest", true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_spec_is_valid() -> Result<()> {
+        let bundle = create_temp_dir("test_default_spec_is_valid")?;
+        std::fs::create_dir_all(bundle.join("rootfs"))?;
+        let spec = Spec::default();
+        assert_eq!(problems(&spec, &bundle), Vec::<String>::new());
+        validate(&spec, &bundle)
+    }
+
+    #[test]
+    fn test_all_problems_are_reported() -> Result<()> {
+        let bundle = create_temp_dir("test_all_problems_are_reported")?;
+        let spec: Spec = serde_json::from_value(json!({
+            "ociVersion": "1.0.2",
+            "root": { "path": "rootfs" },
+            "hostname": "test",
+            "mounts": [
+                { "destination": "proc", "type": "proc" },
+                { "destination": "/dev/../etc", "type": "tmpfs" },
+                { "destination": "/sys", "type": "sysfs" }
+            ],
+            "linux": {
+                "namespaces": [{ "type": "pid" }, { "type": "pid" }, { "type": "user" }],
+                "uidMappings": [
+                    { "containerID": 0, "hostID": 1000, "size": 10 },
+                    { "containerID": 5, "hostID": 2000, "size": 10 }
+                ],
+                "gidMappings": [{ "containerID": 0, "hostID": 1000, "size": 0 }]
+            }
+        }))?;
+
+        // the relative destination proc is accepted
+        let problems = problems(&spec, &bundle);
+        assert_eq!(problems.len(), 6, "{:?}", problems);
+        assert!(
+            !problems.iter().any(|p| p.contains("proc")),
+            "{:?}",
+            problems
+        );
+        assert!(validate(&spec, &bundle).is_err());
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 #!/bin/bash
 
-TARGET=${TARGET-x86_64-unknown-linux-gnu}
+TARGET=${TARGET-$(uname -m)-unknown-linux-gnu}
 if [ "$TARGET" != "" ]; then
     TGT="--target $TARGET"
 fi